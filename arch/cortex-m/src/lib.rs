@@ -196,6 +196,37 @@ core::arch::global_asm!(
     etext = sym _etext,
 );
 
+/// The fault status registers the hard fault handler saved when a process
+/// last faulted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProcessFaultStatus {
+    /// Configurable Fault Status Register.
+    pub cfsr: u32,
+    /// HardFault Status Register.
+    pub hfsr: u32,
+    /// MemManage Fault Address Register, valid if `CFSR.MMARVALID` is set.
+    pub mmfar: u32,
+    /// BusFault Address Register, valid if `CFSR.BFARVALID` is set.
+    pub bfar: u32,
+}
+
+/// Get the fault status saved for the last process fault.
+///
+/// This is only meaningful while handling that fault, e.g. from a
+/// `ProcessFaultPolicy`, as the next process fault overwrites it.
+pub fn process_fault_status() -> ProcessFaultStatus {
+    // Safety: `SCB_REGISTERS` is only written by the hard fault handler for
+    // a process fault, which cannot run while the kernel is running.
+    let registers =
+        unsafe { core::ptr::read_volatile(core::ptr::addr_of!(syscall::SCB_REGISTERS)) };
+    ProcessFaultStatus {
+        cfsr: registers[1],
+        hfsr: registers[2],
+        mmfar: registers[3],
+        bfar: registers[4],
+    }
+}
+
 pub unsafe fn print_cortexm_state(writer: &mut dyn Write) {
     let _ccr = syscall::SCB_REGISTERS[0];
     let cfsr = syscall::SCB_REGISTERS[1];
@@ -53,6 +53,15 @@ pub struct CortexMStoredState {
     psp: usize,
 }
 
+impl CortexMStoredState {
+    /// The process stack pointer. It points at the exception frame (r0-r3,
+    /// r12, lr, pc, xPSR) the hardware stacked when the process last
+    /// stopped running.
+    pub fn psp(&self) -> usize {
+        self.psp
+    }
+}
+
 /// Values for encoding the stored state buffer in a binary slice.
 const VERSION: usize = 1;
 const STORED_STATE_SIZE: usize = size_of::<CortexMStoredState>();
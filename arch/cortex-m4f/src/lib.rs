@@ -17,11 +17,13 @@ pub mod mpu {
 pub use cortexm::dwt;
 pub use cortexm::initialize_ram_jump_to_main;
 pub use cortexm::nvic;
+pub use cortexm::process_fault_status;
 pub use cortexm::scb;
 pub use cortexm::support;
 pub use cortexm::systick;
 pub use cortexm::unhandled_interrupt;
 pub use cortexm::CortexMVariant;
+pub use cortexm::ProcessFaultStatus;
 
 // Enum with no variants to ensure that this type is not instantiable. It is
 // only used to pass architecture-specific constants and functions via the
@@ -57,5 +59,6 @@ impl cortexm::CortexMVariant for CortexM4F {
 }
 
 pub mod syscall {
+    pub use cortexm::syscall::CortexMStoredState;
     pub type SysCall = cortexm::syscall::SysCall<crate::CortexM4F>;
 }
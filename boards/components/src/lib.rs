@@ -51,6 +51,7 @@ pub mod kv;
 pub mod l3gd20;
 pub mod led;
 pub mod led_matrix;
+pub mod lin;
 pub mod lldb;
 pub mod loader;
 pub mod lpm013m126;
@@ -78,6 +79,7 @@ pub mod rng;
 pub mod sched;
 pub mod screen;
 pub mod segger_rtt;
+pub mod serial_number;
 pub mod servo;
 pub mod sh1106;
 pub mod sha;
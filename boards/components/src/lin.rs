@@ -0,0 +1,97 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the LIN syscall interface.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let lin = components::lin::LinComponent::new(
+//!     board_kernel,
+//!     capsules_extra::lin::DRIVER_NUM,
+//!     &peripherals.lin,
+//!     mux_alarm,
+//! )
+//! .finalize(components::lin_component_static!(s32k144::lin::Lin, s32k144::rtc::Rtc));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::lin::LinDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::lin::{self, Lin};
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! lin_component_static {
+    ($L:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let buffer = kernel::static_buf!([u8; kernel::hil::lin::MAX_DATA_LEN]);
+        let lin = kernel::static_buf!(
+            capsules_extra::lin::LinDriver<
+                'static,
+                $L,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, buffer, lin)
+    };};
+}
+
+pub struct LinComponent<L: 'static + Lin<'static>, A: 'static + Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    lin: &'static L,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<L: 'static + Lin<'static>, A: 'static + Alarm<'static>> LinComponent<L, A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        lin: &'static L,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> LinComponent<L, A> {
+        LinComponent {
+            board_kernel,
+            driver_num,
+            lin,
+            alarm_mux,
+        }
+    }
+}
+
+impl<L: 'static + Lin<'static>, A: 'static + Alarm<'static>> Component for LinComponent<L, A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; lin::MAX_DATA_LEN]>,
+        &'static mut MaybeUninit<LinDriver<'static, L, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static LinDriver<'static, L, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let lin_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        lin_alarm.setup();
+
+        let buffer = static_buffer.1.write([0; lin::MAX_DATA_LEN]);
+
+        let lin_driver = static_buffer.2.write(LinDriver::new(
+            self.lin,
+            lin_alarm,
+            buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.lin.set_client(lin_driver);
+        lin_alarm.set_alarm_client(lin_driver);
+
+        lin_driver
+    }
+}
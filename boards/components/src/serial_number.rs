@@ -0,0 +1,62 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the serial number syscall interface.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let serial_number = components::serial_number::SerialNumberComponent::new(
+//!     board_kernel,
+//!     capsules_extra::serial_number::DRIVER_NUM,
+//!     &SERIAL_NUMBER,
+//! )
+//! .finalize(components::serial_number_component_static!());
+//! ```
+
+use capsules_extra::serial_number::SerialNumber;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! serial_number_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::serial_number::SerialNumber)
+    };};
+}
+
+pub struct SerialNumberComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    serial_number: &'static [u8],
+}
+
+impl SerialNumberComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        serial_number: &'static [u8],
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            serial_number,
+        }
+    }
+}
+
+impl Component for SerialNumberComponent {
+    type StaticInput = &'static mut MaybeUninit<SerialNumber>;
+    type Output = &'static SerialNumber;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        s.write(SerialNumber::new(
+            self.serial_number,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ))
+    }
+}
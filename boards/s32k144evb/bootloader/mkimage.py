@@ -0,0 +1,86 @@
+#!/usr/bin/env python3
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2024.
+
+# Builds a kernel image for the S32K144EVB bootloader: the 1K image header
+# block followed by the kernel binary.
+#
+# The image is sent to the bootloader's update mode with XMODEM, which
+# writes the sequence number itself. To program an image into a slot with a
+# debug probe instead, pass `--sequence`, so the bootloader sees it as
+# installed. With `--key`, the header carries the AES-CMAC the bootloader
+# checks when it requires signed images; the key is the one loaded into
+# the CSEc key slot it uses. Signing needs the `cryptography` package.
+#
+# The slot addresses must match `flash_map.ld`.
+
+import argparse
+import hashlib
+import struct
+import sys
+
+IMAGE_MAGIC = 0x4D494B54  # "TKIM"
+IMAGE_VERSION = 1
+HEADER_BLOCK_SIZE = 1024
+SLOT_SIZE = 168 * 1024
+SLOT_ORIGINS = {"a": 0x0000C000, "b": 0x00036000}
+
+
+def cmac(key, data):
+    from cryptography.hazmat.primitives.cmac import CMAC
+    from cryptography.hazmat.primitives.ciphers import algorithms
+
+    c = CMAC(algorithms.AES(key))
+    c.update(data)
+    return c.finalize()
+
+
+def main():
+    parser = argparse.ArgumentParser(description="Build an S32K144EVB kernel image")
+    parser.add_argument("kernel", help="Kernel binary, linked for the slot")
+    parser.add_argument("output", help="Image to write")
+    parser.add_argument(
+        "--slot", choices=SLOT_ORIGINS, required=True, help="Slot the kernel is linked for"
+    )
+    parser.add_argument(
+        "--sequence",
+        type=int,
+        help="Sequence number, for an image programmed with a debug probe",
+    )
+    parser.add_argument("--key", help="AES-128 key to sign the header with, in hex")
+    args = parser.parse_args()
+
+    with open(args.kernel, "rb") as f:
+        kernel = f.read()
+
+    if len(kernel) > SLOT_SIZE - HEADER_BLOCK_SIZE:
+        sys.exit("error: the kernel does not fit in a slot")
+
+    # The reset vector, the second word of the vector table, must point into
+    # the slot, or the kernel was linked for the other one.
+    origin = SLOT_ORIGINS[args.slot]
+    (reset,) = struct.unpack_from("<I", kernel, 4)
+    if not origin <= reset < origin + SLOT_SIZE:
+        sys.exit(
+            "error: the kernel is not linked for slot {} (reset vector {:#x})".format(
+                args.slot.upper(), reset
+            )
+        )
+
+    slot = list(SLOT_ORIGINS).index(args.slot)
+    signed = struct.pack(
+        "<IIII", IMAGE_MAGIC, IMAGE_VERSION, slot, len(kernel)
+    ) + hashlib.sha256(kernel).digest()
+    mac = cmac(bytes.fromhex(args.key), signed) if args.key else bytes(16)
+    sequence = 0xFFFFFFFF if args.sequence is None else args.sequence
+    header = signed + mac + struct.pack("<I", sequence)
+
+    with open(args.output, "wb") as f:
+        f.write(header.ljust(HEADER_BLOCK_SIZE, b"\xff"))
+        f.write(kernel)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,63 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Boot state kept in the first slot of the retained RAM.
+//!
+//! The bootloader counts every start of a kernel slot. The kernel confirms
+//! its boot once it is initialized, which clears the count; a slot that
+//! reaches `MAX_BOOT_ATTEMPTS` unconfirmed starts, because its kernel keeps
+//! resetting or hanging until the reset button is pressed, is not started
+//! again until a new image is written to it.
+
+use kernel::utilities::retained_ram::Retained;
+
+use crate::SLOT_COUNT;
+
+/// Unconfirmed starts after which a slot is given up.
+pub const MAX_BOOT_ATTEMPTS: u32 = 3;
+
+/// Value of `BootState::update` asking the bootloader to wait for a new
+/// image instead of starting a kernel.
+pub const UPDATE_REQUEST: u32 = 0x5550_4454;
+
+/// Value of `BootState::booted` before any slot was started.
+pub const NO_SLOT: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct BootState {
+    /// The slot the bootloader started last, or `NO_SLOT`.
+    pub booted: u32,
+    /// Unconfirmed starts of each slot.
+    pub attempts: [u32; SLOT_COUNT],
+    /// `UPDATE_REQUEST` to enter the update mode on the next reset.
+    pub update: u32,
+}
+
+// Made only of integers, with no padding.
+unsafe impl Retained for BootState {}
+
+impl Default for BootState {
+    fn default() -> Self {
+        Self {
+            booted: NO_SLOT,
+            attempts: [0; SLOT_COUNT],
+            update: 0,
+        }
+    }
+}
+
+impl BootState {
+    /// Whether the slot has not used up its boot attempts.
+    pub fn can_boot(&self, slot: usize) -> bool {
+        self.attempts[slot] < MAX_BOOT_ATTEMPTS
+    }
+
+    /// Record that the kernel in the booted slot came up.
+    pub fn confirm(&mut self) {
+        if let Some(attempts) = self.attempts.get_mut(self.booted as usize) {
+            *attempts = 0;
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! SHA-256 of a flash region, with the kernel's software implementation.
+//!
+//! `Sha256Software` hashes when it is given the data, but reports through a
+//! deferred call; there is no kernel loop here, so `digest` services the
+//! deferred calls itself until the hash is done.
+
+use core::cell::Cell;
+
+use capsules_extra::sha256::Sha256Software;
+use kernel::deferred_call::DeferredCall;
+use kernel::hil::digest::{self, DigestData, DigestHash};
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::leasable_buffer::{SubSlice, SubSliceMut};
+use kernel::ErrorCode;
+
+use s32k144evb_bootloader::image::DIGEST_LENGTH;
+
+pub struct Hasher {
+    sha: &'static Sha256Software<'static>,
+    output: TakeCell<'static, [u8; DIGEST_LENGTH]>,
+    result: Cell<Option<Result<(), ErrorCode>>>,
+}
+
+impl Hasher {
+    pub fn new(
+        sha: &'static Sha256Software<'static>,
+        output: &'static mut [u8; DIGEST_LENGTH],
+    ) -> Self {
+        Self {
+            sha,
+            output: TakeCell::new(output),
+            result: Cell::new(None),
+        }
+    }
+
+    /// Wait for the callback of the operation just started.
+    fn wait(&self) -> Result<(), ErrorCode> {
+        loop {
+            if let Some(result) = self.result.take() {
+                return result;
+            }
+            if DeferredCall::service_next_pending().is_none() {
+                return Err(ErrorCode::FAIL);
+            }
+        }
+    }
+
+    /// SHA-256 of `data`.
+    pub fn digest(&self, data: &'static [u8]) -> Result<[u8; DIGEST_LENGTH], ErrorCode> {
+        let output = self.output.take().ok_or(ErrorCode::BUSY)?;
+        let added = self
+            .sha
+            .add_data(SubSlice::new(data))
+            .map_err(|(e, _)| e)
+            .and_then(|()| self.wait());
+        if let Err(e) = added {
+            self.output.replace(output);
+            return Err(e);
+        }
+        if let Err((e, output)) = self.sha.run(output) {
+            self.output.replace(output);
+            return Err(e);
+        }
+        self.wait()?;
+        self.output.map(|output| *output).ok_or(ErrorCode::FAIL)
+    }
+}
+
+impl digest::ClientData<DIGEST_LENGTH> for Hasher {
+    fn add_data_done(&self, result: Result<(), ErrorCode>, _data: SubSlice<'static, u8>) {
+        self.result.set(Some(result));
+    }
+
+    fn add_mut_data_done(&self, result: Result<(), ErrorCode>, _data: SubSliceMut<'static, u8>) {
+        self.result.set(Some(result));
+    }
+}
+
+impl digest::ClientHash<DIGEST_LENGTH> for Hasher {
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; DIGEST_LENGTH]) {
+        self.output.replace(digest);
+        self.result.set(Some(result));
+    }
+}
+
+impl digest::ClientVerify<DIGEST_LENGTH> for Hasher {
+    fn verification_done(
+        &self,
+        result: Result<bool, ErrorCode>,
+        compare: &'static mut [u8; DIGEST_LENGTH],
+    ) {
+        self.output.replace(compare);
+        self.result.set(Some(result.map(|_| ())));
+    }
+}
@@ -0,0 +1,86 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Header of a kernel image.
+//!
+//! A slot starts with a 1K header block, followed by the kernel, whose
+//! vector table is at the start of the second kilobyte. The block starts with
+//! the header below, in little-endian words; the rest of it is erased flash.
+//! `mkimage.py` builds the header block from a kernel binary. The sequence
+//! number is left erased; the bootloader writes it when it installs the
+//! image, one more than the other slot's, and starts the valid image with
+//! the highest one.
+
+use crate::SLOT_COUNT;
+
+/// "TKIM"
+pub const IMAGE_MAGIC: u32 = 0x4D49_4B54;
+pub const IMAGE_VERSION: u32 = 1;
+
+pub const DIGEST_LENGTH: usize = 32;
+pub const MAC_LENGTH: usize = 16;
+
+#[derive(Clone, Copy)]
+pub struct ImageHeader {
+    /// Slot the kernel is linked for.
+    pub slot: u32,
+    /// Length of the kernel, after the header block.
+    pub length: u32,
+    /// SHA-256 of the kernel.
+    pub digest: [u8; DIGEST_LENGTH],
+    /// AES-CMAC of the first `SIGNED_LENGTH` bytes of the header, or
+    /// zeros if the image is not signed.
+    pub mac: [u8; MAC_LENGTH],
+    /// Written by the bootloader, erased (`u32::MAX`) until then.
+    pub sequence: u32,
+}
+
+impl ImageHeader {
+    /// Bytes covered by the MAC: magic, version, slot, length and digest.
+    pub const SIGNED_LENGTH: usize = 16 + DIGEST_LENGTH;
+    const MAC_OFFSET: usize = Self::SIGNED_LENGTH;
+    const SEQUENCE_OFFSET: usize = Self::MAC_OFFSET + MAC_LENGTH;
+    pub const LENGTH: usize = Self::SEQUENCE_OFFSET + 4;
+
+    /// Parse the header at the start of `bytes`, if it is one.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::LENGTH)?;
+        let word = |offset: usize| {
+            u32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ])
+        };
+        if word(0) != IMAGE_MAGIC || word(4) != IMAGE_VERSION {
+            return None;
+        }
+        let slot = word(8);
+        if slot as usize >= SLOT_COUNT {
+            return None;
+        }
+        let mut digest = [0; DIGEST_LENGTH];
+        digest.copy_from_slice(&bytes[16..Self::MAC_OFFSET]);
+        let mut mac = [0; MAC_LENGTH];
+        mac.copy_from_slice(&bytes[Self::MAC_OFFSET..Self::SEQUENCE_OFFSET]);
+        Some(Self {
+            slot,
+            length: word(12),
+            digest,
+            mac,
+            sequence: word(Self::SEQUENCE_OFFSET),
+        })
+    }
+
+    /// Whether the bootloader installed the image.
+    pub fn installed(&self) -> bool {
+        self.sequence != u32::MAX
+    }
+
+    /// Store `sequence` in the header at the start of `bytes`.
+    pub fn set_sequence(bytes: &mut [u8], sequence: u32) {
+        bytes[Self::SEQUENCE_OFFSET..Self::LENGTH].copy_from_slice(&sequence.to_le_bytes());
+    }
+}
@@ -0,0 +1,15 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! What the S32K144EVB bootloader and the kernels it starts agree on: the
+//! image header at the start of each kernel slot, and the boot state kept in
+//! retained RAM.
+
+#![no_std]
+
+pub mod boot_state;
+pub mod image;
+
+/// Number of kernel slots.
+pub const SLOT_COUNT: usize = 2;
@@ -0,0 +1,231 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Bootloader for the S32K144EVB.
+//!
+//! It runs from reset, picks one of the two kernel slots and starts the
+//! kernel in it: the image with the highest sequence number whose SHA-256,
+//! and signature if required, match its header, among the slots that have
+//! not used up their boot attempts. With SW2 held during reset, after the
+//! kernel's `update` command, or when no kernel can be started, it stays in
+//! update mode and waits for a new image instead (see `update`).
+
+#![no_std]
+// Disable this attribute when documenting, as a workaround for
+// https://github.com/rust-lang/rust/issues/62184.
+#![cfg_attr(not(doc), no_main)]
+
+use core::panic::PanicInfo;
+use core::ptr::{addr_of, addr_of_mut};
+
+use kernel::deferred_call::DeferredCall;
+use kernel::hil::gpio::Configure;
+use kernel::hil::time::{Alarm, Counter};
+use kernel::hil::uart::{Receive, Transmit};
+use kernel::platform::chip::Chip;
+use kernel::static_init;
+use kernel::utilities::registers::interfaces::ReadWriteable;
+use kernel::utilities::retained_ram::{RetainedRam, RetainedSlot};
+
+use s32k144::chip::S32K144DefaultPeripherals;
+use s32k144::csec::{Csec, KeyId};
+use s32k144::gpio::Pin;
+use s32k144::nvmc::FtfcPage;
+use s32k144evb_bootloader::boot_state::{BootState, UPDATE_REQUEST};
+
+mod hasher;
+mod slots;
+mod update;
+
+/// Held during reset to enter the update mode.
+const BUTTON_SW2_PIN: Pin = Pin::PTC12;
+
+/// Standby input of the TJA1044 CAN transceiver.
+const CAN_STANDBY_PIN: Pin = Pin::PTE11;
+
+const CAN_BITRATE: u32 = 500_000;
+const UART_BAUD_RATE: u32 = 115200;
+
+/// CSEc key that kernel images must be signed with: their header carries an
+/// AES-CMAC of its fields under this key. With `None`, only the SHA-256 of
+/// the kernel is checked.
+const REQUIRE_SIGNATURE: Option<KeyId> = None;
+
+// Flash Configuration Field at 0x400. The part is left unsecure; the NMI
+// pin is disabled because neither the bootloader nor the kernel has an NMI
+// handler.
+s32k144::flash_config!(s32k144::flash_config::FlashConfig::new().nmi_pin(false));
+
+/// Dummy buffer that causes the linker to reserve enough space for the stack.
+#[no_mangle]
+#[link_section = ".stack_buffer"]
+pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
+
+type S32K144Chip = s32k144::chip::S32K144<'static, S32K144DefaultPeripherals<'static>>;
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    loop {
+        cortexm4f::support::nop();
+    }
+}
+
+/// Wait for a new image, and reset once it is installed.
+unsafe fn update_mode(
+    peripherals: &'static S32K144DefaultPeripherals<'static>,
+    slots: &'static slots::Slots<'static>,
+    boot_state: &'static RetainedSlot<'static, BootState>,
+) -> ! {
+    peripherals.init();
+
+    peripherals.clock.low_stop();
+    peripherals.clock.high_stop();
+    peripherals.clock.low_start();
+    peripherals.clock.high_start();
+    while !peripherals.clock.low_started() {}
+    while !peripherals.clock.high_started() {}
+
+    peripherals
+        .clock
+        .enable_peripheral_clock(s32k144::clock::Peripheral::PORTE);
+
+    let rtc = &peripherals.rtc;
+    let _ = rtc.start();
+
+    use kernel::hil::uart::Configure;
+    let uart = &peripherals.lpuart0;
+    uart.enable();
+    let _ = uart.configure(kernel::hil::uart::Parameters {
+        baud_rate: UART_BAUD_RATE,
+        stop_bits: kernel::hil::uart::StopBits::One,
+        parity: kernel::hil::uart::Parity::None,
+        hw_flow_control: false,
+        width: kernel::hil::uart::Width::Eight,
+    });
+
+    // PTE4 and PTE5 as CAN0 RX and TX (ALT5), wired to the TJA1044.
+    let porte = s32k144::pinmux::PORT_BASES[4];
+    porte.pcr[4].modify(s32k144::pinmux::PCR::MUX.val(5));
+    porte.pcr[5].modify(s32k144::pinmux::PCR::MUX.val(5));
+
+    let can0 = &peripherals.can0;
+    let can_standby = &peripherals.gpio_port.pins[CAN_STANDBY_PIN as usize];
+    can_standby.make_output();
+    can0.set_standby_pin(can_standby);
+    let _ = kernel::hil::can::Configure::set_bitrate(can0, CAN_BITRATE);
+    let _ = kernel::hil::can::Configure::set_operation_mode(
+        can0,
+        kernel::hil::can::OperationMode::Normal,
+    );
+    let _ = kernel::hil::can::Controller::enable(can0);
+
+    let updater = static_init!(
+        update::Updater<'static, s32k144::rtc::Rtc<'static>, s32k144::can0::FlexCan<'static>>,
+        update::Updater::new(
+            uart,
+            can0,
+            rtc,
+            &peripherals.nvmc,
+            slots,
+            boot_state,
+            static_init!([u8; 1], [0; 1]),
+            static_init!([u8; 2], [0; 2]),
+            static_init!([u8; 8], [0; 8]),
+            static_init!([u8; update::PACKET_LEN], [0; update::PACKET_LEN]),
+            static_init!(FtfcPage, FtfcPage::default()),
+        )
+    );
+    uart.set_receive_client(updater);
+    uart.set_transmit_client(updater);
+    kernel::hil::can::Receive::set_client(can0, Some(updater));
+    kernel::hil::can::Transmit::set_client(can0, Some(updater));
+    rtc.set_alarm_client(updater);
+    updater.start(static_init!([u8; 8], [0; 8]));
+
+    // The kernel's main loop, without processes.
+    let chip = static_init!(S32K144Chip, s32k144::chip::S32K144::new(peripherals));
+    loop {
+        chip.service_pending_interrupts();
+        while DeferredCall::has_tasks() {
+            DeferredCall::service_next_pending();
+        }
+        chip.atomic(|| {
+            if !chip.has_pending_interrupts() && !DeferredCall::has_tasks() {
+                chip.sleep();
+            }
+        });
+    }
+}
+
+#[no_mangle]
+pub unsafe fn main() {
+    let peripherals = static_init!(S32K144DefaultPeripherals, S32K144DefaultPeripherals::new());
+
+    // Code fetched from flash stalls on wait states unless cached, which
+    // makes hashing the kernel several times slower.
+    peripherals.lmem.set_flash_prefetch(true, true);
+    peripherals.lmem.enable();
+
+    extern "C" {
+        /// Beginning of the RAM region kept through warm resets.
+        static mut _sretained: u32;
+        /// End of the RAM region kept through warm resets.
+        static mut _eretained: u32;
+    }
+
+    // The region holds garbage after a power-on or low-voltage reset. The
+    // kernel relies on it having been cleared here.
+    let retained_ram = static_init!(
+        RetainedRam<'static>,
+        RetainedRam::new(
+            core::slice::from_raw_parts_mut(
+                addr_of_mut!(_sretained),
+                (addr_of!(_eretained) as usize - addr_of!(_sretained) as usize) / 4,
+            ),
+            peripherals.rcm.reset_causes().sram_retained(),
+        )
+    );
+    let boot_state = static_init!(
+        RetainedSlot<'static, BootState>,
+        retained_ram.slot().unwrap()
+    );
+    let mut state = boot_state.get().unwrap_or_default();
+
+    // SW2 reads high while pressed, with an external pull-down.
+    peripherals
+        .clock
+        .enable_peripheral_clock(s32k144::clock::Peripheral::PORTC);
+    let sw2 = &peripherals.gpio_port.pins[BUTTON_SW2_PIN as usize];
+    sw2.make_input();
+    let update_requested = state.update == UPDATE_REQUEST || sw2.read();
+    state.update = 0;
+
+    let sha = static_init!(
+        capsules_extra::sha256::Sha256Software<'static>,
+        capsules_extra::sha256::Sha256Software::new()
+    );
+    kernel::deferred_call::DeferredCallClient::register(sha);
+    let hasher = static_init!(
+        hasher::Hasher,
+        hasher::Hasher::new(sha, static_init!([u8; 32], [0; 32]))
+    );
+    kernel::hil::digest::Digest::set_client(sha, hasher);
+    let csec = static_init!(Csec<'static>, Csec::new(&peripherals.nvmc));
+    let slots = static_init!(
+        slots::Slots<'static>,
+        slots::Slots::new(hasher, csec, REQUIRE_SIGNATURE)
+    );
+
+    if !update_requested {
+        if let Some(slot) = slots.choose(|slot| state.can_boot(slot)) {
+            state.attempts[slot] += 1;
+            state.booted = slot as u32;
+            boot_state.set(state);
+            slots::start(slot);
+        }
+    }
+    boot_state.set(state);
+    update_mode(peripherals, slots, boot_state);
+}
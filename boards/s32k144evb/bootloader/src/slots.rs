@@ -0,0 +1,146 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! The kernel slots: checking their images and starting one.
+
+use core::ptr::addr_of;
+
+use s32k144::csec::{Csec, KeyId};
+use s32k144evb_bootloader::image::ImageHeader;
+use s32k144evb_bootloader::SLOT_COUNT;
+
+use crate::hasher::Hasher;
+
+// Defined in flash_map.ld.
+extern "C" {
+    static SLOT_A_ORIGIN: u8;
+    static SLOT_B_ORIGIN: u8;
+    static SLOT_SIZE: u8;
+    static IMAGE_HEADER_SIZE: u8;
+}
+
+/// Address of the start of `slot`, its header block.
+pub fn origin(slot: usize) -> usize {
+    match slot {
+        0 => addr_of!(SLOT_A_ORIGIN) as usize,
+        _ => addr_of!(SLOT_B_ORIGIN) as usize,
+    }
+}
+
+pub fn size() -> usize {
+    addr_of!(SLOT_SIZE) as usize
+}
+
+pub fn header_size() -> usize {
+    addr_of!(IMAGE_HEADER_SIZE) as usize
+}
+
+/// `length` bytes of `slot`, from `offset`. P-Flash is memory mapped.
+fn contents(slot: usize, offset: usize, length: usize) -> &'static [u8] {
+    let length = length.min(size() - offset);
+    // Safety: the range is within the slot, which is only written while no
+    // reference to it is held.
+    unsafe { core::slice::from_raw_parts((origin(slot) + offset) as *const u8, length) }
+}
+
+pub struct Slots<'a> {
+    hasher: &'a Hasher,
+    csec: &'a Csec<'a>,
+    /// Key of the MAC that images must carry, if any.
+    key: Option<KeyId>,
+}
+
+impl<'a> Slots<'a> {
+    pub fn new(hasher: &'a Hasher, csec: &'a Csec<'a>, key: Option<KeyId>) -> Self {
+        Self { hasher, csec, key }
+    }
+
+    /// Parse the header in `bytes` if it describes an image for `slot`
+    /// that fits in it.
+    pub fn parse_header(&self, slot: usize, bytes: &[u8]) -> Option<ImageHeader> {
+        ImageHeader::parse(bytes)
+            .filter(|header| header.slot as usize == slot)
+            .filter(|header| header.length as usize <= size() - header_size())
+    }
+
+    /// The header of the image installed in `slot`, if any.
+    pub fn header(&self, slot: usize) -> Option<ImageHeader> {
+        self.parse_header(slot, contents(slot, 0, ImageHeader::LENGTH))
+            .filter(ImageHeader::installed)
+    }
+
+    /// Whether the kernel in `slot` matches `header`, parsed from
+    /// `header_bytes`: the digest, and the MAC when images must be signed.
+    pub fn verify(&self, slot: usize, header: &ImageHeader, header_bytes: &[u8]) -> bool {
+        let kernel = contents(slot, header_size(), header.length as usize);
+        if self.hasher.digest(kernel) != Ok(header.digest) {
+            return false;
+        }
+        match self.key {
+            None => true,
+            Some(key) => {
+                let signed = &header_bytes[..ImageHeader::SIGNED_LENGTH];
+                self.csec.verify_mac_blocking(key, signed, &header.mac) == Ok(true)
+            }
+        }
+    }
+
+    /// The slot to start: of those `allowed`, the one with the highest
+    /// sequence number whose image is valid. Of two slots with the same
+    /// sequence number, which images programmed with a debug probe can
+    /// have, slot A is tried first.
+    pub fn choose<F: Fn(usize) -> bool>(&self, allowed: F) -> Option<usize> {
+        let mut candidates = [None; SLOT_COUNT];
+        for (slot, candidate) in candidates.iter_mut().enumerate() {
+            if allowed(slot) {
+                *candidate = self.header(slot).map(|header| (slot, header));
+            }
+        }
+        // Empty slots sort first, and are skipped below.
+        candidates.sort_unstable_by_key(|candidate| {
+            candidate.map(|(slot, header)| (core::cmp::Reverse(header.sequence), slot))
+        });
+        candidates
+            .into_iter()
+            .flatten()
+            .find(|(slot, header)| {
+                self.verify(*slot, header, contents(*slot, 0, ImageHeader::LENGTH))
+            })
+            .map(|(slot, _)| slot)
+    }
+
+    /// Sequence number for an image written to `slot`, one more than any
+    /// other installed image.
+    pub fn next_sequence(&self, slot: usize) -> u32 {
+        (0..SLOT_COUNT)
+            .filter(|other| *other != slot)
+            .filter_map(|other| self.header(other))
+            .map(|header| header.sequence + 1)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+/// Start the kernel in `slot`, as if it had been reset: the vector table is
+/// moved to its own, then the stack pointer and the reset handler are loaded
+/// from it.
+#[cfg(all(target_arch = "arm", target_os = "none"))]
+pub unsafe fn start(slot: usize) -> ! {
+    let vectors = (origin(slot) + header_size()) as *const u32;
+    cortexm4f::scb::set_vector_table_offset(vectors.cast());
+    let stack = core::ptr::read_volatile(vectors);
+    let reset = core::ptr::read_volatile(vectors.add(1));
+    core::arch::asm!(
+        "msr msp, {stack}",
+        "bx {reset}",
+        stack = in(reg) stack,
+        reset = in(reg) reset,
+        options(noreturn),
+    );
+}
+
+#[cfg(not(all(target_arch = "arm", target_os = "none")))]
+pub unsafe fn start(_slot: usize) -> ! {
+    unimplemented!()
+}
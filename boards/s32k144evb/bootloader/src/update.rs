@@ -0,0 +1,473 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Update mode: receive a kernel image with XMODEM-CRC and install it.
+//!
+//! The image is received over LPUART0, or over CAN with the XMODEM stream
+//! carried in the data of standard frames: 1 to 8 bytes per frame, with ID
+//! `CAN_REQUEST_ID` from the host and `CAN_RESPONSE_ID` to it. Until a
+//! transfer starts, 'C' is sent on both every second; the transfer then
+//! continues on the link it started on.
+//!
+//! The image goes to the slot its header names, which must not hold the
+//! kernel that ran last unless that kernel has used up its boot attempts,
+//! so a working kernel is always kept. Each sector is erased and written as
+//! it fills, with the header block left erased. On EOT, the kernel is
+//! checked against the header, and only then is the header written, with a
+//! sequence number above the other slot's. The board then resets, and the
+//! bootloader starts the new kernel. A transfer that fails or stalls for
+//! `TIMEOUT_S` seconds is cancelled, and leaves the slot without a valid
+//! image.
+
+use core::cell::Cell;
+
+use kernel::hil::can;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::hil::uart;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::retained_ram::RetainedSlot;
+use kernel::ErrorCode;
+
+use s32k144::nvmc::{Ftfc, FtfcPage, PAGE_SIZE, PFLASH_BASE, PHRASE_SIZE};
+use s32k144evb_bootloader::boot_state::BootState;
+use s32k144evb_bootloader::image::ImageHeader;
+
+use crate::slots::{self, Slots};
+
+/// CAN ID of the frames carrying the XMODEM stream to the board.
+pub const CAN_REQUEST_ID: u16 = 0x7E0;
+/// CAN ID of the frames carrying the XMODEM stream from the board.
+pub const CAN_RESPONSE_ID: u16 = 0x7E8;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+const CRC_MODE: u8 = b'C';
+
+/// Start byte, block number and its complement, data, and CRC.
+pub const PACKET_LEN: usize = 3 + 1024 + 2;
+
+/// Bytes of the header block that are kept until the image is checked: the
+/// header itself, rounded up to whole flash phrases.
+const HEADER_COPY_LEN: usize = ImageHeader::LENGTH.next_multiple_of(PHRASE_SIZE);
+
+const TICK_MS: u32 = 1000;
+const TIMEOUT_S: u32 = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Link {
+    Uart,
+    Can,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// Sending 'C' until a transfer starts.
+    Waiting,
+    /// Receiving blocks over `link`.
+    Receiving,
+    /// The image is installed; the board resets once the last ACK is sent.
+    Resetting,
+}
+
+/// CRC-16/XMODEM.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+pub struct Updater<'a, A: Alarm<'a>, C: can::Can> {
+    uart: &'a dyn uart::UartData<'a>,
+    can: &'a C,
+    alarm: &'a A,
+    flash: &'a Ftfc,
+    slots: &'a Slots<'a>,
+    boot_state: &'a RetainedSlot<'a, BootState>,
+
+    uart_rx: TakeCell<'static, [u8]>,
+    uart_tx: TakeCell<'static, [u8]>,
+    can_tx: TakeCell<'static, [u8; can::STANDARD_CAN_PACKET_SIZE]>,
+    packet: TakeCell<'static, [u8; PACKET_LEN]>,
+    sector: TakeCell<'static, FtfcPage>,
+
+    state: Cell<State>,
+    link: OptionalCell<Link>,
+    /// Bytes of the current packet received, and its full length.
+    received: Cell<usize>,
+    packet_len: Cell<usize>,
+    /// Number of the next block.
+    block: Cell<u8>,
+    /// Bytes of the image received.
+    offset: Cell<usize>,
+    /// Slot being written, once the header has been received.
+    target: OptionalCell<usize>,
+    header: Cell<[u8; HEADER_COPY_LEN]>,
+    /// Ticks since the last byte.
+    idle: Cell<u32>,
+}
+
+impl<'a, A: Alarm<'a>, C: can::Can> Updater<'a, A, C> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        uart: &'a dyn uart::UartData<'a>,
+        can: &'a C,
+        alarm: &'a A,
+        flash: &'a Ftfc,
+        slots: &'a Slots<'a>,
+        boot_state: &'a RetainedSlot<'a, BootState>,
+        uart_rx: &'static mut [u8; 1],
+        uart_tx: &'static mut [u8; 2],
+        can_tx: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+        packet: &'static mut [u8; PACKET_LEN],
+        sector: &'static mut FtfcPage,
+    ) -> Self {
+        sector.0.fill(0xFF);
+        Self {
+            uart,
+            can,
+            alarm,
+            flash,
+            slots,
+            boot_state,
+            uart_rx: TakeCell::new(uart_rx),
+            uart_tx: TakeCell::new(uart_tx),
+            can_tx: TakeCell::new(can_tx),
+            packet: TakeCell::new(packet),
+            sector: TakeCell::new(sector),
+            state: Cell::new(State::Waiting),
+            link: OptionalCell::empty(),
+            received: Cell::new(0),
+            packet_len: Cell::new(0),
+            block: Cell::new(1),
+            offset: Cell::new(0),
+            target: OptionalCell::empty(),
+            header: Cell::new([0xFF; HEADER_COPY_LEN]),
+            idle: Cell::new(0),
+        }
+    }
+
+    /// Start receiving on both links. `can_rx` is lent to the CAN driver.
+    pub fn start(&self, can_rx: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE]) {
+        self.uart_rx.take().map(|buffer| {
+            if let Err((_, buffer)) = self.uart.receive_buffer(buffer, 1) {
+                self.uart_rx.replace(buffer);
+            }
+        });
+        let _ = self.can.start_receive_process(can_rx);
+        self.send_both(&[CRC_MODE]);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TICK_MS));
+    }
+
+    fn send(&self, link: Link, bytes: &[u8]) {
+        match link {
+            Link::Uart => {
+                self.uart_tx.take().map(|buffer| {
+                    buffer[..bytes.len()].copy_from_slice(bytes);
+                    if let Err((_, buffer)) = self.uart.transmit_buffer(buffer, bytes.len()) {
+                        self.uart_tx.replace(buffer);
+                    }
+                });
+            }
+            Link::Can => {
+                self.can_tx.take().map(|buffer| {
+                    buffer[..bytes.len()].copy_from_slice(bytes);
+                    let id = can::Id::Standard(CAN_RESPONSE_ID);
+                    if let Err((_, buffer)) = self.can.send(id, buffer, bytes.len()) {
+                        self.can_tx.replace(buffer);
+                    }
+                });
+            }
+        }
+    }
+
+    fn send_both(&self, bytes: &[u8]) {
+        self.send(Link::Uart, bytes);
+        self.send(Link::Can, bytes);
+    }
+
+    /// Reply on the link of the transfer.
+    fn reply(&self, byte: u8) {
+        self.link.map(|link| self.send(link, &[byte]));
+    }
+
+    /// Drop the transfer, telling the sender if there is one, and wait for
+    /// a new one.
+    fn cancel(&self) {
+        self.link.take().map(|link| self.send(link, &[CAN, CAN]));
+        self.state.set(State::Waiting);
+        self.received.set(0);
+        self.block.set(1);
+        self.offset.set(0);
+        self.target.clear();
+        self.sector.map(|sector| sector.0.fill(0xFF));
+    }
+
+    /// Handle a byte received over `link`.
+    fn receive(&self, link: Link, byte: u8) {
+        match self.state.get() {
+            State::Resetting => return,
+            State::Receiving if self.link.get() != Some(link) => return,
+            State::Waiting | State::Receiving => {}
+        }
+        self.idle.set(0);
+
+        let received = self.received.get();
+        if received == 0 {
+            let packet_len = match byte {
+                SOH => 3 + 128 + 2,
+                STX => PACKET_LEN,
+                EOT if self.state.get() == State::Receiving => {
+                    match self.finish() {
+                        Ok(()) => {
+                            self.reply(ACK);
+                            self.state.set(State::Resetting);
+                        }
+                        Err(_) => self.cancel(),
+                    }
+                    return;
+                }
+                CAN => {
+                    self.cancel();
+                    return;
+                }
+                _ => return,
+            };
+            self.state.set(State::Receiving);
+            self.link.set(link);
+            self.packet_len.set(packet_len);
+        }
+
+        self.packet.map(|packet| packet[received] = byte);
+        self.received.set(received + 1);
+        if received + 1 == self.packet_len.get() {
+            self.received.set(0);
+            self.packet_complete();
+        }
+    }
+
+    fn packet_complete(&self) {
+        let Some(packet) = self.packet.take() else {
+            return;
+        };
+        let len = self.packet_len.get();
+        let block = packet[1];
+        let data = &packet[3..len - 2];
+        let crc = u16::from_be_bytes([packet[len - 2], packet[len - 1]]);
+        let result = if block != !packet[2] || crc16(data) != crc {
+            Err(NAK)
+        } else if block == self.block.get() {
+            self.write(data).map_err(|_| CAN)
+        } else if block == self.block.get().wrapping_sub(1) {
+            // The sender missed the last ACK.
+            Ok(())
+        } else {
+            Err(CAN)
+        };
+        self.packet.replace(packet);
+
+        match result {
+            Ok(()) => {
+                if block == self.block.get() {
+                    self.block.set(block.wrapping_add(1));
+                }
+                self.reply(ACK);
+            }
+            Err(NAK) => self.reply(NAK),
+            Err(_) => self.cancel(),
+        }
+    }
+
+    /// Add `data` to the image, writing each sector as it fills.
+    fn write(&self, data: &[u8]) -> Result<(), ErrorCode> {
+        self.sector.map_or(Err(ErrorCode::NOMEM), |sector| {
+            for byte in data {
+                let offset = self.offset.get();
+                sector[offset % PAGE_SIZE] = *byte;
+                self.offset.set(offset + 1);
+                if (offset + 1) % PAGE_SIZE == 0 {
+                    self.write_sector(sector, offset / PAGE_SIZE)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Write `sector` as sector `index` of the target slot, and erase it in
+    /// RAM for the next one. The first sector holds the header, which picks
+    /// the target slot.
+    fn write_sector(&self, sector: &mut FtfcPage, index: usize) -> Result<(), ErrorCode> {
+        if index == 0 {
+            self.accept_header(&sector.0)?;
+            sector.0[..slots::header_size()].fill(0xFF);
+        }
+        let target = self.target.get().ok_or(ErrorCode::INVAL)?;
+        if (index + 1) * PAGE_SIZE > slots::size() {
+            return Err(ErrorCode::SIZE);
+        }
+        let address = slots::origin(target) + index * PAGE_SIZE;
+        self.flash
+            .erase_page_blocking((address - PFLASH_BASE) / PAGE_SIZE)?;
+        self.flash.program_blocking(address, &sector.0)?;
+        sector.0.fill(0xFF);
+        Ok(())
+    }
+
+    /// Check the header of the image being received and pick its slot.
+    fn accept_header(&self, bytes: &[u8]) -> Result<(), ErrorCode> {
+        let slot = ImageHeader::parse(bytes).ok_or(ErrorCode::INVAL)?.slot as usize;
+        self.slots
+            .parse_header(slot, bytes)
+            .ok_or(ErrorCode::SIZE)?;
+        let state = self.boot_state.get().unwrap_or_default();
+        if state.booted as usize == slot
+            && state.can_boot(slot)
+            && self.slots.header(slot).is_some()
+        {
+            return Err(ErrorCode::RESERVE);
+        }
+        let mut header = [0xFF; HEADER_COPY_LEN];
+        header.copy_from_slice(&bytes[..HEADER_COPY_LEN]);
+        self.header.set(header);
+        self.target.set(slot);
+        Ok(())
+    }
+
+    /// Write the last sector, check the image, and install it.
+    fn finish(&self) -> Result<(), ErrorCode> {
+        let offset = self.offset.get();
+        if offset % PAGE_SIZE != 0 {
+            self.sector.map_or(Err(ErrorCode::NOMEM), |sector| {
+                self.write_sector(sector, offset / PAGE_SIZE)
+            })?;
+        }
+        let target = self.target.get().ok_or(ErrorCode::INVAL)?;
+        let mut header_bytes = self.header.get();
+        let header = self
+            .slots
+            .parse_header(target, &header_bytes)
+            .ok_or(ErrorCode::INVAL)?;
+        if offset < slots::header_size() + header.length as usize {
+            return Err(ErrorCode::SIZE);
+        }
+        if !self.slots.verify(target, &header, &header_bytes) {
+            return Err(ErrorCode::FAIL);
+        }
+
+        ImageHeader::set_sequence(&mut header_bytes, self.slots.next_sequence(target));
+        self.flash
+            .program_blocking(slots::origin(target), &header_bytes)?;
+
+        let mut state = self.boot_state.get().unwrap_or_default();
+        state.attempts[target] = 0;
+        self.boot_state.set(state);
+        Ok(())
+    }
+
+    fn reset(&self) {
+        if self.state.get() == State::Resetting {
+            cortexm4f::support::reset();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: can::Can> AlarmClient for Updater<'a, A, C> {
+    fn alarm(&self) {
+        match self.state.get() {
+            State::Waiting => self.send_both(&[CRC_MODE]),
+            State::Receiving => {
+                let idle = self.idle.get() + 1;
+                self.idle.set(idle);
+                if idle >= TIMEOUT_S {
+                    self.cancel();
+                } else if self.received.get() != 0 {
+                    // Part of a packet was lost: ask for it again.
+                    self.received.set(0);
+                    self.reply(NAK);
+                }
+            }
+            // The last ACK was not sent in time.
+            State::Resetting => self.reset(),
+        }
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TICK_MS));
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: can::Can> uart::ReceiveClient for Updater<'a, A, C> {
+    fn received_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+        _rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        let byte = buffer[0];
+        if let Err((_, buffer)) = self.uart.receive_buffer(buffer, 1) {
+            self.uart_rx.replace(buffer);
+        }
+        if len == 1 {
+            self.receive(Link::Uart, byte);
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: can::Can> uart::TransmitClient for Updater<'a, A, C> {
+    fn transmitted_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        _len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+        self.uart_tx.replace(buffer);
+        if self.link.get() == Some(Link::Uart) {
+            self.reset();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: can::Can> can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }>
+    for Updater<'a, A, C>
+{
+    fn message_received(
+        &self,
+        id: can::Id,
+        buffer: &mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+        len: usize,
+        status: Result<(), can::Error>,
+    ) {
+        if status.is_ok() && matches!(id, can::Id::Standard(id) if id == CAN_REQUEST_ID) {
+            for byte in &buffer[..len.min(can::STANDARD_CAN_PACKET_SIZE)] {
+                self.receive(Link::Can, *byte);
+            }
+        }
+    }
+
+    fn stopped(&self, _buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE]) {}
+}
+
+impl<'a, A: Alarm<'a>, C: can::Can> can::TransmitClient<{ can::STANDARD_CAN_PACKET_SIZE }>
+    for Updater<'a, A, C>
+{
+    fn transmit_complete(
+        &self,
+        _status: Result<(), can::Error>,
+        buffer: &'static mut [u8; can::STANDARD_CAN_PACKET_SIZE],
+    ) {
+        self.can_tx.replace(buffer);
+        if self.link.get() == Some(Link::Can) {
+            self.reset();
+        }
+    }
+}
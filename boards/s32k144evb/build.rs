@@ -0,0 +1,15 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Links the kernel with `layout.ld`, for slot A of the bootloader, or for
+//! slot B when the `slot-b` feature is enabled.
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_SLOT_B");
+    if std::env::var_os("CARGO_FEATURE_SLOT_B").is_some() {
+        println!("cargo:rustc-link-arg=--defsym=LINK_FOR_SLOT_B=1");
+    }
+
+    tock_build_scripts::default_linker_script();
+}
@@ -0,0 +1,530 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Loading an app over the console with XMODEM.
+//!
+//! The `load` console command pauses the process console and receives one
+//! TBF with XMODEM-CRC, in 128- or 1024-byte blocks, for example with
+//! `sx -k app.tbf < /dev/ttyACM0 > /dev/ttyACM0`. The TBF is written to the
+//! app flash right after the last app, aligned to its size as the MPU needs,
+//! with a padding TBF filling the gap. Once the transfer ends the process
+//! loader scans from there, checks the app with the board's process checker
+//! and starts it, without a reboot.
+//!
+//! The XMODEM padding after the end of the TBF is not written, so the app
+//! list still ends after the new app.
+
+use core::cell::Cell;
+use core::fmt::Write;
+
+use capsules_core::process_console::{BoardCommand, ConsoleControl};
+use kernel::debug;
+use kernel::hil::flash;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::hil::uart;
+use kernel::process::{
+    ProcessBinaryError, ProcessLoadError, ProcessLoadingAsync, ProcessLoadingAsyncClient,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+use s32k144::nvmc::{Ftfc, FtfcPage, PAGE_SIZE};
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+/// Sent instead of NAK to ask for XMODEM-CRC.
+const CRC_MODE: u8 = b'C';
+
+/// Block number, its complement, up to 1024 data bytes and the CRC.
+pub const RX_BUFFER_LEN: usize = 2 + 1024 + 2;
+pub const TX_BUFFER_LEN: usize = 2;
+
+/// How many times `C` is sent, once a second, before giving up on the
+/// sender.
+const START_TRIES: u8 = 60;
+/// How long the sender may stay silent during the transfer.
+const TIMEOUT_MS: u32 = 10_000;
+
+/// Length of a TBF header without any TLV, which makes a padding TBF.
+const PADDING_HEADER_LEN: usize = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Asking the sender to start, `C` is sent this many more times.
+    Starting(u8),
+    /// Waiting for the first byte of a block, or for the end.
+    Header,
+    /// Receiving the rest of a block of this many data bytes.
+    Block(usize),
+    /// Writing the data of a block, which is acknowledged once written.
+    Writing,
+    /// Writing the last page after the transfer ended.
+    Finishing,
+    /// The process loader is loading the app.
+    Loading,
+}
+
+pub struct AppLoader<'a, A: Alarm<'a>> {
+    uart: &'a dyn uart::UartData<'a>,
+    alarm: &'a A,
+    flash: &'a Ftfc,
+    /// The app flash region.
+    apps: &'static [u8],
+    console: OptionalCell<&'a dyn ConsoleControl>,
+    loader: OptionalCell<&'a dyn ProcessLoadingAsync<'a>>,
+    state: Cell<State>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    page: TakeCell<'static, FtfcPage>,
+    /// Whether `page` holds the start of the page `address` is in.
+    page_open: Cell<bool>,
+    /// End of the app list when the transfer started. Padding, if needed,
+    /// then the app are written from here.
+    free: Cell<usize>,
+    /// The next flash address to write.
+    address: Cell<usize>,
+    /// Where the app goes, known from the first block.
+    app_start: Cell<usize>,
+    app_end: Cell<usize>,
+    next_block: Cell<u8>,
+    /// The data of the last block in `rx_buffer` not written yet.
+    data: Cell<(usize, usize)>,
+}
+
+impl<'a, A: Alarm<'a>> AppLoader<'a, A> {
+    pub fn new(
+        uart: &'a dyn uart::UartData<'a>,
+        alarm: &'a A,
+        flash: &'a Ftfc,
+        apps: &'static [u8],
+        rx_buffer: &'static mut [u8; RX_BUFFER_LEN],
+        tx_buffer: &'static mut [u8; TX_BUFFER_LEN],
+        page: &'static mut FtfcPage,
+    ) -> Self {
+        Self {
+            uart,
+            alarm,
+            flash,
+            apps,
+            console: OptionalCell::empty(),
+            loader: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            rx_buffer: TakeCell::new(rx_buffer),
+            tx_buffer: TakeCell::new(tx_buffer),
+            page: TakeCell::new(page),
+            page_open: Cell::new(false),
+            free: Cell::new(0),
+            address: Cell::new(0),
+            app_start: Cell::new(0),
+            app_end: Cell::new(0),
+            next_block: Cell::new(1),
+            data: Cell::new((0, 0)),
+        }
+    }
+
+    pub fn set_console(&self, console: &'a dyn ConsoleControl) {
+        self.console.set(console);
+    }
+
+    pub fn set_loader(&self, loader: &'a dyn ProcessLoadingAsync<'a>) {
+        self.loader.set(loader);
+    }
+
+    /// Address of the end of the app list, where the process loader stops
+    /// looking for apps.
+    fn end_of_apps(&self) -> usize {
+        let mut offset = 0;
+        while let Some(header) = self
+            .apps
+            .get(offset..offset + 8)
+            .and_then(|header| header.try_into().ok())
+        {
+            let length = match tock_tbf::parse::parse_tbf_header_lengths(header) {
+                Ok((_, _, length)) => length,
+                Err(tock_tbf::types::InitialTbfParseError::InvalidHeader(length)) => length,
+                Err(tock_tbf::types::InitialTbfParseError::UnableToParse) => break,
+            };
+            if length == 0 {
+                break;
+            }
+            offset += length as usize;
+        }
+        self.apps.as_ptr() as usize + offset
+    }
+
+    fn apps_end(&self) -> usize {
+        self.apps.as_ptr() as usize + self.apps.len()
+    }
+
+    fn start(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let free = self.end_of_apps();
+        if free >= self.apps_end() {
+            return Err(ErrorCode::NOMEM);
+        }
+        self.free.set(free);
+        self.address.set(free);
+        self.app_start.set(0);
+        self.app_end.set(0);
+        self.next_block.set(1);
+        self.page_open.set(false);
+        self.console.map(|console| console.pause());
+        self.state.set(State::Starting(START_TRIES));
+        self.receive(1);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(100));
+        Ok(())
+    }
+
+    fn receive(&self, len: usize) {
+        if let Some(buffer) = self.rx_buffer.take() {
+            if let Err((_, buffer)) = self.uart.receive_buffer(buffer, len) {
+                self.rx_buffer.replace(buffer);
+                self.abort("console receive failed");
+            }
+        }
+    }
+
+    fn send(&self, bytes: &[u8]) {
+        self.tx_buffer.take().map(|buffer| {
+            buffer[..bytes.len()].copy_from_slice(bytes);
+            if let Err((_, buffer)) = self.uart.transmit_buffer(buffer, bytes.len()) {
+                self.tx_buffer.replace(buffer);
+            }
+        });
+    }
+
+    fn restart_timeout(&self) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TIMEOUT_MS));
+    }
+
+    fn abort(&self, reason: &str) {
+        if self.state.get() == State::Idle {
+            return;
+        }
+        self.state.set(State::Idle);
+        let _ = self.alarm.disarm();
+        let _ = self.uart.receive_abort();
+        self.send(&[CAN, CAN]);
+        self.console.map(|console| console.resume());
+        debug!("load: {}", reason);
+    }
+
+    /// Handle the first byte of a block, or the end of the transfer.
+    fn header(&self, byte: u8) {
+        match byte {
+            SOH | STX => {
+                let len = if byte == SOH { 128 } else { 1024 };
+                self.state.set(State::Block(len));
+                self.restart_timeout();
+                self.receive(2 + len + 2);
+            }
+            EOT if self.app_end.get() != 0 && self.address.get() >= self.app_end.get() => {
+                self.state.set(State::Finishing);
+                let _ = self.alarm.disarm();
+                self.flush();
+            }
+            EOT => self.abort("transfer ended before the end of the TBF"),
+            CAN => self.abort("cancelled by the sender"),
+            // Noise before the transfer starts, for example the end of the
+            // `load` command line.
+            _ => self.receive(1),
+        }
+    }
+
+    /// Handle a block of `len` data bytes.
+    fn block(&self, buffer: &'static mut [u8], len: usize) {
+        let number = buffer[0];
+        let data = &buffer[2..2 + len];
+        let crc = u16::from_be_bytes([buffer[2 + len], buffer[3 + len]]);
+        let valid = number == !buffer[1] && crc == crc16(data);
+        let placed = !valid
+            || number != self.next_block.get()
+            || self.app_end.get() != 0
+            || self.place_app(data).is_ok();
+        self.rx_buffer.replace(buffer);
+
+        if !valid {
+            self.state.set(State::Header);
+            self.send(&[NAK]);
+            self.receive(1);
+        } else if number == self.next_block.get().wrapping_sub(1) {
+            // Our ACK of this block was lost.
+            self.state.set(State::Header);
+            self.send(&[ACK]);
+            self.receive(1);
+        } else if number != self.next_block.get() {
+            self.abort("block out of sequence");
+        } else if !placed {
+            self.abort("not a TBF that fits in the app flash");
+        } else {
+            self.next_block.set(number.wrapping_add(1));
+            self.data.set((2, 2 + len));
+            self.state.set(State::Writing);
+            self.write();
+        }
+    }
+
+    /// Decide where the app starts from the TBF header at the start of
+    /// `data`.
+    fn place_app(&self, data: &[u8]) -> Result<(), ()> {
+        let header = data.get(0..8).ok_or(())?.try_into().map_err(|_| ())?;
+        let (_, _, length) = tock_tbf::parse::parse_tbf_header_lengths(header).map_err(|_| ())?;
+        let length = length as usize;
+        let free = self.free.get();
+        let start = free.next_multiple_of(length.next_power_of_two());
+        if (1..PADDING_HEADER_LEN).contains(&(start - free)) || start + length > self.apps_end() {
+            return Err(());
+        }
+        self.app_start.set(start);
+        self.app_end.set(start + length);
+        Ok(())
+    }
+
+    /// The byte to write at `address`, or `None` if it is app data not
+    /// received yet.
+    fn next_byte(&self, address: usize) -> Option<u8> {
+        let free = self.free.get();
+        let app_start = self.app_start.get();
+        if address < app_start {
+            let header = padding_header((app_start - free) as u32);
+            Some(header.get(address - free).copied().unwrap_or(0xFF))
+        } else if address < self.app_end.get() {
+            let (from, to) = self.data.get();
+            if from == to {
+                return None;
+            }
+            self.data.set((from + 1, to));
+            self.rx_buffer.map(|buffer| buffer[from])
+        } else {
+            None
+        }
+    }
+
+    /// Copy what can be written into the page buffer, and write the page
+    /// once it is full. Once everything received is written, acknowledge
+    /// the block.
+    fn write(&self) {
+        let Some(page) = self.page.take() else {
+            return self.abort("page buffer busy");
+        };
+        loop {
+            let address = self.address.get();
+            let Some(byte) = self.next_byte(address) else {
+                break;
+            };
+            if !self.page_open.get() {
+                // Keep what the page holds before the first byte written,
+                // the end of the last app.
+                let page_start = address - address % PAGE_SIZE;
+                page.0.fill(0xFF);
+                for a in page_start.max(self.apps.as_ptr() as usize)..address {
+                    page[a % PAGE_SIZE] = self.apps[a - self.apps.as_ptr() as usize];
+                }
+                self.page_open.set(true);
+            }
+            page[address % PAGE_SIZE] = byte;
+            self.address.set(address + 1);
+            if (address + 1) % PAGE_SIZE == 0 {
+                self.page_open.set(false);
+                if let Err((_, page)) = self.flash.write_page(address / PAGE_SIZE, page) {
+                    self.page.replace(page);
+                    self.abort("flash busy");
+                }
+                return;
+            }
+        }
+        self.page.replace(page);
+
+        // The rest of the block, if any, is XMODEM padding.
+        self.state.set(State::Header);
+        self.restart_timeout();
+        self.send(&[ACK]);
+        self.receive(1);
+    }
+
+    /// Write the last, partial, page.
+    fn flush(&self) {
+        if !self.page_open.get() {
+            return self.finish();
+        }
+        self.page_open.set(false);
+        self.page.take().map(|page| {
+            let page_number = (self.address.get() - 1) / PAGE_SIZE;
+            if let Err((_, page)) = self.flash.write_page(page_number, page) {
+                self.page.replace(page);
+                self.abort("flash busy");
+            }
+        });
+    }
+
+    fn finish(&self) {
+        self.send(&[ACK]);
+        self.state.set(State::Loading);
+        match self.loader.get() {
+            Some(loader) => loader.start(),
+            None => self.abort("no process loader"),
+        }
+    }
+}
+
+/// A TBF header with no TLV, which makes `length` bytes of padding.
+fn padding_header(length: u32) -> [u8; PADDING_HEADER_LEN] {
+    let version_and_size = 2 | (PADDING_HEADER_LEN as u32) << 16;
+    let flags = 0;
+    let checksum = version_and_size ^ length ^ flags;
+    let mut header = [0; PADDING_HEADER_LEN];
+    for (word, value) in header
+        .chunks_exact_mut(4)
+        .zip([version_and_size, length, flags, checksum])
+    {
+        word.copy_from_slice(&value.to_le_bytes());
+    }
+    header
+}
+
+/// CRC-16/XMODEM.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+impl<'a, A: Alarm<'a>> uart::ReceiveClient for AppLoader<'a, A> {
+    fn received_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+        rval: Result<(), ErrorCode>,
+        error: uart::Error,
+    ) {
+        let state = self.state.get();
+        if rval.is_err() || error != uart::Error::None {
+            self.rx_buffer.replace(buffer);
+            match state {
+                State::Starting(_) | State::Header => self.receive(1),
+                State::Block(_) => {
+                    self.state.set(State::Header);
+                    self.send(&[NAK]);
+                    self.receive(1);
+                }
+                _ => {}
+            }
+            return;
+        }
+        match state {
+            State::Starting(_) | State::Header if len == 1 => {
+                let byte = buffer[0];
+                self.rx_buffer.replace(buffer);
+                self.header(byte);
+            }
+            State::Block(data_len) if len == 2 + data_len + 2 => self.block(buffer, data_len),
+            _ => {
+                self.rx_buffer.replace(buffer);
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> uart::TransmitClient for AppLoader<'a, A> {
+    fn transmitted_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        _len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(buffer);
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for AppLoader<'a, A> {
+    fn alarm(&self) {
+        match self.state.get() {
+            State::Starting(0) => self.abort("no sender"),
+            State::Starting(tries) => {
+                self.state.set(State::Starting(tries - 1));
+                self.send(&[CRC_MODE]);
+                self.alarm
+                    .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(1000));
+            }
+            State::Header | State::Block(_) => self.abort("sender timed out"),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> flash::Client<Ftfc> for AppLoader<'a, A> {
+    fn read_complete(&self, _buffer: &'static mut FtfcPage, _result: Result<(), flash::Error>) {}
+
+    fn write_complete(&self, buffer: &'static mut FtfcPage, result: Result<(), flash::Error>) {
+        self.page.replace(buffer);
+        if result.is_err() {
+            return self.abort("flash write failed");
+        }
+        match self.state.get() {
+            State::Writing => self.write(),
+            State::Finishing => self.finish(),
+            _ => {}
+        }
+    }
+
+    fn erase_complete(&self, _result: Result<(), flash::Error>) {}
+}
+
+impl<'a, A: Alarm<'a>> ProcessLoadingAsyncClient for AppLoader<'a, A> {
+    fn process_loaded(&self, result: Result<(), ProcessLoadError>) {
+        if self.state.get() != State::Loading {
+            return;
+        }
+        match result {
+            Ok(()) => debug!("load: app started"),
+            Err(ProcessLoadError::BinaryError(ProcessBinaryError::Padding)) => {}
+            Err(e) => debug!("load: app not started: {:?}", e),
+        }
+    }
+
+    fn process_loading_finished(&self) {
+        if self.state.get() == State::Loading {
+            self.state.set(State::Idle);
+            self.console.map(|console| console.resume());
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> BoardCommand for AppLoader<'a, A> {
+    fn name(&self) -> &'static str {
+        "load"
+    }
+
+    fn execute(&self, _args: &str, out: &mut dyn Write) {
+        match self.start() {
+            Ok(()) => {
+                let _ = write!(
+                    out,
+                    "Send the TBF with XMODEM, it goes at {:#x}\r\n",
+                    self.free.get()
+                );
+            }
+            Err(ErrorCode::NOMEM) => {
+                let _ = write!(out, "load: app flash full\r\n");
+            }
+            Err(e) => {
+                let _ = write!(out, "load: {:?}\r\n", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! The kernel's side of the bootloader: confirming a boot, and the `update`
+//! console command.
+//!
+//! `update` asks the bootloader, through the boot state in retained RAM, to
+//! wait for a new kernel image after the next reset, then resets once the
+//! reply has had time to go out.
+
+use core::fmt::Write;
+
+use capsules_core::process_console::BoardCommand;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::retained_ram::RetainedSlot;
+use s32k144evb_bootloader::boot_state::{BootState, UPDATE_REQUEST};
+use s32k144evb_bootloader::SLOT_COUNT;
+
+/// Time left for the reply to `update` to be written before the reset.
+const RESET_DELAY_MS: u32 = 100;
+
+/// Tell the bootloader that the kernel it started came up, so the slot's
+/// boot attempts start over. Returns the slot the kernel runs from.
+pub fn confirm(boot_state: &RetainedSlot<'static, BootState>) -> Option<usize> {
+    let mut state = boot_state.get()?;
+    state.confirm();
+    boot_state.set(state);
+    Some(state.booted as usize).filter(|slot| *slot < SLOT_COUNT)
+}
+
+pub struct UpdateCommand<'a, A: Alarm<'a>> {
+    boot_state: &'a RetainedSlot<'static, BootState>,
+    alarm: &'a A,
+}
+
+impl<'a, A: Alarm<'a>> UpdateCommand<'a, A> {
+    pub fn new(boot_state: &'a RetainedSlot<'static, BootState>, alarm: &'a A) -> Self {
+        Self { boot_state, alarm }
+    }
+}
+
+impl<'a, A: Alarm<'a>> BoardCommand for UpdateCommand<'a, A> {
+    fn name(&self) -> &'static str {
+        "update"
+    }
+
+    fn execute(&self, _args: &str, out: &mut dyn Write) {
+        let mut state = self.boot_state.get().unwrap_or_default();
+        state.update = UPDATE_REQUEST;
+        self.boot_state.set(state);
+        let _ = write!(out, "Resetting into the bootloader\r\n");
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(RESET_DELAY_MS));
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for UpdateCommand<'a, A> {
+    fn alarm(&self) {
+        cortexm4f::support::reset();
+    }
+}
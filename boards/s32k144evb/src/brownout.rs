@@ -0,0 +1,77 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Response to the supply voltage dropping.
+//!
+//! On a low-voltage warning or detect, the page write or erase in progress
+//! is stopped so no flash command is started on a failing supply, the debug
+//! buffer is written out by polling the console UART so it is not lost, and
+//! applications are told. The debug writer gives up its buffer for the
+//! flush, so later `debug!` output is dropped.
+//!
+//! With the console on RTT the buffer is not flushed: a polled RTT write
+//! waits for the debug probe to read, forever if none is attached.
+
+use core::fmt;
+use kernel::debug::IoWrite;
+use s32k144::power::{PowerClient, PowerEvent};
+
+use capsules_extra::low_voltage::{Event, LowVoltage};
+
+struct Writer<'a>(&'a s32k144::lpuart0::Lpuart0<'a>);
+
+impl fmt::Write for Writer<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl IoWrite for Writer<'_> {
+    fn write(&mut self, buf: &[u8]) -> usize {
+        for &byte in buf {
+            self.0.send_byte(byte);
+        }
+        buf.len()
+    }
+}
+
+pub struct BrownOut {
+    flash: &'static s32k144::nvmc::Ftfc,
+    console: Option<&'static s32k144::lpuart0::Lpuart0<'static>>,
+    apps: &'static LowVoltage,
+    flushed: core::cell::Cell<bool>,
+}
+
+impl BrownOut {
+    pub fn new(
+        flash: &'static s32k144::nvmc::Ftfc,
+        console: Option<&'static s32k144::lpuart0::Lpuart0<'static>>,
+        apps: &'static LowVoltage,
+    ) -> Self {
+        Self {
+            flash,
+            console,
+            apps,
+            flushed: core::cell::Cell::new(false),
+        }
+    }
+}
+
+impl PowerClient for BrownOut {
+    fn handle_power_event(&self, event: PowerEvent) {
+        let event = match event {
+            PowerEvent::LowVoltageWarning => Event::Warning,
+            PowerEvent::LowVoltageDetected => Event::Detected,
+            PowerEvent::EnteredLowPowerMode | PowerEvent::ExitedLowPowerMode => return,
+        };
+        let _ = self.flash.abort();
+        if let Some(console) = self.console {
+            if !self.flushed.replace(true) {
+                unsafe { kernel::debug::flush(&mut Writer(console)) };
+            }
+        }
+        self.apps.notify(event);
+    }
+}
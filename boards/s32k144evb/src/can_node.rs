@@ -0,0 +1,168 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! The EVB as a CAN node: bus-off recovery and the `can` console command.
+//!
+//! Under the back-off policy the controller waits before it starts
+//! recovering from bus-off. The wait starts at `MIN_RECOVERY_DELAY_MS` and
+//! doubles, up to `MAX_RECOVERY_DELAY_MS`, each time the node goes bus-off
+//! again within `STABLE_MS` of rejoining the bus, so a node with a wiring
+//! or bitrate problem stops disturbing the bus.
+
+use core::cell::Cell;
+use core::fmt::Write;
+
+use capsules_core::process_console::BoardCommand;
+use kernel::debug;
+use kernel::hil::can::{Configure, Controller, OperationMode};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
+use kernel::utilities::cells::OptionalCell;
+use s32k144::can0::{BusOffClient, BusOffRecovery, FlexCan};
+
+const MIN_RECOVERY_DELAY_MS: u32 = 100;
+const MAX_RECOVERY_DELAY_MS: u32 = 5_000;
+const STABLE_MS: u32 = 10_000;
+
+pub struct BusOffPolicy<'a, A: Alarm<'a>> {
+    can: &'a FlexCan<'a>,
+    alarm: &'a A,
+    delay_ms: Cell<u32>,
+    recovered_at: OptionalCell<A::Ticks>,
+}
+
+impl<'a, A: Alarm<'a>> BusOffPolicy<'a, A> {
+    pub fn new(can: &'a FlexCan<'a>, alarm: &'a A) -> Self {
+        Self {
+            can,
+            alarm,
+            delay_ms: Cell::new(MIN_RECOVERY_DELAY_MS),
+            recovered_at: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> BusOffClient for BusOffPolicy<'a, A> {
+    fn bus_off(&self) {
+        if self.can.bus_off_recovery() != BusOffRecovery::Manual {
+            debug!("CAN bus-off");
+            return;
+        }
+        let now = self.alarm.now();
+        let unstable = self.recovered_at.take().is_some_and(|recovered_at| {
+            now.wrapping_sub(recovered_at) < self.alarm.ticks_from_ms(STABLE_MS)
+        });
+        let delay = if unstable {
+            core::cmp::min(self.delay_ms.get() * 2, MAX_RECOVERY_DELAY_MS)
+        } else {
+            MIN_RECOVERY_DELAY_MS
+        };
+        self.delay_ms.set(delay);
+        debug!("CAN bus-off, recovering in {} ms", delay);
+        self.alarm.set_alarm(now, self.alarm.ticks_from_ms(delay));
+    }
+
+    fn recovered(&self) {
+        self.recovered_at.set(self.alarm.now());
+        debug!("CAN back on the bus");
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for BusOffPolicy<'a, A> {
+    fn alarm(&self) {
+        let _ = self.can.recover();
+    }
+}
+
+/// `can [on | off | bitrate <bit/s> | mode normal|loopback|listen |
+/// recovery auto|backoff]`. Settings apply right away if the controller is
+/// on. Every form prints the controller status.
+pub struct CanCommand<'a> {
+    can: &'a FlexCan<'a>,
+}
+
+impl<'a> CanCommand<'a> {
+    pub fn new(can: &'a FlexCan<'a>) -> Self {
+        Self { can }
+    }
+
+    fn status(&self, out: &mut dyn Write) {
+        if let Ok(state) = self.can.get_state() {
+            let _ = write!(out, "state: {:?}", state);
+        }
+        match self.can.bitrate() {
+            Some(bitrate) => {
+                let _ = write!(out, ", {} bit/s", bitrate);
+            }
+            None => {
+                let _ = write!(out, ", no bitrate");
+            }
+        }
+        if let Ok(mode) = self.can.get_operation_mode() {
+            let _ = write!(out, ", {:?}", mode);
+        }
+        let recovery = match self.can.bus_off_recovery() {
+            BusOffRecovery::Automatic => "auto",
+            BusOffRecovery::Manual => "backoff",
+        };
+        let _ = write!(out, ", recovery {}\r\n", recovery);
+        if let Some((tec, rec)) = self.can.error_counters() {
+            let (count, last) = self.can.bus_errors();
+            let _ = write!(out, "TEC {} REC {}, {} bus errors", tec, rec, count);
+            if let Some(last) = last {
+                let _ = write!(out, ", last {:?}", last);
+            }
+            let _ = write!(out, "\r\n");
+        }
+    }
+}
+
+impl BoardCommand for CanCommand<'_> {
+    fn name(&self) -> &'static str {
+        "can"
+    }
+
+    fn execute(&self, args: &str, out: &mut dyn Write) {
+        let mut words = args.split_whitespace();
+        let result = match (words.next(), words.next()) {
+            (None, _) => Ok(()),
+            (Some("on"), None) => self.can.enable(),
+            (Some("off"), None) => self.can.disable(),
+            (Some("bitrate"), Some(bitrate)) => match bitrate.parse() {
+                Ok(bitrate) => self.can.set_bitrate(bitrate),
+                Err(_) => Err(kernel::ErrorCode::INVAL),
+            }
+            .and_then(|()| self.can.apply_configuration()),
+            (Some("mode"), Some(mode)) => match mode {
+                "normal" => self.can.set_operation_mode(OperationMode::Normal),
+                "loopback" => self.can.set_operation_mode(OperationMode::Loopback),
+                "listen" => self.can.set_operation_mode(OperationMode::Monitoring),
+                _ => Err(kernel::ErrorCode::INVAL),
+            }
+            .and_then(|()| self.can.apply_configuration()),
+            (Some("recovery"), Some(recovery)) => match recovery {
+                "auto" => Ok(BusOffRecovery::Automatic),
+                "backoff" => Ok(BusOffRecovery::Manual),
+                _ => Err(kernel::ErrorCode::INVAL),
+            }
+            .and_then(|recovery| {
+                self.can.set_bus_off_recovery(recovery);
+                self.can.apply_configuration()
+            }),
+            _ => Err(kernel::ErrorCode::INVAL),
+        };
+        match result {
+            Ok(()) => self.status(out),
+            Err(kernel::ErrorCode::INVAL) => {
+                let _ = write!(
+                    out,
+                    "usage: can [on | off | bitrate <bit/s> | mode normal|loopback|listen \
+                     | recovery auto|backoff]\r\n"
+                );
+            }
+            Err(error) => {
+                let _ = write!(out, "can: {:?}\r\n", error);
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Board configuration, selected with Cargo features.
+//!
+//! As in the kernel's `config` module, the features only set the fields of
+//! the `CONFIG` constant, which `main.rs` tests with plain `if`s, so every
+//! variant of the board is type-checked whatever features are enabled and
+//! the disabled code is folded away. The scheduler is the exception: it is
+//! a type of the board, so it is chosen with `#[cfg]`, here and nowhere
+//! else.
+//!
+//! | Feature        | Effect                                                  |
+//! |----------------|---------------------------------------------------------|
+//! | `no-console`   | No console driver for apps and no process console, and  |
+//! |                | so no board commands; `debug!()` output remains         |
+//! | `rtt-console`  | Console and `debug!()` over SEGGER RTT, not LPUART0     |
+//! | `can-node`     | CAN driver, bus-off policy and `can` command (default)  |
+//! | `low-power`    | No LIN, comparator, DAC, PWM or kernel debug LED        |
+//! | `mlfq`         | MLFQ scheduler instead of round robin                   |
+//! | `procs-2`      | Room for 2 processes instead of 4                       |
+//! | `procs-8`      | Room for 8 processes instead of 4                       |
+//! | `fault-stop`   | Stop a faulted process instead of restarting it         |
+//! | `syscall-deny` | Syscall filter denies apps without `Permissions`        |
+//! | `slot-b`       | Kernel linked for bootloader slot B (see `build.rs`)    |
+
+use kernel::component::Component;
+use kernel::process::Process;
+
+use crate::syscall_filter::Policy;
+
+/// Channel of the console, the process console and `debug!()`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ConsoleTransport {
+    Lpuart0,
+    /// A SEGGER RTT channel read through the debug probe; LPUART0 is then
+    /// left unused.
+    Rtt,
+}
+
+pub struct Config {
+    /// Where the console goes.
+    pub console: ConsoleTransport,
+    /// Whether to give apps the console driver and to run the process
+    /// console, which also carries the `load`, `update` and `can` commands.
+    /// The channel is set up either way, since the kernel writes `debug!()`
+    /// output to it.
+    pub app_console: bool,
+    /// Whether to set the EVB up as a CAN node, with the CAN driver for
+    /// apps.
+    pub can_node: bool,
+    /// Whether to leave out the drivers whose peripherals are not needed by
+    /// a low-power node: LIN on LPUART2, the comparator and its DAC, and the
+    /// FTM0 PWM on the RGB LED. The kernel LED is not used for
+    /// `debug_gpio!()` either.
+    pub low_power: bool,
+    /// Number of concurrent processes: 4, or 2 or 8 with `procs-2` or
+    /// `procs-8`.
+    pub num_procs: usize,
+    /// A faulted process is restarted after `fault_restart_delay_ms`, a
+    /// delay that doubles with each restart. After `fault_max_restarts`
+    /// restarts, its next fault stops it; `fault-stop` sets it to 0, so the
+    /// first fault does. The last fault is recorded and printed after a
+    /// reset.
+    pub fault_restart_delay_ms: u32,
+    pub fault_max_restarts: usize,
+    /// What apps without a TBF `Permissions` header may do. Apps with one
+    /// may only use the drivers and commands it lists. Use `DefaultDeny`,
+    /// with `syscall-deny`, when untrusted apps run next to trusted ones,
+    /// with a `Permissions` header in every app.
+    pub syscall_filter: Policy,
+}
+
+pub const CONFIG: Config = Config {
+    console: if cfg!(feature = "rtt-console") {
+        ConsoleTransport::Rtt
+    } else {
+        ConsoleTransport::Lpuart0
+    },
+    app_console: !cfg!(feature = "no-console"),
+    can_node: cfg!(feature = "can-node"),
+    low_power: cfg!(feature = "low-power"),
+    num_procs: if cfg!(feature = "procs-8") {
+        8
+    } else if cfg!(feature = "procs-2") {
+        2
+    } else {
+        4
+    },
+    fault_restart_delay_ms: 100,
+    fault_max_restarts: if cfg!(feature = "fault-stop") { 0 } else { 5 },
+    syscall_filter: if cfg!(feature = "syscall-deny") {
+        Policy::DefaultDeny
+    } else {
+        Policy::DefaultAllow
+    },
+};
+
+#[cfg(all(feature = "procs-2", feature = "procs-8"))]
+compile_error!("`procs-2` and `procs-8` cannot both be enabled");
+
+#[cfg(not(feature = "mlfq"))]
+pub type Scheduler = kernel::scheduler::round_robin::RoundRobinSched<'static>;
+#[cfg(feature = "mlfq")]
+pub type Scheduler = kernel::scheduler::mlfq::MLFQSched<'static, crate::AlarmMux>;
+
+/// Create the scheduler. MLFQ takes an alarm from `mux_alarm`.
+pub unsafe fn scheduler(
+    processes: &'static [Option<&'static dyn Process>],
+    mux_alarm: &'static capsules_core::virtualizers::virtual_alarm::MuxAlarm<
+        'static,
+        s32k144::rtc::Rtc<'static>,
+    >,
+) -> &'static Scheduler {
+    #[cfg(not(feature = "mlfq"))]
+    {
+        let _ = mux_alarm;
+        components::sched::round_robin::RoundRobinComponent::new(processes)
+            .finalize(components::round_robin_component_static!(crate::NUM_PROCS))
+    }
+    #[cfg(feature = "mlfq")]
+    {
+        components::sched::mlfq::MLFQComponent::new(mux_alarm, processes).finalize(
+            components::mlfq_component_static!(s32k144::rtc::Rtc<'static>, crate::NUM_PROCS),
+        )
+    }
+}
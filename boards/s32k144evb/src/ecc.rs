@@ -0,0 +1,59 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Response to uncorrectable ECC errors.
+//!
+//! An uncorrectable error in a process's RAM, its grants included, faults
+//! that process, which the fault policy then stops or restarts. One in the
+//! kernel's RAM leaves kernel state corrupt, so the kernel panics.
+//! Corrections only need counting, which the ERM does.
+
+use kernel::capabilities;
+use kernel::debug;
+use s32k144::erm::{EccClient, EccError, Memory};
+
+struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct EccMonitor {
+    kernel: &'static kernel::Kernel,
+}
+
+impl EccMonitor {
+    pub fn new(kernel: &'static kernel::Kernel) -> Self {
+        Self { kernel }
+    }
+}
+
+impl EccClient for EccMonitor {
+    fn ecc_error(&self, error: EccError) {
+        if error.correctable {
+            return;
+        }
+        match (error.memory, error.address) {
+            (Memory::Sram(_), Some(address)) => {
+                let address = address as usize;
+                let mut owned = false;
+                self.kernel.process_each_capability(&Capability, |process| {
+                    let addresses = process.get_addresses();
+                    if (addresses.sram_start..addresses.sram_end).contains(&address) {
+                        debug!(
+                            "Uncorrectable ECC error at {:#010x} in {}",
+                            address,
+                            process.get_process_name()
+                        );
+                        process.set_fault_state();
+                        owned = true;
+                    }
+                });
+                if !owned {
+                    panic!("Uncorrectable ECC error in kernel RAM at {:#010x}", address);
+                }
+            }
+            // The FTFC does not say where; a read by the core has already
+            // faulted on its own.
+            _ => debug!("Uncorrectable ECC error in flash"),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! A record of the last process fault, kept in a retained RAM slot so it
+//! can be printed after a reset.
+
+use core::fmt;
+
+use capsules_system::process_policies::{BackoffRestartFaultPolicy, ProcessFaultRecorder};
+use cortexm4f::syscall::CortexMStoredState;
+use kernel::capabilities;
+use kernel::process::Process;
+use kernel::utilities::retained_ram::{Retained, RetainedSlot};
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub type FaultPolicy =
+    BackoffRestartFaultPolicy<'static, crate::AlarmMux, Capability, { crate::NUM_PROCS }>;
+
+const NAME_LEN: usize = 16;
+
+/// Written in place of PC and LR when the process stack pointer does not
+/// point at an exception frame in the process's memory.
+const UNKNOWN: u32 = 0xBAD0_0BAD;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct FaultRecord {
+    name: [u8; NAME_LEN],
+    pc: u32,
+    lr: u32,
+    cfsr: u32,
+    hfsr: u32,
+    mmfar: u32,
+    bfar: u32,
+    restarts: u32,
+    stopped: u32,
+}
+
+// Made only of integers, with no padding.
+unsafe impl Retained for FaultRecord {}
+
+impl FaultRecord {
+    fn name(&self) -> &str {
+        let len = self.name.iter().position(|b| *b == 0).unwrap_or(NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+}
+
+/// Records every process fault the fault policy handles in `slot`.
+pub struct FaultRecorder {
+    slot: RetainedSlot<'static, FaultRecord>,
+}
+
+impl FaultRecorder {
+    pub fn new(slot: RetainedSlot<'static, FaultRecord>) -> Self {
+        Self { slot }
+    }
+
+    /// Return the fault recorded before the last reset, if any, and clear
+    /// it.
+    pub fn take(&self) -> Option<FaultRecord> {
+        self.slot.take()
+    }
+}
+
+impl ProcessFaultRecorder for FaultRecorder {
+    fn record_fault(&self, process: &dyn Process, restarting: bool) {
+        let status = cortexm4f::process_fault_status();
+        let (pc, lr) = exception_frame(process).unwrap_or((UNKNOWN, UNKNOWN));
+        let mut name = [0; NAME_LEN];
+        let process_name = process.get_process_name().as_bytes();
+        let len = process_name.len().min(NAME_LEN);
+        name[..len].copy_from_slice(&process_name[..len]);
+        self.slot.set(FaultRecord {
+            name,
+            pc,
+            lr,
+            cfsr: status.cfsr,
+            hfsr: status.hfsr,
+            mmfar: status.mmfar,
+            bfar: status.bfar,
+            restarts: process.get_restart_count() as u32,
+            stopped: u32::from(!restarting),
+        });
+    }
+}
+
+/// The PC and LR in the exception frame the process faulted with.
+fn exception_frame(process: &dyn Process) -> Option<(u32, u32)> {
+    let mut stored = [0; core::mem::size_of::<CortexMStoredState>() + 3 * 4];
+    let len = process.get_stored_state(&mut stored).ok()?;
+    let state = CortexMStoredState::try_from(&stored[..len]).ok()?;
+    let addresses = process.get_addresses();
+    let frame = state.psp();
+    if frame < addresses.sram_start || frame.saturating_add(32) > addresses.sram_app_brk {
+        return None;
+    }
+    let frame = frame as *const u32;
+    // Safety: the frame is within the process's accessible memory.
+    unsafe {
+        Some((
+            core::ptr::read_volatile(frame.add(6)),
+            core::ptr::read_volatile(frame.add(5)),
+        ))
+    }
+}
+
+const CFSR_BITS: [(u32, &str); 17] = [
+    (1 << 0, "IACCVIOL"),
+    (1 << 1, "DACCVIOL"),
+    (1 << 3, "MUNSTKERR"),
+    (1 << 4, "MSTKERR"),
+    (1 << 5, "MLSPERR"),
+    (1 << 8, "IBUSERR"),
+    (1 << 9, "PRECISERR"),
+    (1 << 10, "IMPRECISERR"),
+    (1 << 11, "UNSTKERR"),
+    (1 << 12, "STKERR"),
+    (1 << 13, "LSPERR"),
+    (1 << 16, "UNDEFINSTR"),
+    (1 << 17, "INVSTATE"),
+    (1 << 18, "INVPC"),
+    (1 << 19, "NOCP"),
+    (1 << 24, "UNALIGNED"),
+    (1 << 25, "DIVBYZERO"),
+];
+const CFSR_MMARVALID: u32 = 1 << 7;
+const CFSR_BFARVALID: u32 = 1 << 15;
+const HFSR_BITS: [(u32, &str); 2] = [(1 << 1, "VECTTBL"), (1 << 30, "FORCED")];
+
+impl fmt::Display for FaultRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "process {} after {} restarts{}\r\n  PC {:#010x}  LR {:#010x}\r\n  CFSR {:#010x}",
+            self.name(),
+            self.restarts,
+            if self.stopped != 0 { ", stopped" } else { "" },
+            self.pc,
+            self.lr,
+            self.cfsr,
+        )?;
+        for (bit, name) in CFSR_BITS {
+            if self.cfsr & bit != 0 {
+                write!(f, " {}", name)?;
+            }
+        }
+        write!(f, "\r\n  HFSR {:#010x}", self.hfsr)?;
+        for (bit, name) in HFSR_BITS {
+            if self.hfsr & bit != 0 {
+                write!(f, " {}", name)?;
+            }
+        }
+        if self.cfsr & CFSR_MMARVALID != 0 {
+            write!(f, "\r\n  MMFAR {:#010x}", self.mmfar)?;
+        }
+        if self.cfsr & CFSR_BFARVALID != 0 {
+            write!(f, "\r\n  BFAR {:#010x}", self.bfar)?;
+        }
+        Ok(())
+    }
+}
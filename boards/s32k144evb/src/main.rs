@@ -11,22 +11,60 @@
 // https://github.com/rust-lang/rust/issues/62184.
 #![cfg_attr(not(doc), no_main)]
 
+use capsules_aes_gcm::aes_gcm;
+use capsules_core::process_console::BoardCommand;
+use capsules_core::virtualizers::virtual_aes_ccm;
 use core::ptr::{addr_of, addr_of_mut};
 
 use kernel::capabilities;
 use kernel::component::Component;
-use kernel::hil::time::Counter;
+use kernel::hil::gpio::{Configure, Interrupt};
+use kernel::hil::led::LedLow;
+use kernel::hil::time::{Alarm, Counter};
+use kernel::hil::uart::{Receive, Transmit};
 use kernel::platform::{KernelResources, SyscallDriverLookup};
-use kernel::scheduler::round_robin::RoundRobinSched;
+use kernel::process::ProcessLoadingAsync;
+use kernel::syscall::SyscallDriver;
+use kernel::utilities::registers::interfaces::ReadWriteable;
+use kernel::utilities::retained_ram::{RetainedRam, RetainedSlot};
+
+use config::{ConsoleTransport, CONFIG};
 
 #[allow(unused_imports)]
 use kernel::{create_capability, debug, debug_gpio, debug_verbose, static_init};
 
 use s32k144::chip::S32K144DefaultPeripherals;
+use s32k144::csec::BootFlavor;
 use s32k144::gpio::Pin;
+use s32k144::nvmc::{
+    CommandOwner, CsecKeySize, EeeSize, FlexRamFunction, FtfcPage, PartitionConfig,
+};
+use s32k144evb_bootloader::boot_state::BootState;
+
+// RGB LED, lit when the pin is driven low. Each pin also carries an FTM0
+// channel.
+const LED_RED_PIN: Pin = Pin::PTD15;
+const LED_GREEN_PIN: Pin = Pin::PTD16;
+const LED_BLUE_PIN: Pin = Pin::PTD0;
+
+// Kernel LED
+const LED_KERNEL_PIN: Pin = LED_BLUE_PIN;
+
+// SW2 and SW3 read high while pressed, with external pull-downs.
+const BUTTON_SW2_PIN: Pin = Pin::PTC12;
+const BUTTON_SW3_PIN: Pin = Pin::PTC13;
+
+/// How long a button must stay still before its new level is reported.
+const BUTTON_DEBOUNCE_MS: u32 = 20;
+
+/// Standby input of the TJA1044 CAN transceiver.
+const CAN_STANDBY_PIN: Pin = Pin::PTE11;
+
+/// Bitrate of the CAN test bench.
+const CAN_BITRATE: u32 = 500_000;
 
-// Kernel LED (same as microphone LED)
-const LED_KERNEL_PIN: Pin = Pin::PTD0; //PTD15 : blue pin
+/// Potentiometer R13, on PTC14.
+const POTENTIOMETER_CHANNEL: s32k144::adc::Channel = s32k144::adc::Channel::AD12;
 
 //UART Pins
 //const UART_TX_PIN: Pin = Pin::PTC6;
@@ -35,13 +73,57 @@ const LED_KERNEL_PIN: Pin = Pin::PTD0; //PTD15 : blue pin
 /// UART Writer for panic!()s.
 pub mod panic;
 
-// State for loading and holding applications.
-// How should the kernel respond when a process faults.
-const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
-    capsules_system::process_policies::PanicFaultPolicy {};
+mod app_loader;
+mod boot;
+mod brownout;
+mod can_node;
+mod config;
+mod ecc;
+mod fault_record;
+mod syscall_filter;
+
+// Tests for drivers.
+#[allow(dead_code)]
+mod test;
+
+/// FlexNVM partition programmed on first boot, if the part has none yet.
+/// `None` leaves the FlexNVM as D-Flash. The emulated EEPROM and the CSEc,
+/// which keeps its keys in FlexRAM, only work on a partitioned part; the
+/// board was developed with 2 KiB of emulated EEPROM, backed by the whole
+/// 64 KiB FlexNVM block, and storage for 20 CSEc keys:
+///
+/// ```rust,ignore
+/// const FLEXNVM_PARTITION: Option<PartitionConfig> = Some(PartitionConfig {
+///     eee_size: EeeSize::Size2K,
+///     flexnvm: FlexNvmPartition::DFlash0K,
+///     csec_keys: CsecKeySize::Keys20,
+///     load_flexram_at_reset: true,
+/// });
+/// ```
+///
+/// WARNING: programming the partition is permanent. It cannot be changed
+/// or undone short of a mass erase of the part, which also erases the CSEc
+/// keys. Keep this set on a part that is already partitioned, so the EEPROM
+/// driver knows which part of FlexRAM holds the CSEc keys.
+const FLEXNVM_PARTITION: Option<PartitionConfig> = None;
+
+/// CSEc secure boot flavor defined on first boot. The CSEc then checks the
+/// bootloader against BOOT_MAC on every reset, and apps are only started
+/// when that check passes. The bootloader in turn checks the kernel image.
+///
+/// BOOT_MAC_KEY must be provisioned with `Csec::load_key` first; BOOT_MAC is
+/// learned on the first reset after that. The boot flavor can only be defined
+/// once, and reflashing the bootloader changes its MAC, so this is left
+/// `None` for development.
+const SECURE_BOOT: Option<BootFlavor> = None;
+
+/// Polls of the CSEc status while it finishes checking the bootloader in
+/// parallel boot, roughly a second at the core clock. A CSEc still busy
+/// after that is reported and the bootloader taken as not authenticated.
+const SECURE_BOOT_POLLS: u32 = 10_000_000;
 
 // Number of concurrent processes this platform supports.
-const NUM_PROCS: usize = 4;
+const NUM_PROCS: usize = CONFIG.num_procs;
 
 static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
     [None; NUM_PROCS];
@@ -57,18 +139,34 @@ pub static mut STACK_MEMORY: [u8; 0x2000] = [0; 0x2000];
 // debug mode requires more stack space
 // pub static mut STACK_MEMORY: [u8; 0x2000] = [0; 0x2000];
 
-#[no_mangle]
-#[inline(never)]
-pub extern "C" fn _start() -> ! {
-    unsafe {
-        main();
+/// AES stack exposed to userspace: the CSEc behind the AES multiplexer, with
+/// GCM layered on top in software.
+type AesDevice = aes_gcm::Aes128Gcm<
+    'static,
+    virtual_aes_ccm::VirtualAES128CCM<'static, s32k144::csec::Csec<'static>>,
+>;
+
+/// Size of the bootloader region at the start of P-Flash, from
+/// `flash_map.ld`. This is the region checked by secure boot.
+fn bootloader_size() -> usize {
+    extern "C" {
+        static BOOTLOADER_SIZE: u8;
     }
-    loop {}
+    core::ptr::addr_of!(BOOTLOADER_SIZE) as usize
 }
 
+type AlarmMux = capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
+    'static,
+    s32k144::rtc::Rtc<'static>,
+>;
+
+/// Button pin, debounced with its own virtual alarm.
+type ButtonPin =
+    capsules_extra::debounce::DebouncedPin<'static, s32k144::gpio::GPIOPin<'static>, AlarmMux>;
+
 /// Supported drivers by the platform
 pub struct S32K144EVB<'a> {
-    console: &'static capsules_core::console::Console<'static>,
+    console: Option<&'static capsules_core::console::Console<'static>>,
     gpio: &'a capsules_core::gpio::GPIO<'a, s32k144::gpio::GPIOPin<'a>>,
     alarm: &'static capsules_core::alarm::AlarmDriver<
         'static,
@@ -77,25 +175,80 @@ pub struct S32K144EVB<'a> {
             s32k144::rtc::Rtc<'static>,
         >,
     >,
-    scheduler: &'static RoundRobinSched<'static>,
+    nonvolatile_storage:
+        &'static capsules_extra::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+    rng: &'static capsules_core::rng::RngDriver<
+        'static,
+        capsules_core::rng::Entropy32ToRandom<'static, s32k144::csec::Csec<'static>>,
+    >,
+    aes: &'static capsules_extra::symmetric_encryption::aes::AesDriver<'static, AesDevice>,
+    secure_boot: &'static capsules_extra::secure_boot::SecureBoot,
+    eui64: &'static capsules_extra::eui64::Eui64,
+    serial_number: &'static capsules_extra::serial_number::SerialNumber,
+    low_voltage: &'static capsules_extra::low_voltage::LowVoltage,
+    crc: &'static capsules_extra::crc::CrcDriver<'static, s32k144::crc::Crc<'static>>,
+    analog_comparator: Option<
+        &'static capsules_extra::analog_comparator::AnalogComparator<
+            'static,
+            s32k144::cmp::Cmp<'static>,
+        >,
+    >,
+    dac: Option<&'static capsules_extra::dac::Dac<'static>>,
+    lin: Option<
+        &'static capsules_extra::lin::LinDriver<
+            'static,
+            s32k144::lin::Lin<'static>,
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
+                'static,
+                s32k144::rtc::Rtc<'static>,
+            >,
+        >,
+    >,
+    scheduler: &'static config::Scheduler,
+    syscall_filter: &'static syscall_filter::PermissionsFilter,
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
     systick: cortexm4::systick::SysTick,
-    //pwm: &'static capsules_extra::pwm::Pwm<'static, 1>,
-    //led: &'a capsules_core::led::LedDriver<'a, s32k144::gpio::GPIOPin<'a>, 1>,
+    led: &'static capsules_core::led::LedDriver<
+        'static,
+        LedLow<'static, s32k144::gpio::GPIOPin<'static>>,
+        3,
+    >,
+    button: &'static capsules_core::button::Button<'static, ButtonPin>,
+    pwm: Option<&'static capsules_extra::pwm::Pwm<'static, 3>>,
+    adc: &'static capsules_core::adc::AdcVirtualized<'static>,
+    can: Option<&'static capsules_extra::can::CanCapsule<'static, s32k144::can0::FlexCan<'static>>>,
 }
 
 impl SyscallDriverLookup for S32K144EVB<'_> {
     fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
     where
-        F: FnOnce(Option<&dyn kernel::syscall::SyscallDriver>) -> R,
+        F: FnOnce(Option<&dyn SyscallDriver>) -> R,
     {
         match driver_num {
-            capsules_core::console::DRIVER_NUM => f(Some(self.console)),
+            capsules_core::console::DRIVER_NUM => f(self.console.map(|d| d as &dyn SyscallDriver)),
             capsules_core::gpio::DRIVER_NUM => f(Some(self.gpio)),
             capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
-            //capsules_core::led::DRIVER_NUM => f(Some(self.led)),
-            //capsules_extra::pwm::DRIVER_NUM => f(Some(self.pwm)),
+            capsules_extra::nonvolatile_storage_driver::DRIVER_NUM => {
+                f(Some(self.nonvolatile_storage))
+            }
+            capsules_core::rng::DRIVER_NUM => f(Some(self.rng)),
+            capsules_extra::symmetric_encryption::aes::DRIVER_NUM => f(Some(self.aes)),
+            capsules_extra::secure_boot::DRIVER_NUM => f(Some(self.secure_boot)),
+            capsules_extra::eui64::DRIVER_NUM => f(Some(self.eui64)),
+            capsules_extra::serial_number::DRIVER_NUM => f(Some(self.serial_number)),
+            capsules_extra::low_voltage::DRIVER_NUM => f(Some(self.low_voltage)),
+            capsules_extra::crc::DRIVER_NUM => f(Some(self.crc)),
+            capsules_extra::analog_comparator::DRIVER_NUM => {
+                f(self.analog_comparator.map(|d| d as &dyn SyscallDriver))
+            }
+            capsules_extra::dac::DRIVER_NUM => f(self.dac.map(|d| d as &dyn SyscallDriver)),
+            capsules_extra::lin::DRIVER_NUM => f(self.lin.map(|d| d as &dyn SyscallDriver)),
+            capsules_core::led::DRIVER_NUM => f(Some(self.led)),
+            capsules_core::button::DRIVER_NUM => f(Some(self.button)),
+            capsules_extra::pwm::DRIVER_NUM => f(self.pwm.map(|d| d as &dyn SyscallDriver)),
+            capsules_core::adc::DRIVER_NUM => f(Some(self.adc)),
+            capsules_extra::can::DRIVER_NUM => f(self.can.map(|d| d as &dyn SyscallDriver)),
             _ => f(None),
         }
     }
@@ -105,9 +258,9 @@ impl KernelResources<s32k144::chip::S32K144<'static, S32K144DefaultPeripherals<'
     for S32K144EVB<'_>
 {
     type SyscallDriverLookup = Self;
-    type SyscallFilter = ();
+    type SyscallFilter = syscall_filter::PermissionsFilter;
     type ProcessFault = ();
-    type Scheduler = RoundRobinSched<'static>;
+    type Scheduler = config::Scheduler;
     type SchedulerTimer = cortexm4::systick::SysTick;
     type WatchDog = ();
     type ContextSwitchCallback = ();
@@ -116,7 +269,7 @@ impl KernelResources<s32k144::chip::S32K144<'static, S32K144DefaultPeripherals<'
         self
     }
     fn syscall_filter(&self) -> &Self::SyscallFilter {
-        &()
+        self.syscall_filter
     }
     fn process_fault(&self) -> &Self::ProcessFault {
         &()
@@ -151,6 +304,10 @@ unsafe fn start() -> (
     // set up circular peripheral dependencies
     s32k144_peripherals.init();
 
+    // Code fetched from flash stalls on wait states unless cached.
+    s32k144_peripherals.lmem.set_flash_prefetch(true, true);
+    s32k144_peripherals.lmem.enable();
+
     let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&*addr_of!(PROCESSES)));
 
     //--------------------------------------------------------------------------
@@ -159,10 +316,19 @@ unsafe fn start() -> (
 
     // Create capabilities that the board needs to call certain protected kernel
     // functions.
-    let process_management_capability =
-        create_capability!(capabilities::ProcessManagementCapability);
     let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
 
+    // Every pin is configured through its PORT, which must be clocked first.
+    for port in [
+        s32k144::clock::Peripheral::PORTA,
+        s32k144::clock::Peripheral::PORTB,
+        s32k144::clock::Peripheral::PORTC,
+        s32k144::clock::Peripheral::PORTD,
+        s32k144::clock::Peripheral::PORTE,
+    ] {
+        s32k144_peripherals.clock.enable_peripheral_clock(port);
+    }
+
     //--------------------------------------------------------------------------
     // DEBUG GPIO
     //--------------------------------------------------------------------------
@@ -173,11 +339,13 @@ unsafe fn start() -> (
 
     const LED_KERNEL_PIN_INDEX: usize = LED_KERNEL_PIN as usize;
 
-    kernel::debug::assign_gpios(
-        Some(&s32k144_peripherals.gpio_port.pins[LED_KERNEL_PIN_INDEX]),
-        None,
-        None,
-    );
+    if !CONFIG.low_power {
+        kernel::debug::assign_gpios(
+            Some(&s32k144_peripherals.gpio_port.pins[LED_KERNEL_PIN_INDEX]),
+            None,
+            None,
+        );
+    }
 
     //--------------------------------------------------------------------------
     // GPIO
@@ -189,7 +357,6 @@ unsafe fn start() -> (
         components::gpio_component_helper!(
             s32k144::gpio::GPIOPin,
             9 => &s32k144_peripherals.gpio_port.pins[Pin::PTC1 as usize],
-            16 => &s32k144_peripherals.gpio_port.pins[Pin::PTD0 as usize],
         ),
     )
     .finalize(components::gpio_component_static!(s32k144::gpio::GPIOPin));
@@ -210,60 +377,626 @@ unsafe fn start() -> (
     )
     .finalize(components::alarm_component_static!(s32k144::rtc::Rtc));
 
+    //--------------------------------------------------------------------------
+    // LEDS
+    //--------------------------------------------------------------------------
+
+    let led = components::led::LedsComponent::new().finalize(components::led_component_static!(
+        LedLow<'static, s32k144::gpio::GPIOPin>,
+        LedLow::new(&s32k144_peripherals.gpio_port.pins[LED_RED_PIN as usize]),
+        LedLow::new(&s32k144_peripherals.gpio_port.pins[LED_GREEN_PIN as usize]),
+        LedLow::new(&s32k144_peripherals.gpio_port.pins[LED_BLUE_PIN as usize]),
+    ));
+
+    //--------------------------------------------------------------------------
+    // BUTTONS
+    //--------------------------------------------------------------------------
+
+    let sw2_pin = &s32k144_peripherals.gpio_port.pins[BUTTON_SW2_PIN as usize];
+    let sw2_alarm = static_init!(AlarmMux, AlarmMux::new(mux_alarm));
+    sw2_alarm.setup();
+    let sw2 = static_init!(
+        ButtonPin,
+        ButtonPin::new(sw2_pin, sw2_alarm, BUTTON_DEBOUNCE_MS)
+    );
+    sw2_pin.set_client(sw2);
+    sw2_alarm.set_alarm_client(sw2);
+
+    let sw3_pin = &s32k144_peripherals.gpio_port.pins[BUTTON_SW3_PIN as usize];
+    let sw3_alarm = static_init!(AlarmMux, AlarmMux::new(mux_alarm));
+    sw3_alarm.setup();
+    let sw3 = static_init!(
+        ButtonPin,
+        ButtonPin::new(sw3_pin, sw3_alarm, BUTTON_DEBOUNCE_MS)
+    );
+    sw3_pin.set_client(sw3);
+    sw3_alarm.set_alarm_client(sw3);
+
+    let button = components::button::ButtonComponent::new(
+        board_kernel,
+        capsules_core::button::DRIVER_NUM,
+        components::button_component_helper!(
+            ButtonPin,
+            (
+                sw2,
+                kernel::hil::gpio::ActivationMode::ActiveHigh,
+                kernel::hil::gpio::FloatingState::PullNone
+            ),
+            (
+                sw3,
+                kernel::hil::gpio::ActivationMode::ActiveHigh,
+                kernel::hil::gpio::FloatingState::PullNone
+            ),
+        ),
+    )
+    .finalize(components::button_component_static!(ButtonPin));
+
+    //--------------------------------------------------------------------------
+    // PWM
+    //--------------------------------------------------------------------------
+
+    let pwm = if CONFIG.low_power {
+        None
+    } else {
+        // The RGB LED channels. Starting PWM on a channel takes its pin from the
+        // LED driver until the channel is stopped.
+        s32k144_peripherals
+            .clock
+            .enable_peripheral_clock(s32k144::clock::Peripheral::FTM0);
+        let mux_pwm = components::pwm::PwmMuxComponent::new(&s32k144_peripherals.pwm0)
+            .finalize(components::pwm_mux_component_static!(s32k144::ftm0::Pwm));
+        let pwm_pins = [LED_RED_PIN, LED_GREEN_PIN, LED_BLUE_PIN].map(|pin| {
+            let (port, number) = pin.port_and_pin();
+            let pinmux = s32k144::pinmux::Pinmux::new(port, number);
+            let _ = s32k144_peripherals.pwm0.set_active_low(pinmux, true);
+            pinmux
+        });
+        let pwm_red = components::pwm::PwmPinUserComponent::new(mux_pwm, pwm_pins[0]).finalize(
+            components::pwm_pin_user_component_static!(s32k144::ftm0::Pwm),
+        );
+        let pwm_green = components::pwm::PwmPinUserComponent::new(mux_pwm, pwm_pins[1]).finalize(
+            components::pwm_pin_user_component_static!(s32k144::ftm0::Pwm),
+        );
+        let pwm_blue = components::pwm::PwmPinUserComponent::new(mux_pwm, pwm_pins[2]).finalize(
+            components::pwm_pin_user_component_static!(s32k144::ftm0::Pwm),
+        );
+        Some(
+            components::pwm::PwmDriverComponent::new(board_kernel, capsules_extra::pwm::DRIVER_NUM)
+                .finalize(components::pwm_driver_component_helper!(
+                    pwm_red, pwm_green, pwm_blue
+                )),
+        )
+    };
+
+    //--------------------------------------------------------------------------
+    // ADC
+    //--------------------------------------------------------------------------
+
+    // PTC14 keeps its reset function, the analog input.
+    let adc_mux = components::adc::AdcMuxComponent::new(&s32k144_peripherals.adc0)
+        .finalize(components::adc_mux_component_static!(s32k144::adc::Adc));
+    let adc =
+        components::adc::AdcVirtualComponent::new(board_kernel, capsules_core::adc::DRIVER_NUM)
+            .finalize(components::adc_syscall_component_helper!(
+                components::adc::AdcComponent::new(adc_mux, POTENTIOMETER_CHANNEL)
+                    .finalize(components::adc_component_static!(s32k144::adc::Adc)),
+            ));
+
     //--------------------------------------------------------------------------
     // UART & CONSOLE & DEBUG
     //--------------------------------------------------------------------------
 
     use kernel::hil::uart::Configure;
 
-    s32k144_peripherals.lpuart0.enable();
-
-    match s32k144_peripherals
-        .lpuart0
-        .configure(kernel::hil::uart::Parameters {
-            baud_rate: 115200,
-            stop_bits: kernel::hil::uart::StopBits::One,
-            parity: kernel::hil::uart::Parity::None,
-            hw_flow_control: false,
-            width: kernel::hil::uart::Width::Eight, // 8-bit
-        }) {
-        Ok(_) => debug!("UART configured successfully"),
-        Err(e) => debug!("UART configuration failed: {:?}", e),
-    }
+    // The console, the process console and `debug!()` share one channel:
+    // LPUART0, or the debug probe's RTT channel.
+    let (uart, console_uart): (
+        &'static dyn kernel::hil::uart::Uart<'static>,
+        Option<&'static s32k144::lpuart0::Lpuart0<'static>>,
+    ) = if CONFIG.console == ConsoleTransport::Rtt {
+        let rtt_memory = components::segger_rtt::SeggerRttMemoryComponent::new()
+            .finalize(components::segger_rtt_memory_component_static!());
+        let rtt = components::segger_rtt::SeggerRttComponent::new(mux_alarm, rtt_memory)
+            .finalize(components::segger_rtt_component_static!(s32k144::rtc::Rtc));
+        (rtt, None)
+    } else {
+        s32k144_peripherals.lpuart0.enable();
+
+        match s32k144_peripherals
+            .lpuart0
+            .configure(kernel::hil::uart::Parameters {
+                baud_rate: 115200,
+                stop_bits: kernel::hil::uart::StopBits::One,
+                parity: kernel::hil::uart::Parity::None,
+                hw_flow_control: false,
+                width: kernel::hil::uart::Width::Eight, // 8-bit
+            }) {
+            Ok(_) => debug!("UART configured successfully"),
+            Err(e) => debug!("UART configuration failed: {:?}", e),
+        }
+        (
+            &s32k144_peripherals.lpuart0,
+            Some(&s32k144_peripherals.lpuart0),
+        )
+    };
 
     // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(&s32k144_peripherals.lpuart0, 115200)
+    let uart_mux = components::console::UartMuxComponent::new(uart, 115200)
         .finalize(components::uart_mux_component_static!());
 
-    // Setup the console.
-    let console = components::console::ConsoleComponent::new(
-        board_kernel,
-        capsules_core::console::DRIVER_NUM,
-        uart_mux,
-    )
-    .finalize(components::console_component_static!());
-    // Create the debugger object that handles calls to `debug!()`.
+    // Setup the console. Without it, the channel only carries `debug!()`.
+    let console = if CONFIG.app_console {
+        Some(
+            components::console::ConsoleComponent::new(
+                board_kernel,
+                capsules_core::console::DRIVER_NUM,
+                uart_mux,
+            )
+            .finalize(components::console_component_static!()),
+        )
+    } else {
+        None
+    };
+    // Create the debugger object that handles calls to `debug!()`. The
+    // kernel needs it whatever the features, so `debug!()` output is never
+    // left out.
     components::debug_writer::DebugWriterComponent::new(uart_mux)
         .finalize(components::debug_writer_component_static!());
 
     //--------------------------------------------------------------------------
-    // Process Console
+    // EMULATED EEPROM
     //--------------------------------------------------------------------------
-    let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
-        .finalize(components::process_printer_text_component_static!());
-    PROCESS_PRINTER = Some(process_printer);
 
-    let _process_console = components::process_console::ProcessConsoleComponent::new(
+    let nvmc = &s32k144_peripherals.nvmc;
+    if let Some(config) = FLEXNVM_PARTITION {
+        if nvmc.partition().is_none() {
+            match nvmc.program_partition(&config) {
+                Ok(()) => debug!("FlexNVM partitioned for emulated EEPROM"),
+                Err(e) => debug!("FlexNVM partition failed: {:?}", e),
+            }
+        }
+    }
+    let eee_size = nvmc.partition().unwrap_or(EeeSize::None);
+    let csec_keys = FLEXNVM_PARTITION.map_or(CsecKeySize::None, |config| config.csec_keys);
+    if eee_size != EeeSize::None && !nvmc.eee_ready() {
+        let _ = nvmc.set_flexram_function(FlexRamFunction::EmulatedEeprom);
+    }
+
+    let eee = static_init!(
+        s32k144::eee::FlexRamEeprom<'static>,
+        s32k144::eee::FlexRamEeprom::new(nvmc, eee_size, csec_keys)
+    );
+    nvmc.set_command_client(CommandOwner::Eeprom, eee);
+    kernel::deferred_call::DeferredCallClient::register(eee);
+
+    let nonvolatile_storage_buffer = static_init!(
+        [u8; capsules_extra::nonvolatile_storage_driver::BUF_LEN],
+        [0; capsules_extra::nonvolatile_storage_driver::BUF_LEN]
+    );
+    let nonvolatile_storage = static_init!(
+        capsules_extra::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+        capsules_extra::nonvolatile_storage_driver::NonvolatileStorage::new(
+            eee,
+            board_kernel.create_grant(
+                capsules_extra::nonvolatile_storage_driver::DRIVER_NUM,
+                &memory_allocation_capability
+            ),
+            0,          // Start address for userspace accessible region
+            eee.size(), // Length of userspace accessible region
+            0,          // Start address of kernel region
+            0,          // Length of kernel region
+            nonvolatile_storage_buffer,
+        )
+    );
+    kernel::hil::nonvolatile_storage::NonvolatileStorage::set_client(eee, nonvolatile_storage);
+
+    //--------------------------------------------------------------------------
+    // CSEc (AES, CMAC and PRNG)
+    //--------------------------------------------------------------------------
+
+    let csec = static_init!(s32k144::csec::Csec<'static>, s32k144::csec::Csec::new(nvmc));
+    nvmc.set_command_client(CommandOwner::Csec, csec);
+
+    let rng =
+        components::rng::RngComponent::new(board_kernel, capsules_core::rng::DRIVER_NUM, csec)
+            .finalize(components::rng_component_static!(
+                s32k144::csec::Csec<'static>
+            ));
+
+    let aes_mux = static_init!(
+        virtual_aes_ccm::MuxAES128CCM<'static, s32k144::csec::Csec<'static>>,
+        virtual_aes_ccm::MuxAES128CCM::new(csec)
+    );
+    kernel::deferred_call::DeferredCallClient::register(aes_mux);
+    kernel::hil::symmetric_encryption::AES128::set_client(csec, aes_mux);
+
+    let ccm_client = components::aes::AesVirtualComponent::new(aes_mux).finalize(
+        components::aes_virtual_component_static!(s32k144::csec::Csec<'static>),
+    );
+    let gcm_crypt_buf = static_init!(
+        [u8; 7 * kernel::hil::symmetric_encryption::AES128_BLOCK_SIZE],
+        [0; 7 * kernel::hil::symmetric_encryption::AES128_BLOCK_SIZE]
+    );
+    let gcm_client = static_init!(
+        AesDevice,
+        aes_gcm::Aes128Gcm::new(ccm_client, gcm_crypt_buf)
+    );
+    kernel::hil::symmetric_encryption::AES128CCM::set_client(ccm_client, gcm_client);
+
+    let aes = components::aes::AesDriverComponent::new(
         board_kernel,
-        uart_mux,
-        mux_alarm,
-        process_printer,
-        Some(cortexm4f::support::reset),
+        capsules_extra::symmetric_encryption::aes::DRIVER_NUM,
+        gcm_client,
     )
-    .finalize(components::process_console_component_static!(
-        s32k144::rtc::Rtc
-    ));
-    let _ = _process_console.start();
+    .finalize(components::aes_driver_component_static!(AesDevice));
+
+    //--------------------------------------------------------------------------
+    // CRC
+    //--------------------------------------------------------------------------
+
+    let crc = components::crc::CrcComponent::new(
+        board_kernel,
+        capsules_extra::crc::DRIVER_NUM,
+        &s32k144_peripherals.crc,
+    )
+    .finalize(components::crc_component_static!(s32k144::crc::Crc));
+
+    //--------------------------------------------------------------------------
+    // ANALOG COMPARATOR AND DAC
+    //--------------------------------------------------------------------------
+
+    let (analog_comparator, dac) = if CONFIG.low_power {
+        (None, None)
+    } else {
+        // Channel `n` is CMP0 input INn, compared against the CMP0 DAC, which
+        // userspace sets through the DAC driver.
+        let analog_comparator = components::analog_comparator::AnalogComparatorComponent::new(
+            &s32k144_peripherals.cmp0,
+            components::analog_comparator_component_helper!(
+                s32k144::cmp::Channel,
+                &s32k144::cmp::CHANNEL_IN0,
+                &s32k144::cmp::CHANNEL_IN1,
+                &s32k144::cmp::CHANNEL_IN2,
+                &s32k144::cmp::CHANNEL_IN3,
+                &s32k144::cmp::CHANNEL_IN4,
+                &s32k144::cmp::CHANNEL_IN5,
+                &s32k144::cmp::CHANNEL_IN6
+            ),
+            board_kernel,
+            capsules_extra::analog_comparator::DRIVER_NUM,
+        )
+        .finalize(components::analog_comparator_component_static!(
+            s32k144::cmp::Cmp
+        ));
+
+        let dac = components::dac::DacComponent::new(&s32k144_peripherals.cmp0)
+            .finalize(components::dac_component_static!());
+        (Some(analog_comparator), Some(dac))
+    };
+
+    //--------------------------------------------------------------------------
+    // DEVICE IDENTITY
+    //--------------------------------------------------------------------------
+
+    // The factory-programmed unique ID serves as the serial number, so
+    // boards can be identified from the console without provisioning.
+    let unique_id = s32k144::sim::unique_id();
+    let eui64 = components::eui64::Eui64Component::new(s32k144::sim::eui64())
+        .finalize(components::eui64_component_static!());
+    let serial_number = components::serial_number::SerialNumberComponent::new(
+        board_kernel,
+        capsules_extra::serial_number::DRIVER_NUM,
+        static_init!([u8; 16], unique_id.to_be_bytes()),
+    )
+    .finalize(components::serial_number_component_static!());
+    debug!(
+        "Serial number: {:032x}, EUI-64: {:016x}",
+        unique_id,
+        s32k144::sim::eui64()
+    );
+
+    //--------------------------------------------------------------------------
+    // ECC
+    //--------------------------------------------------------------------------
+
+    extern "C" {
+        /// Beginning of the RAM region for app memory, at the start of SRAM_U.
+        static _sappmem: u8;
+    }
+
+    // Startup diagnostic of the ECC of both SRAM banks: SRAM_L on a word of
+    // the kernel stack, SRAM_U on the first word of app memory, which no
+    // process owns yet.
+    let ecc_test_word = core::cell::Cell::new(0);
+    let app_memory_word = &*addr_of!(_sappmem).cast::<core::cell::Cell<u32>>();
+    for word in [&ecc_test_word, app_memory_word] {
+        if let Err(e) = s32k144_peripherals
+            .erm
+            .self_test(&s32k144_peripherals.eim, word)
+        {
+            panic!("SRAM ECC self-test failed: {:?}", e);
+        }
+    }
+
+    let ecc_monitor = static_init!(ecc::EccMonitor, ecc::EccMonitor::new(board_kernel));
+    s32k144_peripherals.erm.set_client(ecc_monitor);
+    s32k144_peripherals.erm.enable();
+    s32k144_peripherals.nvmc.enable_double_bit_fault_interrupt();
+
+    //--------------------------------------------------------------------------
+    // RETAINED RAM
+    //--------------------------------------------------------------------------
+
+    extern "C" {
+        /// Beginning of the RAM region kept through warm resets.
+        static mut _sretained: u32;
+        /// End of the RAM region kept through warm resets.
+        static mut _eretained: u32;
+    }
+
+    // The bootloader has cleared the region if it held garbage, after a
+    // power-on or low-voltage reset. The boot state it shares with the
+    // kernel comes first.
+    let retained_ram = static_init!(
+        RetainedRam<'static>,
+        RetainedRam::new(
+            core::slice::from_raw_parts_mut(
+                addr_of_mut!(_sretained),
+                (addr_of!(_eretained) as usize - addr_of!(_sretained) as usize) / 4,
+            ),
+            true,
+        )
+    );
+    let boot_state = static_init!(
+        RetainedSlot<'static, BootState>,
+        retained_ram.slot().unwrap()
+    );
+
+    //--------------------------------------------------------------------------
+    // PROCESS FAULTS
+    //--------------------------------------------------------------------------
+
+    let fault_recorder = static_init!(
+        fault_record::FaultRecorder,
+        fault_record::FaultRecorder::new(retained_ram.slot().unwrap())
+    );
+    if let Some(record) = fault_recorder.take() {
+        debug!("Last process fault: {}", record);
+    }
+
+    let fault_alarm = static_init!(AlarmMux, AlarmMux::new(mux_alarm));
+    fault_alarm.setup();
+    let fault_policy = static_init!(
+        fault_record::FaultPolicy,
+        fault_record::FaultPolicy::new(
+            board_kernel,
+            fault_alarm,
+            fault_record::Capability,
+            CONFIG.fault_restart_delay_ms,
+            CONFIG.fault_max_restarts,
+        )
+    );
+    fault_alarm.set_alarm_client(fault_policy);
+    fault_policy.set_recorder(fault_recorder);
+
+    //--------------------------------------------------------------------------
+    // LOW VOLTAGE
+    //--------------------------------------------------------------------------
+
+    let low_voltage = static_init!(
+        capsules_extra::low_voltage::LowVoltage,
+        capsules_extra::low_voltage::LowVoltage::new(board_kernel.create_grant(
+            capsules_extra::low_voltage::DRIVER_NUM,
+            &memory_allocation_capability
+        ))
+    );
+    let brownout = static_init!(
+        brownout::BrownOut,
+        brownout::BrownOut::new(&s32k144_peripherals.nvmc, console_uart, low_voltage)
+    );
+    s32k144_peripherals.pwr_clk.set_client(brownout);
+    // A warning leaves time to save state; below the detect threshold the
+    // chip is held in reset rather than run unreliably.
+    s32k144_peripherals
+        .pwr_clk
+        .configure_low_voltage(s32k144::power::LowVoltageAction::Reset, true);
+
+    //--------------------------------------------------------------------------
+    // LIN
+    //--------------------------------------------------------------------------
+
+    let lin = if CONFIG.low_power {
+        None
+    } else {
+        // PTD6 and PTD7 as LPUART2 RX and TX (ALT2), wired to the TJA1027.
+        let portd = s32k144::pinmux::PORT_BASES[3];
+        portd.pcr[6].modify(s32k144::pinmux::PCR::MUX.val(2));
+        portd.pcr[7].modify(s32k144::pinmux::PCR::MUX.val(2));
+
+        Some(
+            components::lin::LinComponent::new(
+                board_kernel,
+                capsules_extra::lin::DRIVER_NUM,
+                &s32k144_peripherals.lin,
+                mux_alarm,
+            )
+            .finalize(components::lin_component_static!(
+                s32k144::lin::Lin,
+                s32k144::rtc::Rtc
+            )),
+        )
+    };
+
+    //--------------------------------------------------------------------------
+    // CAN
+    //--------------------------------------------------------------------------
+
+    let (can, can_command) = if CONFIG.can_node {
+        // PTE4 and PTE5 as CAN0 RX and TX (ALT5), wired to the TJA1044. The
+        // controller is configured for the test bench and switched on by an
+        // application or the `can on` console command.
+        let porte = s32k144::pinmux::PORT_BASES[4];
+        porte.pcr[4].modify(s32k144::pinmux::PCR::MUX.val(5));
+        porte.pcr[5].modify(s32k144::pinmux::PCR::MUX.val(5));
+
+        let can0 = &s32k144_peripherals.can0;
+        let can_standby = &s32k144_peripherals.gpio_port.pins[CAN_STANDBY_PIN as usize];
+        can_standby.make_output();
+        can0.set_standby_pin(can_standby);
+        let _ = kernel::hil::can::Configure::set_bitrate(can0, CAN_BITRATE);
+        let _ = kernel::hil::can::Configure::set_operation_mode(
+            can0,
+            kernel::hil::can::OperationMode::Normal,
+        );
+        can0.set_bus_off_recovery(s32k144::can0::BusOffRecovery::Manual);
+
+        let can_alarm = static_init!(AlarmMux, AlarmMux::new(mux_alarm));
+        can_alarm.setup();
+        let bus_off_policy = static_init!(
+            can_node::BusOffPolicy<'static, AlarmMux>,
+            can_node::BusOffPolicy::new(can0, can_alarm)
+        );
+        can_alarm.set_alarm_client(bus_off_policy);
+        can0.set_bus_off_client(bus_off_policy);
+
+        let can =
+            components::can::CanComponent::new(board_kernel, capsules_extra::can::DRIVER_NUM, can0)
+                .finalize(components::can_component_static!(
+                    s32k144::can0::FlexCan<'static>
+                ));
+        let can_command = static_init!(
+            can_node::CanCommand<'static>,
+            can_node::CanCommand::new(can0)
+        );
+        (Some(can), Some(can_command))
+    } else {
+        (None, None)
+    };
+
+    //--------------------------------------------------------------------------
+    // SECURE BOOT
+    //--------------------------------------------------------------------------
+
+    if let Some(flavor) = SECURE_BOOT {
+        if !csec.boot_status().initialized {
+            match csec.boot_define(bootloader_size(), flavor) {
+                Ok(()) => debug!("CSEc secure boot defined, active from the next reset"),
+                Err(e) => debug!("CSEc BOOT_DEFINE failed: {:?}", e),
+            }
+        }
+    }
+
+    // In parallel boot the CSEc may still be checking the image.
+    let mut polls = 0;
+    while csec.boot_status().busy && polls < SECURE_BOOT_POLLS {
+        polls += 1;
+    }
+    let boot = csec.boot_status();
+    let authenticated = !boot.busy && boot.authenticated();
+    if boot.busy {
+        debug!("CSEc still busy after {} polls", SECURE_BOOT_POLLS);
+    } else if boot.secure_boot && !boot.finished {
+        let _ = if authenticated {
+            csec.boot_ok()
+        } else {
+            csec.boot_failure()
+        };
+    }
+    let boot_status = match (boot.initialized, boot.secure_boot, authenticated) {
+        (false, _, _) => "not configured",
+        (true, false, _) => "not run (is BOOT_MAC_KEY loaded?)",
+        (true, true, true) => "bootloader authenticated",
+        (true, true, false) => "bootloader authentication failed",
+    };
+    debug!("Secure boot: {}", boot_status);
+
+    let secure_boot = static_init!(
+        capsules_extra::secure_boot::SecureBoot,
+        capsules_extra::secure_boot::SecureBoot::new(boot.initialized, authenticated)
+    );
+
+    //--------------------------------------------------------------------------
+    // APP LOADING
+    //--------------------------------------------------------------------------
+
+    extern "C" {
+        /// Beginning of the flash region for apps.
+        static _sapps: u8;
+        /// End of the flash region for apps.
+        static _eapps: u8;
+    }
+
+    let app_loader_uart = static_init!(
+        capsules_core::virtualizers::virtual_uart::UartDevice<'static>,
+        capsules_core::virtualizers::virtual_uart::UartDevice::new(uart_mux, true)
+    );
+    app_loader_uart.setup();
+    let app_loader_alarm = static_init!(AlarmMux, AlarmMux::new(mux_alarm));
+    app_loader_alarm.setup();
+    let app_loader = static_init!(
+        app_loader::AppLoader<'static, AlarmMux>,
+        app_loader::AppLoader::new(
+            app_loader_uart,
+            app_loader_alarm,
+            &s32k144_peripherals.nvmc,
+            core::slice::from_raw_parts(
+                addr_of!(_sapps),
+                addr_of!(_eapps) as usize - addr_of!(_sapps) as usize,
+            ),
+            static_init!(
+                [u8; app_loader::RX_BUFFER_LEN],
+                [0; app_loader::RX_BUFFER_LEN]
+            ),
+            static_init!(
+                [u8; app_loader::TX_BUFFER_LEN],
+                [0; app_loader::TX_BUFFER_LEN]
+            ),
+            static_init!(FtfcPage, FtfcPage::default()),
+        )
+    );
+    app_loader_uart.set_transmit_client(app_loader);
+    app_loader_uart.set_receive_client(app_loader);
+    app_loader_alarm.set_alarm_client(app_loader);
+    kernel::hil::flash::HasClient::set_client(&s32k144_peripherals.nvmc, app_loader);
+
+    //--------------------------------------------------------------------------
+    // Process Console
+    //--------------------------------------------------------------------------
+    let update_alarm = static_init!(AlarmMux, AlarmMux::new(mux_alarm));
+    update_alarm.setup();
+    let update_command = static_init!(
+        boot::UpdateCommand<'static, AlarmMux>,
+        boot::UpdateCommand::new(boot_state, update_alarm)
+    );
+
+    if CONFIG.app_console {
+        let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
+            .finalize(components::process_printer_text_component_static!());
+        PROCESS_PRINTER = Some(process_printer);
+
+        let _process_console = components::process_console::ProcessConsoleComponent::new(
+            board_kernel,
+            uart_mux,
+            mux_alarm,
+            process_printer,
+            Some(cortexm4f::support::reset),
+        )
+        .finalize(components::process_console_component_static!(
+            s32k144::rtc::Rtc
+        ));
+        _process_console.set_boot_status(boot_status);
+        let board_commands: &'static [&'static dyn BoardCommand] = match can_command {
+            Some(can_command) => static_init!(
+                [&'static dyn BoardCommand; 3],
+                [app_loader, update_command, can_command]
+            ),
+            None => static_init!([&'static dyn BoardCommand; 2], [app_loader, update_command]),
+        };
+        _process_console.set_board_commands(board_commands);
+        app_loader.set_console(_process_console);
+        update_alarm.set_alarm_client(update_command);
+        let _ = _process_console.start();
+    }
 
     //--------------------------------------------------------------------------
     // FINAL SETUP AND BOARD BOOT
@@ -277,14 +1010,35 @@ unsafe fn start() -> (
     while !s32k144_peripherals.clock.low_started() {}
     while !s32k144_peripherals.clock.high_started() {}
 
-    let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
-        .finalize(components::round_robin_component_static!(NUM_PROCS));
+    let syscall_filter = static_init!(
+        syscall_filter::PermissionsFilter,
+        syscall_filter::PermissionsFilter::new(CONFIG.syscall_filter)
+    );
+
+    let scheduler = config::scheduler(&*addr_of!(PROCESSES), mux_alarm);
 
     let s32k144evb = S32K144EVB {
         console,
         gpio,
         alarm,
+        nonvolatile_storage,
+        rng,
+        aes,
+        secure_boot,
+        eui64,
+        serial_number,
+        low_voltage,
+        crc,
+        analog_comparator,
+        dac,
+        lin,
+        led,
+        button,
+        pwm,
+        adc,
+        can,
         scheduler,
+        syscall_filter,
         systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
         ipc: kernel::ipc::IPC::new(
             board_kernel,
@@ -305,37 +1059,51 @@ unsafe fn start() -> (
     // PROCESSES AND MAIN LOOP
     //--------------------------------------------------------------------------
 
-    // These symbols are defined in the linker script.
-    extern "C" {
-        /// Beginning of the ROM region containing app images.
-        static _sapps: u8;
-        /// End of the ROM region containing app images.
-        static _eapps: u8;
-        /// Beginning of the RAM region for app memory.
-        static mut _sappmem: u8;
-        /// End of the RAM region for app memory.
-        static _eappmem: u8;
-    }
+    // With secure boot configured, apps only run if the kernel that is about
+    // to start them was authenticated.
+    let kernel_trusted = SECURE_BOOT.is_none() || authenticated;
+    let null_policy = components::appid::checker_null::AppCheckerNullComponent::new()
+        .finalize(components::app_checker_null_component_static!());
+    let checking_policy = static_init!(
+        capsules_system::process_checker::secure_boot::AppCheckerSecureBoot<'static>,
+        capsules_system::process_checker::secure_boot::AppCheckerSecureBoot::new(
+            kernel_trusted,
+            null_policy
+        )
+    );
+    let assigner = components::appid::assigner_name::AppIdAssignerNamesComponent::new()
+        .finalize(components::appid_assigner_names_component_static!());
+    let checker = components::appid::checker::ProcessCheckerMachineComponent::new(checking_policy)
+        .finalize(components::process_checker_machine_component_static!());
+    let storage_permissions_policy =
+        components::storage_permissions::null::StoragePermissionsNullComponent::new().finalize(
+            components::storage_permissions_null_component_static!(
+                s32k144::chip::S32K144<S32K144DefaultPeripherals>,
+                kernel::process::ProcessStandardDebugFull,
+            ),
+        );
 
-    kernel::process::load_processes(
+    let loader = components::loader::sequential::ProcessLoaderSequentialComponent::new(
+        checker,
+        &mut *addr_of_mut!(PROCESSES),
         board_kernel,
         chip,
-        core::slice::from_raw_parts(
-            core::ptr::addr_of!(_sapps),
-            core::ptr::addr_of!(_eapps) as usize - core::ptr::addr_of!(_sapps) as usize,
-        ),
-        core::slice::from_raw_parts_mut(
-            core::ptr::addr_of_mut!(_sappmem),
-            core::ptr::addr_of!(_eappmem) as usize - core::ptr::addr_of!(_sappmem) as usize,
-        ),
-        &mut *addr_of_mut!(PROCESSES),
-        &FAULT_RESPONSE,
-        &process_management_capability,
+        fault_policy,
+        assigner,
+        storage_permissions_policy,
     )
-    .unwrap_or_else(|err| {
-        debug!("Error loading processes!");
-        debug!("{:?}", err);
-    });
+    .finalize(components::process_loader_sequential_component_static!(
+        s32k144::chip::S32K144<S32K144DefaultPeripherals>,
+        kernel::process::ProcessStandardDebugFull,
+        NUM_PROCS
+    ));
+    app_loader.set_loader(loader);
+    loader.set_client(app_loader);
+
+    match boot::confirm(boot_state) {
+        Some(slot) => debug!("Kernel started from slot {}", ["A", "B"][slot]),
+        None => debug!("Kernel started without the bootloader"),
+    }
 
     (board_kernel, s32k144evb, chip)
 }
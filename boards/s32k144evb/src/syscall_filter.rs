@@ -0,0 +1,107 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! System call filtering by the TBF `Permissions` header.
+//!
+//! Every denied call is reported on the console with the app, the driver
+//! and why it was denied. A process retrying the same driver is only
+//! reported once, until it is denied something else.
+
+use core::cell::Cell;
+
+use kernel::debug;
+use kernel::platform::{SyscallFilter, TbfHeaderFilterDefaultAllow, TbfHeaderFilterDefaultDeny};
+use kernel::process::{Process, ProcessId};
+use kernel::syscall::Syscall;
+use kernel::ErrorCode;
+use tock_tbf::types::CommandPermissions;
+
+/// What apps without a `Permissions` header may do.
+#[derive(Clone, Copy)]
+pub enum Policy {
+    /// Use any driver, as with no filter at all.
+    DefaultAllow,
+    /// Use no driver; they can only yield, exit and use memop.
+    DefaultDeny,
+}
+
+pub struct PermissionsFilter {
+    policy: Policy,
+    last_denied: Cell<Option<(ProcessId, usize)>>,
+}
+
+impl PermissionsFilter {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            last_denied: Cell::new(None),
+        }
+    }
+
+    fn report(&self, process: &dyn Process, syscall: &Syscall, error: ErrorCode) {
+        let (call, driver, subdriver) = match *syscall {
+            Syscall::Subscribe {
+                driver_number,
+                subdriver_number,
+                ..
+            } => ("subscribe", driver_number, subdriver_number),
+            Syscall::Command {
+                driver_number,
+                subdriver_number,
+                ..
+            } => ("command", driver_number, subdriver_number),
+            Syscall::ReadWriteAllow {
+                driver_number,
+                subdriver_number,
+                ..
+            }
+            | Syscall::UserspaceReadableAllow {
+                driver_number,
+                subdriver_number,
+                ..
+            }
+            | Syscall::ReadOnlyAllow {
+                driver_number,
+                subdriver_number,
+                ..
+            } => ("allow", driver_number, subdriver_number),
+            Syscall::Yield { .. } | Syscall::Memop { .. } | Syscall::Exit { .. } => return,
+        };
+
+        let denied = Some((process.processid(), driver));
+        if self.last_denied.get() == denied {
+            return;
+        }
+        self.last_denied.set(denied);
+
+        let offset = if call == "command" { subdriver / 64 } else { 0 };
+        let reason = match process.get_command_permissions(driver, offset) {
+            CommandPermissions::NoPermsAtAll => "it has no Permissions header",
+            CommandPermissions::NoPermsThisDriver => "the driver is not in its Permissions",
+            CommandPermissions::Mask(_) => "the command is not in its Permissions",
+        };
+        debug!(
+            "{}: {} {} on driver {:#x} denied ({:?}), {}",
+            process.get_process_name(),
+            call,
+            subdriver,
+            driver,
+            error,
+            reason
+        );
+    }
+}
+
+impl SyscallFilter for PermissionsFilter {
+    fn filter_syscall(&self, process: &dyn Process, syscall: &Syscall) -> Result<(), ErrorCode> {
+        let result = match self.policy {
+            Policy::DefaultAllow => TbfHeaderFilterDefaultAllow {}.filter_syscall(process, syscall),
+            Policy::DefaultDeny => TbfHeaderFilterDefaultDeny {}.filter_syscall(process, syscall),
+        };
+        if let Err(error) = result {
+            self.report(process, syscall, error);
+        }
+        result
+    }
+}
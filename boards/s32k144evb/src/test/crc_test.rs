@@ -0,0 +1,40 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test that the CRC unit runs a request through to `crc_done`.
+//!
+//! To test, add the following line to the end of `main()`, before the
+//! kernel loop starts:
+//! ```
+//!     test::crc_test::run_crc(&s32k144_peripherals.crc);
+//! ```
+//! The test replaces the CRC capsule as the client of the unit. You should
+//! see the following output:
+//! ```
+//!     CRC32: 0xcbf43926
+//!     CRC32C: 0xe3069283
+//!     CRC16CCITT: 0x89f6
+//! ```
+//!
+//! These results are for computing the CRC over the string "123456789"
+//! (not including the quotes), from
+//! <https://reveng.sourceforge.io/crc-catalogue/17plus.htm>. A driver that
+//! hands back a buffer with data left in it never reaches the first line.
+
+use capsules_extra::test::crc::TestCrc;
+use kernel::hil::crc::Crc as _;
+use kernel::static_init;
+use s32k144::crc::Crc;
+
+pub unsafe fn run_crc(crc: &'static Crc<'static>) {
+    let t = static_init_crc(crc);
+    crc.set_client(t);
+
+    t.run();
+}
+
+unsafe fn static_init_crc(crc: &'static Crc<'static>) -> &'static TestCrc<'static, Crc<'static>> {
+    let data = static_init!([u8; 9], *b"123456789");
+    static_init!(TestCrc<'static, Crc>, TestCrc::new(crc, data))
+}
@@ -0,0 +1,38 @@
+#!/usr/bin/env bash
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2024.
+
+# Build the kernel with every combination of the board variant features in
+# `src/config.rs` that add or remove drivers, so that no variant breaks
+# unnoticed, then each feature that only changes a value on its own. It
+# builds rather than checks, since linking tells whether a variant still
+# fits its slot.
+
+set -e
+
+cd "$(dirname "$0")"
+
+FEATURES=(no-console rtt-console can-node low-power mlfq)
+VALUES=(procs-2 procs-8 fault-stop syscall-deny)
+COUNT=${#FEATURES[@]}
+
+for ((mask = 0; mask < 1 << COUNT; mask++)); do
+    selected=()
+    for ((i = 0; i < COUNT; i++)); do
+        if ((mask & 1 << i)); then
+            selected+=("${FEATURES[i]}")
+        fi
+    done
+    list=$(IFS=,; echo "${selected[*]}")
+    echo "== ${list:-no features}"
+    cargo build --quiet --release --target thumbv7em-none-eabihf \
+        --no-default-features --features "$list" "$@"
+done
+
+for value in "${VALUES[@]}"; do
+    echo "== $value"
+    cargo build --quiet --release --target thumbv7em-none-eabihf \
+        --features "$value" "$@"
+done
@@ -34,6 +34,7 @@ pub enum NUM {
     UsbUser               = 0x20005,
     I2cMasterSlave        = 0x20006,
     Can                   = 0x20007,
+    Lin                   = 0x20008,
 
     // Radio
     BleAdvertising        = 0x30000,
@@ -51,6 +52,7 @@ pub enum NUM {
     CtapHid               = 0x40004,
     Sha                   = 0x40005,
     Aes                   = 0x40006,
+    SecureBoot            = 0x40007,
 
     // Storage
     AppFlash              = 0x50000,
@@ -97,5 +99,7 @@ pub enum NUM {
     DateTime              = 0x90007,
     CycleCount            = 0x90008,
     Servo                 = 0x90009,
+    SerialNumber          = 0x9000A,
+    LowVoltage            = 0x9000B,
 }
 }
@@ -15,6 +15,7 @@ use kernel::capabilities::ProcessManagementCapability;
 use kernel::capabilities::ProcessStartCapability;
 use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::ProcessId;
 
@@ -208,6 +209,29 @@ pub struct KernelAddresses {
     pub bss_end: *const u8,
 }
 
+/// A command provided by the board, for example to reconfigure a peripheral
+/// from the console.
+pub trait BoardCommand {
+    /// The word that selects the command.
+    fn name(&self) -> &'static str;
+
+    /// Run the command. `args` is the rest of the command line, and the
+    /// reply is written to `out`. The reply must be shorter than
+    /// [`QUEUE_BUF_LEN`] bytes.
+    fn execute(&self, args: &str, out: &mut dyn fmt::Write);
+}
+
+/// Lets a board command take the console input over for a while, for
+/// example to receive a file.
+pub trait ConsoleControl {
+    /// Stop reading commands. Received bytes are dropped without echo and
+    /// no prompt is shown.
+    fn pause(&self);
+
+    /// Read commands again, starting with a new prompt.
+    fn resume(&self);
+}
+
 /// Track the operational state of the process console.
 #[derive(Clone, Copy, PartialEq)]
 enum ProcessConsoleState {
@@ -220,6 +244,9 @@ enum ProcessConsoleState {
     /// the console to be installed on a board but to not interfere with a
     /// console-based app.
     Hibernating,
+    /// The console has been paused by a board command, which is using the
+    /// UART input. Received bytes are dropped.
+    Paused,
 }
 
 pub struct ProcessConsole<
@@ -270,6 +297,12 @@ pub struct ProcessConsole<
     /// Function used to reset the device in bootloader mode
     reset_function: Option<fn() -> !>,
 
+    /// Secure boot status reported by the board, printed by `kernel`.
+    boot_status: OptionalCell<&'static str>,
+
+    /// Commands added by the board, listed by `help`.
+    board_commands: OptionalCell<&'a [&'a dyn BoardCommand]>,
+
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
@@ -476,10 +509,23 @@ impl<
             kernel,
             kernel_addresses,
             reset_function,
+            boot_status: OptionalCell::empty(),
+            board_commands: OptionalCell::empty(),
             capability,
         }
     }
 
+    /// Set the secure boot status printed by the `kernel` command, for
+    /// example whether the kernel image was authenticated on this reset.
+    pub fn set_boot_status(&self, status: &'static str) {
+        self.boot_status.set(status);
+    }
+
+    /// Add board-specific commands to the console.
+    pub fn set_board_commands(&self, commands: &'a [&'a dyn BoardCommand]) {
+        self.board_commands.set(commands);
+    }
+
     /// Start the process console listening for user commands.
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.mode.get() == ProcessConsoleState::Off {
@@ -788,6 +834,14 @@ impl<
                             let _ = self.write_bytes(b"Welcome to the process console.\r\n");
                             let _ = self.write_bytes(b"Valid commands are: ");
                             let _ = self.write_bytes(VALID_COMMANDS_STR);
+                            self.board_commands.map(|commands| {
+                                let _ = self.write_bytes(b"Board commands:");
+                                for command in commands.iter() {
+                                    let _ = self.write_bytes(b" ");
+                                    let _ = self.write_bytes(command.name().as_bytes());
+                                }
+                                let _ = self.write_bytes(b"\r\n");
+                            });
                         } else if clean_str.starts_with("console-stop") {
                             let _ = self.write_bytes(b"Disabling the process console.\r\n");
                             let _ = self.write_bytes(b"Run console-start to reactivate.\r\n");
@@ -987,6 +1041,12 @@ impl<
                                     option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown")
                                 ),
                             );
+                            self.boot_status.map(|status| {
+                                let _ = write(
+                                    &mut console_writer,
+                                    format_args!("Secure boot: {}\r\n", status),
+                                );
+                            });
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
                             console_writer.clear();
 
@@ -1004,6 +1064,17 @@ impl<
                             );
                         } else if clean_str.starts_with("panic") {
                             panic!("Process Console forced a kernel panic.");
+                        } else if let Some(command) =
+                            self.board_commands.get().and_then(|commands| {
+                                commands.iter().find(|command| {
+                                    clean_str.split_whitespace().next() == Some(command.name())
+                                })
+                            })
+                        {
+                            let mut console_writer = ConsoleWriter::new();
+                            let args = clean_str[command.name().len()..].trim_start();
+                            command.execute(args, &mut console_writer);
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
                         } else {
                             let _ = self.write_bytes(b"Valid commands are: ");
                             let _ = self.write_bytes(VALID_COMMANDS_STR);
@@ -1145,6 +1216,27 @@ impl<
     }
 }
 
+impl<
+        'a,
+        const COMMAND_HISTORY_LEN: usize,
+        A: Alarm<'a>,
+        C: ProcessManagementCapability + ProcessStartCapability,
+    > ConsoleControl for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
+{
+    fn pause(&self) {
+        if self.mode.get() == ProcessConsoleState::Active {
+            self.mode.set(ProcessConsoleState::Paused);
+        }
+    }
+
+    fn resume(&self) {
+        if self.mode.get() == ProcessConsoleState::Paused {
+            self.mode.set(ProcessConsoleState::Active);
+            self.prompt();
+        }
+    }
+}
+
 impl<
         'a,
         const COMMAND_HISTORY_LEN: usize,
@@ -1199,6 +1291,10 @@ impl<
         _rcode: Result<(), ErrorCode>,
         error: uart::Error,
     ) {
+        if self.mode.get() == ProcessConsoleState::Paused {
+            let _ = self.uart.receive_buffer(read_buf, 1);
+            return;
+        }
         if error == uart::Error::None {
             match rx_len {
                 0 => debug!("ProcessConsole had read of 0 bytes"),
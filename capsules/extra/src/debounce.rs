@@ -0,0 +1,169 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Debounces a GPIO input connected to a mechanical switch.
+//!
+//! A switch contact bounces for a few milliseconds when it opens or closes,
+//! so the pin sees a burst of edges for a single press. [`DebouncedPin`]
+//! wraps the pin: on the first edge it stops listening to the pin for the
+//! debounce window, then reads the pin again and tells its client only if
+//! the level settled to a new value that matches the requested edges.
+//!
+//! `DebouncedPin` is itself an interrupt pin, so it can stand in for the raw
+//! pin, for example in the button capsule.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//! let sw2_alarm = static_init!(VirtualMuxAlarm<'static, Rtc>, VirtualMuxAlarm::new(mux_alarm));
+//! sw2_alarm.setup();
+//! let sw2 = static_init!(
+//!     DebouncedPin<'static, GPIOPin, VirtualMuxAlarm<'static, Rtc>>,
+//!     DebouncedPin::new(&gpio_port.pins[Pin::PTC12 as usize], sw2_alarm, 20)
+//! );
+//! gpio_port.pins[Pin::PTC12 as usize].set_client(sw2);
+//! sw2_alarm.set_alarm_client(sw2);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::gpio::{self, Configuration, FloatingState, InterruptEdge};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::cells::OptionalCell;
+
+pub struct DebouncedPin<'a, P: gpio::InterruptPin<'a>, A: Alarm<'a>> {
+    pin: &'a P,
+    alarm: &'a A,
+    window_ms: u32,
+    /// Edges the client asked for, `None` while interrupts are disabled.
+    edge: OptionalCell<InterruptEdge>,
+    /// Last settled level.
+    level: Cell<bool>,
+    client: OptionalCell<&'a dyn gpio::Client>,
+}
+
+impl<'a, P: gpio::InterruptPin<'a>, A: Alarm<'a>> DebouncedPin<'a, P, A> {
+    /// Debounce `pin` with a window of `window_ms` milliseconds.
+    pub fn new(pin: &'a P, alarm: &'a A, window_ms: u32) -> Self {
+        Self {
+            pin,
+            alarm,
+            window_ms,
+            edge: OptionalCell::empty(),
+            level: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<'a, P: gpio::InterruptPin<'a>, A: Alarm<'a>> gpio::Client for DebouncedPin<'a, P, A> {
+    fn fired(&self) {
+        // Ignore the rest of the bounce until the window ends.
+        self.pin.disable_interrupts();
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.window_ms));
+    }
+}
+
+impl<'a, P: gpio::InterruptPin<'a>, A: Alarm<'a>> AlarmClient for DebouncedPin<'a, P, A> {
+    fn alarm(&self) {
+        let Some(edge) = self.edge.get() else {
+            return;
+        };
+        self.pin.enable_interrupts(InterruptEdge::EitherEdge);
+
+        let level = self.pin.read();
+        if level == self.level.replace(level) {
+            return;
+        }
+        let wanted = match edge {
+            InterruptEdge::RisingEdge => level,
+            InterruptEdge::FallingEdge => !level,
+            InterruptEdge::EitherEdge => true,
+        };
+        if wanted {
+            self.client.map(|client| client.fired());
+        }
+    }
+}
+
+impl<'a, P: gpio::InterruptPin<'a>, A: Alarm<'a>> gpio::Interrupt<'a> for DebouncedPin<'a, P, A> {
+    fn set_client(&self, client: &'a dyn gpio::Client) {
+        self.client.set(client);
+    }
+
+    fn enable_interrupts(&self, mode: InterruptEdge) {
+        self.level.set(self.pin.read());
+        self.edge.set(mode);
+        // Both edges are needed to follow the level, whatever the client
+        // asked for.
+        self.pin.enable_interrupts(InterruptEdge::EitherEdge);
+    }
+
+    fn disable_interrupts(&self) {
+        self.edge.clear();
+        self.pin.disable_interrupts();
+        let _ = self.alarm.disarm();
+    }
+
+    fn is_pending(&self) -> bool {
+        self.pin.is_pending()
+    }
+}
+
+impl<'a, P: gpio::InterruptPin<'a>, A: Alarm<'a>> gpio::Input for DebouncedPin<'a, P, A> {
+    fn read(&self) -> bool {
+        self.pin.read()
+    }
+}
+
+impl<'a, P: gpio::InterruptPin<'a>, A: Alarm<'a>> gpio::Output for DebouncedPin<'a, P, A> {
+    fn set(&self) {
+        self.pin.set();
+    }
+
+    fn clear(&self) {
+        self.pin.clear();
+    }
+
+    fn toggle(&self) -> bool {
+        self.pin.toggle()
+    }
+}
+
+impl<'a, P: gpio::InterruptPin<'a>, A: Alarm<'a>> gpio::Configure for DebouncedPin<'a, P, A> {
+    fn configuration(&self) -> Configuration {
+        self.pin.configuration()
+    }
+
+    fn make_output(&self) -> Configuration {
+        self.pin.make_output()
+    }
+
+    fn disable_output(&self) -> Configuration {
+        self.pin.disable_output()
+    }
+
+    fn make_input(&self) -> Configuration {
+        self.pin.make_input()
+    }
+
+    fn disable_input(&self) -> Configuration {
+        self.pin.disable_input()
+    }
+
+    fn deactivate_to_low_power(&self) {
+        self.pin.deactivate_to_low_power();
+    }
+
+    fn set_floating_state(&self, state: FloatingState) {
+        self.pin.set_floating_state(state);
+    }
+
+    fn floating_state(&self) -> FloatingState {
+        self.pin.floating_state()
+    }
+}
@@ -34,6 +34,7 @@ pub mod crc;
 pub mod cycle_count;
 pub mod dac;
 pub mod date_time;
+pub mod debounce;
 pub mod debug_process_restart;
 pub mod dfrobot_rainfall_sensor;
 pub mod distance;
@@ -55,7 +56,9 @@ pub mod kv_driver;
 pub mod kv_store_permissions;
 pub mod l3gd20;
 pub mod led_matrix;
+pub mod lin;
 pub mod log;
+pub mod low_voltage;
 pub mod lpm013m126;
 pub mod lps22hb;
 pub mod lps25hb;
@@ -86,6 +89,8 @@ pub mod rf233_const;
 pub mod screen;
 pub mod screen_shared;
 pub mod sdcard;
+pub mod secure_boot;
+pub mod serial_number;
 pub mod servo;
 pub mod seven_segment;
 pub mod sg90;
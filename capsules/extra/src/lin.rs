@@ -0,0 +1,475 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Syscall driver capsule for a LIN node, master or slave.
+//!
+//! The application describes each frame it takes part in with a frame
+//! table entry: whether the node publishes the response, subscribes to it or
+//! ignores the frame, how many data bytes it has and which checksum model it
+//! uses. Whenever a header for a configured frame is seen, the capsule sends
+//! the latest published data or receives the response without waking the
+//! application, which only gets an upcall once the frame is complete.
+//!
+//! A master node additionally runs a schedule table: a list of slots, each
+//! sending the header of one frame and lasting a number of milliseconds.
+//! The schedule repeats until it is stopped. A response that is not complete
+//! by the end of its slot, or within the maximum response time of the LIN
+//! specification when no schedule runs, ends with a timeout error.
+//!
+//! Only one application can use the capsule at a time.
+//!
+//! Usage
+//! -----
+//!
+//! See `components::lin::LinComponent`.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::lin::{self, ChecksumModel, Error};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Lin as usize;
+
+/// Number of slots in the schedule table.
+pub const MAX_SLOTS: usize = 16;
+
+/// Number of frame identifiers.
+const NUM_FRAMES: usize = lin::MAX_ID as usize + 1;
+
+mod upcall {
+    /// A subscribed frame was received, or failed: `(id, len, error)`.
+    pub const FRAME_RECEIVED: usize = 0;
+    /// A published frame was sent, or failed: `(id, error, 0)`.
+    pub const FRAME_SENT: usize = 1;
+    /// A header was broken: `(error, 0, 0)`.
+    pub const BUS_ERROR: usize = 2;
+    pub const COUNT: u8 = 3;
+}
+
+mod ro_allow {
+    /// Data to publish, copied into the frame table by command 5.
+    pub const PUBLISH: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+mod rw_allow {
+    /// Data of the last received frame.
+    pub const RECEIVE: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Ignore,
+    Publish,
+    Subscribe,
+}
+
+#[derive(Clone, Copy)]
+struct Frame {
+    direction: Direction,
+    len: usize,
+    model: ChecksumModel,
+    data: [u8; lin::MAX_DATA_LEN],
+}
+
+impl Frame {
+    const IGNORED: Frame = Frame {
+        direction: Direction::Ignore,
+        len: 0,
+        model: ChecksumModel::Enhanced,
+        data: [0; lin::MAX_DATA_LEN],
+    };
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    id: u8,
+    ms: u32,
+}
+
+/// The upcall argument for `result`: 0 on success, otherwise one more than
+/// the index of the error in [`lin::Error`].
+fn error_code(result: Result<(), Error>) -> usize {
+    match result {
+        Ok(()) => 0,
+        Err(Error::Header) => 1,
+        Err(Error::Bit) => 2,
+        Err(Error::Checksum) => 3,
+        Err(Error::Framing) => 4,
+        Err(Error::Timeout) => 5,
+    }
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct LinDriver<'a, L: lin::Lin<'a>, A: Alarm<'a>> {
+    lin: &'a L,
+    alarm: &'a A,
+    buffer: TakeCell<'static, [u8]>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    processid: OptionalCell<ProcessId>,
+    baud_rate: Cell<u32>,
+    enabled: Cell<bool>,
+    frames: [Cell<Frame>; NUM_FRAMES],
+    schedule: [Cell<Option<Slot>>; MAX_SLOTS],
+    /// The slot being run, while the schedule runs.
+    slot: OptionalCell<usize>,
+    /// The frame whose response is being sent or received.
+    response: OptionalCell<u8>,
+}
+
+impl<'a, L: lin::Lin<'a>, A: Alarm<'a>> LinDriver<'a, L, A> {
+    /// `buffer` must hold at least [`lin::MAX_DATA_LEN`] bytes.
+    pub fn new(
+        lin: &'a L,
+        alarm: &'a A,
+        buffer: &'static mut [u8],
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> Self {
+        Self {
+            lin,
+            alarm,
+            buffer: TakeCell::new(buffer),
+            apps: grant,
+            processid: OptionalCell::empty(),
+            baud_rate: Cell::new(19200),
+            enabled: Cell::new(false),
+            frames: [const { Cell::new(Frame::IGNORED) }; NUM_FRAMES],
+            schedule: [const { Cell::new(None) }; MAX_SLOTS],
+            slot: OptionalCell::empty(),
+            response: OptionalCell::empty(),
+        }
+    }
+
+    fn schedule_upcall(&self, upcall: usize, data: (usize, usize, usize)) {
+        self.processid.map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data.schedule_upcall(upcall, data).ok();
+            });
+        });
+    }
+
+    fn is_valid_process(&self, processid: ProcessId) -> bool {
+        self.processid.map_or(true, |owning_process| {
+            self.apps
+                .enter(owning_process, |_, _| owning_process == processid)
+                .unwrap_or(true)
+        })
+    }
+
+    /// Configure frame `id` from the packed `arg`: the direction (0 ignore,
+    /// 1 publish, 2 subscribe) in bits 8 to 15, the data length in bits 16
+    /// to 23 and the checksum model (0 classic, 1 enhanced) in bits 24 to 31.
+    fn configure_frame(&self, arg: usize) -> Result<(), ErrorCode> {
+        let id = (arg & 0xFF) as u8;
+        let direction = match (arg >> 8) & 0xFF {
+            0 => Direction::Ignore,
+            1 => Direction::Publish,
+            2 => Direction::Subscribe,
+            _ => return Err(ErrorCode::INVAL),
+        };
+        let len = (arg >> 16) & 0xFF;
+        let model = match arg >> 24 {
+            0 => ChecksumModel::Classic,
+            1 => ChecksumModel::Enhanced,
+            _ => return Err(ErrorCode::INVAL),
+        };
+        if id > lin::MAX_ID {
+            return Err(ErrorCode::INVAL);
+        }
+        if direction != Direction::Ignore && !(1..=lin::MAX_DATA_LEN).contains(&len) {
+            return Err(ErrorCode::SIZE);
+        }
+        let frame = &self.frames[id as usize];
+        frame.set(Frame {
+            direction,
+            len,
+            model,
+            ..frame.get()
+        });
+        Ok(())
+    }
+
+    /// Copy the data of published frame `id` from the read-only allow.
+    fn publish(&self, processid: ProcessId, id: usize) -> Result<(), ErrorCode> {
+        let frame = self.frames.get(id).ok_or(ErrorCode::INVAL)?;
+        let mut value = frame.get();
+        if value.direction != Direction::Publish {
+            return Err(ErrorCode::INVAL);
+        }
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::PUBLISH)
+                    .and_then(|publish| {
+                        publish.enter(|data| {
+                            if data.len() < value.len {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            data[..value.len].copy_to_slice(&mut value.data[..value.len]);
+                            Ok(())
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        frame.set(value);
+        Ok(())
+    }
+
+    /// Set slot `index` of the schedule to send the header of frame `id`
+    /// and last `ms` milliseconds. A zero length clears the slot.
+    fn set_slot(&self, index: usize, id: u8, ms: u32) -> Result<(), ErrorCode> {
+        let slot = self.schedule.get(index).ok_or(ErrorCode::INVAL)?;
+        if id > lin::MAX_ID {
+            return Err(ErrorCode::INVAL);
+        }
+        slot.set((ms > 0).then_some(Slot { id, ms }));
+        Ok(())
+    }
+
+    /// Run the first slot of the schedule from `index` on, wrapping around.
+    fn run_slot(&self, index: usize) -> Result<(), ErrorCode> {
+        let (index, slot) = (0..MAX_SLOTS)
+            .map(|offset| (index + offset) % MAX_SLOTS)
+            .find_map(|index| self.schedule[index].get().map(|slot| (index, slot)))
+            .ok_or(ErrorCode::INVAL)?;
+        self.slot.set(index);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(slot.ms));
+        // A header that cannot go out because the bus is busy is skipped;
+        // the schedule goes on with the next slot.
+        let _ = self.lin.send_header(slot.id);
+        Ok(())
+    }
+
+    fn stop_schedule(&self) {
+        if self.slot.take().is_some() {
+            let _ = self.alarm.disarm();
+        }
+    }
+
+    /// Stop the response in progress and report it as timed out.
+    fn abort_response(&self) {
+        if let Some(buffer) = self.lin.abort_response() {
+            self.buffer.replace(buffer);
+        }
+        self.response.take().map(|id| {
+            let result = error_code(Err(Error::Timeout));
+            match self.frames[id as usize].get().direction {
+                Direction::Publish => {
+                    self.schedule_upcall(upcall::FRAME_SENT, (id as usize, result, 0))
+                }
+                _ => self.schedule_upcall(upcall::FRAME_RECEIVED, (id as usize, 0, result)),
+            }
+        });
+    }
+
+    /// Longest time a response of `len` data bytes may take: 40% more than
+    /// its nominal ten bit times per byte, checksum included.
+    fn response_timeout_us(&self, len: usize) -> u32 {
+        14 * (len as u32 + 1) * 1_000_000 / self.baud_rate.get()
+    }
+
+    /// Take back `buffer` after a response and return the frame it was for.
+    fn response_done(&self, buffer: &'static mut [u8]) -> Option<u8> {
+        self.buffer.replace(buffer);
+        if self.slot.is_none() {
+            let _ = self.alarm.disarm();
+        }
+        self.response.take()
+    }
+
+    fn disable(&self) -> Result<(), ErrorCode> {
+        self.stop_schedule();
+        if let Some(buffer) = self.lin.abort_response() {
+            self.buffer.replace(buffer);
+        }
+        self.response.clear();
+        let _ = self.alarm.disarm();
+        self.lin.disable()?;
+        self.enabled.set(false);
+        self.processid.clear();
+        Ok(())
+    }
+}
+
+impl<'a, L: lin::Lin<'a>, A: Alarm<'a>> SyscallDriver for LinDriver<'a, L, A> {
+    /// Control the LIN node.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Set the baud rate to `arg1` bit/s. The node must be disabled.
+    /// - `2`: Enable the node.
+    /// - `3`: Disable the node, stopping the schedule, and release it for
+    ///   other applications.
+    /// - `4`: Configure a frame. `arg1` holds the identifier in bits 0 to 7,
+    ///   the direction (0 ignore, 1 publish, 2 subscribe) in bits 8 to 15,
+    ///   the data length in bits 16 to 23 and the checksum model (0 classic,
+    ///   1 enhanced) in bits 24 to 31.
+    /// - `5`: Copy the data of published frame `arg1` from the read-only
+    ///   allow. It is sent with every following header for that frame.
+    /// - `6`: Set schedule slot `arg1 & 0xFF` to send the header of frame
+    ///   `arg1 >> 8` and last `arg2` milliseconds, or clear it if `arg2` is
+    ///   0.
+    /// - `7`: Start the schedule from its first slot (master only).
+    /// - `8`: Stop the schedule.
+    /// - `9`: Send the header of frame `arg1` once (master only). Fails with
+    ///   `BUSY` while the schedule runs.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+
+        if !self.is_valid_process(processid) {
+            return CommandReturn::failure(ErrorCode::RESERVE);
+        }
+        self.processid.set(processid);
+
+        let result = match command_num {
+            1 => u32::try_from(arg1)
+                .map_err(|_| ErrorCode::INVAL)
+                .and_then(|baud_rate| {
+                    self.lin.set_baud_rate(baud_rate)?;
+                    self.baud_rate.set(baud_rate);
+                    Ok(())
+                }),
+            2 => self.lin.enable().map(|()| self.enabled.set(true)),
+            3 => self.disable(),
+            4 => self.configure_frame(arg1),
+            5 => self.publish(processid, arg1),
+            6 => self.set_slot(arg1 & 0xFF, (arg1 >> 8) as u8, arg2 as u32),
+            7 => {
+                if !self.enabled.get() {
+                    Err(ErrorCode::OFF)
+                } else if self.slot.is_some() {
+                    Err(ErrorCode::ALREADY)
+                } else {
+                    self.run_slot(0)
+                }
+            }
+            8 => {
+                if self.slot.is_some() {
+                    self.stop_schedule();
+                    Ok(())
+                } else {
+                    Err(ErrorCode::ALREADY)
+                }
+            }
+            9 => {
+                if self.slot.is_some() {
+                    Err(ErrorCode::BUSY)
+                } else {
+                    u8::try_from(arg1)
+                        .map_err(|_| ErrorCode::INVAL)
+                        .and_then(|id| self.lin.send_header(id))
+                }
+            }
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+        CommandReturn::from(result)
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+impl<'a, L: lin::Lin<'a>, A: Alarm<'a>> lin::Client for LinDriver<'a, L, A> {
+    fn header_received(&self, id: u8) {
+        let frame = self.frames[id as usize].get();
+        if frame.direction == Direction::Ignore {
+            return;
+        }
+        let Some(buffer) = self.buffer.take() else {
+            return;
+        };
+        let result = if frame.direction == Direction::Publish {
+            buffer[..frame.len].copy_from_slice(&frame.data[..frame.len]);
+            self.lin.send_response(buffer, frame.len, frame.model)
+        } else {
+            self.lin.receive_response(buffer, frame.len, frame.model)
+        };
+        match result {
+            Ok(()) => {
+                self.response.set(id);
+                // While the schedule runs, the end of the slot bounds the
+                // response.
+                if self.slot.is_none() {
+                    let timeout = self.response_timeout_us(frame.len);
+                    self.alarm
+                        .set_alarm(self.alarm.now(), self.alarm.ticks_from_us(timeout));
+                }
+            }
+            Err((_, buffer)) => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+
+    fn bus_error(&self, error: Error) {
+        self.schedule_upcall(upcall::BUS_ERROR, (error_code(Err(error)), 0, 0));
+    }
+
+    fn response_sent(&self, buffer: &'static mut [u8], result: Result<(), Error>) {
+        if let Some(id) = self.response_done(buffer) {
+            self.schedule_upcall(upcall::FRAME_SENT, (id as usize, error_code(result), 0));
+        }
+    }
+
+    fn response_received(&self, buffer: &'static mut [u8], len: usize, result: Result<(), Error>) {
+        if result.is_ok() {
+            self.processid.map(|processid| {
+                let _ = self.apps.enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::RECEIVE)
+                        .and_then(|receive| {
+                            receive.mut_enter(|data| {
+                                let len = len.min(data.len());
+                                data[..len].copy_from_slice(&buffer[..len]);
+                            })
+                        })
+                });
+            });
+        }
+        if let Some(id) = self.response_done(buffer) {
+            self.schedule_upcall(
+                upcall::FRAME_RECEIVED,
+                (id as usize, len, error_code(result)),
+            );
+        }
+    }
+}
+
+impl<'a, L: lin::Lin<'a>, A: Alarm<'a>> AlarmClient for LinDriver<'a, L, A> {
+    fn alarm(&self) {
+        self.abort_response();
+        self.slot.map(|index| self.run_slot(index + 1));
+    }
+}
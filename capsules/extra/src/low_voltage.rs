@@ -0,0 +1,74 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Tells applications that the supply voltage is low, so they can save
+//! state before a brown-out.
+//!
+//! The board forwards the events of its voltage monitor with
+//! [`LowVoltage::notify`]; every application that subscribed to upcall 0
+//! receives them.
+//!
+//! ### Upcalls
+//!
+//! - `0`: A low-voltage event, as `(event, 0, 0)` where `event` is 0 for a
+//!   warning and 1 for a detect, the more severe of the two.
+
+use core::cell::Cell;
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::LowVoltage as usize;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The supply is dropping; operation continues for now.
+    Warning = 0,
+    /// The supply is close to where operation is no longer guaranteed.
+    Detected = 1,
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct LowVoltage {
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    events: Cell<u32>,
+}
+
+impl LowVoltage {
+    pub fn new(grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>) -> Self {
+        Self {
+            apps: grant,
+            events: Cell::new(0),
+        }
+    }
+
+    pub fn notify(&self, event: Event) {
+        self.events.set(self.events.get().saturating_add(1));
+        self.apps.each(|_, _, kernel_data| {
+            kernel_data.schedule_upcall(0, (event as usize, 0, 0)).ok();
+        });
+    }
+}
+
+impl SyscallDriver for LowVoltage {
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Number of low-voltage events since boot, in a u32.
+    fn command(&self, command_num: usize, _: usize, _: usize, _: ProcessId) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.events.get()),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,55 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Reports to userspace whether the kernel image was authenticated by the
+//! hardware secure boot on the last reset.
+//!
+//! The board reads the boot status from the chip while it boots and passes
+//! it to this capsule; the status cannot change until the next reset.
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SecureBoot as usize;
+
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+pub struct SecureBoot {
+    configured: bool,
+    authenticated: bool,
+}
+
+impl SecureBoot {
+    /// `configured` is whether the hardware is set up to check the kernel
+    /// image on reset, and `authenticated` whether that check passed on the
+    /// last reset.
+    pub fn new(configured: bool, authenticated: bool) -> SecureBoot {
+        SecureBoot {
+            configured,
+            authenticated,
+        }
+    }
+}
+
+impl SyscallDriver for SecureBoot {
+    /// Query the secure boot status.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Whether secure boot is configured, as 0 or 1 in a u32.
+    /// - `2`: Whether the kernel was authenticated on the last reset, as 0 or
+    ///   1 in a u32.
+    fn command(&self, command_num: usize, _: usize, _: usize, _: ProcessId) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.configured as u32),
+            2 => CommandReturn::success_u32(self.authenticated as u32),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
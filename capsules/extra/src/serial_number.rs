@@ -0,0 +1,96 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Provides the board's serial number to userspace.
+//!
+//! The serial number is a fixed string of bytes chosen by the board, usually
+//! a unique ID read from the chip, so that a board can be told apart from
+//! others without provisioning it.
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SerialNumber as usize;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+mod rw_allow {
+    /// Buffer the serial number is copied into.
+    pub const SERIAL_NUMBER: usize = 0;
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct SerialNumber {
+    serial_number: &'static [u8],
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+}
+
+impl SerialNumber {
+    pub fn new(
+        serial_number: &'static [u8],
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    ) -> SerialNumber {
+        SerialNumber {
+            serial_number,
+            apps: grant,
+        }
+    }
+}
+
+impl SyscallDriver for SerialNumber {
+    /// Read the serial number.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Length of the serial number in bytes, in a u32.
+    /// - `2`: Copy the serial number into the read-write allow buffer,
+    ///   returning the number of bytes copied in a u32. Fails with `SIZE` if
+    ///   the buffer is too short.
+    fn command(
+        &self,
+        command_num: usize,
+        _: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.serial_number.len() as u32),
+            2 => {
+                let result = self
+                    .apps
+                    .enter(processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readwrite_processbuffer(rw_allow::SERIAL_NUMBER)
+                            .and_then(|buffer| {
+                                buffer.mut_enter(|buffer| {
+                                    let len = self.serial_number.len();
+                                    if buffer.len() < len {
+                                        return Err(ErrorCode::SIZE);
+                                    }
+                                    buffer[..len].copy_from_slice(self.serial_number);
+                                    Ok(len)
+                                })
+                            })
+                            .unwrap_or(Err(ErrorCode::RESERVE))
+                    })
+                    .unwrap_or_else(|err| Err(err.into()));
+                match result {
+                    Ok(len) => CommandReturn::success_u32(len as u32),
+                    Err(err) => CommandReturn::failure(err),
+                }
+            }
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -260,7 +260,10 @@ impl Sha256Software<'_> {
             let mut s1 = self.right_rotate(message_schedule[i - 2], 17);
             s1 ^= self.right_rotate(message_schedule[i - 2], 19);
             s1 ^= message_schedule[i - 2] >> 10;
-            message_schedule[i] = message_schedule[i - 16] + s0 + message_schedule[i - 7] + s1;
+            message_schedule[i] = message_schedule[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(message_schedule[i - 7])
+                .wrapping_add(s1);
         }
 
         // Compression
@@ -271,12 +274,16 @@ impl Sha256Software<'_> {
                 ^ self.right_rotate(hashes[4], 25);
             let ch = (hashes[4] & hashes[5]) ^ ((!hashes[4]) & hashes[6]);
             let constant = ROUND_CONSTANTS[i];
-            let temp1 = hashes[7] + s1 + ch + constant + message_schedule[i];
+            let temp1 = hashes[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(constant)
+                .wrapping_add(message_schedule[i]);
             let s0 = self.right_rotate(hashes[0], 2)
                 ^ self.right_rotate(hashes[0], 13)
                 ^ self.right_rotate(hashes[0], 22);
             let maj = (hashes[0] & hashes[1]) ^ (hashes[0] & hashes[2]) ^ (hashes[1] & hashes[2]);
-            let temp2 = s0 + maj;
+            let temp2 = s0.wrapping_add(maj);
 
             hashes[7] = hashes[6];
             hashes[6] = hashes[5];
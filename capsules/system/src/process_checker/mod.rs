@@ -3,5 +3,6 @@
 // Copyright Tock Contributors 2024.
 
 pub mod basic;
+pub mod secure_boot;
 pub mod signature;
 pub mod tbf;
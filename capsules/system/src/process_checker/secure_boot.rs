@@ -0,0 +1,50 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Credentials checking policy that only runs applications when the kernel
+//! itself passed the hardware secure boot.
+//!
+//! Whether the kernel was authenticated is decided by the board at boot. If
+//! it was, every credential is passed to the wrapped policy. If it was not,
+//! credentials are required and none are accepted, so no process is started.
+
+use kernel::process_checker::{AppCredentialsPolicy, AppCredentialsPolicyClient};
+use kernel::ErrorCode;
+use tock_tbf::types::TbfFooterV2Credentials;
+
+pub struct AppCheckerSecureBoot<'a> {
+    kernel_authenticated: bool,
+    policy: &'a dyn AppCredentialsPolicy<'a>,
+}
+
+impl<'a> AppCheckerSecureBoot<'a> {
+    pub fn new(kernel_authenticated: bool, policy: &'a dyn AppCredentialsPolicy<'a>) -> Self {
+        Self {
+            kernel_authenticated,
+            policy,
+        }
+    }
+}
+
+impl<'a> AppCredentialsPolicy<'a> for AppCheckerSecureBoot<'a> {
+    fn require_credentials(&self) -> bool {
+        !self.kernel_authenticated || self.policy.require_credentials()
+    }
+
+    fn check_credentials(
+        &self,
+        credentials: TbfFooterV2Credentials,
+        binary: &'a [u8],
+    ) -> Result<(), (ErrorCode, TbfFooterV2Credentials, &'a [u8])> {
+        if self.kernel_authenticated {
+            self.policy.check_credentials(credentials, binary)
+        } else {
+            Err((ErrorCode::NOSUPPORT, credentials, binary))
+        }
+    }
+
+    fn set_client(&self, client: &'a dyn AppCredentialsPolicyClient<'a>) {
+        self.policy.set_client(client);
+    }
+}
@@ -8,9 +8,13 @@
 //! managing processes. For example, these policies control decisions such as
 //! whether a specific process should be restarted.
 
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Ticks};
 use kernel::process;
 use kernel::process::Process;
 use kernel::process::ProcessFaultPolicy;
+use kernel::utilities::cells::OptionalCell;
+use kernel::{Kernel, ProcessId};
 
 /// Simply panic the entire board if a process faults.
 pub struct PanicFaultPolicy {}
@@ -116,3 +120,147 @@ impl ProcessFaultPolicy for ThresholdRestartThenPanicFaultPolicy {
         }
     }
 }
+
+/// Receives every process fault that a [`BackoffRestartFaultPolicy`] handles,
+/// e.g. to keep a record of it.
+pub trait ProcessFaultRecorder {
+    /// `process` faulted. `restarting` is false if it has used up its
+    /// restarts and is stopped for good.
+    ///
+    /// This is called before the process is stopped, so its stored state
+    /// and memory are still those it faulted with.
+    fn record_fault(&self, process: &dyn Process, restarting: bool);
+}
+
+/// Implementation of `ProcessFaultPolicy` that restarts a faulted process
+/// after a delay which doubles with every restart, so a process that keeps
+/// faulting uses less and less of the system.
+///
+/// The process is stopped when it faults and restarted `base_delay_ms << n`
+/// ms later, where `n` is the number of times it was restarted before. Once
+/// it has been restarted `max_restarts` times the next fault stops it for
+/// good. `NUM_PROCS` must be the number of process slots of the board.
+pub struct BackoffRestartFaultPolicy<
+    'a,
+    A: Alarm<'a>,
+    C: ProcessManagementCapability,
+    const NUM_PROCS: usize,
+> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    capability: C,
+    base_delay_ms: u32,
+    max_restarts: usize,
+    /// Processes waiting for their restart, with the time they stopped and
+    /// how long they wait.
+    pending: [OptionalCell<(ProcessId, A::Ticks, A::Ticks)>; NUM_PROCS],
+    recorder: OptionalCell<&'a dyn ProcessFaultRecorder>,
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const NUM_PROCS: usize>
+    BackoffRestartFaultPolicy<'a, A, C, NUM_PROCS>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        capability: C,
+        base_delay_ms: u32,
+        max_restarts: usize,
+    ) -> Self {
+        Self {
+            kernel,
+            alarm,
+            capability,
+            base_delay_ms,
+            max_restarts,
+            pending: [const { OptionalCell::empty() }; NUM_PROCS],
+            recorder: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_recorder(&self, recorder: &'a dyn ProcessFaultRecorder) {
+        self.recorder.set(recorder);
+    }
+
+    /// Set the alarm for the earliest pending restart, if any.
+    fn arm(&self, now: A::Ticks) {
+        let next = self
+            .pending
+            .iter()
+            .filter_map(|pending| pending.get())
+            .map(|(_, stopped_at, delay)| {
+                delay.wrapping_sub(now.wrapping_sub(stopped_at).min(delay))
+            })
+            .min();
+        if let Some(remaining) = next {
+            self.alarm.set_alarm(now, remaining);
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const NUM_PROCS: usize> ProcessFaultPolicy
+    for BackoffRestartFaultPolicy<'a, A, C, NUM_PROCS>
+{
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        let restarts = process.get_restart_count();
+        let slot = self.pending.iter().find(|pending| pending.is_none());
+        let restarting = restarts < self.max_restarts && slot.is_some();
+        self.recorder
+            .map(|recorder| recorder.record_fault(process, restarting));
+
+        if let (true, Some(slot)) = (restarting, slot) {
+            let shift = u32::try_from(restarts).unwrap_or(u32::MAX);
+            let delay_ms = self
+                .base_delay_ms
+                .checked_shl(shift)
+                .filter(|delay| delay >> shift == self.base_delay_ms)
+                .unwrap_or(u32::MAX);
+            kernel::debug!(
+                "Process {} faulted, restarting in {} ms.",
+                process.get_process_name(),
+                delay_ms
+            );
+            let now = self.alarm.now();
+            slot.set((process.processid(), now, self.alarm.ticks_from_ms(delay_ms)));
+            self.arm(now);
+        } else {
+            kernel::debug!(
+                "Process {} faulted after {} restarts and was stopped.",
+                process.get_process_name(),
+                restarts
+            );
+        }
+        process::FaultAction::Stop
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const NUM_PROCS: usize> AlarmClient
+    for BackoffRestartFaultPolicy<'a, A, C, NUM_PROCS>
+{
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        for pending in self.pending.iter() {
+            let due = pending.map_or(false, |(_, stopped_at, delay)| {
+                now.wrapping_sub(stopped_at) >= delay
+            });
+            if !due {
+                continue;
+            }
+            if let Some((processid, _, _)) = pending.take() {
+                self.kernel.process_map_or_external(
+                    (),
+                    processid,
+                    |process| {
+                        // It may have been restarted or stopped from the
+                        // process console in the meantime.
+                        if process.get_state() == process::State::Faulted {
+                            process.try_restart(None);
+                        }
+                    },
+                    &self.capability,
+                );
+            }
+        }
+        self.arm(now);
+    }
+}
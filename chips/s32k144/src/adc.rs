@@ -0,0 +1,331 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! 12-bit SAR ADCs (ADC0 and ADC1) for the S32K144.
+//!
+//! Each ADC has sixteen conversion slots, `SC1A` to `SC1P`, whose results
+//! land in `RA` to `RP`. Software-started conversions only use the first
+//! slot and back `hil::adc::Adc`.
+//!
+//! In hardware-triggered mode, slot `n` is started by pre-trigger `n % 8` of
+//! channel `n / 8` of the matching PDB (PDB0 for ADC0, PDB1 for ADC1), with
+//! no CPU involvement. [`Adc::start_triggered`] fills the first slots with a
+//! list of channels, and once the last of them has been converted the
+//! [`TriggeredClient`] gets all results of that sequence. The PDB decides
+//! when each slot converts, see the `pdb` module.
+//!
+//! The ADCs are clocked from FIRCDIV2 divided by two (24 MHz, unless the
+//! board divides FIRCDIV2 further) and are calibrated the first time they
+//! are enabled. Samples are left-justified to 16 bits, as `hil::adc`
+//! expects.
+
+use core::cell::Cell;
+use kernel::hil;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadOnly, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::clock::{Clock, Peripheral};
+
+register_structs! {
+    pub AdcRegisters {
+        /// Status and Control registers 1, SC1A to SC1P
+        (0x00 => sc1: [ReadWrite<u32, SC1::Register>; 16]),
+        /// Configuration register 1
+        (0x40 => cfg1: ReadWrite<u32, CFG1::Register>),
+        /// Configuration register 2
+        (0x44 => cfg2: ReadWrite<u32, CFG2::Register>),
+        /// Data result registers, RA to RP
+        (0x48 => r: [ReadOnly<u32>; 16]),
+        /// Compare value registers
+        (0x88 => cv: [ReadWrite<u32>; 2]),
+        /// Status and Control register 2
+        (0x90 => sc2: ReadWrite<u32, SC2::Register>),
+        /// Status and Control register 3
+        (0x94 => sc3: ReadWrite<u32, SC3::Register>),
+        (0x98 => @END),
+    }
+}
+
+register_bitfields![u32,
+    SC1 [
+        /// Input channel select
+        ADCH OFFSET(0) NUMBITS(6) [
+            Disabled = 0x3F
+        ],
+        /// Interrupt enable
+        AIEN OFFSET(6) NUMBITS(1) [],
+        /// Conversion complete flag
+        COCO OFFSET(7) NUMBITS(1) []
+    ],
+    CFG1 [
+        /// Input clock select
+        ADICLK OFFSET(0) NUMBITS(2) [
+            Alt1 = 0
+        ],
+        /// Conversion mode selection
+        MODE OFFSET(2) NUMBITS(2) [
+            Bits8 = 0,
+            Bits12 = 1,
+            Bits10 = 2
+        ],
+        /// Clock divide select
+        ADIV OFFSET(5) NUMBITS(2) [
+            Div1 = 0,
+            Div2 = 1,
+            Div4 = 2,
+            Div8 = 3
+        ]
+    ],
+    CFG2 [
+        /// Sample time select, in ADC clocks minus one
+        SMPLTS OFFSET(0) NUMBITS(8) []
+    ],
+    SC2 [
+        /// Voltage reference selection
+        REFSEL OFFSET(0) NUMBITS(2) [
+            VrefhVrefl = 0,
+            ValtIn = 1
+        ],
+        /// DMA enable
+        DMAEN OFFSET(2) NUMBITS(1) [],
+        /// Conversion trigger select
+        ADTRG OFFSET(6) NUMBITS(1) [
+            Software = 0,
+            Hardware = 1
+        ],
+        /// Conversion active
+        ADACT OFFSET(7) NUMBITS(1) [],
+        /// Trigger status error flags
+        TRGSTERR OFFSET(24) NUMBITS(4) []
+    ],
+    SC3 [
+        /// Hardware average select
+        AVGS OFFSET(0) NUMBITS(2) [
+            Samples4 = 0,
+            Samples8 = 1,
+            Samples16 = 2,
+            Samples32 = 3
+        ],
+        /// Hardware average enable
+        AVGE OFFSET(2) NUMBITS(1) [],
+        /// Continuous conversion enable
+        ADCO OFFSET(3) NUMBITS(1) [],
+        /// Calibration
+        CAL OFFSET(7) NUMBITS(1) []
+    ]
+];
+
+pub const ADC0_BASE: StaticRef<AdcRegisters> =
+    unsafe { StaticRef::new(0x4003_B000 as *const AdcRegisters) };
+pub const ADC1_BASE: StaticRef<AdcRegisters> =
+    unsafe { StaticRef::new(0x4002_7000 as *const AdcRegisters) };
+
+/// Number of conversion slots, and so of channels in a triggered sequence.
+pub const NUM_SLOTS: usize = 16;
+
+/// Sample time in ADC clocks, enough for the EVB's potentiometer and
+/// similar high-impedance sources.
+const SAMPLE_TIME: u32 = 24;
+
+/// External ADC inputs.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(u32)]
+pub enum Channel {
+    AD0 = 0,
+    AD1 = 1,
+    AD2 = 2,
+    AD3 = 3,
+    AD4 = 4,
+    AD5 = 5,
+    AD6 = 6,
+    AD7 = 7,
+    AD8 = 8,
+    AD9 = 9,
+    AD10 = 10,
+    AD11 = 11,
+    AD12 = 12,
+    AD13 = 13,
+    AD14 = 14,
+    AD15 = 15,
+}
+
+/// Receives the results of hardware-triggered sequences.
+pub trait TriggeredClient {
+    /// All channels passed to `start_triggered` have been converted.
+    /// `samples[n]` is the result of the n-th channel.
+    fn samples_ready(&self, samples: &[u16]);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Idle,
+    Single,
+    /// Hardware-triggered, with this many slots in use.
+    Triggered(usize),
+}
+
+pub struct Adc<'a> {
+    registers: StaticRef<AdcRegisters>,
+    clock: Clock,
+    peripheral: Peripheral,
+    reference_mv: usize,
+    mode: Cell<Mode>,
+    calibrated: Cell<bool>,
+    client: OptionalCell<&'a dyn hil::adc::Client>,
+    triggered_client: OptionalCell<&'a dyn TriggeredClient>,
+}
+
+impl<'a> Adc<'a> {
+    /// Create an ADC whose VREFH is `reference_mv` millivolts.
+    pub const fn new(
+        registers: StaticRef<AdcRegisters>,
+        peripheral: Peripheral,
+        reference_mv: usize,
+    ) -> Self {
+        Self {
+            registers,
+            clock: Clock::new(),
+            peripheral,
+            reference_mv,
+            mode: Cell::new(Mode::Idle),
+            calibrated: Cell::new(false),
+            client: OptionalCell::empty(),
+            triggered_client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_triggered_client(&self, client: &'a dyn TriggeredClient) {
+        self.triggered_client.set(client);
+    }
+
+    /// Convert `channels` in order each time the PDB fires its
+    /// pre-triggers, until `stop_triggered` is called.
+    pub fn start_triggered(&self, channels: &[Channel]) -> Result<(), ErrorCode> {
+        if channels.is_empty() || channels.len() > NUM_SLOTS {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.mode.get() != Mode::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.enable();
+        // Switch to hardware triggers first, as writing SC1A while
+        // software-triggered starts a conversion.
+        self.registers.sc2.modify(SC2::ADTRG::Hardware);
+        let last = channels.len() - 1;
+        for (slot, channel) in channels.iter().enumerate() {
+            self.registers.sc1[slot]
+                .write(SC1::ADCH.val(*channel as u32) + SC1::AIEN.val(u32::from(slot == last)));
+        }
+        self.mode.set(Mode::Triggered(channels.len()));
+        Ok(())
+    }
+
+    /// Stop hardware-triggered conversions.
+    pub fn stop_triggered(&self) -> Result<(), ErrorCode> {
+        let Mode::Triggered(slots) = self.mode.get() else {
+            return Err(ErrorCode::OFF);
+        };
+        for slot in &self.registers.sc1[..slots] {
+            slot.write(SC1::ADCH::Disabled);
+        }
+        self.registers.sc2.modify(SC2::ADTRG::Software);
+        self.mode.set(Mode::Idle);
+        Ok(())
+    }
+
+    pub fn handle_interrupt(&self) {
+        match self.mode.get() {
+            Mode::Idle => {}
+            Mode::Single => {
+                // Reading the result clears COCO.
+                let sample = self.registers.r[0].get() as u16;
+                self.registers.sc1[0].write(SC1::ADCH::Disabled);
+                self.mode.set(Mode::Idle);
+                self.client.map(|client| client.sample_ready(sample << 4));
+            }
+            Mode::Triggered(slots) => {
+                let mut samples = [0u16; NUM_SLOTS];
+                for (sample, result) in samples.iter_mut().zip(&self.registers.r[..slots]) {
+                    *sample = (result.get() as u16) << 4;
+                }
+                self.triggered_client
+                    .map(|client| client.samples_ready(&samples[..slots]));
+            }
+        }
+    }
+
+    fn enable(&self) {
+        self.clock.enable_peripheral_clock(self.peripheral);
+        self.registers
+            .cfg1
+            .write(CFG1::ADICLK::Alt1 + CFG1::MODE::Bits12 + CFG1::ADIV::Div2);
+        self.registers.cfg2.write(CFG2::SMPLTS.val(SAMPLE_TIME - 1));
+        self.registers
+            .sc2
+            .write(SC2::REFSEL::VrefhVrefl + SC2::ADTRG::Software);
+        if !self.calibrated.get() {
+            self.calibrate();
+        }
+    }
+
+    /// Run the self-calibration, averaging 32 samples as the reference
+    /// manual recommends. The calibration values are kept by the ADC.
+    fn calibrate(&self) {
+        self.registers
+            .sc3
+            .write(SC3::CAL::SET + SC3::AVGE::SET + SC3::AVGS::Samples32);
+        while self.registers.sc3.is_set(SC3::CAL) {}
+        // Completion also sets COCO of the first slot, cleared by reading
+        // its result.
+        let _ = self.registers.r[0].get();
+        self.registers.sc3.set(0);
+        self.calibrated.set(true);
+    }
+}
+
+impl<'a> hil::adc::Adc<'a> for Adc<'a> {
+    type Channel = Channel;
+
+    fn sample(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
+        if self.mode.get() != Mode::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.enable();
+        self.mode.set(Mode::Single);
+        self.registers.sc1[0].write(SC1::ADCH.val(*channel as u32) + SC1::AIEN::SET);
+        Ok(())
+    }
+
+    fn sample_continuous(
+        &self,
+        _channel: &Self::Channel,
+        _frequency: u32,
+    ) -> Result<(), ErrorCode> {
+        // Periodic sampling is done with the PDB, see `start_triggered`.
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn stop_sampling(&self) -> Result<(), ErrorCode> {
+        if self.mode.get() == Mode::Single {
+            self.registers.sc1[0].write(SC1::ADCH::Disabled);
+            self.mode.set(Mode::Idle);
+        }
+        Ok(())
+    }
+
+    fn get_resolution_bits(&self) -> usize {
+        12
+    }
+
+    fn get_voltage_reference_mv(&self) -> Option<usize> {
+        Some(self.reference_mv)
+    }
+
+    fn set_client(&self, client: &'a dyn hil::adc::Client) {
+        self.client.set(client);
+    }
+}
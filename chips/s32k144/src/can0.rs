@@ -0,0 +1,879 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Classic CAN on FlexCAN0, for the S32K144.
+//!
+//! Received frames go through the legacy RX FIFO, which occupies message
+//! buffers 0 to 7 with its filter table, and is set up to accept every
+//! frame. Frames are sent one at a time from message buffer 8. The
+//! protocol engine runs from the system clock, 48 MHz on the FIRC.
+//!
+//! Bit timing and operation mode are stored by [`can::Configure`] and take
+//! effect on the next `enable`, or right away with
+//! [`FlexCan::apply_configuration`]. After bus-off the controller either
+//! rejoins the bus by itself or waits for [`FlexCan::recover`], see
+//! [`BusOffRecovery`].
+//!
+//! On the EVB, CAN0_RX and CAN0_TX are on PTE4 and PTE5 (ALT5).
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::can::{self, StandardBitTiming, STANDARD_CAN_PACKET_SIZE};
+use kernel::hil::gpio;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadOnly, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::clock::{Clock, Peripheral};
+
+register_structs! {
+    MessageBuffer {
+        (0x0 => cs: ReadWrite<u32, CS::Register>),
+        (0x4 => id: ReadWrite<u32, ID::Register>),
+        /// Payload, first byte in the most significant byte of `data[0]`.
+        (0x8 => data: [ReadWrite<u32>; 2]),
+        (0x10 => @END),
+    }
+}
+
+register_structs! {
+    FlexCanRegisters {
+        /// Module configuration register
+        (0x000 => mcr: ReadWrite<u32, MCR::Register>),
+        /// Control 1 register
+        (0x004 => ctrl1: ReadWrite<u32, CTRL1::Register>),
+        /// Free running timer
+        (0x008 => timer: ReadWrite<u32>),
+        (0x00C => _reserved0),
+        /// RX mailboxes global mask register
+        (0x010 => rxmgmask: ReadWrite<u32>),
+        /// RX buffer 14 mask register
+        (0x014 => rx14mask: ReadWrite<u32>),
+        /// RX buffer 15 mask register
+        (0x018 => rx15mask: ReadWrite<u32>),
+        /// Error counter
+        (0x01C => ecr: ReadWrite<u32, ECR::Register>),
+        /// Error and status 1 register
+        (0x020 => esr1: ReadWrite<u32, ESR1::Register>),
+        (0x024 => _reserved1),
+        /// Interrupt masks 1 register, one bit per message buffer
+        (0x028 => imask1: ReadWrite<u32>),
+        (0x02C => _reserved2),
+        /// Interrupt flags 1 register, one bit per message buffer
+        (0x030 => iflag1: ReadWrite<u32>),
+        /// Control 2 register
+        (0x034 => ctrl2: ReadWrite<u32, CTRL2::Register>),
+        (0x038 => _reserved3),
+        /// Legacy RX FIFO global mask register
+        (0x048 => rxfgmask: ReadWrite<u32>),
+        /// Legacy RX FIFO information register
+        (0x04C => rxfir: ReadOnly<u32>),
+        (0x050 => _reserved4),
+        /// Message buffers
+        (0x080 => mb: [MessageBuffer; NUM_MESSAGE_BUFFERS]),
+        (0x280 => _reserved5),
+        /// RX individual mask registers
+        (0x880 => rximr: [ReadWrite<u32>; NUM_MESSAGE_BUFFERS]),
+        (0x900 => @END),
+    }
+}
+
+register_bitfields![u32,
+    MCR [
+        /// Number of the last message buffer
+        MAXMB OFFSET(0) NUMBITS(7) [],
+        /// ID acceptance mode of the RX FIFO filter table
+        IDAM OFFSET(8) NUMBITS(2) [
+            FormatA = 0,
+            FormatB = 1,
+            FormatC = 2,
+            FormatD = 3
+        ],
+        /// Abort enable
+        AEN OFFSET(12) NUMBITS(1) [],
+        /// Individual RX masking and queue enable
+        IRMQ OFFSET(16) NUMBITS(1) [],
+        /// Self reception disable
+        SRXDIS OFFSET(17) NUMBITS(1) [],
+        /// Low-power mode acknowledge
+        LPMACK OFFSET(20) NUMBITS(1) [],
+        /// Warning interrupt enable
+        WRNEN OFFSET(21) NUMBITS(1) [],
+        /// Freeze mode acknowledge
+        FRZACK OFFSET(24) NUMBITS(1) [],
+        /// Soft reset
+        SOFTRST OFFSET(25) NUMBITS(1) [],
+        /// FlexCAN not ready
+        NOTRDY OFFSET(27) NUMBITS(1) [],
+        /// Halt FlexCAN
+        HALT OFFSET(28) NUMBITS(1) [],
+        /// Legacy RX FIFO enable
+        RFEN OFFSET(29) NUMBITS(1) [],
+        /// Freeze enable
+        FRZ OFFSET(30) NUMBITS(1) [],
+        /// Module disable
+        MDIS OFFSET(31) NUMBITS(1) []
+    ],
+    CTRL1 [
+        /// Propagation segment, minus one
+        PROPSEG OFFSET(0) NUMBITS(3) [],
+        /// Listen-only mode
+        LOM OFFSET(3) NUMBITS(1) [],
+        /// Bus-off recovery disable
+        BOFFREC OFFSET(6) NUMBITS(1) [],
+        /// RX warning interrupt mask
+        RWRNMSK OFFSET(10) NUMBITS(1) [],
+        /// TX warning interrupt mask
+        TWRNMSK OFFSET(11) NUMBITS(1) [],
+        /// Loopback mode
+        LPB OFFSET(12) NUMBITS(1) [],
+        /// Protocol engine clock source
+        CLKSRC OFFSET(13) NUMBITS(1) [
+            Oscillator = 0,
+            PeripheralClock = 1
+        ],
+        /// Error interrupt mask
+        ERRMSK OFFSET(14) NUMBITS(1) [],
+        /// Bus-off interrupt mask
+        BOFFMSK OFFSET(15) NUMBITS(1) [],
+        /// Phase segment 2, minus one
+        PSEG2 OFFSET(16) NUMBITS(3) [],
+        /// Phase segment 1, minus one
+        PSEG1 OFFSET(19) NUMBITS(3) [],
+        /// Resync jump width, minus one
+        RJW OFFSET(22) NUMBITS(2) [],
+        /// Prescaler division factor, minus one
+        PRESDIV OFFSET(24) NUMBITS(8) []
+    ],
+    ECR [
+        /// Transmit error counter
+        TXERRCNT OFFSET(0) NUMBITS(8) [],
+        /// Receive error counter
+        RXERRCNT OFFSET(8) NUMBITS(8) []
+    ],
+    ESR1 [
+        /// Error interrupt
+        ERRINT OFFSET(1) NUMBITS(1) [],
+        /// Bus-off interrupt
+        BOFFINT OFFSET(2) NUMBITS(1) [],
+        /// Fault confinement state
+        FLTCONF OFFSET(4) NUMBITS(2) [
+            ErrorActive = 0,
+            ErrorPassive = 1
+        ],
+        /// RX error warning, REC at 96 or more
+        RXWRN OFFSET(8) NUMBITS(1) [],
+        /// TX error warning, TEC at 96 or more
+        TXWRN OFFSET(9) NUMBITS(1) [],
+        /// Stuffing error
+        STFERR OFFSET(10) NUMBITS(1) [],
+        /// Form error
+        FRMERR OFFSET(11) NUMBITS(1) [],
+        /// CRC error
+        CRCERR OFFSET(12) NUMBITS(1) [],
+        /// Acknowledge error
+        ACKERR OFFSET(13) NUMBITS(1) [],
+        /// A dominant bit was read as recessive
+        BIT0ERR OFFSET(14) NUMBITS(1) [],
+        /// A recessive bit was read as dominant
+        BIT1ERR OFFSET(15) NUMBITS(1) [],
+        /// RX warning interrupt
+        RWRNINT OFFSET(16) NUMBITS(1) [],
+        /// TX warning interrupt
+        TWRNINT OFFSET(17) NUMBITS(1) [],
+        /// Bus-off done interrupt
+        BOFFDONEINT OFFSET(19) NUMBITS(1) []
+    ],
+    CTRL2 [
+        /// Number of legacy RX FIFO filters, in groups of eight
+        RFFN OFFSET(24) NUMBITS(4) [],
+        /// Bus-off done interrupt mask
+        BOFFDONEMSK OFFSET(30) NUMBITS(1) []
+    ],
+    CS [
+        /// Length of the payload
+        DLC OFFSET(16) NUMBITS(4) [],
+        /// Remote transmission request
+        RTR OFFSET(20) NUMBITS(1) [],
+        /// Extended identifier
+        IDE OFFSET(21) NUMBITS(1) [],
+        /// Substitute remote request, must be set for extended frames
+        SRR OFFSET(22) NUMBITS(1) [],
+        /// Message buffer code
+        CODE OFFSET(24) NUMBITS(4) [
+            RxInactive = 0b0000,
+            TxInactive = 0b1000,
+            TxData = 0b1100
+        ]
+    ],
+    ID [
+        /// Extended identifier
+        EXT OFFSET(0) NUMBITS(29) [],
+        /// Standard identifier
+        STD OFFSET(18) NUMBITS(11) []
+    ]
+];
+
+const NUM_MESSAGE_BUFFERS: usize = 32;
+
+const CAN0_BASE: StaticRef<FlexCanRegisters> =
+    unsafe { StaticRef::new(0x4002_4000 as *const FlexCanRegisters) };
+
+/// Frequency of the protocol engine clock, the system clock.
+const CLOCK_HZ: u32 = 48_000_000;
+
+/// Message buffer used for transmission, the first one after the RX FIFO
+/// and its eight filters.
+const TX_MB: usize = 8;
+
+/// IFLAG1 bits.
+const TX_FLAG: u32 = 1 << TX_MB;
+const RX_FIFO_AVAILABLE: u32 = 1 << 5;
+const RX_FIFO_WARNING: u32 = 1 << 6;
+const RX_FIFO_OVERFLOW: u32 = 1 << 7;
+const RX_FIFO_FLAGS: u32 = RX_FIFO_AVAILABLE | RX_FIFO_WARNING | RX_FIFO_OVERFLOW;
+
+/// What the controller does after going bus-off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusOffRecovery {
+    /// Rejoin the bus as soon as 128 sequences of 11 recessive bits were
+    /// seen, as the CAN specification allows.
+    Automatic,
+    /// Stay bus-off until [`FlexCan::recover`] is called.
+    Manual,
+}
+
+/// Told when the controller goes bus-off and when it is back on the bus.
+pub trait BusOffClient {
+    fn bus_off(&self);
+    fn recovered(&self);
+}
+
+#[derive(Clone, Copy)]
+enum ControllerEvent {
+    Enabled,
+    Disabled,
+}
+
+pub struct FlexCan<'a> {
+    registers: StaticRef<FlexCanRegisters>,
+    clock: Clock,
+    state: Cell<can::State>,
+    bit_timing: OptionalCell<can::BitTiming>,
+    operation_mode: OptionalCell<can::OperationMode>,
+    bus_off_recovery: Cell<BusOffRecovery>,
+    /// Transceiver standby input, driven high while the controller is off.
+    standby: OptionalCell<&'a dyn gpio::Output>,
+    bus_off_client: OptionalCell<&'a dyn BusOffClient>,
+    controller_client: OptionalCell<&'static dyn can::ControllerClient>,
+    transmit_client: OptionalCell<&'static dyn can::TransmitClient<STANDARD_CAN_PACKET_SIZE>>,
+    receive_client: OptionalCell<&'static dyn can::ReceiveClient<STANDARD_CAN_PACKET_SIZE>>,
+    tx_buffer: TakeCell<'static, [u8; STANDARD_CAN_PACKET_SIZE]>,
+    rx_buffer: TakeCell<'static, [u8; STANDARD_CAN_PACKET_SIZE]>,
+    /// Bus errors seen since the controller was enabled, and the last one.
+    error_count: Cell<u32>,
+    last_error: OptionalCell<can::Error>,
+    controller_event: OptionalCell<ControllerEvent>,
+    receive_stopped: Cell<bool>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> FlexCan<'a> {
+    pub fn new() -> Self {
+        Self {
+            registers: CAN0_BASE,
+            clock: Clock::new(),
+            state: Cell::new(can::State::Disabled),
+            bit_timing: OptionalCell::empty(),
+            operation_mode: OptionalCell::empty(),
+            bus_off_recovery: Cell::new(BusOffRecovery::Automatic),
+            standby: OptionalCell::empty(),
+            bus_off_client: OptionalCell::empty(),
+            controller_client: OptionalCell::empty(),
+            transmit_client: OptionalCell::empty(),
+            receive_client: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            rx_buffer: TakeCell::empty(),
+            error_count: Cell::new(0),
+            last_error: OptionalCell::empty(),
+            controller_event: OptionalCell::empty(),
+            receive_stopped: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Control the transceiver's standby input: high while the controller
+    /// is disabled, low while it is enabled. The pin must be an output.
+    pub fn set_standby_pin(&self, pin: &'a dyn gpio::Output) {
+        if self.state.get() == can::State::Disabled {
+            pin.set();
+        } else {
+            pin.clear();
+        }
+        self.standby.set(pin);
+    }
+
+    pub fn set_bus_off_client(&self, client: &'a dyn BusOffClient) {
+        self.bus_off_client.set(client);
+    }
+
+    /// Takes effect on the next `enable`, or with
+    /// [`FlexCan::apply_configuration`].
+    pub fn set_bus_off_recovery(&self, recovery: BusOffRecovery) {
+        self.bus_off_recovery.set(recovery);
+    }
+
+    pub fn bus_off_recovery(&self) -> BusOffRecovery {
+        self.bus_off_recovery.get()
+    }
+
+    /// Leave bus-off under [`BusOffRecovery::Manual`]. The controller
+    /// rejoins the bus after 128 sequences of 11 recessive bits.
+    pub fn recover(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != can::State::Error(can::Error::BusOff) {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.registers.ctrl1.modify(CTRL1::BOFFREC::CLEAR);
+        Ok(())
+    }
+
+    /// Apply the stored bit timing, operation mode and bus-off recovery to
+    /// the running controller. The controller leaves the bus for the time
+    /// it takes to finish the current frame.
+    pub fn apply_configuration(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == can::State::Disabled {
+            // Applied by the next `enable`.
+            return Ok(());
+        }
+        self.enter_freeze();
+        self.configure_bus()?;
+        if !matches!(self.operation_mode.get(), Some(can::OperationMode::Freeze)) {
+            self.leave_freeze();
+        }
+        Ok(())
+    }
+
+    /// The bitrate of the stored bit timing.
+    pub fn bitrate(&self) -> Option<u32> {
+        self.bit_timing.get().map(|timing| {
+            let quanta = u32::from(<Self as can::Configure>::SYNC_SEG)
+                + u32::from(timing.propagation)
+                + u32::from(timing.segment1)
+                + 1
+                + u32::from(timing.segment2)
+                + 1;
+            CLOCK_HZ / ((timing.baud_rate_prescaler + 1) * quanta)
+        })
+    }
+
+    /// The transmit and receive error counters, while enabled.
+    pub fn error_counters(&self) -> Option<(u8, u8)> {
+        if self.state.get() == can::State::Disabled {
+            return None;
+        }
+        let ecr = self.registers.ecr.extract();
+        Some((ecr.read(ECR::TXERRCNT) as u8, ecr.read(ECR::RXERRCNT) as u8))
+    }
+
+    /// The number of bus errors since the controller was enabled, and the
+    /// last one.
+    pub fn bus_errors(&self) -> (u32, Option<can::Error>) {
+        (self.error_count.get(), self.last_error.get())
+    }
+
+    fn enter_freeze(&self) {
+        self.registers.mcr.modify(MCR::FRZ::SET + MCR::HALT::SET);
+        while !self.registers.mcr.is_set(MCR::FRZACK) {}
+    }
+
+    fn leave_freeze(&self) {
+        self.registers
+            .mcr
+            .modify(MCR::FRZ::CLEAR + MCR::HALT::CLEAR);
+        while self.registers.mcr.is_set(MCR::FRZACK) {}
+        while self.registers.mcr.is_set(MCR::NOTRDY) {}
+    }
+
+    /// Program the bit timing, operation mode and interrupt masks. The
+    /// controller must be frozen.
+    fn configure_bus(&self) -> Result<(), ErrorCode> {
+        let timing = self.bit_timing.get().ok_or(ErrorCode::INVAL)?;
+        let mode = self.operation_mode.get().ok_or(ErrorCode::INVAL)?;
+        let mode_bits = match mode {
+            can::OperationMode::Loopback => CTRL1::LPB::SET + CTRL1::LOM::CLEAR,
+            can::OperationMode::Monitoring => CTRL1::LPB::CLEAR + CTRL1::LOM::SET,
+            can::OperationMode::Freeze | can::OperationMode::Normal => {
+                CTRL1::LPB::CLEAR + CTRL1::LOM::CLEAR
+            }
+        };
+        let recovery = match self.bus_off_recovery.get() {
+            BusOffRecovery::Automatic => CTRL1::BOFFREC::CLEAR,
+            BusOffRecovery::Manual => CTRL1::BOFFREC::SET,
+        };
+        // `propagation` is in time quanta, the other fields are already in
+        // register encoding, as produced by `StandardBitTiming`.
+        self.registers.ctrl1.write(
+            CTRL1::PRESDIV.val(timing.baud_rate_prescaler)
+                + CTRL1::RJW.val(timing.sync_jump_width)
+                + CTRL1::PSEG1.val(u32::from(timing.segment1))
+                + CTRL1::PSEG2.val(u32::from(timing.segment2))
+                + CTRL1::PROPSEG.val(u32::from(timing.propagation.saturating_sub(1)))
+                + CTRL1::CLKSRC::PeripheralClock
+                + CTRL1::BOFFMSK::SET
+                + CTRL1::ERRMSK::SET
+                + CTRL1::TWRNMSK::SET
+                + CTRL1::RWRNMSK::SET
+                + mode_bits
+                + recovery,
+        );
+        // Frames sent in loopback are only seen through self reception.
+        if matches!(mode, can::OperationMode::Loopback) {
+            self.registers.mcr.modify(MCR::SRXDIS::CLEAR);
+        } else {
+            self.registers.mcr.modify(MCR::SRXDIS::SET);
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Result<(), ErrorCode> {
+        self.clock.enable_peripheral_clock(Peripheral::CAN0);
+        let registers = self.registers;
+
+        // The clock source can only be selected while the module is
+        // disabled.
+        registers.mcr.modify(MCR::MDIS::SET);
+        while !registers.mcr.is_set(MCR::LPMACK) {}
+        registers.ctrl1.modify(CTRL1::CLKSRC::PeripheralClock);
+        registers.mcr.modify(MCR::MDIS::CLEAR);
+        while registers.mcr.is_set(MCR::LPMACK) {}
+        registers.mcr.modify(MCR::SOFTRST::SET);
+        while registers.mcr.is_set(MCR::SOFTRST) {}
+        self.enter_freeze();
+
+        // The message buffer RAM has ECC and must be written before it is
+        // read. All-zero filters with a zero global mask let the RX FIFO
+        // accept every frame.
+        for mb in registers.mb.iter() {
+            mb.cs.set(0);
+            mb.id.set(0);
+            mb.data[0].set(0);
+            mb.data[1].set(0);
+        }
+        for mask in registers.rximr.iter() {
+            mask.set(0);
+        }
+        registers.rxmgmask.set(0);
+        registers.rx14mask.set(0);
+        registers.rx15mask.set(0);
+        registers.rxfgmask.set(0);
+        registers.mcr.modify(
+            MCR::RFEN::SET
+                + MCR::IRMQ::CLEAR
+                + MCR::WRNEN::SET
+                + MCR::AEN::CLEAR
+                + MCR::IDAM::FormatA
+                + MCR::MAXMB.val(TX_MB as u32),
+        );
+        registers
+            .ctrl2
+            .modify(CTRL2::RFFN.val(0) + CTRL2::BOFFDONEMSK::SET);
+        if let Err(error) = self.configure_bus() {
+            self.stop();
+            return Err(error);
+        }
+        registers.mb[TX_MB].cs.write(CS::CODE::TxInactive);
+        registers.iflag1.set(u32::MAX);
+        let rx = if self.rx_buffer.is_some() {
+            RX_FIFO_FLAGS
+        } else {
+            0
+        };
+        registers.imask1.set(TX_FLAG | rx);
+
+        if !matches!(self.operation_mode.get(), Some(can::OperationMode::Freeze)) {
+            self.leave_freeze();
+        }
+        self.standby.map(|pin| pin.clear());
+        Ok(())
+    }
+
+    fn stop(&self) {
+        self.standby.map(|pin| pin.set());
+        self.registers.imask1.set(0);
+        self.enter_freeze();
+        self.registers.mcr.modify(MCR::MDIS::SET);
+        while !self.registers.mcr.is_set(MCR::LPMACK) {}
+        self.clock.disable_peripheral_clock(Peripheral::CAN0);
+    }
+
+    /// Give back the frame being sent, without sending it.
+    fn abort_transmission(&self, error: can::Error) {
+        if let Some(buffer) = self.tx_buffer.take() {
+            self.registers.mb[TX_MB].cs.write(CS::CODE::TxInactive);
+            self.registers.iflag1.set(TX_FLAG);
+            self.transmit_client
+                .map(move |client| client.transmit_complete(Err(error), buffer));
+        }
+    }
+
+    fn update_state(&self, esr1: u32) {
+        if self.state.get() == can::State::Disabled {
+            return;
+        }
+        let esr1 =
+            kernel::utilities::registers::LocalRegisterCopy::<u32, ESR1::Register>::new(esr1);
+        let state = match esr1.read_as_enum(ESR1::FLTCONF) {
+            Some(ESR1::FLTCONF::Value::ErrorActive) => {
+                if esr1.is_set(ESR1::TXWRN) || esr1.is_set(ESR1::RXWRN) {
+                    can::State::Error(can::Error::Warning)
+                } else {
+                    can::State::Running
+                }
+            }
+            Some(ESR1::FLTCONF::Value::ErrorPassive) => can::State::Error(can::Error::Passive),
+            None => can::State::Error(can::Error::BusOff),
+        };
+        if self.state.replace(state) != state {
+            self.controller_client
+                .map(|client| client.state_changed(state));
+        }
+    }
+
+    /// The CAN0 bus-off, warning and error interrupts.
+    pub fn handle_status_interrupt(&self) {
+        let esr1 = self.registers.esr1.extract();
+        // The interrupt flags are write-1-to-clear and the bus error bits
+        // clear on read; the rest of the register is read-only.
+        self.registers.esr1.set(esr1.get());
+
+        if esr1.is_set(ESR1::ERRINT) {
+            let error = if esr1.is_set(ESR1::BIT1ERR) {
+                Some(can::Error::BitRecessive)
+            } else if esr1.is_set(ESR1::BIT0ERR) {
+                Some(can::Error::BitDominant)
+            } else if esr1.is_set(ESR1::ACKERR) {
+                Some(can::Error::Ack)
+            } else if esr1.is_set(ESR1::CRCERR) {
+                Some(can::Error::Crc)
+            } else if esr1.is_set(ESR1::FRMERR) {
+                Some(can::Error::Form)
+            } else if esr1.is_set(ESR1::STFERR) {
+                Some(can::Error::Stuff)
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                self.error_count.set(self.error_count.get().wrapping_add(1));
+                self.last_error.set(error);
+            }
+        }
+
+        self.update_state(esr1.get());
+        if esr1.is_set(ESR1::BOFFINT) {
+            self.abort_transmission(can::Error::BusOff);
+            self.bus_off_client.map(|client| client.bus_off());
+        }
+        if esr1.is_set(ESR1::BOFFDONEINT) {
+            if self.bus_off_recovery.get() == BusOffRecovery::Manual {
+                self.registers.ctrl1.modify(CTRL1::BOFFREC::SET);
+            }
+            self.bus_off_client.map(|client| client.recovered());
+        }
+    }
+
+    /// The CAN0 message buffer 0-15 interrupt.
+    pub fn handle_message_interrupt(&self) {
+        let flags = self.registers.iflag1.get() & self.registers.imask1.get();
+        if flags & TX_FLAG != 0 {
+            self.registers.iflag1.set(TX_FLAG);
+            if let Some(buffer) = self.tx_buffer.take() {
+                self.transmit_client
+                    .map(move |client| client.transmit_complete(Ok(()), buffer));
+            }
+        }
+        if flags & (RX_FIFO_WARNING | RX_FIFO_OVERFLOW) != 0 {
+            self.registers
+                .iflag1
+                .set(flags & (RX_FIFO_WARNING | RX_FIFO_OVERFLOW));
+        }
+        if flags & RX_FIFO_AVAILABLE != 0 {
+            while self.registers.iflag1.get() & RX_FIFO_AVAILABLE != 0 {
+                self.receive_frame();
+                // Clearing the flag moves the next frame to the output.
+                self.registers.iflag1.set(RX_FIFO_AVAILABLE);
+            }
+        }
+        self.update_state(self.registers.esr1.get());
+    }
+
+    /// Hand the frame at the RX FIFO output to the receive client.
+    fn receive_frame(&self) {
+        let output = &self.registers.mb[0];
+        let cs = output.cs.extract();
+        let id = if cs.is_set(CS::IDE) {
+            can::Id::Extended(output.id.read(ID::EXT))
+        } else {
+            can::Id::Standard(output.id.read(ID::STD) as u16)
+        };
+        let len = core::cmp::min(cs.read(CS::DLC) as usize, STANDARD_CAN_PACKET_SIZE);
+        let high = output.data[0].get().to_be_bytes();
+        let low = output.data[1].get().to_be_bytes();
+        self.rx_buffer.map(|buffer| {
+            buffer[..4].copy_from_slice(&high);
+            buffer[4..].copy_from_slice(&low);
+            self.receive_client
+                .map(|client| client.message_received(id, buffer, len, Ok(())));
+        });
+    }
+}
+
+impl DeferredCallClient for FlexCan<'_> {
+    fn handle_deferred_call(&self) {
+        match self.controller_event.take() {
+            Some(ControllerEvent::Enabled) => {
+                self.controller_client.map(|client| {
+                    client.state_changed(self.state.get());
+                    client.enabled(Ok(()));
+                });
+            }
+            Some(ControllerEvent::Disabled) => {
+                if let Some(buffer) = self.tx_buffer.take() {
+                    self.transmit_client.map(move |client| {
+                        client.transmit_complete(Err(can::Error::SetBySoftware), buffer)
+                    });
+                }
+                self.controller_client.map(|client| {
+                    client.state_changed(can::State::Disabled);
+                    client.disabled(Ok(()));
+                });
+            }
+            None => {}
+        }
+        if self.receive_stopped.replace(false) {
+            if let Some(buffer) = self.rx_buffer.take() {
+                self.receive_client
+                    .map(move |client| client.stopped(buffer));
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl can::Configure for FlexCan<'_> {
+    const MIN_BIT_TIMINGS: can::BitTiming = can::BitTiming {
+        segment1: 1,
+        segment2: 2,
+        propagation: 1,
+        sync_jump_width: 1,
+        baud_rate_prescaler: 1,
+    };
+
+    const MAX_BIT_TIMINGS: can::BitTiming = can::BitTiming {
+        segment1: 8,
+        segment2: 8,
+        propagation: 8,
+        sync_jump_width: 4,
+        baud_rate_prescaler: 256,
+    };
+
+    fn set_bitrate(&self, bitrate: u32) -> Result<(), ErrorCode> {
+        let bit_timing = Self::bit_timing_for_bitrate(CLOCK_HZ, bitrate)?;
+        self.set_bit_timing(bit_timing)
+    }
+
+    fn set_bit_timing(&self, bit_timing: can::BitTiming) -> Result<(), ErrorCode> {
+        self.bit_timing.set(bit_timing);
+        Ok(())
+    }
+
+    fn set_operation_mode(&self, mode: can::OperationMode) -> Result<(), ErrorCode> {
+        self.operation_mode.set(mode);
+        Ok(())
+    }
+
+    fn get_bit_timing(&self) -> Result<can::BitTiming, ErrorCode> {
+        self.bit_timing.get().ok_or(ErrorCode::INVAL)
+    }
+
+    fn get_operation_mode(&self) -> Result<can::OperationMode, ErrorCode> {
+        self.operation_mode.get().ok_or(ErrorCode::INVAL)
+    }
+
+    fn set_automatic_retransmission(&self, automatic: bool) -> Result<(), ErrorCode> {
+        // FlexCAN always retransmits until the frame is sent or aborted.
+        if automatic {
+            Ok(())
+        } else {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+
+    fn set_wake_up(&self, wake_up: bool) -> Result<(), ErrorCode> {
+        if wake_up {
+            Err(ErrorCode::NOSUPPORT)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn get_automatic_retransmission(&self) -> Result<bool, ErrorCode> {
+        Ok(true)
+    }
+
+    fn get_wake_up(&self) -> Result<bool, ErrorCode> {
+        Ok(false)
+    }
+
+    fn receive_fifo_count(&self) -> usize {
+        1
+    }
+}
+
+impl can::Controller for FlexCan<'_> {
+    fn set_client(&self, client: Option<&'static dyn can::ControllerClient>) {
+        match client {
+            Some(client) => self.controller_client.set(client),
+            None => self.controller_client.clear(),
+        }
+    }
+
+    fn enable(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != can::State::Disabled {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.controller_event.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.start()?;
+        self.error_count.set(0);
+        self.last_error.clear();
+        self.state.set(can::State::Running);
+        self.controller_event.set(ControllerEvent::Enabled);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn disable(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == can::State::Disabled {
+            return Err(ErrorCode::OFF);
+        }
+        if self.controller_event.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.stop();
+        self.state.set(can::State::Disabled);
+        self.controller_event.set(ControllerEvent::Disabled);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn get_state(&self) -> Result<can::State, ErrorCode> {
+        Ok(self.state.get())
+    }
+}
+
+impl can::Transmit<STANDARD_CAN_PACKET_SIZE> for FlexCan<'_> {
+    fn set_client(
+        &self,
+        client: Option<&'static dyn can::TransmitClient<STANDARD_CAN_PACKET_SIZE>>,
+    ) {
+        match client {
+            Some(client) => self.transmit_client.set(client),
+            None => self.transmit_client.clear(),
+        }
+    }
+
+    fn send(
+        &self,
+        id: can::Id,
+        buffer: &'static mut [u8; STANDARD_CAN_PACKET_SIZE],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8; STANDARD_CAN_PACKET_SIZE])> {
+        match self.state.get() {
+            can::State::Disabled | can::State::Error(can::Error::BusOff) => {
+                return Err((ErrorCode::OFF, buffer))
+            }
+            can::State::Running | can::State::Error(_) => {}
+        }
+        if self.tx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if len > STANDARD_CAN_PACKET_SIZE {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+
+        let mb = &self.registers.mb[TX_MB];
+        mb.cs.write(CS::CODE::TxInactive);
+        let format = match id {
+            can::Id::Standard(id) => {
+                mb.id.write(ID::STD.val(u32::from(id)));
+                CS::IDE::CLEAR + CS::SRR::CLEAR
+            }
+            can::Id::Extended(id) => {
+                mb.id.write(ID::EXT.val(id));
+                CS::IDE::SET + CS::SRR::SET
+            }
+        };
+        mb.data[0].set(u32::from_be_bytes([
+            buffer[0], buffer[1], buffer[2], buffer[3],
+        ]));
+        mb.data[1].set(u32::from_be_bytes([
+            buffer[4], buffer[5], buffer[6], buffer[7],
+        ]));
+        self.tx_buffer.replace(buffer);
+        mb.cs
+            .write(CS::CODE::TxData + CS::DLC.val(len as u32) + CS::RTR::CLEAR + format);
+        Ok(())
+    }
+}
+
+impl can::Receive<STANDARD_CAN_PACKET_SIZE> for FlexCan<'_> {
+    fn set_client(
+        &self,
+        client: Option<&'static dyn can::ReceiveClient<STANDARD_CAN_PACKET_SIZE>>,
+    ) {
+        match client {
+            Some(client) => self.receive_client.set(client),
+            None => self.receive_client.clear(),
+        }
+    }
+
+    fn start_receive_process(
+        &self,
+        buffer: &'static mut [u8; STANDARD_CAN_PACKET_SIZE],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; STANDARD_CAN_PACKET_SIZE])> {
+        if self.state.get() == can::State::Disabled {
+            return Err((ErrorCode::OFF, buffer));
+        }
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        self.rx_buffer.replace(buffer);
+        self.registers
+            .imask1
+            .set(self.registers.imask1.get() | RX_FIFO_FLAGS);
+        Ok(())
+    }
+
+    fn stop_receive(&self) -> Result<(), ErrorCode> {
+        if self.rx_buffer.is_none() || self.receive_stopped.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.state.get() != can::State::Disabled {
+            self.registers
+                .imask1
+                .set(self.registers.imask1.get() & !RX_FIFO_FLAGS);
+        }
+        // The buffer may be lent to the client right now, so it is given
+        // back from a deferred call.
+        self.receive_stopped.set(true);
+        self.deferred_call.set();
+        Ok(())
+    }
+}
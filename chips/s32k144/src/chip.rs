@@ -4,6 +4,7 @@
 
 use core::fmt::Write;
 use cortexm4f::{nvic, CortexM4F, CortexMVariant};
+use kernel::debug;
 use kernel::platform::chip::InterruptService;
 
 pub struct S32K144<'a, I: InterruptService + 'a> {
@@ -28,29 +29,61 @@ impl<'a, I: InterruptService + 'a> S32K144<'a, I> {
 /// should not be used or imported, and a modified version should be
 /// constructed manually in main.rs.
 pub struct S32K144DefaultPeripherals<'a> {
+    pub adc0: crate::adc::Adc<'a>,
+    pub adc1: crate::adc::Adc<'a>,
+    pub can0: crate::can0::FlexCan<'a>,
+    pub pdb0: crate::pdb::Pdb<'a>,
+    pub pdb1: crate::pdb::Pdb<'a>,
+    pub trgmux: crate::trgmux::Trgmux,
     pub pwr_clk: crate::power::Power<'a>,
     pub rtc: crate::rtc::Rtc<'a>,
     pub timer0: crate::lpit0::TimerAlarm<'a>,
     pub timer1: crate::lptmr0::TimerAlarm<'a>,
     pub timer2: crate::lpit0::Lpit0Timer<'a>,
     pub lpuart0: crate::lpuart0::Lpuart0<'a>,
+    pub lin: crate::lin::Lin<'a>,
     pub nvmc: crate::nvmc::Ftfc,
+    pub erm: crate::erm::Erm<'a>,
+    pub eim: crate::eim::Eim,
+    pub rcm: crate::rcm::Rcm,
     pub clock: crate::clock::Clock,
+    pub lmem: crate::lmem::Lmem,
+    pub cmp0: crate::cmp::Cmp<'a>,
+    pub crc: crate::crc::Crc<'a>,
+    pub flexio: crate::flexio::FlexIo<'a>,
     pub pwm0: crate::ftm0::Pwm,
-    pub gpio_port: crate::gpio::Port<'a, 48>,
+    pub gpio_port: crate::gpio::Port<'a, { crate::gpio::NUM_PINS }>,
 }
 
 impl S32K144DefaultPeripherals<'_> {
     pub fn new() -> Self {
         Self {
+            // Default to the EVB's 5 V VREFH.
+            adc0: crate::adc::Adc::new(crate::adc::ADC0_BASE, crate::clock::Peripheral::ADC0, 5000),
+            adc1: crate::adc::Adc::new(crate::adc::ADC1_BASE, crate::clock::Peripheral::ADC1, 5000),
+            can0: crate::can0::FlexCan::new(),
+            pdb0: crate::pdb::Pdb::new(crate::pdb::PDB0_BASE, crate::clock::Peripheral::PDB0),
+            pdb1: crate::pdb::Pdb::new(crate::pdb::PDB1_BASE, crate::clock::Peripheral::PDB1),
+            trgmux: crate::trgmux::Trgmux::new(),
             pwr_clk: crate::power::Power::new(),
             rtc: crate::rtc::Rtc::new(),
             timer0: crate::lpit0::TimerAlarm::new(),
             timer1: crate::lptmr0::TimerAlarm::new(),
             timer2: crate::lpit0::Lpit0Timer::new(),
             lpuart0: crate::lpuart0::Lpuart0::new(crate::lpuart0::LPUART0_BASE),
+            lin: crate::lin::Lin::new(
+                crate::lpuart0::LPUART2_BASE,
+                crate::clock::Peripheral::LPUART2,
+            ),
             nvmc: crate::nvmc::Ftfc::new(),
+            erm: crate::erm::Erm::new(),
+            eim: crate::eim::Eim::new(),
+            rcm: crate::rcm::Rcm::new(),
             clock: crate::clock::Clock::new(),
+            lmem: crate::lmem::Lmem::new(),
+            cmp0: crate::cmp::Cmp::new(),
+            crc: crate::crc::Crc::new(),
+            flexio: crate::flexio::FlexIo::new(),
             pwm0: crate::ftm0::Pwm::new(),
             gpio_port: crate::gpio::s32k144_gpio_create(),
         }
@@ -58,6 +91,44 @@ impl S32K144DefaultPeripherals<'_> {
     // Necessary for setting up circular dependencies
     pub fn init(&'static self) {
         kernel::deferred_call::DeferredCallClient::register(&self.nvmc);
+        kernel::deferred_call::DeferredCallClient::register(&self.crc);
+        kernel::deferred_call::DeferredCallClient::register(&self.can0);
+        // The FTFC signals command completion through its interrupt.
+        unsafe { nvic::Nvic::new(crate::peripheral_interrupts::FTFC).enable() };
+        unsafe { nvic::Nvic::new(crate::peripheral_interrupts::CMP0).enable() };
+        for interrupt in [
+            crate::peripheral_interrupts::ADC0,
+            crate::peripheral_interrupts::ADC1,
+            crate::peripheral_interrupts::PDB0,
+            crate::peripheral_interrupts::PDB1,
+            crate::peripheral_interrupts::FLEXIO,
+            crate::peripheral_interrupts::LPUART2,
+            crate::peripheral_interrupts::ERM_SINGLE_FAULT,
+            crate::peripheral_interrupts::ERM_DOUBLE_FAULT,
+            crate::peripheral_interrupts::FTFC_FAULT,
+            crate::peripheral_interrupts::POWER_CLOCK,
+            crate::peripheral_interrupts::LPUART0,
+            crate::peripheral_interrupts::RTC,
+            crate::peripheral_interrupts::RTC_SECONDS,
+            crate::peripheral_interrupts::LPIT0_CH0,
+            crate::peripheral_interrupts::LPTMR0,
+            crate::peripheral_interrupts::PORTA,
+            crate::peripheral_interrupts::PORTB,
+            crate::peripheral_interrupts::PORTC,
+            crate::peripheral_interrupts::PORTD,
+            crate::peripheral_interrupts::PORTE,
+            crate::peripheral_interrupts::FTM0_CH0_CH1,
+            crate::peripheral_interrupts::FTM0_CH2_CH3,
+            crate::peripheral_interrupts::FTM0_CH4_CH5,
+            crate::peripheral_interrupts::FTM0_CH6_CH7,
+            crate::peripheral_interrupts::FTM0_FAULT,
+            crate::peripheral_interrupts::FTM0_OVF_RELOAD,
+            crate::peripheral_interrupts::CAN0_ORED,
+            crate::peripheral_interrupts::CAN0_ERROR,
+            crate::peripheral_interrupts::CAN0_ORED_0_15_MB,
+        ] {
+            unsafe { nvic::Nvic::new(interrupt).enable() };
+        }
     }
 }
 impl kernel::platform::chip::InterruptService for S32K144DefaultPeripherals<'_> {
@@ -65,10 +136,44 @@ impl kernel::platform::chip::InterruptService for S32K144DefaultPeripherals<'_>
         match interrupt {
             crate::peripheral_interrupts::POWER_CLOCK => self.pwr_clk.handle_interrupt(),
             crate::peripheral_interrupts::RTC => self.rtc.handle_interrupt(),
-            crate::peripheral_interrupts::TIMER0 => self.timer0.handle_interrupt(),
-            crate::peripheral_interrupts::TIMER1 => self.timer1.handle_interrupt(),
-            crate::peripheral_interrupts::TIMER2 => self.timer2.handle_interrupt(),
+            crate::peripheral_interrupts::RTC_SECONDS => self.rtc.handle_seconds_interrupt(),
+            // Both LPIT drivers run on channel 0.
+            crate::peripheral_interrupts::LPIT0_CH0 => {
+                self.timer0.handle_interrupt();
+                self.timer2.handle_interrupt();
+            }
+            crate::peripheral_interrupts::LPTMR0 => self.timer1.handle_interrupt(),
             crate::peripheral_interrupts::LPUART0 => self.lpuart0.handle_interrupt(),
+            crate::peripheral_interrupts::LPUART2 => self.lin.handle_interrupt(),
+            crate::peripheral_interrupts::FTFC => self.nvmc.handle_interrupt(),
+            crate::peripheral_interrupts::FTFC_FAULT => {
+                if self.nvmc.take_double_bit_fault() {
+                    self.erm.flash_fault();
+                }
+            }
+            crate::peripheral_interrupts::ERM_SINGLE_FAULT
+            | crate::peripheral_interrupts::ERM_DOUBLE_FAULT => self.erm.handle_interrupt(),
+            crate::peripheral_interrupts::CMP0 => self.cmp0.handle_interrupt(),
+            crate::peripheral_interrupts::ADC0 => self.adc0.handle_interrupt(),
+            crate::peripheral_interrupts::ADC1 => self.adc1.handle_interrupt(),
+            crate::peripheral_interrupts::PDB0 => self.pdb0.handle_interrupt(),
+            crate::peripheral_interrupts::PDB1 => self.pdb1.handle_interrupt(),
+            crate::peripheral_interrupts::FLEXIO => self.flexio.handle_interrupt(),
+            crate::peripheral_interrupts::PORTA..=crate::peripheral_interrupts::PORTE => self
+                .gpio_port
+                .handle_interrupt((interrupt - crate::peripheral_interrupts::PORTA) as usize),
+            crate::peripheral_interrupts::FTM0_CH0_CH1
+                ..=crate::peripheral_interrupts::FTM0_CH6_CH7 => {
+                self.pwm0.handle_channel_interrupt(
+                    (interrupt - crate::peripheral_interrupts::FTM0_CH0_CH1) as usize,
+                )
+            }
+            crate::peripheral_interrupts::FTM0_FAULT => self.pwm0.handle_fault_interrupt(),
+            crate::peripheral_interrupts::FTM0_OVF_RELOAD => self.pwm0.handle_overflow_interrupt(),
+            crate::peripheral_interrupts::CAN0_ORED | crate::peripheral_interrupts::CAN0_ERROR => {
+                self.can0.handle_status_interrupt()
+            }
+            crate::peripheral_interrupts::CAN0_ORED_0_15_MB => self.can0.handle_message_interrupt(),
             _ => return false,
         }
         true
@@ -91,12 +196,16 @@ impl<'a, I: InterruptService + 'a> kernel::platform::chip::Chip for S32K144<'a,
         unsafe {
             loop {
                 if let Some(interrupt) = nvic::next_pending() {
-                    if !self.interrupt_service.service_interrupt(interrupt) {
-                        panic!("unhandled interrupt {}", interrupt);
-                    }
                     let n = nvic::Nvic::new(interrupt);
                     n.clear_pending();
-                    n.enable();
+                    // The generic ISR disabled the interrupt; leave it
+                    // disabled if no driver handles it, so it cannot fire
+                    // again.
+                    if self.interrupt_service.service_interrupt(interrupt) {
+                        n.enable();
+                    } else {
+                        debug!("unhandled interrupt {}, disabling it", interrupt);
+                    }
                 } else {
                     break;
                 }
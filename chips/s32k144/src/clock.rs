@@ -1,6 +1,6 @@
 use kernel::utilities::cells::OptionalCell;
-use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
-use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadOnly, ReadWrite};
 use kernel::utilities::StaticRef;
 
 #[repr(C)]
@@ -576,20 +576,44 @@ register_bitfields![u32,
 pub const SCG_BASE: StaticRef<ScgRegisters> =
     unsafe { StaticRef::new(0x40064000 as *const ScgRegisters) };
 
-#[repr(C)]
-pub struct PccRegisters {
-    pub ftfc: ReadWrite<u32, PCC_FTFC::Register>,
-    pub dmamux: ReadWrite<u32, PCC_DMAMUX::Register>,
-    pub can0: ReadWrite<u32, PCC_CAN0::Register>,
-    pub lpit: ReadWrite<u32, PCC_LPIT::Register>,
-    pub lptmr0: ReadWrite<u32, PCC_LPTMR0::Register>,
-    pub porta: ReadWrite<u32, PCC_PORTA::Register>,
-    pub portb: ReadWrite<u32, PCC_PORTB::Register>,
-    pub portc: ReadWrite<u32, PCC_PORTC::Register>,
-    pub portd: ReadWrite<u32, PCC_PORTD::Register>,
-    pub porte: ReadWrite<u32, PCC_PORTE::Register>,
-    pub lpuart0: ReadWrite<u32, PCC_LPUART0::Register>,
-    pub ftm0: ReadWrite<u32, PCC_FTM0::Register>,
+register_structs! {
+    /// PCC registers. Every peripheral has its own register, at a fixed
+    /// offset from the PCC base.
+    pub PccRegisters {
+        (0x000 => _reserved0),
+        (0x080 => pub ftfc: ReadWrite<u32, PCC_FTFC::Register>),
+        (0x084 => pub dmamux: ReadWrite<u32, PCC_DMAMUX::Register>),
+        (0x088 => _reserved1),
+        (0x090 => pub can0: ReadWrite<u32, PCC_CAN0::Register>),
+        (0x094 => _reserved2),
+        (0x09C => pub adc1: ReadWrite<u32, PCC_ASYNC::Register>),
+        (0x0A0 => _reserved3),
+        (0x0C4 => pub pdb1: ReadWrite<u32, PCC_PDB::Register>),
+        (0x0C8 => pub crc: ReadWrite<u32, PCC_CRC::Register>),
+        (0x0CC => _reserved4),
+        (0x0D8 => pub pdb0: ReadWrite<u32, PCC_PDB::Register>),
+        (0x0DC => pub lpit: ReadWrite<u32, PCC_LPIT::Register>),
+        (0x0E0 => pub ftm0: ReadWrite<u32, PCC_FTM0::Register>),
+        (0x0E4 => _reserved5),
+        (0x0EC => pub adc0: ReadWrite<u32, PCC_ASYNC::Register>),
+        (0x0F0 => _reserved6),
+        (0x100 => pub lptmr0: ReadWrite<u32, PCC_LPTMR0::Register>),
+        (0x104 => _reserved7),
+        (0x124 => pub porta: ReadWrite<u32, PCC_PORTA::Register>),
+        (0x128 => pub portb: ReadWrite<u32, PCC_PORTB::Register>),
+        (0x12C => pub portc: ReadWrite<u32, PCC_PORTC::Register>),
+        (0x130 => pub portd: ReadWrite<u32, PCC_PORTD::Register>),
+        (0x134 => pub porte: ReadWrite<u32, PCC_PORTE::Register>),
+        (0x138 => _reserved8),
+        (0x168 => pub flexio: ReadWrite<u32, PCC_ASYNC::Register>),
+        (0x16C => _reserved9),
+        (0x1A8 => pub lpuart0: ReadWrite<u32, PCC_LPUART0::Register>),
+        (0x1AC => pub lpuart1: ReadWrite<u32, PCC_ASYNC::Register>),
+        (0x1B0 => pub lpuart2: ReadWrite<u32, PCC_ASYNC::Register>),
+        (0x1B4 => _reserved10),
+        (0x1CC => pub cmp0: ReadWrite<u32, PCC_CMP0::Register>),
+        (0x1D0 => @END),
+    }
 }
 
 register_bitfields![u32,
@@ -613,6 +637,54 @@ register_bitfields![u32,
             Enabled = 1
         ]
     ],
+    PCC_CRC [
+        PR OFFSET(31) NUMBITS(1) [
+            NotPresent = 0,
+            Present = 1
+        ],
+        CGC OFFSET(30) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+    PCC_CMP0 [
+        PR OFFSET(31) NUMBITS(1) [
+            NotPresent = 0,
+            Present = 1
+        ],
+        CGC OFFSET(30) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+    PCC_ASYNC [
+        /// Peripheral Clock Source Select
+        PCS OFFSET(24) NUMBITS(3) [
+            Off = 0,
+            SoscDiv2 = 1,
+            SircDiv2 = 2,
+            FircDiv2 = 3,
+            SpllDiv2 = 6
+        ],
+        CGC OFFSET(30) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+        PR OFFSET(31) NUMBITS(1) [
+            NotPresent = 0,
+            Present = 1
+        ]
+    ],
+    PCC_PDB [
+        PR OFFSET(31) NUMBITS(1) [
+            NotPresent = 0,
+            Present = 1
+        ],
+        CGC OFFSET(30) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
     PCC_CAN0 [
         PR OFFSET(31) NUMBITS(1) [
             NotPresent = 0,
@@ -817,7 +889,17 @@ impl Clock {
     /// Enable clock for a specific peripheral in PCC
     pub fn enable_peripheral_clock(&self, peripheral: Peripheral) {
         match peripheral {
+            Peripheral::ADC0 => self.enable_async_clock(&self.pcc_registers.adc0),
+            Peripheral::ADC1 => self.enable_async_clock(&self.pcc_registers.adc1),
+            Peripheral::PDB0 => self.pcc_registers.pdb0.modify(PCC_PDB::CGC::SET),
+            Peripheral::PDB1 => self.pcc_registers.pdb1.modify(PCC_PDB::CGC::SET),
+            Peripheral::CAN0 => self.pcc_registers.can0.modify(PCC_CAN0::CGC::SET),
+            Peripheral::CMP0 => self.pcc_registers.cmp0.modify(PCC_CMP0::CGC::SET),
+            Peripheral::CRC => self.pcc_registers.crc.modify(PCC_CRC::CGC::SET),
+            Peripheral::FLEXIO => self.enable_async_clock(&self.pcc_registers.flexio),
             Peripheral::LPUART0 => self.pcc_registers.lpuart0.modify(PCC_LPUART0::CGC::SET),
+            Peripheral::LPUART1 => self.enable_async_clock(&self.pcc_registers.lpuart1),
+            Peripheral::LPUART2 => self.enable_async_clock(&self.pcc_registers.lpuart2),
             Peripheral::FTM0 => self.pcc_registers.ftm0.modify(PCC_FTM0::CGC::SET),
             Peripheral::LPIT => self.pcc_registers.lpit.modify(PCC_LPIT::CGC::SET),
             Peripheral::LPTMR0 => self.pcc_registers.lptmr0.modify(PCC_LPTMR0::CGC::SET),
@@ -832,7 +914,17 @@ impl Clock {
     /// Disable clock for a specific peripheral in PCC
     pub fn disable_peripheral_clock(&self, peripheral: Peripheral) {
         match peripheral {
+            Peripheral::ADC0 => self.pcc_registers.adc0.modify(PCC_ASYNC::CGC::CLEAR),
+            Peripheral::ADC1 => self.pcc_registers.adc1.modify(PCC_ASYNC::CGC::CLEAR),
+            Peripheral::PDB0 => self.pcc_registers.pdb0.modify(PCC_PDB::CGC::CLEAR),
+            Peripheral::PDB1 => self.pcc_registers.pdb1.modify(PCC_PDB::CGC::CLEAR),
+            Peripheral::CAN0 => self.pcc_registers.can0.modify(PCC_CAN0::CGC::CLEAR),
+            Peripheral::CMP0 => self.pcc_registers.cmp0.modify(PCC_CMP0::CGC::CLEAR),
+            Peripheral::CRC => self.pcc_registers.crc.modify(PCC_CRC::CGC::CLEAR),
+            Peripheral::FLEXIO => self.pcc_registers.flexio.modify(PCC_ASYNC::CGC::CLEAR),
             Peripheral::LPUART0 => self.pcc_registers.lpuart0.modify(PCC_LPUART0::CGC::CLEAR),
+            Peripheral::LPUART1 => self.pcc_registers.lpuart1.modify(PCC_ASYNC::CGC::CLEAR),
+            Peripheral::LPUART2 => self.pcc_registers.lpuart2.modify(PCC_ASYNC::CGC::CLEAR),
             Peripheral::FTM0 => self.pcc_registers.ftm0.modify(PCC_FTM0::CGC::CLEAR),
             Peripheral::LPIT => self.pcc_registers.lpit.modify(PCC_LPIT::CGC::CLEAR),
             Peripheral::LPTMR0 => self.pcc_registers.lptmr0.modify(PCC_LPTMR0::CGC::CLEAR),
@@ -844,6 +936,19 @@ impl Clock {
         }
     }
 
+    /// The ADCs, FlexIO, LPUART1 and LPUART2 run on an asynchronous clock,
+    /// which is taken from the 48 MHz FIRC through FIRCDIV2. The source can
+    /// only be changed while the clock is gated.
+    fn enable_async_clock(&self, pcc: &ReadWrite<u32, PCC_ASYNC::Register>) {
+        if self.scg_registers.fircdiv.read(FIRCDIV::FIRCDIV2) == 0 {
+            self.scg_registers.fircdiv.modify(FIRCDIV::FIRCDIV2::Div1);
+        }
+        if !pcc.is_set(PCC_ASYNC::CGC) {
+            pcc.write(PCC_ASYNC::PCS::FircDiv2);
+            pcc.modify(PCC_ASYNC::CGC::SET);
+        }
+    }
+
     /// Select the system clock source
     pub fn set_system_clock(&self, clock_source: SystemClockSource) {
         self.scg_registers
@@ -932,11 +1037,22 @@ pub enum SystemClockSource {
 }
 
 /// Enum for peripherals controlled by PCC
+#[derive(Clone, Copy)]
 pub enum Peripheral {
+    ADC0,
+    ADC1,
+    CAN0,
+    CMP0,
+    CRC,
+    FLEXIO,
     LPUART0,
+    LPUART1,
+    LPUART2,
     FTM0,
     LPIT,
     LPTMR0,
+    PDB0,
+    PDB1,
     PORTA,
     PORTB,
     PORTC,
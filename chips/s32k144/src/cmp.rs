@@ -0,0 +1,379 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Analog comparator (CMP0) for the S32K144.
+//!
+//! CMP0 compares two analog inputs, each picked from an 8-input mux or from
+//! the comparator's own 8-bit DAC. This driver compares one of the external
+//! inputs IN0 to IN6 (the HIL channels) on the positive port against the DAC
+//! on the negative port. The channel output is `true` when the input is above
+//! the DAC voltage.
+//!
+//! One channel can be compared at a time. While it is started, the
+//! comparator runs continuously and calls `fired` on the edges chosen with
+//! [`Cmp::set_edges`]. The default is rising edges, so `fired` reports inputs
+//! going above the reference. Starting a second channel returns `NOSUPPORT`:
+//! the comparator's round-robin mode would need a periodic trigger routed to
+//! it through TRGMUX, which this driver does not set up. `comparison` works
+//! on any channel at any time.
+//!
+//! Hysteresis and the digital output filter are set with
+//! [`Cmp::set_hysteresis`] and [`Cmp::set_filter`].
+//!
+//! The DAC is also exposed as a `hil::dac::DacChannel`. It has 256 steps
+//! between 0 V and VDDA, and setting it moves the comparison threshold.
+
+use core::cell::Cell;
+use kernel::hil::analog_comparator;
+use kernel::hil::dac;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, FieldValue, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::clock::{Clock, Peripheral};
+
+register_structs! {
+    CmpRegisters {
+        /// CMP Control Register 0
+        (0x0 => c0: ReadWrite<u32, C0::Register>),
+        /// CMP Control Register 1
+        (0x4 => c1: ReadWrite<u32, C1::Register>),
+        /// CMP Control Register 2
+        (0x8 => c2: ReadWrite<u32, C2::Register>),
+        (0xC => @END),
+    }
+}
+
+register_bitfields![u32,
+    C0 [
+        /// Comparator hard block hysteresis control
+        HYSTCTR OFFSET(0) NUMBITS(2) [],
+        /// Filter sample count
+        FILTER_CNT OFFSET(4) NUMBITS(3) [],
+        /// Comparator module enable
+        EN OFFSET(8) NUMBITS(1) [],
+        /// Comparator output pin enable
+        OPE OFFSET(9) NUMBITS(1) [],
+        /// Comparator output select
+        COS OFFSET(10) NUMBITS(1) [],
+        /// Comparator invert
+        INVT OFFSET(11) NUMBITS(1) [],
+        /// Power mode select
+        PMODE OFFSET(12) NUMBITS(1) [
+            LowSpeed = 0,
+            HighSpeed = 1
+        ],
+        /// Filter sample period
+        FPR OFFSET(16) NUMBITS(8) [],
+        /// Analog comparator output
+        COUT OFFSET(24) NUMBITS(1) [],
+        /// Analog comparator flag falling (write 1 to clear)
+        CFF OFFSET(25) NUMBITS(1) [],
+        /// Analog comparator flag rising (write 1 to clear)
+        CFR OFFSET(26) NUMBITS(1) [],
+        /// Comparator interrupt enable falling
+        IEF OFFSET(27) NUMBITS(1) [],
+        /// Comparator interrupt enable rising
+        IER OFFSET(28) NUMBITS(1) []
+    ],
+    C1 [
+        /// DAC output voltage select
+        VOSEL OFFSET(0) NUMBITS(8) [],
+        /// Minus input mux control
+        MSEL OFFSET(8) NUMBITS(3) [],
+        /// Plus input mux control
+        PSEL OFFSET(11) NUMBITS(3) [],
+        /// Supply voltage reference source select
+        VRSEL OFFSET(14) NUMBITS(1) [
+            Vin1 = 0,
+            Vin2 = 1
+        ],
+        /// DAC enable
+        DACEN OFFSET(15) NUMBITS(1) [],
+        /// Round-robin channel enables, one bit per mux input
+        CHN OFFSET(16) NUMBITS(8) [],
+        /// Selection of the input to the negative port of the comparator
+        INNSEL OFFSET(24) NUMBITS(2) [
+            Dac = 0,
+            Mux = 1
+        ],
+        /// Selection of the input to the positive port of the comparator
+        INPSEL OFFSET(27) NUMBITS(2) [
+            Dac = 0,
+            Mux = 1
+        ]
+    ],
+    C2 [
+        /// Comparator output of each round-robin channel
+        ACO OFFSET(0) NUMBITS(8) [],
+        /// Comparator and DAC initialization delay modulus
+        INITMOD OFFSET(8) NUMBITS(6) [],
+        /// Number of sample clocks before a channel is compared
+        NSAM OFFSET(14) NUMBITS(2) [],
+        /// Per-channel output change flags (write 1 to clear)
+        CHF OFFSET(16) NUMBITS(8) [],
+        /// Fixed mux channel
+        FXMXCH OFFSET(25) NUMBITS(3) [],
+        /// Fixed mux port
+        FXMP OFFSET(29) NUMBITS(1) [
+            Plus = 0,
+            Minus = 1
+        ],
+        /// Round-robin interrupt enable
+        RRIE OFFSET(30) NUMBITS(1) [],
+        /// Round-robin enable
+        RRE OFFSET(31) NUMBITS(1) []
+    ]
+];
+
+const CMP0_BASE: StaticRef<CmpRegisters> =
+    unsafe { StaticRef::new(0x4007_3000 as *const CmpRegisters) };
+
+/// An external comparator input, compared against the DAC.
+pub struct Channel {
+    input: u32,
+}
+
+impl Channel {
+    const fn new(input: u32) -> Self {
+        Self { input }
+    }
+
+    fn mask(&self) -> u32 {
+        1 << self.input
+    }
+}
+
+pub static CHANNEL_IN0: Channel = Channel::new(0);
+pub static CHANNEL_IN1: Channel = Channel::new(1);
+pub static CHANNEL_IN2: Channel = Channel::new(2);
+pub static CHANNEL_IN3: Channel = Channel::new(3);
+pub static CHANNEL_IN4: Channel = Channel::new(4);
+pub static CHANNEL_IN5: Channel = Channel::new(5);
+pub static CHANNEL_IN6: Channel = Channel::new(6);
+
+/// Hysteresis of the analog comparator.
+#[derive(Clone, Copy)]
+pub enum Hysteresis {
+    Level0 = 0,
+    Level1 = 1,
+    Level2 = 2,
+    Level3 = 3,
+}
+
+/// Output transitions that call `fired`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Edges {
+    Rising,
+    Falling,
+    Both,
+}
+
+pub struct Cmp<'a> {
+    registers: StaticRef<CmpRegisters>,
+    clock: Clock,
+    client: OptionalCell<&'a dyn analog_comparator::Client>,
+    /// The registers are only accessible while the comparator is clocked.
+    enabled: Cell<bool>,
+    /// Mask of the channel currently started, if any.
+    active: Cell<u32>,
+    edges: Cell<Edges>,
+    hysteresis: Cell<Hysteresis>,
+    /// Filter sample count and period.
+    filter: Cell<(u8, u8)>,
+    /// DAC step (VOSEL) setting.
+    level: Cell<u8>,
+}
+
+impl Cmp<'_> {
+    pub fn new() -> Self {
+        Self {
+            registers: CMP0_BASE,
+            clock: Clock::new(),
+            client: OptionalCell::empty(),
+            enabled: Cell::new(false),
+            active: Cell::new(0),
+            edges: Cell::new(Edges::Rising),
+            hysteresis: Cell::new(Hysteresis::Level0),
+            filter: Cell::new((0, 0)),
+            level: Cell::new(0x80),
+        }
+    }
+
+    /// Set the hysteresis of the comparator. Higher levels reject more
+    /// noise around the threshold.
+    pub fn set_hysteresis(&self, hysteresis: Hysteresis) {
+        self.hysteresis.set(hysteresis);
+        if self.enabled.get() {
+            self.modify_c0(C0::HYSTCTR.val(hysteresis as u32));
+        }
+    }
+
+    /// Filter the comparator output. The output only changes after `count`
+    /// consecutive equal samples, taken every `period` bus clocks. A `count`
+    /// of zero or one disables the filter. `count` can be at most 7.
+    pub fn set_filter(&self, count: u8, period: u8) -> Result<(), ErrorCode> {
+        if count > 7 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.filter.set((count, period));
+        if self.enabled.get() {
+            self.modify_c0(C0::FILTER_CNT.val(count.into()) + C0::FPR.val(period.into()));
+        }
+        Ok(())
+    }
+
+    /// Choose which output transitions of the compared channel call
+    /// `fired`.
+    pub fn set_edges(&self, edges: Edges) {
+        self.edges.set(edges);
+        if self.active.get() != 0 {
+            self.modify_c0(self.interrupt_enables());
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        if !self.enabled.get() {
+            return;
+        }
+        let active = self.active.get();
+        let c0 = self.registers.c0.extract();
+        let rising = c0.read(C0::CFR);
+        let falling = c0.read(C0::CFF);
+        self.modify_c0(C0::CFR.val(rising) + C0::CFF.val(falling));
+        let reported = match self.edges.get() {
+            Edges::Rising => rising != 0,
+            Edges::Falling => falling != 0,
+            Edges::Both => rising != 0 || falling != 0,
+        };
+        if reported && active != 0 {
+            let input = active.trailing_zeros() as usize;
+            self.client.map(|client| client.fired(input));
+        }
+    }
+
+    fn enable(&self) {
+        if self.enabled.get() {
+            return;
+        }
+        self.clock.enable_peripheral_clock(Peripheral::CMP0);
+        self.enabled.set(true);
+        let (count, period) = self.filter.get();
+        self.registers.c1.write(
+            C1::VOSEL.val(self.level.get().into())
+                + C1::VRSEL::Vin2
+                + C1::DACEN::SET
+                + C1::INPSEL::Mux
+                + C1::INNSEL::Dac,
+        );
+        self.registers.c0.write(
+            C0::HYSTCTR.val(self.hysteresis.get() as u32)
+                + C0::FILTER_CNT.val(count.into())
+                + C0::FPR.val(period.into())
+                + C0::PMODE::HighSpeed
+                + C0::CFR::SET
+                + C0::CFF::SET
+                + C0::EN::SET,
+        );
+    }
+
+    fn disable(&self) {
+        if !self.enabled.get() {
+            return;
+        }
+        self.registers.c0.write(C0::CFR::SET + C0::CFF::SET);
+        self.registers.c1.set(0);
+        self.clock.disable_peripheral_clock(Peripheral::CMP0);
+        self.enabled.set(false);
+    }
+
+    /// Modify C0 without clearing pending flags by writing them back.
+    fn modify_c0(&self, field: FieldValue<u32, C0::Register>) {
+        let c0 = self.registers.c0.extract();
+        self.registers
+            .c0
+            .modify_no_read(c0, C0::CFR::CLEAR + C0::CFF::CLEAR + field);
+    }
+
+    fn interrupt_enables(&self) -> FieldValue<u32, C0::Register> {
+        match self.edges.get() {
+            Edges::Rising => C0::IER::SET + C0::IEF::CLEAR,
+            Edges::Falling => C0::IER::CLEAR + C0::IEF::SET,
+            Edges::Both => C0::IER::SET + C0::IEF::SET,
+        }
+    }
+
+    /// Reprogram the comparator for the started channel.
+    fn configure(&self) {
+        let active = self.active.get();
+        if active == 0 {
+            self.disable();
+            return;
+        }
+        self.enable();
+        self.registers
+            .c1
+            .modify(C1::INPSEL::Mux + C1::INNSEL::Dac + C1::PSEL.val(active.trailing_zeros()));
+        self.modify_c0(C0::CFR::SET + C0::CFF::SET + self.interrupt_enables());
+    }
+}
+
+impl<'a> analog_comparator::AnalogComparator<'a> for Cmp<'a> {
+    type Channel = Channel;
+
+    fn comparison(&self, channel: &Self::Channel) -> bool {
+        let was_enabled = self.enabled.get();
+        self.enable();
+        self.registers
+            .c1
+            .modify(C1::INPSEL::Mux + C1::INNSEL::Dac + C1::PSEL.val(channel.input));
+        // In high-speed mode the output settles within a few hundred
+        // nanoseconds; give the DAC and the comparator that long.
+        for _ in 0..100 {
+            cortexm4f::support::nop();
+        }
+        let above = self.registers.c0.is_set(C0::COUT);
+        if was_enabled {
+            // Go back to the channel being compared, and drop any edge the
+            // channel switch produced.
+            self.configure();
+        } else {
+            self.disable();
+        }
+        above
+    }
+
+    fn start_comparing(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
+        let active = self.active.get();
+        if active != 0 && active != channel.mask() {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.active.set(channel.mask());
+        self.configure();
+        Ok(())
+    }
+
+    fn stop_comparing(&self, channel: &Self::Channel) -> Result<(), ErrorCode> {
+        self.active.set(self.active.get() & !channel.mask());
+        self.configure();
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn analog_comparator::Client) {
+        self.client.set(client);
+    }
+}
+
+impl dac::DacChannel for Cmp<'_> {
+    fn set_value(&self, value: usize) -> Result<(), ErrorCode> {
+        let level = u8::try_from(value).map_err(|_| ErrorCode::INVAL)?;
+        self.level.set(level);
+        if self.enabled.get() {
+            self.registers.c1.modify(C1::VOSEL.val(level.into()));
+        }
+        Ok(())
+    }
+}
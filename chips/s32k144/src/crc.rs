@@ -0,0 +1,255 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Cyclic Redundancy Check (CRC) unit for the S32K144.
+//!
+//! The CRC unit computes 16- or 32-bit CRCs with a programmable polynomial
+//! over data written by the core to its DATA register, 8, 16 or 32 bits at a
+//! time. It can transpose the input and output bits and complement the
+//! result, which covers the post-processing of every `CrcAlgorithm`:
+//!
+//! | Algorithm    | Polynomial   | Seed         | Input    | Output                   |
+//! |--------------|--------------|--------------|----------|--------------------------|
+//! | `Crc32`      | `0x04C11DB7` | `0xFFFFFFFF` | reversed | reversed and complemented |
+//! | `Crc32C`     | `0x1EDC6F41` | `0xFFFFFFFF` | reversed | reversed and complemented |
+//! | `Crc16CCITT` | `0x1021`     | `0xFFFF`     | reversed | as computed              |
+//!
+//! The unit has no DMA or interrupt of its own, so data is fed to it
+//! synchronously and the HIL callbacks are issued from a deferred call.
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::crc::{self, CrcAlgorithm, CrcOutput};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::clock::{Clock, Peripheral};
+
+register_structs! {
+    CrcRegisters {
+        /// Data register. Writes feed the CRC, 8, 16 or 32 bits at a time;
+        /// reads return the CRC. With `CTRL[WAS]` set, writes load the seed.
+        (0x0 => data: ReadWrite<u32>),
+        /// Polynomial register
+        (0x4 => gpoly: ReadWrite<u32>),
+        /// Control register
+        (0x8 => ctrl: ReadWrite<u32, CTRL::Register>),
+        (0xC => @END),
+    }
+}
+
+register_bitfields![u32,
+    CTRL [
+        /// Width of the CRC protocol
+        TCRC OFFSET(24) NUMBITS(1) [
+            Crc16 = 0,
+            Crc32 = 1
+        ],
+        /// Write CRC data register as seed
+        WAS OFFSET(25) NUMBITS(1) [],
+        /// Complement the read CRC
+        FXOR OFFSET(26) NUMBITS(1) [],
+        /// Transpose of reads
+        TOTR OFFSET(28) NUMBITS(2) [
+            None = 0,
+            Bits = 1,
+            BitsAndBytes = 2,
+            Bytes = 3
+        ],
+        /// Transpose of writes
+        TOT OFFSET(30) NUMBITS(2) [
+            None = 0,
+            Bits = 1,
+            BitsAndBytes = 2,
+            Bytes = 3
+        ]
+    ]
+];
+
+const CRC_ADDRESS: usize = 0x4003_2000;
+
+const CRC_BASE: StaticRef<CrcRegisters> =
+    unsafe { StaticRef::new(CRC_ADDRESS as *const CrcRegisters) };
+
+/// DATA register, for the 8- and 16-bit writes the register interface does
+/// not offer. The unit consumes narrow writes from the low lanes.
+const CRC_DATA: usize = CRC_ADDRESS;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Input,
+    Compute,
+}
+
+pub struct Crc<'a> {
+    registers: StaticRef<CrcRegisters>,
+    clock: Clock,
+    client: OptionalCell<&'a dyn crc::Client>,
+    algorithm: OptionalCell<CrcAlgorithm>,
+    state: Cell<State>,
+    buffer: MapCell<SubSliceMut<'static, u8>>,
+    deferred_call: DeferredCall,
+}
+
+impl Crc<'_> {
+    pub fn new() -> Self {
+        Self {
+            registers: CRC_BASE,
+            clock: Clock::new(),
+            client: OptionalCell::empty(),
+            algorithm: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            buffer: MapCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn enable(&self) {
+        self.clock.enable_peripheral_clock(Peripheral::CRC);
+    }
+
+    /// Program the polynomial and transposition of `algorithm` and load its
+    /// seed, discarding any data already written.
+    fn configure(&self, algorithm: CrcAlgorithm) {
+        let (polynomial, seed) = match algorithm {
+            CrcAlgorithm::Crc32 => (0x04C1_1DB7, 0xFFFF_FFFF),
+            CrcAlgorithm::Crc32C => (0x1EDC_6F41, 0xFFFF_FFFF),
+            CrcAlgorithm::Crc16CCITT => (0x1021, 0xFFFF),
+        };
+        let ctrl = match algorithm {
+            CrcAlgorithm::Crc32 | CrcAlgorithm::Crc32C => {
+                CTRL::TCRC::Crc32 + CTRL::TOT::Bits + CTRL::TOTR::BitsAndBytes + CTRL::FXOR::SET
+            }
+            CrcAlgorithm::Crc16CCITT => {
+                CTRL::TCRC::Crc16 + CTRL::TOT::Bits + CTRL::TOTR::None + CTRL::FXOR::CLEAR
+            }
+        };
+        self.registers.ctrl.write(ctrl);
+        self.registers.gpoly.set(polynomial);
+        self.registers.ctrl.modify(CTRL::WAS::SET);
+        self.registers.data.set(seed);
+        self.registers.ctrl.modify(CTRL::WAS::CLEAR);
+    }
+
+    fn write_u8(&self, value: u8) {
+        unsafe { core::ptr::write_volatile(CRC_DATA as *mut u8, value) };
+    }
+
+    fn write_u16(&self, value: u16) {
+        unsafe { core::ptr::write_volatile(CRC_DATA as *mut u16, value) };
+    }
+
+    /// Feed `bytes` to the CRC in order. The unit consumes wider writes most
+    /// significant byte first, so bytes are packed big-endian.
+    fn feed(&self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(4);
+        for word in &mut words {
+            self.registers
+                .data
+                .set(u32::from_be_bytes([word[0], word[1], word[2], word[3]]));
+        }
+        let mut rest = words.remainder();
+        if rest.len() >= 2 {
+            self.write_u16(u16::from_be_bytes([rest[0], rest[1]]));
+            rest = &rest[2..];
+        }
+        if let Some(byte) = rest.first() {
+            self.write_u8(*byte);
+        }
+    }
+}
+
+impl<'a> crc::Crc<'a> for Crc<'a> {
+    fn set_client(&self, client: &'a dyn crc::Client) {
+        self.client.set(client);
+    }
+
+    fn algorithm_supported(&self, _algorithm: CrcAlgorithm) -> bool {
+        true
+    }
+
+    fn set_algorithm(&self, algorithm: CrcAlgorithm) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.enable();
+        self.configure(algorithm);
+        self.algorithm.set(algorithm);
+        Ok(())
+    }
+
+    fn input(
+        &self,
+        mut data: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if self.algorithm.is_none() {
+            return Err((ErrorCode::RESERVE, data));
+        }
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, data));
+        }
+        self.enable();
+        self.feed(data.as_slice());
+        self.buffer.replace(data);
+        self.state.set(State::Input);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn compute(&self) -> Result<(), ErrorCode> {
+        if self.algorithm.is_none() {
+            return Err(ErrorCode::RESERVE);
+        }
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.enable();
+        self.state.set(State::Compute);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn disable(&self) {
+        self.clock.disable_peripheral_clock(Peripheral::CRC);
+    }
+}
+
+impl DeferredCallClient for Crc<'_> {
+    fn handle_deferred_call(&self) {
+        let state = self.state.get();
+        self.state.set(State::Idle);
+        match state {
+            State::Idle => {}
+            State::Input => {
+                self.buffer.take().map(|mut buffer| {
+                    // All of the data was fed to the unit; an empty buffer
+                    // tells the client there is nothing left to input.
+                    buffer.slice(buffer.len()..);
+                    self.client
+                        .map(move |client| client.input_done(Ok(()), buffer));
+                });
+            }
+            State::Compute => {
+                let result = self.registers.data.get();
+                let output = self.algorithm.map(|algorithm| match algorithm {
+                    CrcAlgorithm::Crc32 => CrcOutput::Crc32(result),
+                    CrcAlgorithm::Crc32C => CrcOutput::Crc32C(result),
+                    CrcAlgorithm::Crc16CCITT => CrcOutput::Crc16CCITT(result as u16),
+                });
+                self.client.map(|client| {
+                    client.crc_done(output.ok_or(ErrorCode::RESERVE));
+                });
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
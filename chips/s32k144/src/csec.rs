@@ -0,0 +1,996 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Cryptographic Services Engine (CSEc) driver for the S32K144.
+//!
+//! The CSEc is a SHE (Secure Hardware Extension) implementation that lives
+//! behind the FTFC. Commands are issued through the 128-byte CSE_PRAM window
+//! in FlexRAM: the parameters and data are written to pages 1-7 (and the end
+//! of page 0), and writing the command header at offset 0 launches the
+//! command. The FTFC clears `FSTAT[CCIF]` while the command runs, so
+//! completion is reported through the shared FTFC command-complete interrupt
+//! (see `Ftfc::delegate`). CSE_PRAM words are big-endian: byte 0 of a page
+//! is the most significant byte of its first word.
+//!
+//! The CSEc is only available once the FlexNVM has been partitioned with
+//! key storage (`PartitionConfig::csec_keys`).
+//!
+//! This driver provides:
+//!
+//! - AES-128 ECB, CBC and CTR through `hil::symmetric_encryption`. The CSEc
+//!   has no counter mode, so CTR encrypts the counter blocks with ENC_ECB
+//!   and XORs the result with the data. A key passed to `set_key` is loaded
+//!   into the volatile `RAM_KEY` slot with LOAD_PLAIN_KEY before the next
+//!   operation. `select_key` uses one of the non-volatile key slots instead.
+//! - AES-CMAC generation and verification through the `Cmac` trait.
+//! - Key updates with the SHE M1-M5 protocol (`load_key`).
+//! - The CSEc PRNG through `hil::entropy::Entropy32`. The PRNG is seeded
+//!   with INIT_RNG before its first use.
+//! - Secure boot configuration (`boot_define`) and status (`boot_status`).
+//!   These run synchronously, as they are only used while the board boots.
+//!
+//! Commands longer than seven pages are split into several calls with the
+//! `CallSeq` field of the header set, the next call being issued from the
+//! command-complete interrupt.
+//!
+//! The CSEc error codes (ERC) are reported as:
+//!
+//! | ERC                                   | `ErrorCode` |
+//! |---------------------------------------|-------------|
+//! | SEQUENCE_ERROR, KEY_INVALID           | `INVAL`     |
+//! | KEY_NOT_AVAILABLE, KEY_WRITE_PROTECTED| `RESERVE`   |
+//! | KEY_EMPTY                             | `NODEVICE`  |
+//! | RNG_SEED                              | `OFF`       |
+//! | NO_SECURE_BOOT, NO_DEBUGGING          | `NOSUPPORT` |
+//! | any other error                       | `FAIL`      |
+//!
+//! The raw ERC of the last command is available from `last_error`.
+
+use core::cell::Cell;
+use core::cmp;
+use kernel::hil::entropy::{self, Continue};
+use kernel::hil::symmetric_encryption::{
+    self, AES128Ctr, AES128, AES128CBC, AES128ECB, AES128_BLOCK_SIZE, AES128_KEY_SIZE,
+};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{register_structs, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::nvmc::{CommandClient, CommandOwner, Ftfc, FCSESTAT};
+
+register_structs! {
+    pub CsePramRegisters {
+        /// Page 0 holds the command header and parameters, pages 1-7 the
+        /// command data.
+        (0x00 => pub word: [ReadWrite<u32>; 32]),
+        (0x80 => @END),
+    }
+}
+
+const CSE_PRAM_BASE: StaticRef<CsePramRegisters> =
+    unsafe { StaticRef::new(0x1400_1000 as *const CsePramRegisters) };
+
+/// Size of a CSE_PRAM page.
+const PAGE: usize = 16;
+/// Number of CSE_PRAM pages, including the header page.
+const PAGES: usize = 8;
+
+/// Size of the SHE key update messages passed to `load_key`: M1 (16 bytes),
+/// M2 (32 bytes) and M3 (16 bytes) on input, followed by room for M4 (32
+/// bytes) and M5 (16 bytes) on output.
+pub const KEY_UPDATE_LENGTH: usize = 112;
+/// Offset of M4 in the `load_key` buffer.
+pub const KEY_UPDATE_M4_OFFSET: usize = 64;
+/// Size of an AES-CMAC.
+pub const CMAC_LENGTH: usize = 16;
+
+/// CSEc commands, written to the FuncID field of the header.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum CsecCommand {
+    EncEcb = 0x01,
+    EncCbc = 0x02,
+    DecEcb = 0x03,
+    DecCbc = 0x04,
+    GenerateMac = 0x05,
+    VerifyMac = 0x06,
+    LoadKey = 0x07,
+    LoadPlainKey = 0x08,
+    ExportRamKey = 0x09,
+    InitRng = 0x0A,
+    ExtendSeed = 0x0B,
+    Rnd = 0x0C,
+    SecureBoot = 0x0D,
+    BootFailure = 0x0E,
+    BootOk = 0x0F,
+    GetId = 0x10,
+    BootDefine = 0x11,
+    DbgChal = 0x12,
+    DbgAuth = 0x13,
+}
+
+/// SHE key slots.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum KeyId {
+    MasterEcuKey = 0x1,
+    BootMacKey = 0x2,
+    BootMac = 0x3,
+    Key1 = 0x4,
+    Key2 = 0x5,
+    Key3 = 0x6,
+    Key4 = 0x7,
+    Key5 = 0x8,
+    Key6 = 0x9,
+    Key7 = 0xA,
+    Key8 = 0xB,
+    Key9 = 0xC,
+    Key10 = 0xD,
+    RamKey = 0xF,
+}
+
+/// CSEc error codes, reported in the ERC field after every command.
+pub mod erc {
+    pub const NO_ERROR: u16 = 0x0001;
+    pub const SEQUENCE_ERROR: u16 = 0x0002;
+    pub const KEY_NOT_AVAILABLE: u16 = 0x0004;
+    pub const KEY_INVALID: u16 = 0x0008;
+    pub const KEY_EMPTY: u16 = 0x0010;
+    pub const NO_SECURE_BOOT: u16 = 0x0020;
+    pub const KEY_WRITE_PROTECTED: u16 = 0x0040;
+    pub const KEY_UPDATE_ERROR: u16 = 0x0080;
+    pub const RNG_SEED: u16 = 0x0100;
+    pub const NO_DEBUGGING: u16 = 0x0200;
+    pub const MEMORY_FAILURE: u16 = 0x0400;
+    pub const GENERAL_ERROR: u16 = 0x0800;
+}
+
+fn erc_to_result(erc: u16) -> Result<(), ErrorCode> {
+    match erc {
+        erc::NO_ERROR => Ok(()),
+        erc::SEQUENCE_ERROR | erc::KEY_INVALID => Err(ErrorCode::INVAL),
+        erc::KEY_NOT_AVAILABLE | erc::KEY_WRITE_PROTECTED => Err(ErrorCode::RESERVE),
+        erc::KEY_EMPTY => Err(ErrorCode::NODEVICE),
+        erc::RNG_SEED => Err(ErrorCode::OFF),
+        erc::NO_SECURE_BOOT | erc::NO_DEBUGGING => Err(ErrorCode::NOSUPPORT),
+        _ => Err(ErrorCode::FAIL),
+    }
+}
+
+/// Receives the result of `Cmac` operations.
+pub trait CmacClient {
+    /// A MAC has been generated into `mac`.
+    fn mac_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        data: &'static mut [u8],
+        mac: &'static mut [u8; CMAC_LENGTH],
+    );
+
+    /// `mac` has been checked against `data`. `Ok(true)` means the MAC
+    /// matches.
+    fn verify_done(
+        &self,
+        result: Result<bool, ErrorCode>,
+        data: &'static mut [u8],
+        mac: &'static mut [u8; CMAC_LENGTH],
+    );
+}
+
+/// AES-CMAC generation and verification with a key held by the hardware.
+pub trait Cmac<'a> {
+    fn set_client(&'a self, client: &'a dyn CmacClient);
+
+    /// Select the key slot used by `generate_mac` and `verify_mac`.
+    fn set_mac_key(&self, key: KeyId) -> Result<(), ErrorCode>;
+
+    /// Compute the CMAC of the first `length` bytes of `data` into `mac`.
+    /// `CmacClient::mac_done` is called once the MAC is available.
+    fn generate_mac(
+        &self,
+        data: &'static mut [u8],
+        length: usize,
+        mac: &'static mut [u8; CMAC_LENGTH],
+    ) -> Result<(), (ErrorCode, &'static mut [u8], &'static mut [u8; CMAC_LENGTH])>;
+
+    /// Check `mac` against the first `length` bytes of `data`.
+    /// `CmacClient::verify_done` is called with the outcome.
+    fn verify_mac(
+        &self,
+        data: &'static mut [u8],
+        length: usize,
+        mac: &'static mut [u8; CMAC_LENGTH],
+    ) -> Result<(), (ErrorCode, &'static mut [u8], &'static mut [u8; CMAC_LENGTH])>;
+}
+
+/// How the CSEc checks the boot image after reset, set with BOOT_DEFINE.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum BootFlavor {
+    /// The core is held in reset until BOOT_MAC has been checked, and stays
+    /// there if the check fails.
+    Strict = 0x0,
+    /// The core is held in reset until BOOT_MAC has been checked, and then
+    /// started whatever the result.
+    Serial = 0x1,
+    /// The core runs while BOOT_MAC is checked.
+    Parallel = 0x2,
+}
+
+/// Outcome of the secure boot of the current reset, from `FCSESTAT`.
+#[derive(Clone, Copy, PartialEq)]
+pub struct BootStatus {
+    /// A boot flavor has been configured with BOOT_DEFINE.
+    pub initialized: bool,
+    /// Secure boot ran on this reset.
+    pub secure_boot: bool,
+    /// The BOOT_MAC check has finished.
+    pub finished: bool,
+    /// The boot image matched BOOT_MAC.
+    pub ok: bool,
+    /// The CSEc is still busy, for example checking the image in parallel
+    /// boot.
+    pub busy: bool,
+}
+
+impl BootStatus {
+    /// Whether the image that was booted was authenticated against BOOT_MAC.
+    pub fn authenticated(&self) -> bool {
+        self.secure_boot && self.ok
+    }
+}
+
+/// Receives the result of `Csec::load_key`.
+pub trait KeyUpdateClient {
+    /// The key update has completed. On success `buffer` holds the M4 and M5
+    /// verification messages at `KEY_UPDATE_M4_OFFSET`.
+    fn load_key_done(&self, result: Result<(), ErrorCode>, buffer: &'static mut [u8]);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Chaining {
+    Ecb,
+    Cbc,
+    Ctr,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Mode {
+    chaining: Chaining,
+    encrypting: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Idle,
+    Crypt(Mode),
+    GenerateMac,
+    VerifyMac,
+    LoadKey(KeyId),
+    Random,
+}
+
+pub struct Csec<'a> {
+    ftfc: &'a Ftfc,
+    pram: StaticRef<CsePramRegisters>,
+    operation: Cell<Operation>,
+    /// Command in flight.
+    command: Cell<CsecCommand>,
+    /// Number of bytes of the current operation already processed.
+    position: Cell<usize>,
+    /// First page and length of the data of the command in flight.
+    chunk: Cell<(usize, usize)>,
+    last_erc: Cell<u16>,
+
+    aes_client: OptionalCell<&'a dyn symmetric_encryption::Client<'a>>,
+    mode: Cell<Option<Mode>>,
+    key_id: Cell<KeyId>,
+    key: Cell<[u8; AES128_KEY_SIZE]>,
+    ram_key_loaded: Cell<bool>,
+    iv: Cell<[u8; AES128_BLOCK_SIZE]>,
+    /// IV for the next `crypt` of the current CBC message, or the next
+    /// counter block of the current CTR message.
+    chain: Cell<[u8; AES128_BLOCK_SIZE]>,
+    source: TakeCell<'static, [u8]>,
+    data: TakeCell<'static, [u8]>,
+    start: Cell<usize>,
+    length: Cell<usize>,
+
+    cmac_client: OptionalCell<&'a dyn CmacClient>,
+    mac_key_id: Cell<KeyId>,
+    mac: TakeCell<'static, [u8; CMAC_LENGTH]>,
+    mac_valid: Cell<bool>,
+
+    key_client: OptionalCell<&'a dyn KeyUpdateClient>,
+
+    rng_client: OptionalCell<&'a dyn entropy::Client32>,
+    rng_seeded: Cell<bool>,
+    rng_pending: Cell<bool>,
+    rng_cancelled: Cell<bool>,
+}
+
+impl<'a> Csec<'a> {
+    pub fn new(ftfc: &'a Ftfc) -> Self {
+        Self {
+            ftfc,
+            pram: CSE_PRAM_BASE,
+            operation: Cell::new(Operation::Idle),
+            command: Cell::new(CsecCommand::GetId),
+            position: Cell::new(0),
+            chunk: Cell::new((0, 0)),
+            last_erc: Cell::new(erc::NO_ERROR),
+            aes_client: OptionalCell::empty(),
+            mode: Cell::new(None),
+            key_id: Cell::new(KeyId::RamKey),
+            key: Cell::new([0; AES128_KEY_SIZE]),
+            ram_key_loaded: Cell::new(false),
+            iv: Cell::new([0; AES128_BLOCK_SIZE]),
+            chain: Cell::new([0; AES128_BLOCK_SIZE]),
+            source: TakeCell::empty(),
+            data: TakeCell::empty(),
+            start: Cell::new(0),
+            length: Cell::new(0),
+            cmac_client: OptionalCell::empty(),
+            mac_key_id: Cell::new(KeyId::RamKey),
+            mac: TakeCell::empty(),
+            mac_valid: Cell::new(false),
+            key_client: OptionalCell::empty(),
+            rng_client: OptionalCell::empty(),
+            rng_seeded: Cell::new(false),
+            rng_pending: Cell::new(false),
+            rng_cancelled: Cell::new(false),
+        }
+    }
+
+    /// ERC reported by the last completed command.
+    pub fn last_error(&self) -> u16 {
+        self.last_erc.get()
+    }
+
+    /// Secure boot status of the current reset.
+    pub fn boot_status(&self) -> BootStatus {
+        let status = self.ftfc.csec_status();
+        BootStatus {
+            initialized: status.is_set(FCSESTAT::BIN),
+            secure_boot: status.is_set(FCSESTAT::SB),
+            finished: status.is_set(FCSESTAT::BFN),
+            ok: status.is_set(FCSESTAT::BOK),
+            busy: status.is_set(FCSESTAT::BSY),
+        }
+    }
+
+    /// Configure secure boot to check the first `size` bytes of P-Flash
+    /// against BOOT_MAC on every reset.
+    ///
+    /// This can only be done once. The BOOT_MAC_KEY slot must hold a key;
+    /// if BOOT_MAC is empty the CSEc computes and stores it on the next
+    /// reset. Blocks until the command has completed.
+    pub fn boot_define(&self, size: usize, flavor: BootFlavor) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        // The parameters share CSE_PRAM with any operation in flight.
+        self.pram.word[6].set(flavor as u32);
+        self.pram.word[7].set(size as u32);
+        self.run_blocking(CsecCommand::BootDefine)
+    }
+
+    /// Report that the booted image is trusted, finishing the secure boot.
+    pub fn boot_ok(&self) -> Result<(), ErrorCode> {
+        self.run_blocking(CsecCommand::BootOk)
+    }
+
+    /// Report that the booted image is not trusted. Keys flagged as boot
+    /// protected stay unavailable until the next reset.
+    pub fn boot_failure(&self) -> Result<(), ErrorCode> {
+        self.run_blocking(CsecCommand::BootFailure)
+    }
+
+    /// Run a command that takes no data and wait for it to complete.
+    fn run_blocking(&self, command: CsecCommand) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let header = (command as u32) << 24;
+        self.ftfc
+            .delegate_blocking(|| self.pram.word[0].set(header))?;
+        let erc = (self.pram.word[1].get() >> 16) as u16;
+        self.last_erc.set(erc);
+        erc_to_result(erc)
+    }
+
+    /// Check `mac` against the CMAC of `message` under `key` and wait for
+    /// the result. `message` and the MAC must fit in one command, so at most
+    /// 96 bytes. This is meant for code that runs without the kernel's main
+    /// loop, such as a bootloader checking an image signature.
+    pub fn verify_mac_blocking(
+        &self,
+        key: KeyId,
+        message: &[u8],
+        mac: &[u8; CMAC_LENGTH],
+    ) -> Result<bool, ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        // As in `next_command`, the MAC starts on the page after the message.
+        let padded = message.len().div_ceil(PAGE) * PAGE;
+        if padded + CMAC_LENGTH > (PAGES - 1) * PAGE {
+            return Err(ErrorCode::SIZE);
+        }
+        self.pram.word[2].set((CMAC_LENGTH * 8) as u32);
+        self.pram.word[3].set((message.len() * 8) as u32);
+        self.write_bytes(PAGE, padded + CMAC_LENGTH, |i| match i {
+            i if i < message.len() => message[i],
+            i if i >= padded => mac[i - padded],
+            _ => 0,
+        });
+        let header = (CsecCommand::VerifyMac as u32) << 24 | key as u32;
+        self.ftfc
+            .delegate_blocking(|| self.pram.word[0].set(header))?;
+        let erc = (self.pram.word[1].get() >> 16) as u16;
+        self.last_erc.set(erc);
+        erc_to_result(erc)?;
+        Ok(self.pram.word[5].get() >> 16 == 0)
+    }
+
+    /// Use the non-volatile key in `key` for AES operations, instead of a
+    /// key passed to `set_key`.
+    pub fn select_key(&self, key: KeyId) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.key_id.set(key);
+        Ok(())
+    }
+
+    pub fn set_key_client(&self, client: &'a dyn KeyUpdateClient) {
+        self.key_client.set(client);
+    }
+
+    /// Update a key slot with the SHE key update protocol.
+    ///
+    /// `buffer` must be at least `KEY_UPDATE_LENGTH` bytes and hold M1, M2
+    /// and M3 in its first 64 bytes. M1 names the slot being updated; `key`
+    /// selects the key bank and is normally the same slot.
+    pub fn load_key(
+        &self,
+        key: KeyId,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.operation.get() != Operation::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if buffer.len() < KEY_UPDATE_LENGTH {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        self.data.replace(buffer);
+        match self.begin(Operation::LoadKey(key)) {
+            Ok(()) => Ok(()),
+            Err(e) => Err((e, self.data.take().unwrap())),
+        }
+    }
+
+    /// Write `length` bytes to CSE_PRAM at `offset`, taking byte `i` from
+    /// `byte(i)`. `offset` and `length` must be multiples of 4.
+    fn write_bytes<F: Fn(usize) -> u8>(&self, offset: usize, length: usize, byte: F) {
+        for word in 0..length / 4 {
+            let i = word * 4;
+            let value = u32::from_be_bytes([byte(i), byte(i + 1), byte(i + 2), byte(i + 3)]);
+            self.pram.word[offset / 4 + word].set(value);
+        }
+    }
+
+    /// Read `out.len()` bytes from CSE_PRAM at `offset`, which must be a
+    /// multiple of 4.
+    fn read_bytes(&self, offset: usize, out: &mut [u8]) {
+        for (word, chunk) in out.chunks_mut(4).enumerate() {
+            let bytes = self.pram.word[offset / 4 + word].get().to_be_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    /// Launch `command` by writing the header. The parameters must already
+    /// be in CSE_PRAM.
+    fn launch(&self, command: CsecCommand, sequence: u8, key: u8) -> Result<(), ErrorCode> {
+        let header = (command as u32) << 24 | (sequence as u32) << 8 | key as u32;
+        self.ftfc
+            .delegate(CommandOwner::Csec, || self.pram.word[0].set(header))?;
+        self.command.set(command);
+        Ok(())
+    }
+
+    fn uses_ram_key(&self, operation: Operation) -> bool {
+        match operation {
+            Operation::Crypt(_) => self.key_id.get() == KeyId::RamKey,
+            Operation::GenerateMac | Operation::VerifyMac => self.mac_key_id.get() == KeyId::RamKey,
+            _ => false,
+        }
+    }
+
+    fn begin(&self, operation: Operation) -> Result<(), ErrorCode> {
+        self.operation.set(operation);
+        self.position.set(0);
+        let result = if self.uses_ram_key(operation) && !self.ram_key_loaded.get() {
+            let key = self.key.get();
+            self.write_bytes(PAGE, AES128_KEY_SIZE, |i| key[i]);
+            self.launch(CsecCommand::LoadPlainKey, 0, KeyId::RamKey as u8)
+        } else {
+            self.next_command()
+        };
+        if result.is_err() {
+            self.operation.set(Operation::Idle);
+        }
+        result
+    }
+
+    /// Issue the next command of the current operation.
+    fn next_command(&self) -> Result<(), ErrorCode> {
+        let position = self.position.get();
+        let sequence = u8::from(position != 0);
+        match self.operation.get() {
+            Operation::Idle => Ok(()),
+            Operation::Crypt(mode) => {
+                let total = self.length.get();
+                let cbc = mode.chaining == Chaining::Cbc;
+                let first_page = if cbc && position == 0 { 2 } else { 1 };
+                let length = cmp::min(total - position, (PAGES - first_page) * PAGE);
+                if position == 0 {
+                    self.pram.word[3].set((total / PAGE) as u32);
+                    if cbc {
+                        let iv = self.chain.get();
+                        self.write_bytes(PAGE, PAGE, |i| iv[i]);
+                    }
+                }
+                let offset = first_page * PAGE;
+                if mode.chaining == Chaining::Ctr {
+                    // Encrypt the counter blocks; the data is XORed with
+                    // the result in `command_output`.
+                    let mut counter = self.chain.get();
+                    for block in (0..length).step_by(PAGE) {
+                        self.write_bytes(offset + block, PAGE, |i| counter[i]);
+                        counter = u128::from_be_bytes(counter).wrapping_add(1).to_be_bytes();
+                    }
+                    self.chain.set(counter);
+                } else {
+                    let written = self
+                        .source
+                        .map(|source| self.write_bytes(offset, length, |i| source[position + i]));
+                    if written.is_none() {
+                        let start = self.start.get() + position;
+                        self.data
+                            .map(|data| self.write_bytes(offset, length, |i| data[start + i]));
+                    }
+                }
+                self.chunk.set((first_page, length));
+                let command = match (mode.chaining, mode.encrypting) {
+                    (Chaining::Ecb, true) | (Chaining::Ctr, _) => CsecCommand::EncEcb,
+                    (Chaining::Ecb, false) => CsecCommand::DecEcb,
+                    (Chaining::Cbc, true) => CsecCommand::EncCbc,
+                    (Chaining::Cbc, false) => CsecCommand::DecCbc,
+                };
+                self.launch(command, sequence, self.key_id.get() as u8)
+            }
+            operation @ (Operation::GenerateMac | Operation::VerifyMac) => {
+                let message = self.length.get();
+                // The MAC to verify starts on the page after the message.
+                let padded = message.div_ceil(PAGE) * PAGE;
+                let total = match operation {
+                    Operation::VerifyMac => padded + CMAC_LENGTH,
+                    _ => padded,
+                };
+                let length = cmp::min(total - position, (PAGES - 1) * PAGE);
+                if position == 0 {
+                    self.pram.word[3].set((message * 8) as u32);
+                    if operation == Operation::VerifyMac {
+                        self.pram.word[2].set((CMAC_LENGTH * 8) as u32);
+                    }
+                }
+                self.data.map(|data| {
+                    self.mac.map(|mac| {
+                        self.write_bytes(PAGE, length, |i| match position + i {
+                            index if index < message => data[index],
+                            index if index >= padded => mac[index - padded],
+                            _ => 0,
+                        })
+                    })
+                });
+                self.chunk.set((1, length));
+                let command = match operation {
+                    Operation::VerifyMac => CsecCommand::VerifyMac,
+                    _ => CsecCommand::GenerateMac,
+                };
+                self.launch(command, sequence, self.mac_key_id.get() as u8)
+            }
+            Operation::LoadKey(key) => self.data.map_or(Err(ErrorCode::NOMEM), |buffer| {
+                self.write_bytes(PAGE, KEY_UPDATE_M4_OFFSET, |i| buffer[i]);
+                self.launch(CsecCommand::LoadKey, 0, key as u8)
+            }),
+            Operation::Random => {
+                if self.rng_seeded.get() {
+                    self.launch(CsecCommand::Rnd, 0, 0)
+                } else {
+                    self.launch(CsecCommand::InitRng, 0, 0)
+                }
+            }
+        }
+    }
+
+    /// Collect the output of the command that has just completed. Returns
+    /// whether the operation has more commands to issue.
+    fn command_output(&self) -> bool {
+        let position = self.position.get();
+        let (first_page, length) = self.chunk.get();
+        match self.operation.get() {
+            Operation::Crypt(mode) => {
+                let total = self.length.get();
+                let last = position + length == total;
+                let start = self.start.get();
+                let cbc = mode.chaining == Chaining::Cbc;
+                self.data.map(|data| {
+                    if mode.chaining == Chaining::Ctr {
+                        for block in (0..length).step_by(PAGE) {
+                            let mut keystream = [0; PAGE];
+                            self.read_bytes(first_page * PAGE + block, &mut keystream);
+                            for (i, byte) in keystream.iter().enumerate() {
+                                let index = position + block + i;
+                                let input = self
+                                    .source
+                                    .map_or(data[start + index], |source| source[index]);
+                                data[start + index] = input ^ byte;
+                            }
+                        }
+                        return;
+                    }
+                    if last && cbc && !mode.encrypting {
+                        // Decrypting in CBC mode chains on the last
+                        // ciphertext block, which is about to be overwritten.
+                        let mut chain = [0; AES128_BLOCK_SIZE];
+                        let block = total - AES128_BLOCK_SIZE;
+                        match self.source.map(|source| {
+                            chain.copy_from_slice(&source[block..total]);
+                        }) {
+                            Some(()) => {}
+                            None => chain.copy_from_slice(&data[start + block..start + total]),
+                        }
+                        self.chain.set(chain);
+                    }
+                    let range = start + position..start + position + length;
+                    self.read_bytes(first_page * PAGE, &mut data[range]);
+                    if last && cbc && mode.encrypting {
+                        let mut chain = [0; AES128_BLOCK_SIZE];
+                        chain.copy_from_slice(
+                            &data[start + total - AES128_BLOCK_SIZE..start + total],
+                        );
+                        self.chain.set(chain);
+                    }
+                });
+                self.position.set(position + length);
+                !last
+            }
+            Operation::GenerateMac => {
+                self.position.set(position + length);
+                if self.position.get() < self.length.get() {
+                    return true;
+                }
+                self.mac.map(|mac| self.read_bytes(2 * PAGE, &mut mac[..]));
+                false
+            }
+            Operation::VerifyMac => {
+                self.position.set(position + length);
+                if self.position.get() < self.length.get().div_ceil(PAGE) * PAGE + CMAC_LENGTH {
+                    return true;
+                }
+                let status = (self.pram.word[5].get() >> 16) as u16;
+                self.mac_valid.set(status == 0);
+                false
+            }
+            Operation::LoadKey(_) => {
+                self.data.map(|buffer| {
+                    self.read_bytes(
+                        5 * PAGE,
+                        &mut buffer[KEY_UPDATE_M4_OFFSET..KEY_UPDATE_LENGTH],
+                    )
+                });
+                false
+            }
+            Operation::Random | Operation::Idle => false,
+        }
+    }
+
+    /// Finish the current operation and report `result` to its client.
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        let operation = self.operation.get();
+        self.operation.set(Operation::Idle);
+        match operation {
+            Operation::Idle => {}
+            Operation::Crypt(_) => {
+                // The AES HIL has no way to report an error; the client gets
+                // its buffers back either way.
+                let source = self.source.take();
+                self.data.take().map(|data| {
+                    self.aes_client
+                        .map(move |client| client.crypt_done(source, data));
+                });
+            }
+            Operation::GenerateMac => {
+                if let (Some(data), Some(mac)) = (self.data.take(), self.mac.take()) {
+                    self.cmac_client
+                        .map(move |client| client.mac_done(result, data, mac));
+                }
+            }
+            Operation::VerifyMac => {
+                let result = result.map(|()| self.mac_valid.get());
+                if let (Some(data), Some(mac)) = (self.data.take(), self.mac.take()) {
+                    self.cmac_client
+                        .map(move |client| client.verify_done(result, data, mac));
+                }
+            }
+            Operation::LoadKey(_) => {
+                self.data.take().map(|buffer| {
+                    self.key_client
+                        .map(move |client| client.load_key_done(result, buffer));
+                });
+            }
+            Operation::Random => {
+                let mut words = [0; 4];
+                for (i, word) in words.iter_mut().enumerate() {
+                    *word = self.pram.word[4 + i].get();
+                }
+                let result = if self.rng_cancelled.replace(false) {
+                    Err(ErrorCode::CANCEL)
+                } else {
+                    result
+                };
+                let available = if result.is_ok() { words.len() } else { 0 };
+                let more = self.rng_client.map_or(false, |client| {
+                    let mut entropy = words.iter().copied().take(available);
+                    client.entropy_available(&mut entropy, result) == Continue::More
+                });
+                if result.is_ok() && more {
+                    self.rng_pending.set(true);
+                }
+            }
+        }
+
+        if self.operation.get() == Operation::Idle && self.rng_pending.replace(false) {
+            if let Err(e) = self.begin(Operation::Random) {
+                self.rng_client.map(|client| {
+                    client.entropy_available(&mut core::iter::empty(), Err(e));
+                });
+            }
+        }
+    }
+}
+
+impl CommandClient for Csec<'_> {
+    fn command_complete(&self, result: Result<(), ErrorCode>) {
+        if self.operation.get() == Operation::Idle {
+            return;
+        }
+        let erc = (self.pram.word[1].get() >> 16) as u16;
+        self.last_erc.set(erc);
+        if let Err(e) = result.and_then(|()| erc_to_result(erc)) {
+            self.finish(Err(e));
+            return;
+        }
+
+        let next = match self.command.get() {
+            CsecCommand::LoadPlainKey => {
+                self.ram_key_loaded.set(true);
+                self.next_command()
+            }
+            CsecCommand::InitRng => {
+                self.rng_seeded.set(true);
+                self.next_command()
+            }
+            _ => {
+                if !self.command_output() {
+                    self.finish(Ok(()));
+                    return;
+                }
+                self.next_command()
+            }
+        };
+        if let Err(e) = next {
+            self.finish(Err(e));
+        }
+    }
+}
+
+impl<'a> AES128<'a> for Csec<'a> {
+    fn enable(&self) {}
+
+    fn disable(&self) {}
+
+    fn set_client(&'a self, client: &'a dyn symmetric_encryption::Client<'a>) {
+        self.aes_client.set(client);
+    }
+
+    fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode> {
+        if key.len() != AES128_KEY_SIZE {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let mut bytes = [0; AES128_KEY_SIZE];
+        bytes.copy_from_slice(key);
+        self.key.set(bytes);
+        self.key_id.set(KeyId::RamKey);
+        self.ram_key_loaded.set(false);
+        Ok(())
+    }
+
+    fn set_iv(&self, iv: &[u8]) -> Result<(), ErrorCode> {
+        if iv.len() != AES128_BLOCK_SIZE {
+            return Err(ErrorCode::INVAL);
+        }
+        let mut bytes = [0; AES128_BLOCK_SIZE];
+        bytes.copy_from_slice(iv);
+        self.iv.set(bytes);
+        Ok(())
+    }
+
+    fn start_message(&self) {
+        if let Operation::Crypt(_) = self.operation.get() {
+            return;
+        }
+        self.chain.set(self.iv.get());
+    }
+
+    fn crypt(
+        &self,
+        source: Option<&'static mut [u8]>,
+        dest: &'static mut [u8],
+        start_index: usize,
+        stop_index: usize,
+    ) -> Option<(
+        Result<(), ErrorCode>,
+        Option<&'static mut [u8]>,
+        &'static mut [u8],
+    )> {
+        if self.operation.get() != Operation::Idle {
+            return Some((Err(ErrorCode::BUSY), source, dest));
+        }
+        let mode = match self.mode.get() {
+            Some(mode) => mode,
+            None => return Some((Err(ErrorCode::INVAL), source, dest)),
+        };
+        if stop_index <= start_index
+            || stop_index > dest.len()
+            || (stop_index - start_index) % AES128_BLOCK_SIZE != 0
+            || source
+                .as_ref()
+                .is_some_and(|source| source.len() != stop_index - start_index)
+        {
+            return Some((Err(ErrorCode::INVAL), source, dest));
+        }
+
+        self.source.put(source);
+        self.data.replace(dest);
+        self.start.set(start_index);
+        self.length.set(stop_index - start_index);
+        match self.begin(Operation::Crypt(mode)) {
+            Ok(()) => None,
+            Err(e) => Some((Err(e), self.source.take(), self.data.take().unwrap())),
+        }
+    }
+}
+
+impl AES128ECB for Csec<'_> {
+    fn set_mode_aes128ecb(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.mode.set(Some(Mode {
+            chaining: Chaining::Ecb,
+            encrypting,
+        }));
+        Ok(())
+    }
+}
+
+impl AES128CBC for Csec<'_> {
+    fn set_mode_aes128cbc(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.mode.set(Some(Mode {
+            chaining: Chaining::Cbc,
+            encrypting,
+        }));
+        Ok(())
+    }
+}
+
+impl AES128Ctr for Csec<'_> {
+    /// Encryption and decryption are the same operation in counter mode.
+    fn set_mode_aes128ctr(&self, encrypting: bool) -> Result<(), ErrorCode> {
+        self.mode.set(Some(Mode {
+            chaining: Chaining::Ctr,
+            encrypting,
+        }));
+        Ok(())
+    }
+}
+
+impl<'a> Cmac<'a> for Csec<'a> {
+    fn set_client(&'a self, client: &'a dyn CmacClient) {
+        self.cmac_client.set(client);
+    }
+
+    fn set_mac_key(&self, key: KeyId) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.mac_key_id.set(key);
+        Ok(())
+    }
+
+    fn generate_mac(
+        &self,
+        data: &'static mut [u8],
+        length: usize,
+        mac: &'static mut [u8; CMAC_LENGTH],
+    ) -> Result<(), (ErrorCode, &'static mut [u8], &'static mut [u8; CMAC_LENGTH])> {
+        self.start_mac(Operation::GenerateMac, data, length, mac)
+    }
+
+    fn verify_mac(
+        &self,
+        data: &'static mut [u8],
+        length: usize,
+        mac: &'static mut [u8; CMAC_LENGTH],
+    ) -> Result<(), (ErrorCode, &'static mut [u8], &'static mut [u8; CMAC_LENGTH])> {
+        self.start_mac(Operation::VerifyMac, data, length, mac)
+    }
+}
+
+impl Csec<'_> {
+    fn start_mac(
+        &self,
+        operation: Operation,
+        data: &'static mut [u8],
+        length: usize,
+        mac: &'static mut [u8; CMAC_LENGTH],
+    ) -> Result<(), (ErrorCode, &'static mut [u8], &'static mut [u8; CMAC_LENGTH])> {
+        if self.operation.get() != Operation::Idle {
+            return Err((ErrorCode::BUSY, data, mac));
+        }
+        if length > data.len() {
+            return Err((ErrorCode::SIZE, data, mac));
+        }
+        self.data.replace(data);
+        self.mac.replace(mac);
+        self.length.set(length);
+        match self.begin(operation) {
+            Ok(()) => Ok(()),
+            Err(e) => Err((e, self.data.take().unwrap(), self.mac.take().unwrap())),
+        }
+    }
+}
+
+impl<'a> entropy::Entropy32<'a> for Csec<'a> {
+    fn get(&self) -> Result<(), ErrorCode> {
+        match self.operation.get() {
+            Operation::Idle => self.begin(Operation::Random),
+            Operation::Random => Ok(()),
+            _ => {
+                // Run once the current operation has finished.
+                self.rng_pending.set(true);
+                Ok(())
+            }
+        }
+    }
+
+    fn cancel(&self) -> Result<(), ErrorCode> {
+        self.rng_pending.set(false);
+        if self.operation.get() == Operation::Random {
+            self.rng_cancelled.set(true);
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn set_client(&'a self, client: &'a dyn entropy::Client32) {
+        self.rng_client.set(client);
+    }
+}
@@ -0,0 +1,248 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Emulated EEPROM (EEE) in FlexRAM.
+//!
+//! Once the FlexNVM block has been partitioned with an EEPROM backup and
+//! FlexRAM has been switched to its EEPROM function (see
+//! `Ftfc::program_partition` and `Ftfc::set_flexram_function`), the start of
+//! FlexRAM behaves as byte-writable non-volatile memory. Reads are plain
+//! memory reads. Every write is backed up to FlexNVM by the FTFC, and FlexRAM
+//! must not be written again until that has finished: the FTFC clears
+//! `FSTAT[CCIF]` for the duration of the backup, and `FCNFG[EEERDY]` reports
+//! when the EEE can be used again.
+//!
+//! This driver writes one aligned word (or byte, at unaligned edges) at a
+//! time, skipping any that already hold the requested value, and issues the
+//! next write from the FTFC command-complete interrupt.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let eee = static_init!(
+//!     s32k144::eee::FlexRamEeprom<'static>,
+//!     s32k144::eee::FlexRamEeprom::new(&peripherals.nvmc, eee_size, csec_keys)
+//! );
+//! peripherals.nvmc.set_command_client(CommandOwner::Eeprom, eee);
+//! kernel::deferred_call::DeferredCallClient::register(eee);
+//! ```
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::nvmc::{CommandClient, CommandOwner, CsecKeySize, EeeSize, Ftfc};
+
+/// Start of FlexRAM in the memory map.
+pub const FLEXRAM_BASE: usize = 0x1400_0000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Read,
+    Write,
+}
+
+pub struct FlexRamEeprom<'a> {
+    ftfc: &'a Ftfc,
+    size: usize,
+    client: OptionalCell<&'a dyn NonvolatileStorageClient>,
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    address: Cell<usize>,
+    length: Cell<usize>,
+    /// Number of bytes of the current request already in FlexRAM.
+    position: Cell<usize>,
+    /// Size of the write in flight, starting at `position`.
+    pending: Cell<usize>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> FlexRamEeprom<'a> {
+    /// `size` is the emulated EEPROM of the partition, and `csec_keys` the
+    /// CSEc key storage it reserved, which takes up the top of it.
+    pub fn new(ftfc: &'a Ftfc, size: EeeSize, csec_keys: CsecKeySize) -> Self {
+        Self {
+            ftfc,
+            size: size.bytes().saturating_sub(csec_keys.bytes()),
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            state: Cell::new(State::Idle),
+            address: Cell::new(0),
+            length: Cell::new(0),
+            position: Cell::new(0),
+            pending: Cell::new(0),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Size of the emulated EEPROM available for data, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn check_request(&self, buffer: &[u8], address: usize, length: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if length > buffer.len()
+            || address
+                .checked_add(length)
+                .map_or(true, |end| end > self.size)
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        if !self.ftfc.eee_ready() {
+            return Err(ErrorCode::OFF);
+        }
+        Ok(())
+    }
+
+    /// Start the next write of the current request that changes FlexRAM.
+    /// Returns `Ok(false)` once all of the request is in FlexRAM.
+    fn write_next(&self) -> Result<bool, ErrorCode> {
+        self.buffer.map_or(Err(ErrorCode::NOMEM), |buffer| {
+            let base = self.address.get();
+            let length = self.length.get();
+            let mut position = self.position.get();
+
+            while position < length {
+                let address = FLEXRAM_BASE + base + position;
+                if address % 4 == 0 && length - position >= 4 {
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&buffer[position..position + 4]);
+                    let word = u32::from_le_bytes(bytes);
+                    let ptr = address as *mut u32;
+                    if unsafe { core::ptr::read_volatile(ptr) } != word {
+                        self.ftfc.delegate(CommandOwner::Eeprom, || unsafe {
+                            core::ptr::write_volatile(ptr, word)
+                        })?;
+                        self.position.set(position);
+                        self.pending.set(4);
+                        return Ok(true);
+                    }
+                    position += 4;
+                } else {
+                    let byte = buffer[position];
+                    let ptr = address as *mut u8;
+                    if unsafe { core::ptr::read_volatile(ptr) } != byte {
+                        self.ftfc.delegate(CommandOwner::Eeprom, || unsafe {
+                            core::ptr::write_volatile(ptr, byte)
+                        })?;
+                        self.position.set(position);
+                        self.pending.set(1);
+                        return Ok(true);
+                    }
+                    position += 1;
+                }
+            }
+
+            self.position.set(length);
+            Ok(false)
+        })
+    }
+
+    /// Finish the current request, reporting how much of it was completed.
+    fn done(&self) {
+        let state = self.state.get();
+        self.state.set(State::Idle);
+        let length = match state {
+            State::Write => self.position.get(),
+            _ => self.length.get(),
+        };
+        self.buffer.take().map(|buffer| {
+            self.client.map(move |client| match state {
+                State::Read => client.read_done(buffer, length),
+                _ => client.write_done(buffer, length),
+            });
+        });
+    }
+}
+
+impl<'a> NonvolatileStorage<'a> for FlexRamEeprom<'a> {
+    fn set_client(&self, client: &'a dyn NonvolatileStorageClient) {
+        self.client.set(client);
+    }
+
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        self.check_request(buffer, address, length)?;
+
+        for (i, byte) in buffer[..length].iter_mut().enumerate() {
+            let ptr = (FLEXRAM_BASE + address + i) as *const u8;
+            *byte = unsafe { core::ptr::read_volatile(ptr) };
+        }
+
+        self.buffer.replace(buffer);
+        self.address.set(address);
+        self.length.set(length);
+        self.state.set(State::Read);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        buffer: &'static mut [u8],
+        address: usize,
+        length: usize,
+    ) -> Result<(), ErrorCode> {
+        self.check_request(buffer, address, length)?;
+
+        self.buffer.replace(buffer);
+        self.address.set(address);
+        self.length.set(length);
+        self.position.set(0);
+        self.state.set(State::Write);
+
+        match self.write_next() {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                // Everything already held the requested value.
+                self.deferred_call.set();
+                Ok(())
+            }
+            Err(e) => {
+                self.state.set(State::Idle);
+                self.buffer.take();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl CommandClient for FlexRamEeprom<'_> {
+    fn command_complete(&self, result: Result<(), ErrorCode>) {
+        if self.state.get() != State::Write {
+            return;
+        }
+        if result.is_err() || !self.ftfc.eee_ready() {
+            // The last write did not make it to the EEPROM backup.
+            self.done();
+            return;
+        }
+        self.position.set(self.position.get() + self.pending.get());
+        match self.write_next() {
+            Ok(true) => {}
+            Ok(false) | Err(_) => self.done(),
+        }
+    }
+}
+
+impl DeferredCallClient for FlexRamEeprom<'_> {
+    fn handle_deferred_call(&self) {
+        self.done();
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -0,0 +1,92 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Error Injection Module (EIM) for the S32K144.
+//!
+//! While a channel is enabled, every read from its SRAM has the bits of its
+//! data and check masks flipped before the ECC decoder sees them. One
+//! flipped bit is a correctable error, two are an uncorrectable one. The
+//! memory itself is not changed. Channel 0 is SRAM_L and channel 1 SRAM_U,
+//! as for the ERM.
+
+use kernel::utilities::registers::interfaces::Writeable;
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite};
+use kernel::utilities::StaticRef;
+
+use crate::erm::Channel;
+
+register_structs! {
+    EimRegisters {
+        /// Error Injection Module Configuration Register
+        (0x000 => eimcr: ReadWrite<u32, EIMCR::Register>),
+        /// Error Injection Channel Enable register
+        (0x004 => eichen: ReadWrite<u32, EICHEN::Register>),
+        (0x008 => _reserved0),
+        /// Channel 0 check bit mask
+        (0x100 => eichd0_word0: ReadWrite<u32, WORD0::Register>),
+        /// Channel 0 data mask
+        (0x104 => eichd0_word1: ReadWrite<u32>),
+        (0x108 => _reserved1),
+        /// Channel 1 check bit mask
+        (0x200 => eichd1_word0: ReadWrite<u32, WORD0::Register>),
+        /// Channel 1 data mask
+        (0x204 => eichd1_word1: ReadWrite<u32>),
+        (0x208 => @END),
+    }
+}
+
+register_bitfields![u32,
+    EIMCR [
+        /// Global error injection enable
+        GEIEN OFFSET(0) NUMBITS(1) []
+    ],
+    EICHEN [
+        EICH1EN OFFSET(30) NUMBITS(1) [],
+        EICH0EN OFFSET(31) NUMBITS(1) []
+    ],
+    WORD0 [
+        CHKBIT_MASK OFFSET(25) NUMBITS(7) []
+    ]
+];
+
+const EIM_BASE: StaticRef<EimRegisters> =
+    unsafe { StaticRef::new(0x4001_9000 as *const EimRegisters) };
+
+pub struct Eim {
+    registers: StaticRef<EimRegisters>,
+}
+
+impl Eim {
+    pub const fn new() -> Self {
+        Self {
+            registers: EIM_BASE,
+        }
+    }
+
+    /// Flip the bits of `data_mask` in the data and `check_mask` in the
+    /// seven check bits of every read from the SRAM of `channel`, until
+    /// [`Eim::stop`].
+    pub fn inject(&self, channel: Channel, data_mask: u32, check_mask: u8) {
+        let check_mask = WORD0::CHKBIT_MASK.val(u32::from(check_mask));
+        match channel {
+            Channel::SramL => {
+                self.registers.eichd0_word0.write(check_mask);
+                self.registers.eichd0_word1.set(data_mask);
+                self.registers.eichen.write(EICHEN::EICH0EN::SET);
+            }
+            Channel::SramU => {
+                self.registers.eichd1_word0.write(check_mask);
+                self.registers.eichd1_word1.set(data_mask);
+                self.registers.eichen.write(EICHEN::EICH1EN::SET);
+            }
+        }
+        self.registers.eimcr.write(EIMCR::GEIEN::SET);
+    }
+
+    /// Stop injecting errors on all channels.
+    pub fn stop(&self) {
+        self.registers.eimcr.set(0);
+        self.registers.eichen.set(0);
+    }
+}
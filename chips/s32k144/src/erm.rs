@@ -0,0 +1,247 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! ECC error reporting for the S32K144 memories.
+//!
+//! The Error Reporting Module (ERM) records single-bit corrections and
+//! uncorrectable errors of the SRAM_L and SRAM_U ECC decoders, along with the
+//! address of the last access that hit one. Flash ECC errors are not seen by
+//! the ERM: the FTFC only flags uncorrectable ones, without an address, and
+//! the chip forwards those here so a client sees all ECC errors in one place.
+//!
+//! An uncorrectable error also terminates the failing access with a bus
+//! error, so the core faults on it directly; the report from here covers
+//! accesses by other bus masters and lets the client deal with whatever owns
+//! the memory. Corrections are only counted, for health monitoring.
+//!
+//! [`Erm::self_test`] uses the EIM to check at startup that a corrupted read
+//! is corrected and reported.
+
+use core::cell::Cell;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadOnly, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::eim::Eim;
+
+register_structs! {
+    ErmRegisters {
+        /// ERM Configuration Register 0
+        (0x000 => cr0: ReadWrite<u32, CR0::Register>),
+        (0x004 => _reserved0),
+        /// ERM Status Register 0
+        (0x010 => sr0: ReadWrite<u32, SR0::Register>),
+        (0x014 => _reserved1),
+        /// ERM Memory 0 Error Address Register
+        (0x100 => ear0: ReadOnly<u32>),
+        (0x104 => _reserved2),
+        /// ERM Memory 1 Error Address Register
+        (0x110 => ear1: ReadOnly<u32>),
+        (0x114 => @END),
+    }
+}
+
+register_bitfields![u32,
+    CR0 [
+        /// Memory 1 non-correctable error interrupt enable
+        ENCIE1 OFFSET(26) NUMBITS(1) [],
+        /// Memory 1 single-bit correction interrupt enable
+        ESCIE1 OFFSET(27) NUMBITS(1) [],
+        /// Memory 0 non-correctable error interrupt enable
+        ENCIE0 OFFSET(30) NUMBITS(1) [],
+        /// Memory 0 single-bit correction interrupt enable
+        ESCIE0 OFFSET(31) NUMBITS(1) []
+    ],
+    SR0 [
+        /// Memory 1 non-correctable error event
+        NCE1 OFFSET(26) NUMBITS(1) [],
+        /// Memory 1 single-bit correction event
+        SBC1 OFFSET(27) NUMBITS(1) [],
+        /// Memory 0 non-correctable error event
+        NCE0 OFFSET(30) NUMBITS(1) [],
+        /// Memory 0 single-bit correction event
+        SBC0 OFFSET(31) NUMBITS(1) []
+    ]
+];
+
+const ERM_BASE: StaticRef<ErmRegisters> =
+    unsafe { StaticRef::new(0x4001_8000 as *const ErmRegisters) };
+
+/// An SRAM with its own ECC decoder, ERM channel and EIM channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// `0x1FFF_8000` to `0x1FFF_FFFF`
+    SramL = 0,
+    /// `0x2000_0000` to `0x2000_6FFF`
+    SramU = 1,
+}
+
+impl Channel {
+    /// The SRAM holding `address`.
+    pub fn of(address: u32) -> Option<Channel> {
+        match address {
+            0x1FFF_8000..=0x1FFF_FFFF => Some(Channel::SramL),
+            0x2000_0000..=0x2000_6FFF => Some(Channel::SramU),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Memory {
+    Sram(Channel),
+    Flash,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EccError {
+    pub memory: Memory,
+    /// Address of the access, for the SRAMs.
+    pub address: Option<u32>,
+    /// Whether the data was corrected, as opposed to lost.
+    pub correctable: bool,
+}
+
+pub trait EccClient {
+    fn ecc_error(&self, error: EccError);
+}
+
+/// Data read back by the self-test. Any value works; this one has set and
+/// cleared bits in every byte.
+const SELF_TEST_PATTERN: u32 = 0xA55A_5AA5;
+
+pub struct Erm<'a> {
+    registers: StaticRef<ErmRegisters>,
+    client: OptionalCell<&'a dyn EccClient>,
+    corrected: [Cell<u32>; 2],
+    last_error: OptionalCell<EccError>,
+}
+
+impl<'a> Erm<'a> {
+    pub const fn new() -> Self {
+        Self {
+            registers: ERM_BASE,
+            client: OptionalCell::empty(),
+            corrected: [Cell::new(0), Cell::new(0)],
+            last_error: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn EccClient) {
+        self.client.set(client);
+    }
+
+    /// Interrupt on every correction and uncorrectable error of both SRAMs.
+    pub fn enable(&self) {
+        self.clear_events();
+        self.registers
+            .cr0
+            .write(CR0::ESCIE0::SET + CR0::ENCIE0::SET + CR0::ESCIE1::SET + CR0::ENCIE1::SET);
+    }
+
+    /// Number of single-bit errors corrected in the SRAM of `channel` since
+    /// reset.
+    pub fn corrected_count(&self, channel: Channel) -> u32 {
+        self.corrected[channel as usize].get()
+    }
+
+    /// The most recent error, corrected or not.
+    pub fn last_error(&self) -> Option<EccError> {
+        self.last_error.get()
+    }
+
+    /// Handle both the single-bit and the non-correctable ERM interrupt.
+    pub fn handle_interrupt(&self) {
+        let status = self.registers.sr0.extract();
+        let channels = [
+            (
+                Channel::SramL,
+                SR0::SBC0,
+                SR0::NCE0,
+                self.registers.ear0.get(),
+            ),
+            (
+                Channel::SramU,
+                SR0::SBC1,
+                SR0::NCE1,
+                self.registers.ear1.get(),
+            ),
+        ];
+        for (channel, sbc, nce, address) in channels {
+            if status.is_set(sbc) {
+                self.registers.sr0.write(sbc.val(1));
+                let count = &self.corrected[channel as usize];
+                count.set(count.get().saturating_add(1));
+                self.report(Memory::Sram(channel), Some(address), true);
+            }
+            if status.is_set(nce) {
+                self.registers.sr0.write(nce.val(1));
+                self.report(Memory::Sram(channel), Some(address), false);
+            }
+        }
+    }
+
+    /// Report an uncorrectable flash ECC error flagged by the FTFC.
+    pub(crate) fn flash_fault(&self) {
+        self.report(Memory::Flash, None, false);
+    }
+
+    fn report(&self, memory: Memory, address: Option<u32>, correctable: bool) {
+        let error = EccError {
+            memory,
+            address,
+            correctable,
+        };
+        self.last_error.set(error);
+        self.client.map(|client| client.ecc_error(error));
+    }
+
+    fn clear_events(&self) {
+        self.registers
+            .sr0
+            .write(SR0::SBC0::SET + SR0::NCE0::SET + SR0::SBC1::SET + SR0::NCE1::SET);
+    }
+
+    /// Startup diagnostic of the ECC of the SRAM holding `word`: write it,
+    /// read it back with one data bit flipped by the EIM, and check that the
+    /// value read is the one written and that the ERM saw the correction.
+    ///
+    /// Only a correctable error is injected, since reading an uncorrectable
+    /// one would fault. `word` is overwritten, and the check neither counts
+    /// as a correction nor reaches the client.
+    pub fn self_test(&self, eim: &Eim, word: &Cell<u32>) -> Result<(), ErrorCode> {
+        let pointer = word.as_ptr();
+        let channel = Channel::of(pointer as u32).ok_or(ErrorCode::INVAL)?;
+        let corrected = match channel {
+            Channel::SramL => SR0::SBC0,
+            Channel::SramU => SR0::SBC1,
+        };
+
+        let interrupts = self.registers.cr0.get();
+        self.registers.cr0.set(0);
+        self.clear_events();
+
+        // SAFETY: `pointer` comes from a reference, so it is valid and
+        // aligned. Volatile accesses make sure the read really goes to the
+        // SRAM while the EIM is injecting.
+        unsafe { core::ptr::write_volatile(pointer, SELF_TEST_PATTERN) };
+        eim.inject(channel, 1, 0);
+        let read = unsafe { core::ptr::read_volatile(pointer) };
+        eim.stop();
+
+        let reported = self.registers.sr0.is_set(corrected);
+        // Reads of other variables in the same SRAM while the EIM was
+        // injecting may have raised more events; none of them are real.
+        self.clear_events();
+        self.registers.cr0.set(interrupts);
+
+        if read == SELF_TEST_PATTERN && reported {
+            Ok(())
+        } else {
+            Err(ErrorCode::FAIL)
+        }
+    }
+}
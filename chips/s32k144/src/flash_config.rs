@@ -0,0 +1,236 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Flash Configuration Field (FCF) for the S32K144.
+//!
+//! The 16 bytes at 0x400 of the P-Flash are loaded into the FTFC on every
+//! reset: the backdoor comparison key, the P-Flash, D-Flash and EEPROM
+//! protection, the security byte (FSEC) and the chip options (FOPT). A
+//! wrong FSEC can secure the part with mass erase disabled and no backdoor
+//! key, after which it can never be erased or debugged again, so
+//! [`FlashConfig::build`] refuses such a value at compile time.
+//!
+//! The board places the field with [`flash_config!`], and its linker
+//! script keeps the `.flash_config` section at 0x400.
+
+/// Whether the flash and debug access are secured after reset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Security {
+    Unsecure,
+    Secure,
+}
+
+/// Contents of the Flash Configuration Field, built at compile time.
+#[derive(Clone, Copy)]
+pub struct FlashConfig {
+    backdoor_key: Option<[u8; 8]>,
+    security: Security,
+    mass_erase: bool,
+    factory_access: bool,
+    nmi_pin: bool,
+    reset_pin: bool,
+    pflash_protected: u32,
+    dflash_protected: u8,
+    eeprom_protected: u8,
+}
+
+// FSEC fields. For SEC, MEEN and KEYEN only one encoding selects the
+// non-default behaviour; every other value means the opposite.
+const FSEC_SEC_UNSECURE: u8 = 0b10;
+const FSEC_SEC_SECURE: u8 = 0b11;
+const FSEC_FSLACC_GRANTED: u8 = 0b11 << 2;
+const FSEC_FSLACC_DENIED: u8 = 0b10 << 2;
+const FSEC_MEEN_ENABLED: u8 = 0b11 << 4;
+const FSEC_MEEN_DISABLED: u8 = 0b10 << 4;
+const FSEC_KEYEN_ENABLED: u8 = 0b10 << 6;
+const FSEC_KEYEN_DISABLED: u8 = 0b11 << 6;
+
+// FOPT fields. The other bits are reserved and kept at their erased value,
+// except bit 7 which the reference startup code clears.
+const FOPT_RESERVED: u8 = 0x73;
+const FOPT_NMI_PIN_CFG: u8 = 1 << 2;
+const FOPT_RESET_PIN_CFG: u8 = 1 << 3;
+
+impl FlashConfig {
+    /// The erased-flash defaults: unsecure, mass erase enabled, no backdoor
+    /// key, nothing protected, NMI and RESET pins enabled.
+    pub const fn new() -> Self {
+        Self {
+            backdoor_key: None,
+            security: Security::Unsecure,
+            mass_erase: true,
+            factory_access: true,
+            nmi_pin: true,
+            reset_pin: true,
+            pflash_protected: 0,
+            dflash_protected: 0,
+            eeprom_protected: 0,
+        }
+    }
+
+    pub const fn security(mut self, security: Security) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Allow the debugger's mass erase command, which unsecures the part.
+    pub const fn mass_erase(mut self, enabled: bool) -> Self {
+        self.mass_erase = enabled;
+        self
+    }
+
+    /// Allow NXP factory access to a secured part.
+    pub const fn factory_access(mut self, granted: bool) -> Self {
+        self.factory_access = granted;
+        self
+    }
+
+    /// Enable the backdoor key, which software can present to the FTFC to
+    /// unsecure the part until the next reset. A key of all zeroes or all
+    /// ones is rejected by the FTFC, and by [`FlashConfig::build`].
+    pub const fn backdoor_key(mut self, key: [u8; 8]) -> Self {
+        self.backdoor_key = Some(key);
+        self
+    }
+
+    /// Route PTD3 to the NMI after reset. With the pin left floating, a
+    /// disabled NMI avoids spurious NMIs during boot.
+    pub const fn nmi_pin(mut self, enabled: bool) -> Self {
+        self.nmi_pin = enabled;
+        self
+    }
+
+    /// Route PTA5 to the RESET input after reset, instead of GPIO.
+    pub const fn reset_pin(mut self, enabled: bool) -> Self {
+        self.reset_pin = enabled;
+        self
+    }
+
+    /// Write-protect P-Flash regions: bit `n` protects the `n`th 32nd of the
+    /// P-Flash.
+    pub const fn pflash_protection(mut self, regions: u32) -> Self {
+        self.pflash_protected = regions;
+        self
+    }
+
+    /// Write-protect D-Flash regions: bit `n` protects the `n`th 8th of the
+    /// D-Flash.
+    pub const fn dflash_protection(mut self, regions: u8) -> Self {
+        self.dflash_protected = regions;
+        self
+    }
+
+    /// Write-protect EEPROM regions: bit `n` protects the `n`th 8th of the
+    /// emulated EEPROM.
+    pub const fn eeprom_protection(mut self, regions: u8) -> Self {
+        self.eeprom_protected = regions;
+        self
+    }
+
+    const fn valid_key(key: [u8; 8]) -> bool {
+        let mut zeroes = true;
+        let mut ones = true;
+        let mut i = 0;
+        while i < key.len() {
+            zeroes &= key[i] == 0x00;
+            ones &= key[i] == 0xFF;
+            i += 1;
+        }
+        !zeroes && !ones
+    }
+
+    /// The 16 bytes of the field. Panics, so fails to compile in a constant,
+    /// if the part would be secured with no way to unsecure it.
+    pub const fn build(self) -> [u8; 16] {
+        let key_enabled = match self.backdoor_key {
+            Some(key) => {
+                assert!(
+                    Self::valid_key(key),
+                    "backdoor key must not be all zeroes or all ones"
+                );
+                true
+            }
+            None => false,
+        };
+        assert!(
+            !(matches!(self.security, Security::Secure) && !self.mass_erase && !key_enabled),
+            "flash configuration secures the part with mass erase disabled and no backdoor key, \
+             which locks it permanently"
+        );
+
+        let fsec = match self.security {
+            Security::Unsecure => FSEC_SEC_UNSECURE,
+            Security::Secure => FSEC_SEC_SECURE,
+        } | if self.factory_access {
+            FSEC_FSLACC_GRANTED
+        } else {
+            FSEC_FSLACC_DENIED
+        } | if self.mass_erase {
+            FSEC_MEEN_ENABLED
+        } else {
+            FSEC_MEEN_DISABLED
+        } | if key_enabled {
+            FSEC_KEYEN_ENABLED
+        } else {
+            FSEC_KEYEN_DISABLED
+        };
+        let fopt = FOPT_RESERVED
+            | if self.nmi_pin { FOPT_NMI_PIN_CFG } else { 0 }
+            | if self.reset_pin {
+                FOPT_RESET_PIN_CFG
+            } else {
+                0
+            };
+
+        let key = match self.backdoor_key {
+            Some(key) => key,
+            None => [0xFF; 8],
+        };
+        // A set FPROT bit leaves the region unprotected. FPROT0 holds the
+        // lowest regions and sits at the highest address.
+        let fprot = (!self.pflash_protected).to_be_bytes();
+        [
+            key[0],
+            key[1],
+            key[2],
+            key[3],
+            key[4],
+            key[5],
+            key[6],
+            key[7],
+            fprot[0],
+            fprot[1],
+            fprot[2],
+            fprot[3],
+            fsec,
+            fopt,
+            !self.eeprom_protected,
+            !self.dflash_protected,
+        ]
+    }
+}
+
+impl Default for FlashConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Place the Flash Configuration Field built from `$config`, a constant
+/// [`FlashConfig`](crate::flash_config::FlashConfig), in the
+/// `.flash_config` section.
+#[macro_export]
+macro_rules! flash_config {
+    ($config:expr) => {
+        #[cfg_attr(
+            all(target_arch = "arm", target_os = "none"),
+            link_section = ".flash_config"
+        )]
+        #[cfg_attr(all(target_arch = "arm", target_os = "none"), used)]
+        pub static FLASH_CONFIG: [u8; 16] = {
+            let config: $crate::flash_config::FlashConfig = $config;
+            config.build()
+        };
+    };
+}
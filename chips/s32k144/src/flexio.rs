@@ -0,0 +1,429 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! FlexIO for the S32K144.
+//!
+//! FlexIO is a set of four shifters and four timers that can be wired to
+//! eight pins, FXIO_D0 to FXIO_D7, and combined to emulate serial
+//! interfaces. This module owns the block and hands out shifters and timers
+//! to the emulated interfaces:
+//!
+//! - [`flexio_uart`](crate::flexio_uart): UART, one shifter and one timer
+//!   per direction.
+//! - [`flexio_spi`](crate::flexio_spi): SPI master, two shifters and one
+//!   timer.
+//! - [`flexio_i2c`](crate::flexio_i2c): I2C master, two shifters and two
+//!   timers.
+//!
+//! So a board can have, for example, a UART and a SPI bus, or an I2C bus
+//! and a SPI bus, at the same time. The board picks the interfaces it
+//! needs, muxes their port pins to FlexIO and calls each interface's
+//! `claim` to take its shifters and timers:
+//!
+//! ```rust,ignore
+//! let uart = static_init!(
+//!     s32k144::flexio_uart::FlexIoUart<'static>,
+//!     s32k144::flexio_uart::FlexIoUart::new(&peripherals.flexio, 0, 1)
+//! );
+//! uart.claim()?;
+//! kernel::deferred_call::DeferredCallClient::register(uart);
+//! ```
+//!
+//! FlexIO runs from FIRCDIV2 at 48 MHz, which bounds the bit rates the
+//! interfaces can produce.
+
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadOnly, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use core::cell::Cell;
+
+use crate::clock::{Clock, Peripheral};
+
+register_structs! {
+    pub FlexIoRegisters {
+        /// Version ID register
+        (0x000 => verid: ReadOnly<u32>),
+        /// Parameter register
+        (0x004 => param: ReadOnly<u32>),
+        /// FlexIO control register
+        (0x008 => ctrl: ReadWrite<u32, CTRL::Register>),
+        /// Pin state register
+        (0x00C => pub pin: ReadOnly<u32>),
+        /// Shifter status register (write 1 to clear)
+        (0x010 => pub shiftstat: ReadWrite<u32>),
+        /// Shifter error register (write 1 to clear)
+        (0x014 => pub shifterr: ReadWrite<u32>),
+        /// Timer status register (write 1 to clear)
+        (0x018 => pub timstat: ReadWrite<u32>),
+        (0x01C => _reserved0),
+        /// Shifter status interrupt enable
+        (0x020 => pub shiftsien: ReadWrite<u32>),
+        /// Shifter error interrupt enable
+        (0x024 => pub shifteien: ReadWrite<u32>),
+        /// Timer interrupt enable register
+        (0x028 => pub timien: ReadWrite<u32>),
+        (0x02C => _reserved1),
+        /// Shifter status DMA enable
+        (0x030 => shiftsden: ReadWrite<u32>),
+        (0x034 => _reserved2),
+        /// Shifter control registers
+        (0x080 => pub shiftctl: [ReadWrite<u32, SHIFTCTL::Register>; 4]),
+        (0x090 => _reserved3),
+        /// Shifter configuration registers
+        (0x100 => pub shiftcfg: [ReadWrite<u32, SHIFTCFG::Register>; 4]),
+        (0x110 => _reserved4),
+        /// Shifter buffers
+        (0x200 => pub shiftbuf: [ReadWrite<u32>; 4]),
+        (0x210 => _reserved5),
+        /// Shifter buffers, bit swapped
+        (0x280 => pub shiftbufbis: [ReadWrite<u32>; 4]),
+        (0x290 => _reserved6),
+        /// Shifter buffers, byte swapped
+        (0x300 => pub shiftbufbys: [ReadWrite<u32>; 4]),
+        (0x310 => _reserved7),
+        /// Shifter buffers, bit swapped within bytes
+        (0x380 => pub shiftbufbbs: [ReadWrite<u32>; 4]),
+        (0x390 => _reserved8),
+        /// Timer control registers
+        (0x400 => pub timctl: [ReadWrite<u32, TIMCTL::Register>; 4]),
+        (0x410 => _reserved9),
+        /// Timer configuration registers
+        (0x480 => pub timcfg: [ReadWrite<u32, TIMCFG::Register>; 4]),
+        (0x490 => _reserved10),
+        /// Timer compare registers
+        (0x500 => pub timcmp: [ReadWrite<u32>; 4]),
+        (0x510 => @END),
+    }
+}
+
+register_bitfields![u32,
+    CTRL [
+        /// FlexIO enable
+        FLEXEN OFFSET(0) NUMBITS(1) [],
+        /// Software reset
+        SWRST OFFSET(1) NUMBITS(1) [],
+        /// Fast access
+        FASTACC OFFSET(2) NUMBITS(1) [],
+        /// Debug enable
+        DBGE OFFSET(30) NUMBITS(1) []
+    ],
+    pub SHIFTCTL [
+        /// Shifter mode
+        SMOD OFFSET(0) NUMBITS(3) [
+            Disabled = 0,
+            Receive = 1,
+            Transmit = 2
+        ],
+        /// Shifter pin polarity
+        PINPOL OFFSET(7) NUMBITS(1) [
+            ActiveHigh = 0,
+            ActiveLow = 1
+        ],
+        /// Shifter pin select
+        PINSEL OFFSET(8) NUMBITS(3) [],
+        /// Shifter pin configuration
+        PINCFG OFFSET(16) NUMBITS(2) [
+            Disabled = 0,
+            OpenDrain = 1,
+            Bidirectional = 2,
+            Output = 3
+        ],
+        /// Timer polarity
+        TIMPOL OFFSET(23) NUMBITS(1) [
+            PosEdge = 0,
+            NegEdge = 1
+        ],
+        /// Timer select
+        TIMSEL OFFSET(24) NUMBITS(2) []
+    ],
+    pub SHIFTCFG [
+        /// Shifter start bit
+        SSTART OFFSET(0) NUMBITS(2) [
+            /// Load data on enable
+            Disabled = 0,
+            /// Load data on the first shift
+            DisabledShift = 1,
+            Zero = 2,
+            One = 3
+        ],
+        /// Shifter stop bit
+        SSTOP OFFSET(4) NUMBITS(2) [
+            Disabled = 0,
+            Zero = 2,
+            One = 3
+        ],
+        /// Input source
+        INSRC OFFSET(8) NUMBITS(1) [
+            Pin = 0,
+            NextShifter = 1
+        ]
+    ],
+    pub TIMCTL [
+        /// Timer mode
+        TIMOD OFFSET(0) NUMBITS(2) [
+            Disabled = 0,
+            DualBaud8 = 1,
+            DualPwm8 = 2,
+            Counter16 = 3
+        ],
+        /// Timer pin polarity
+        PINPOL OFFSET(7) NUMBITS(1) [
+            ActiveHigh = 0,
+            ActiveLow = 1
+        ],
+        /// Timer pin select
+        PINSEL OFFSET(8) NUMBITS(3) [],
+        /// Timer pin configuration
+        PINCFG OFFSET(16) NUMBITS(2) [
+            Disabled = 0,
+            OpenDrain = 1,
+            Bidirectional = 2,
+            Output = 3
+        ],
+        /// Trigger source
+        TRGSRC OFFSET(22) NUMBITS(1) [
+            External = 0,
+            Internal = 1
+        ],
+        /// Trigger polarity
+        TRGPOL OFFSET(23) NUMBITS(1) [
+            ActiveHigh = 0,
+            ActiveLow = 1
+        ],
+        /// Trigger select. For internal triggers, `4 * n + 1` is the status
+        /// flag of shifter `n`.
+        TRGSEL OFFSET(24) NUMBITS(4) []
+    ],
+    pub TIMCFG [
+        /// Timer start bit
+        TSTART OFFSET(1) NUMBITS(1) [],
+        /// Timer stop bit
+        TSTOP OFFSET(4) NUMBITS(2) [
+            Disabled = 0,
+            OnCompare = 1,
+            OnDisable = 2,
+            OnCompareAndDisable = 3
+        ],
+        /// Timer enable
+        TIMENA OFFSET(8) NUMBITS(3) [
+            Always = 0,
+            OnPreviousEnable = 1,
+            TriggerHigh = 2,
+            TriggerAndPinHigh = 3,
+            PinRisingEdge = 4,
+            PinRisingEdgeAndTriggerHigh = 5,
+            TriggerRisingEdge = 6,
+            TriggerBothEdges = 7
+        ],
+        /// Timer disable
+        TIMDIS OFFSET(12) NUMBITS(3) [
+            Never = 0,
+            OnPreviousDisable = 1,
+            OnCompare = 2,
+            OnCompareAndTriggerLow = 3,
+            PinBothEdges = 4,
+            PinBothEdgesAndTriggerHigh = 5,
+            TriggerFallingEdge = 6
+        ],
+        /// Timer reset
+        TIMRST OFFSET(16) NUMBITS(3) [
+            Never = 0,
+            PinEqualsOutput = 2,
+            TriggerEqualsOutput = 3,
+            PinRisingEdge = 4,
+            TriggerRisingEdge = 6,
+            TriggerBothEdges = 7
+        ],
+        /// Timer decrement
+        TIMDEC OFFSET(20) NUMBITS(2) [
+            /// Decrement on the FlexIO clock, shift on the timer output
+            FlexIoClock = 0,
+            /// Decrement on trigger edges, shift on the trigger
+            Trigger = 1,
+            /// Decrement on pin edges, shift on the pin
+            Pin = 2,
+            /// Decrement on trigger edges, shift on the trigger
+            TriggerShift = 3
+        ],
+        /// Timer output
+        TIMOUT OFFSET(24) NUMBITS(2) [
+            One = 0,
+            Zero = 1,
+            OneOnReset = 2,
+            ZeroOnReset = 3
+        ]
+    ]
+];
+
+const FLEXIO_BASE: StaticRef<FlexIoRegisters> =
+    unsafe { StaticRef::new(0x4005_A000 as *const FlexIoRegisters) };
+
+/// Frequency of the FlexIO functional clock.
+pub const CLOCK_HZ: u32 = 48_000_000;
+
+pub const NUM_SHIFTERS: usize = 4;
+pub const NUM_TIMERS: usize = 4;
+
+/// Number of interfaces that can share FlexIO.
+const MAX_DEVICES: usize = 4;
+
+/// Shifters and timers claimed by an emulated interface. Both are
+/// consecutive blocks, as some configurations refer to the previous timer.
+#[derive(Clone, Copy)]
+pub struct Resources {
+    /// The first claimed shifter.
+    pub shifter: usize,
+    /// The first claimed timer.
+    pub timer: usize,
+}
+
+/// Implemented by the emulated interfaces, which share the FlexIO interrupt.
+pub trait FlexIoDevice {
+    /// Check and handle the flags of this interface's shifters and timers.
+    fn handle_interrupt(&self);
+}
+
+pub struct FlexIo<'a> {
+    registers: StaticRef<FlexIoRegisters>,
+    clock: Clock,
+    /// Bitmask of the claimed shifters.
+    shifters: Cell<u8>,
+    /// Bitmask of the claimed timers.
+    timers: Cell<u8>,
+    devices: [OptionalCell<&'a dyn FlexIoDevice>; MAX_DEVICES],
+}
+
+impl<'a> FlexIo<'a> {
+    pub const fn new() -> Self {
+        Self {
+            registers: FLEXIO_BASE,
+            clock: Clock::new(),
+            shifters: Cell::new(0),
+            timers: Cell::new(0),
+            devices: [
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+            ],
+        }
+    }
+
+    /// Claim `shifters` consecutive shifters and `timers` consecutive
+    /// timers for `device`, and enable FlexIO if this is its first user.
+    ///
+    /// Returns `NOMEM` when there are not enough free shifters or timers.
+    pub fn allocate(
+        &self,
+        device: &'a dyn FlexIoDevice,
+        shifters: usize,
+        timers: usize,
+    ) -> Result<Resources, ErrorCode> {
+        let slot = self
+            .devices
+            .iter()
+            .find(|slot| slot.is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        let shifter = Self::find_block(self.shifters.get(), shifters, NUM_SHIFTERS)?;
+        let timer = Self::find_block(self.timers.get(), timers, NUM_TIMERS)?;
+
+        if self.shifters.get() == 0 && self.timers.get() == 0 {
+            self.clock.enable_peripheral_clock(Peripheral::FLEXIO);
+            self.registers.ctrl.write(CTRL::SWRST::SET);
+            self.registers.ctrl.write(CTRL::FLEXEN::SET);
+        }
+        self.shifters
+            .set(self.shifters.get() | Self::block_mask(shifter, shifters));
+        self.timers
+            .set(self.timers.get() | Self::block_mask(timer, timers));
+        slot.set(device);
+        Ok(Resources { shifter, timer })
+    }
+
+    pub(crate) fn registers(&self) -> &FlexIoRegisters {
+        &self.registers
+    }
+
+    /// Whether the status flag of `shifter` is set: its buffer is empty for
+    /// a transmitter, and full for a receiver.
+    pub(crate) fn shifter_status(&self, shifter: usize) -> bool {
+        self.registers.shiftstat.get() & (1 << shifter) != 0
+    }
+
+    /// Read and clear the error flag of `shifter`: an underrun for a
+    /// transmitter, or an overrun or wrong start or stop bit for a
+    /// receiver.
+    pub(crate) fn take_shifter_error(&self, shifter: usize) -> bool {
+        let error = self.registers.shifterr.get() & (1 << shifter) != 0;
+        if error {
+            self.registers.shifterr.set(1 << shifter);
+        }
+        error
+    }
+
+    /// Read and clear the status flag of `timer`, set when its counter
+    /// reaches zero.
+    pub(crate) fn take_timer_status(&self, timer: usize) -> bool {
+        let status = self.registers.timstat.get() & (1 << timer) != 0;
+        if status {
+            self.registers.timstat.set(1 << timer);
+        }
+        status
+    }
+
+    pub(crate) fn set_shifter_interrupt(&self, shifter: usize, enabled: bool) {
+        Self::set_bit(&self.registers.shiftsien, shifter, enabled);
+    }
+
+    pub(crate) fn set_shifter_error_interrupt(&self, shifter: usize, enabled: bool) {
+        Self::set_bit(&self.registers.shifteien, shifter, enabled);
+    }
+
+    pub(crate) fn set_timer_interrupt(&self, timer: usize, enabled: bool) {
+        Self::set_bit(&self.registers.timien, timer, enabled);
+    }
+
+    pub fn handle_interrupt(&self) {
+        for device in self.devices.iter() {
+            device.map(|device| device.handle_interrupt());
+        }
+    }
+
+    fn set_bit(register: &ReadWrite<u32>, bit: usize, value: bool) {
+        let mask = 1 << bit;
+        if value {
+            register.set(register.get() | mask);
+        } else {
+            register.set(register.get() & !mask);
+        }
+    }
+
+    fn block_mask(first: usize, count: usize) -> u8 {
+        (((1u16 << count) - 1) << first) as u8
+    }
+
+    fn find_block(used: u8, count: usize, total: usize) -> Result<usize, ErrorCode> {
+        (0..=total.saturating_sub(count))
+            .find(|&first| used & Self::block_mask(first, count) == 0)
+            .filter(|_| count <= total)
+            .ok_or(ErrorCode::NOMEM)
+    }
+}
+
+/// Divider for a timer in dual 8-bit baud mode that toggles its output at
+/// twice `rate`, and the rate it actually produces. Fails if `rate` is
+/// outside what the FlexIO clock can produce.
+pub(crate) fn baud_divider(rate: u32) -> Result<(u32, u32), ErrorCode> {
+    if rate == 0 {
+        return Err(ErrorCode::INVAL);
+    }
+    let divider = (CLOCK_HZ / (2 * rate)).saturating_sub(1);
+    if divider > 0xFF {
+        return Err(ErrorCode::INVAL);
+    }
+    Ok((divider, CLOCK_HZ / (2 * (divider + 1))))
+}
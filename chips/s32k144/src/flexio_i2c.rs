@@ -0,0 +1,414 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! I2C master emulated on FlexIO.
+//!
+//! A transmit and a receive shifter share SDA, both open drain. The first
+//! timer generates SCL for the whole transaction, and the second frames the
+//! nine bits of every byte from the SCL pin, which also lets slaves stretch
+//! the clock. The receive shifter sees every byte on the bus, including the
+//! ones sent by the master, and flags a missing acknowledge as an error.
+//!
+//! The SCL timer counts the edges of a whole transaction in eight bits, so a
+//! transaction carries at most [`MAX_LEN`] data bytes. There is no repeated
+//! start: `write_read` is a write and a read with a STOP in between, which
+//! most devices accept. Arbitration is not detected, so this is meant for
+//! buses with a single master.
+
+use core::cell::Cell;
+use kernel::hil::i2c::{Error, I2CHwMasterClient, I2CMaster};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::ErrorCode;
+
+use crate::flexio::{self, FlexIo, FlexIoDevice, Resources, SHIFTCFG, SHIFTCTL, TIMCFG, TIMCTL};
+
+/// Most data bytes in one transaction.
+pub const MAX_LEN: usize = 13;
+
+/// Rate used until `set_rate` is called.
+const DEFAULT_RATE: u32 = 100_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Idle,
+    Write,
+    Read,
+}
+
+pub struct FlexIoI2c<'a> {
+    flexio: &'a FlexIo<'a>,
+    sda_pin: u32,
+    scl_pin: u32,
+    resources: OptionalCell<Resources>,
+    client: OptionalCell<&'a dyn I2CHwMasterClient>,
+    divider: Cell<u32>,
+    buffer: TakeCell<'static, [u8]>,
+    operation: Cell<Operation>,
+    address: Cell<u8>,
+    /// Bytes of the transaction, including the address.
+    words: Cell<usize>,
+    /// Bytes loaded into the transmit shifter.
+    tx_index: Cell<usize>,
+    /// Bytes seen by the receive shifter.
+    rx_index: Cell<usize>,
+    /// Bytes to read once the write of a `write_read` has completed.
+    pending_read: Cell<usize>,
+    error: OptionalCell<Error>,
+}
+
+impl<'a> FlexIoI2c<'a> {
+    /// Create an I2C master with SDA and SCL on the given FXIO_Dn pins.
+    pub const fn new(flexio: &'a FlexIo<'a>, sda_pin: u32, scl_pin: u32) -> Self {
+        Self {
+            flexio,
+            sda_pin,
+            scl_pin,
+            resources: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+            divider: Cell::new(0),
+            buffer: TakeCell::empty(),
+            operation: Cell::new(Operation::Idle),
+            address: Cell::new(0),
+            words: Cell::new(0),
+            tx_index: Cell::new(0),
+            rx_index: Cell::new(0),
+            pending_read: Cell::new(0),
+            error: OptionalCell::empty(),
+        }
+    }
+
+    /// Claim two shifters and two timers. Must be called once before the
+    /// bus is enabled.
+    pub fn claim(&'a self) -> Result<(), ErrorCode> {
+        if self.sda_pin > 7 || self.scl_pin > 7 {
+            return Err(ErrorCode::INVAL);
+        }
+        let resources = self.flexio.allocate(self, 2, 2)?;
+        self.resources.set(resources);
+        self.set_rate(DEFAULT_RATE)?;
+        Ok(())
+    }
+
+    /// Set the SCL frequency, returning the one actually produced. Takes
+    /// effect from the next transaction.
+    pub fn set_rate(&self, rate: u32) -> Result<u32, ErrorCode> {
+        let (divider, actual) = flexio::baud_divider(rate)?;
+        self.divider.set(divider);
+        Ok(actual)
+    }
+
+    fn tx_shifter(resources: Resources) -> usize {
+        resources.shifter
+    }
+
+    fn rx_shifter(resources: Resources) -> usize {
+        resources.shifter + 1
+    }
+
+    fn scl_timer(resources: Resources) -> usize {
+        resources.timer
+    }
+
+    fn bit_timer(resources: Resources) -> usize {
+        resources.timer + 1
+    }
+
+    /// Start a transaction of `len` data bytes after the address.
+    fn start(&self, resources: Resources, operation: Operation, len: usize) {
+        let registers = self.flexio.registers();
+        let words = len + 1;
+        self.operation.set(operation);
+        self.words.set(words);
+        self.tx_index.set(1);
+        self.rx_index.set(0);
+        self.error.clear();
+
+        let (tx_shifter, rx_shifter) = (Self::tx_shifter(resources), Self::rx_shifter(resources));
+        let _ = registers.shiftbufbis[rx_shifter].get();
+        self.flexio.take_shifter_error(rx_shifter);
+        self.flexio.take_timer_status(Self::scl_timer(resources));
+        registers.shiftcfg[tx_shifter].modify(SHIFTCFG::SSTOP::Zero);
+        registers.shiftcfg[rx_shifter].modify(SHIFTCFG::SSTOP::Zero);
+        // Two edges per bit, nine bits per byte, and one more edge for the
+        // STOP condition.
+        registers.timcmp[Self::scl_timer(resources)]
+            .set(((words as u32 * 18 + 1) << 8) | self.divider.get());
+
+        let address = (self.address.get() << 1) | u8::from(operation == Operation::Read);
+        self.send(resources, address);
+        self.flexio.set_shifter_interrupt(tx_shifter, true);
+        self.flexio.set_shifter_interrupt(rx_shifter, true);
+    }
+
+    /// Load `byte` into the transmit shifter. The output is inverted, so
+    /// that ones drive SDA low, and written to the top of the bit-reversed
+    /// buffer, so that it goes out from bit 0 MSB first.
+    fn send(&self, resources: Resources, byte: u8) {
+        self.flexio.registers().shiftbufbis[Self::tx_shifter(resources)]
+            .set(!(u32::from(byte) << 24));
+    }
+
+    /// The transmit buffer is empty: the byte `tx_index - 1` has moved to
+    /// the shifter, so set its acknowledge bit and load the next one.
+    fn transmit_next(&self, resources: Resources) {
+        let registers = self.flexio.registers();
+        let tx_shifter = Self::tx_shifter(resources);
+        let index = self.tx_index.get();
+        let words = self.words.get();
+
+        // While reading, the master acknowledges every byte but the last.
+        let acknowledge = self.operation.get() == Operation::Read && index > 1 && index < words;
+        registers.shiftcfg[tx_shifter].modify(if acknowledge {
+            SHIFTCFG::SSTOP::One
+        } else {
+            SHIFTCFG::SSTOP::Zero
+        });
+
+        if index < words {
+            let byte = match self.operation.get() {
+                Operation::Write if self.error.is_none() => {
+                    self.buffer.map_or(0xFF, |buffer| buffer[index - 1])
+                }
+                // Release SDA for the slave to drive.
+                _ => 0xFF,
+            };
+            self.send(resources, byte);
+        } else {
+            // The start bit of this word pulls SDA low during the last SCL
+            // edge, and releasing it once the timers stop makes the STOP.
+            registers.shiftbufbis[tx_shifter].set(0);
+            self.flexio.set_shifter_interrupt(tx_shifter, false);
+        }
+        self.tx_index.set(index + 1);
+    }
+
+    /// The receive shifter has seen the byte `rx_index` on the bus.
+    fn receive_next(&self, resources: Resources) {
+        let registers = self.flexio.registers();
+        let rx_shifter = Self::rx_shifter(resources);
+        let index = self.rx_index.get();
+        let nak = self.flexio.take_shifter_error(rx_shifter);
+        let byte = registers.shiftbufbis[rx_shifter].get() as u8;
+
+        if index == 0 {
+            if nak {
+                self.error.set(Error::AddressNak);
+            }
+            if self.operation.get() == Operation::Read {
+                // The master drives the acknowledge of the bytes it reads.
+                registers.shiftcfg[rx_shifter].modify(SHIFTCFG::SSTOP::Disabled);
+            }
+        } else if self.operation.get() == Operation::Write {
+            if nak && self.error.is_none() {
+                self.error.set(Error::DataNak);
+            }
+        } else {
+            self.buffer.map(|buffer| buffer[index - 1] = byte);
+        }
+
+        self.rx_index.set(index + 1);
+        if index + 1 == self.words.get() {
+            self.flexio.set_shifter_interrupt(rx_shifter, false);
+            self.flexio
+                .set_timer_interrupt(Self::scl_timer(resources), true);
+        }
+    }
+
+    /// The SCL timer has stopped, so the STOP condition is on the bus.
+    fn complete(&self, resources: Resources) {
+        self.flexio
+            .set_timer_interrupt(Self::scl_timer(resources), false);
+        let pending_read = self.pending_read.replace(0);
+        let result = match self.error.take() {
+            Some(error) => Err(error),
+            None if pending_read > 0 => {
+                self.start(resources, Operation::Read, pending_read);
+                return;
+            }
+            None => Ok(()),
+        };
+        self.operation.set(Operation::Idle);
+        self.buffer.take().map(|buffer| {
+            self.client
+                .map(|client| client.command_complete(buffer, result));
+        });
+    }
+
+    fn begin(
+        &self,
+        addr: u8,
+        buffer: &'static mut [u8],
+        operation: Operation,
+        len: usize,
+        pending_read: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        let Some(resources) = self.resources.get() else {
+            return Err((Error::NotSupported, buffer));
+        };
+        if self.operation.get() != Operation::Idle {
+            return Err((Error::Busy, buffer));
+        }
+        if len == 0 || len > MAX_LEN || len > buffer.len() || pending_read > buffer.len() {
+            return Err((Error::NotSupported, buffer));
+        }
+        self.address.set(addr);
+        self.pending_read.set(pending_read);
+        self.buffer.replace(buffer);
+        self.start(resources, operation, len);
+        Ok(())
+    }
+}
+
+impl FlexIoDevice for FlexIoI2c<'_> {
+    fn handle_interrupt(&self) {
+        let Some(resources) = self.resources.get() else {
+            return;
+        };
+        if self.operation.get() == Operation::Idle {
+            return;
+        }
+        let registers = self.flexio.registers();
+        let (tx_shifter, rx_shifter) = (Self::tx_shifter(resources), Self::rx_shifter(resources));
+        if registers.shiftsien.get() & (1 << tx_shifter) != 0
+            && self.flexio.shifter_status(tx_shifter)
+        {
+            self.transmit_next(resources);
+        }
+        if registers.shiftsien.get() & (1 << rx_shifter) != 0
+            && self.flexio.shifter_status(rx_shifter)
+        {
+            self.receive_next(resources);
+        }
+        let scl_timer = Self::scl_timer(resources);
+        if registers.timien.get() & (1 << scl_timer) != 0
+            && self.flexio.take_timer_status(scl_timer)
+        {
+            self.complete(resources);
+        }
+    }
+}
+
+impl<'a> I2CMaster<'a> for FlexIoI2c<'a> {
+    fn set_master_client(&self, master_client: &'a dyn I2CHwMasterClient) {
+        self.client.set(master_client);
+    }
+
+    fn enable(&self) {
+        self.resources.map(|resources| {
+            let registers = self.flexio.registers();
+            let (tx_shifter, rx_shifter) =
+                (Self::tx_shifter(resources), Self::rx_shifter(resources));
+            let (scl_timer, bit_timer) = (Self::scl_timer(resources), Self::bit_timer(resources));
+
+            registers.shiftcfg[tx_shifter]
+                .write(SHIFTCFG::SSTART::One + SHIFTCFG::SSTOP::Zero + SHIFTCFG::INSRC::Pin);
+            registers.shiftctl[tx_shifter].write(
+                SHIFTCTL::SMOD::Transmit
+                    + SHIFTCTL::PINSEL.val(self.sda_pin)
+                    + SHIFTCTL::PINPOL::ActiveLow
+                    + SHIFTCTL::PINCFG::OpenDrain
+                    + SHIFTCTL::TIMSEL.val(bit_timer as u32)
+                    + SHIFTCTL::TIMPOL::PosEdge,
+            );
+            registers.shiftcfg[rx_shifter]
+                .write(SHIFTCFG::SSTART::Disabled + SHIFTCFG::SSTOP::Zero + SHIFTCFG::INSRC::Pin);
+            registers.shiftctl[rx_shifter].write(
+                SHIFTCTL::SMOD::Receive
+                    + SHIFTCTL::PINSEL.val(self.sda_pin)
+                    + SHIFTCTL::PINPOL::ActiveHigh
+                    + SHIFTCTL::PINCFG::Disabled
+                    + SHIFTCTL::TIMSEL.val(bit_timer as u32)
+                    + SHIFTCTL::TIMPOL::NegEdge,
+            );
+
+            // SCL runs from the first byte loaded until the compare, and
+            // restarts its count whenever a slave stretches the clock.
+            registers.timcfg[scl_timer].write(
+                TIMCFG::TSTART::SET
+                    + TIMCFG::TSTOP::OnDisable
+                    + TIMCFG::TIMENA::TriggerHigh
+                    + TIMCFG::TIMDIS::OnCompare
+                    + TIMCFG::TIMRST::PinEqualsOutput
+                    + TIMCFG::TIMDEC::FlexIoClock
+                    + TIMCFG::TIMOUT::Zero,
+            );
+            registers.timctl[scl_timer].write(
+                TIMCTL::TIMOD::DualBaud8
+                    + TIMCTL::TRGSEL.val(4 * tx_shifter as u32 + 1)
+                    + TIMCTL::TRGPOL::ActiveLow
+                    + TIMCTL::TRGSRC::Internal
+                    + TIMCTL::PINSEL.val(self.scl_pin)
+                    + TIMCTL::PINPOL::ActiveHigh
+                    + TIMCTL::PINCFG::OpenDrain,
+            );
+
+            // Eight data bits between a start and a stop bit, counted on
+            // the edges of the SCL pin.
+            registers.timcmp[bit_timer].set(0x0F);
+            registers.timcfg[bit_timer].write(
+                TIMCFG::TSTART::SET
+                    + TIMCFG::TSTOP::OnCompare
+                    + TIMCFG::TIMENA::OnPreviousEnable
+                    + TIMCFG::TIMDIS::OnPreviousDisable
+                    + TIMCFG::TIMRST::Never
+                    + TIMCFG::TIMDEC::Pin
+                    + TIMCFG::TIMOUT::One,
+            );
+            registers.timctl[bit_timer].write(
+                TIMCTL::TIMOD::Counter16
+                    + TIMCTL::TRGPOL::ActiveHigh
+                    + TIMCTL::TRGSRC::Internal
+                    + TIMCTL::PINSEL.val(self.scl_pin)
+                    + TIMCTL::PINPOL::ActiveLow
+                    + TIMCTL::PINCFG::Disabled,
+            );
+        });
+    }
+
+    fn disable(&self) {
+        self.resources.map(|resources| {
+            let registers = self.flexio.registers();
+            for shifter in [Self::tx_shifter(resources), Self::rx_shifter(resources)] {
+                self.flexio.set_shifter_interrupt(shifter, false);
+                registers.shiftctl[shifter].write(SHIFTCTL::SMOD::Disabled);
+            }
+            for timer in [Self::scl_timer(resources), Self::bit_timer(resources)] {
+                self.flexio.set_timer_interrupt(timer, false);
+                registers.timctl[timer].write(TIMCTL::TIMOD::Disabled);
+            }
+        });
+    }
+
+    fn write_read(
+        &self,
+        addr: u8,
+        data: &'static mut [u8],
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        if read_len == 0 || read_len > MAX_LEN {
+            return Err((Error::NotSupported, data));
+        }
+        self.begin(addr, data, Operation::Write, write_len, read_len)
+    }
+
+    fn write(
+        &self,
+        addr: u8,
+        data: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.begin(addr, data, Operation::Write, len, 0)
+    }
+
+    fn read(
+        &self,
+        addr: u8,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.begin(addr, buffer, Operation::Read, len, 0)
+    }
+}
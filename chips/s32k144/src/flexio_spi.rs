@@ -0,0 +1,357 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! SPI master emulated on FlexIO.
+//!
+//! Uses a transmit shifter on MOSI, a receive shifter on MISO and one timer
+//! that generates SCK while the transmit shifter has data. Bytes go out MSB
+//! first, one at a time, each started from the interrupt of the previous
+//! one. Chip select is a GPIO pin driven by software, so any number of
+//! devices can share the bus.
+//!
+//! SCK is the 48 MHz FlexIO clock divided by an even number between 2 and
+//! 512, so rates from about 94 kHz to 12 MHz are available.
+
+use core::cell::Cell;
+use kernel::hil::spi::cs::ChipSelectPolar;
+use kernel::hil::spi::{ClockPhase, ClockPolarity, SpiMaster, SpiMasterClient};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::ErrorCode;
+
+use crate::flexio::{self, FlexIo, FlexIoDevice, Resources, SHIFTCFG, SHIFTCTL, TIMCFG, TIMCTL};
+
+/// Data bits per transfer.
+const DATA_BITS: u32 = 8;
+
+/// Rate used until `set_rate` is called.
+const DEFAULT_RATE: u32 = 1_000_000;
+
+pub struct FlexIoSpi<'a> {
+    flexio: &'a FlexIo<'a>,
+    sck_pin: u32,
+    mosi_pin: u32,
+    miso_pin: u32,
+    resources: OptionalCell<Resources>,
+    client: OptionalCell<&'a dyn SpiMasterClient>,
+    chip_select: OptionalCell<ChipSelectPolar<'a, crate::gpio::GPIOPin<'a>>>,
+    hold_low: Cell<bool>,
+    divider: Cell<u32>,
+    rate: Cell<u32>,
+    polarity: Cell<ClockPolarity>,
+    phase: Cell<ClockPhase>,
+    tx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    rx_buffer: MapCell<SubSliceMut<'static, u8>>,
+    len: Cell<usize>,
+    position: Cell<usize>,
+}
+
+impl<'a> FlexIoSpi<'a> {
+    /// Create a SPI master with SCK, MOSI and MISO on the given FXIO_Dn
+    /// pins.
+    pub const fn new(flexio: &'a FlexIo<'a>, sck_pin: u32, mosi_pin: u32, miso_pin: u32) -> Self {
+        Self {
+            flexio,
+            sck_pin,
+            mosi_pin,
+            miso_pin,
+            resources: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+            chip_select: OptionalCell::empty(),
+            hold_low: Cell::new(false),
+            divider: Cell::new(0),
+            rate: Cell::new(0),
+            polarity: Cell::new(ClockPolarity::IdleLow),
+            phase: Cell::new(ClockPhase::SampleLeading),
+            tx_buffer: MapCell::empty(),
+            rx_buffer: MapCell::empty(),
+            len: Cell::new(0),
+            position: Cell::new(0),
+        }
+    }
+
+    /// Claim two shifters and a timer. Must be called once before
+    /// `SpiMaster::init`.
+    pub fn claim(&'a self) -> Result<(), ErrorCode> {
+        if self.sck_pin > 7 || self.mosi_pin > 7 || self.miso_pin > 7 {
+            return Err(ErrorCode::INVAL);
+        }
+        let resources = self.flexio.allocate(self, 2, 1)?;
+        self.resources.set(resources);
+        Ok(())
+    }
+
+    fn tx_shifter(resources: Resources) -> usize {
+        resources.shifter
+    }
+
+    fn rx_shifter(resources: Resources) -> usize {
+        resources.shifter + 1
+    }
+
+    /// Program the shifters and the timer for the current rate, polarity
+    /// and phase. Only called between transfers.
+    fn configure(&self, resources: Resources) {
+        let registers = self.flexio.registers();
+        let (tx_shifter, rx_shifter, timer) = (
+            Self::tx_shifter(resources),
+            Self::rx_shifter(resources),
+            resources.timer,
+        );
+        // With SampleLeading the first bit must be on MOSI before the first
+        // edge, so it is loaded when the timer starts and the timer stops
+        // with an extra half bit to hold the last one.
+        let (start, tx_edge, rx_edge, stop) = match self.phase.get() {
+            ClockPhase::SampleLeading => (
+                SHIFTCFG::SSTART::Disabled,
+                SHIFTCTL::TIMPOL::NegEdge,
+                SHIFTCTL::TIMPOL::PosEdge,
+                TIMCFG::TSTOP::OnDisable,
+            ),
+            ClockPhase::SampleTrailing => (
+                SHIFTCFG::SSTART::DisabledShift,
+                SHIFTCTL::TIMPOL::PosEdge,
+                SHIFTCTL::TIMPOL::NegEdge,
+                TIMCFG::TSTOP::Disabled,
+            ),
+        };
+        let sck_polarity = match self.polarity.get() {
+            ClockPolarity::IdleLow => TIMCTL::PINPOL::ActiveHigh,
+            ClockPolarity::IdleHigh => TIMCTL::PINPOL::ActiveLow,
+        };
+
+        registers.shiftcfg[tx_shifter]
+            .write(start + SHIFTCFG::SSTOP::Disabled + SHIFTCFG::INSRC::Pin);
+        registers.shiftctl[tx_shifter].write(
+            SHIFTCTL::SMOD::Transmit
+                + SHIFTCTL::PINSEL.val(self.mosi_pin)
+                + SHIFTCTL::PINPOL::ActiveHigh
+                + SHIFTCTL::PINCFG::Output
+                + SHIFTCTL::TIMSEL.val(timer as u32)
+                + tx_edge,
+        );
+        registers.shiftcfg[rx_shifter]
+            .write(SHIFTCFG::SSTART::Disabled + SHIFTCFG::SSTOP::Disabled + SHIFTCFG::INSRC::Pin);
+        registers.shiftctl[rx_shifter].write(
+            SHIFTCTL::SMOD::Receive
+                + SHIFTCTL::PINSEL.val(self.miso_pin)
+                + SHIFTCTL::PINPOL::ActiveHigh
+                + SHIFTCTL::PINCFG::Disabled
+                + SHIFTCTL::TIMSEL.val(timer as u32)
+                + rx_edge,
+        );
+
+        registers.timcmp[timer].set(((DATA_BITS * 2 - 1) << 8) | self.divider.get());
+        registers.timcfg[timer].write(
+            TIMCFG::TSTART::SET
+                + stop
+                + TIMCFG::TIMENA::TriggerHigh
+                + TIMCFG::TIMDIS::OnCompare
+                + TIMCFG::TIMRST::Never
+                + TIMCFG::TIMDEC::FlexIoClock
+                + TIMCFG::TIMOUT::Zero,
+        );
+        registers.timctl[timer].write(
+            TIMCTL::TIMOD::DualBaud8
+                + TIMCTL::TRGSEL.val(4 * tx_shifter as u32 + 1)
+                + TIMCTL::TRGPOL::ActiveLow
+                + TIMCTL::TRGSRC::Internal
+                + TIMCTL::PINSEL.val(self.sck_pin)
+                + sck_polarity
+                + TIMCTL::PINCFG::Output,
+        );
+    }
+
+    /// Start shifting `byte` out. The shifter sends bit 0 first, so the
+    /// byte is written to the top of the bit-reversed buffer, which puts its
+    /// most significant bit there.
+    fn send(&self, resources: Resources, byte: u8) {
+        self.flexio.registers().shiftbufbis[Self::tx_shifter(resources)].set(u32::from(byte) << 24);
+    }
+
+    /// The byte received during the last transfer, reading which clears the
+    /// receive status flag.
+    fn receive(&self, resources: Resources) -> u8 {
+        self.flexio.registers().shiftbufbis[Self::rx_shifter(resources)].get() as u8
+    }
+
+    fn finish(&self, resources: Resources) {
+        self.flexio
+            .set_shifter_interrupt(Self::rx_shifter(resources), false);
+        if !self.hold_low.get() {
+            self.chip_select.map(|cs| cs.deactivate());
+        }
+        let len = self.len.get();
+        if let Some(tx_buffer) = self.tx_buffer.take() {
+            let rx_buffer = self.rx_buffer.take();
+            self.client
+                .map(|client| client.read_write_done(tx_buffer, rx_buffer, Ok(len)));
+        }
+    }
+}
+
+impl FlexIoDevice for FlexIoSpi<'_> {
+    fn handle_interrupt(&self) {
+        let Some(resources) = self.resources.get() else {
+            return;
+        };
+        if self.tx_buffer.is_none() || !self.flexio.shifter_status(Self::rx_shifter(resources)) {
+            return;
+        }
+        let position = self.position.get();
+        let byte = self.receive(resources);
+        self.rx_buffer.map(|buffer| buffer[position] = byte);
+        let next = position + 1;
+        self.position.set(next);
+        if next < self.len.get() {
+            let byte = self.tx_buffer.map_or(0, |buffer| buffer[next]);
+            self.send(resources, byte);
+        } else {
+            self.finish(resources);
+        }
+    }
+}
+
+impl<'a> SpiMaster<'a> for FlexIoSpi<'a> {
+    type ChipSelect = ChipSelectPolar<'a, crate::gpio::GPIOPin<'a>>;
+
+    fn init(&self) -> Result<(), ErrorCode> {
+        let resources = self.resources.get().ok_or(ErrorCode::OFF)?;
+        if self.rate.get() == 0 {
+            let (divider, rate) = flexio::baud_divider(DEFAULT_RATE)?;
+            self.divider.set(divider);
+            self.rate.set(rate);
+        }
+        self.configure(resources);
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn SpiMasterClient) {
+        self.client.set(client);
+    }
+
+    fn is_busy(&self) -> bool {
+        self.tx_buffer.is_some()
+    }
+
+    fn read_write_bytes(
+        &self,
+        write_buffer: SubSliceMut<'static, u8>,
+        read_buffer: Option<SubSliceMut<'static, u8>>,
+    ) -> Result<
+        (),
+        (
+            ErrorCode,
+            SubSliceMut<'static, u8>,
+            Option<SubSliceMut<'static, u8>>,
+        ),
+    > {
+        let Some(resources) = self.resources.get() else {
+            return Err((ErrorCode::OFF, write_buffer, read_buffer));
+        };
+        if self.is_busy() {
+            return Err((ErrorCode::BUSY, write_buffer, read_buffer));
+        }
+        let len = read_buffer.as_ref().map_or(write_buffer.len(), |read| {
+            read.len().min(write_buffer.len())
+        });
+        if len == 0 {
+            return Err((ErrorCode::INVAL, write_buffer, read_buffer));
+        }
+        // Drop a byte left over from a blocking call.
+        let _ = self.receive(resources);
+        self.chip_select.map(|cs| cs.activate());
+        self.len.set(len);
+        self.position.set(0);
+        self.send(resources, write_buffer[0]);
+        self.tx_buffer.replace(write_buffer);
+        if let Some(read_buffer) = read_buffer {
+            self.rx_buffer.replace(read_buffer);
+        }
+        self.flexio
+            .set_shifter_interrupt(Self::rx_shifter(resources), true);
+        Ok(())
+    }
+
+    fn write_byte(&self, val: u8) -> Result<(), ErrorCode> {
+        self.read_write_byte(val).map(|_| ())
+    }
+
+    fn read_byte(&self) -> Result<u8, ErrorCode> {
+        self.read_write_byte(0)
+    }
+
+    fn read_write_byte(&self, val: u8) -> Result<u8, ErrorCode> {
+        let resources = self.resources.get().ok_or(ErrorCode::OFF)?;
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        let _ = self.receive(resources);
+        self.send(resources, val);
+        while !self.flexio.shifter_status(Self::rx_shifter(resources)) {}
+        Ok(self.receive(resources))
+    }
+
+    fn specify_chip_select(&self, cs: Self::ChipSelect) -> Result<(), ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        cs.deactivate();
+        self.chip_select.set(cs);
+        Ok(())
+    }
+
+    fn set_rate(&self, rate: u32) -> Result<u32, ErrorCode> {
+        let resources = self.resources.get().ok_or(ErrorCode::OFF)?;
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        let (divider, actual) = flexio::baud_divider(rate)?;
+        self.divider.set(divider);
+        self.rate.set(actual);
+        self.configure(resources);
+        Ok(actual)
+    }
+
+    fn get_rate(&self) -> u32 {
+        self.rate.get()
+    }
+
+    fn set_polarity(&self, polarity: ClockPolarity) -> Result<(), ErrorCode> {
+        let resources = self.resources.get().ok_or(ErrorCode::OFF)?;
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.polarity.set(polarity);
+        self.configure(resources);
+        Ok(())
+    }
+
+    fn get_polarity(&self) -> ClockPolarity {
+        self.polarity.get()
+    }
+
+    fn set_phase(&self, phase: ClockPhase) -> Result<(), ErrorCode> {
+        let resources = self.resources.get().ok_or(ErrorCode::OFF)?;
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.phase.set(phase);
+        self.configure(resources);
+        Ok(())
+    }
+
+    fn get_phase(&self) -> ClockPhase {
+        self.phase.get()
+    }
+
+    fn hold_low(&self) {
+        self.hold_low.set(true);
+    }
+
+    fn release_low(&self) {
+        self.hold_low.set(false);
+    }
+}
@@ -0,0 +1,370 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! UART emulated on FlexIO.
+//!
+//! Uses one shifter and one timer per direction. The transmit timer runs
+//! while the transmit shifter has data, and the receive timer is started by
+//! the falling edge of a start bit and samples in the middle of each bit.
+//!
+//! Aborted transfers are returned to their client from a deferred call, so
+//! the board must register the UART with `DeferredCallClient::register`.
+//!
+//! Only 8N1 framing without flow control is supported. The timers divide the
+//! 48 MHz FlexIO clock by at most 512, so the slowest baud rate is about
+//! 94 kBd; 115200 Bd and faster work.
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::uart::{self, Configure, Parameters, Receive, Transmit};
+use kernel::hil::uart::{ReceiveClient, TransmitClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::ErrorCode;
+
+use crate::flexio::{self, FlexIo, FlexIoDevice, Resources, SHIFTCFG, SHIFTCTL, TIMCFG, TIMCTL};
+
+/// Data bits per frame.
+const DATA_BITS: u32 = 8;
+
+pub struct FlexIoUart<'a> {
+    flexio: &'a FlexIo<'a>,
+    tx_pin: u32,
+    rx_pin: u32,
+    resources: OptionalCell<Resources>,
+    tx_client: OptionalCell<&'a dyn TransmitClient>,
+    rx_client: OptionalCell<&'a dyn ReceiveClient>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    rx_len: Cell<usize>,
+    tx_position: Cell<usize>,
+    rx_position: Cell<usize>,
+    rx_error: Cell<uart::Error>,
+    /// Transfers aborted, to be returned from the deferred call.
+    tx_aborted: Cell<bool>,
+    rx_aborted: Cell<bool>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> FlexIoUart<'a> {
+    /// Create a UART that transmits on FXIO_D`tx_pin` and receives on
+    /// FXIO_D`rx_pin`.
+    pub fn new(flexio: &'a FlexIo<'a>, tx_pin: u32, rx_pin: u32) -> Self {
+        Self {
+            flexio,
+            tx_pin,
+            rx_pin,
+            resources: OptionalCell::empty(),
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            rx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            rx_len: Cell::new(0),
+            tx_position: Cell::new(0),
+            rx_position: Cell::new(0),
+            rx_error: Cell::new(uart::Error::None),
+            tx_aborted: Cell::new(false),
+            rx_aborted: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Claim two shifters and two timers. Must be called once before the
+    /// UART is configured.
+    pub fn claim(&'a self) -> Result<(), ErrorCode> {
+        if self.tx_pin > 7 || self.rx_pin > 7 {
+            return Err(ErrorCode::INVAL);
+        }
+        let resources = self.flexio.allocate(self, 2, 2)?;
+        self.resources.set(resources);
+        Ok(())
+    }
+
+    fn tx_shifter(resources: Resources) -> usize {
+        resources.shifter
+    }
+
+    fn rx_shifter(resources: Resources) -> usize {
+        resources.shifter + 1
+    }
+
+    fn transmit_next(&self, resources: Resources) {
+        let shifter = Self::tx_shifter(resources);
+        self.tx_buffer.take().map(|buffer| {
+            let position = self.tx_position.get();
+            if position < self.tx_len.get() {
+                self.flexio.registers().shiftbuf[shifter].set(u32::from(buffer[position]));
+                self.tx_position.set(position + 1);
+                self.tx_buffer.replace(buffer);
+            } else {
+                self.flexio.set_shifter_interrupt(shifter, false);
+                self.tx_client
+                    .map(|client| client.transmitted_buffer(buffer, self.tx_len.get(), Ok(())));
+            }
+        });
+    }
+
+    fn receive_next(&self, resources: Resources) {
+        let shifter = Self::rx_shifter(resources);
+        if self.flexio.take_shifter_error(shifter) {
+            self.rx_error.set(uart::Error::FramingError);
+        }
+        // Bits shift in from the top, so the byte ends up in the highest
+        // eight bits. Reading the buffer clears the status flag.
+        let byte = (self.flexio.registers().shiftbuf[shifter].get() >> 24) as u8;
+        self.rx_buffer.take().map(|buffer| {
+            let position = self.rx_position.get();
+            buffer[position] = byte;
+            self.rx_position.set(position + 1);
+            if position + 1 < self.rx_len.get() {
+                self.rx_buffer.replace(buffer);
+            } else {
+                self.flexio.set_shifter_interrupt(shifter, false);
+                self.flexio.set_shifter_error_interrupt(shifter, false);
+                let error = self.rx_error.replace(uart::Error::None);
+                let rval = match error {
+                    uart::Error::None => Ok(()),
+                    _ => Err(ErrorCode::FAIL),
+                };
+                self.rx_client
+                    .map(|client| client.received_buffer(buffer, self.rx_len.get(), rval, error));
+            }
+        });
+    }
+}
+
+impl FlexIoDevice for FlexIoUart<'_> {
+    fn handle_interrupt(&self) {
+        self.resources.map(|resources| {
+            if self.tx_buffer.is_some()
+                && !self.tx_aborted.get()
+                && self.flexio.shifter_status(Self::tx_shifter(resources))
+            {
+                self.transmit_next(resources);
+            }
+            let rx_shifter = Self::rx_shifter(resources);
+            if self.rx_buffer.is_some()
+                && !self.rx_aborted.get()
+                && (self.flexio.shifter_status(rx_shifter)
+                    || self.flexio.registers().shifterr.get() & (1 << rx_shifter) != 0)
+            {
+                self.receive_next(resources);
+            }
+        });
+    }
+}
+
+impl<'a> Transmit<'a> for FlexIoUart<'a> {
+    fn set_transmit_client(&self, client: &'a dyn TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let Some(resources) = self.resources.get() else {
+            return Err((ErrorCode::OFF, buffer));
+        };
+        if len == 0 || len > buffer.len() {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        if self.tx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        self.tx_buffer.replace(buffer);
+        self.tx_len.set(len);
+        self.tx_position.set(0);
+        // The buffer is empty, so the interrupt fires straight away and
+        // loads the first byte.
+        self.flexio
+            .set_shifter_interrupt(Self::tx_shifter(resources), true);
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        if self.tx_buffer.is_none() || self.tx_aborted.get() {
+            return Ok(());
+        }
+        self.resources.map(|resources| {
+            self.flexio
+                .set_shifter_interrupt(Self::tx_shifter(resources), false)
+        });
+        self.tx_aborted.set(true);
+        self.deferred_call.set();
+        Err(ErrorCode::BUSY)
+    }
+}
+
+impl<'a> Receive<'a> for FlexIoUart<'a> {
+    fn set_receive_client(&self, client: &'a dyn ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let Some(resources) = self.resources.get() else {
+            return Err((ErrorCode::OFF, buffer));
+        };
+        if len == 0 || len > buffer.len() {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        let shifter = Self::rx_shifter(resources);
+        // Drop whatever arrived while nobody was receiving.
+        let _ = self.flexio.registers().shiftbuf[shifter].get();
+        self.flexio.take_shifter_error(shifter);
+        self.rx_buffer.replace(buffer);
+        self.rx_len.set(len);
+        self.rx_position.set(0);
+        self.rx_error.set(uart::Error::None);
+        self.flexio.set_shifter_interrupt(shifter, true);
+        self.flexio.set_shifter_error_interrupt(shifter, true);
+        Ok(())
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        if self.rx_buffer.is_none() || self.rx_aborted.get() {
+            return Ok(());
+        }
+        self.resources.map(|resources| {
+            let shifter = Self::rx_shifter(resources);
+            self.flexio.set_shifter_interrupt(shifter, false);
+            self.flexio.set_shifter_error_interrupt(shifter, false);
+        });
+        self.rx_aborted.set(true);
+        self.deferred_call.set();
+        Err(ErrorCode::BUSY)
+    }
+}
+
+impl DeferredCallClient for FlexIoUart<'_> {
+    fn handle_deferred_call(&self) {
+        if self.tx_aborted.replace(false) {
+            if let Some(buffer) = self.tx_buffer.take() {
+                self.tx_client.map(move |client| {
+                    client.transmitted_buffer(
+                        buffer,
+                        self.tx_position.get(),
+                        Err(ErrorCode::CANCEL),
+                    )
+                });
+            }
+        }
+        if self.rx_aborted.replace(false) {
+            if let Some(buffer) = self.rx_buffer.take() {
+                self.rx_client.map(move |client| {
+                    client.received_buffer(
+                        buffer,
+                        self.rx_position.get(),
+                        Err(ErrorCode::CANCEL),
+                        uart::Error::Aborted,
+                    )
+                });
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl Configure for FlexIoUart<'_> {
+    fn configure(&self, params: Parameters) -> Result<(), ErrorCode> {
+        let resources = self.resources.get().ok_or(ErrorCode::OFF)?;
+        if params.width != uart::Width::Eight
+            || params.parity != uart::Parity::None
+            || params.stop_bits != uart::StopBits::One
+            || params.hw_flow_control
+        {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let (divider, _) = flexio::baud_divider(params.baud_rate)?;
+        let compare = ((DATA_BITS * 2 - 1) << 8) | divider;
+        let registers = self.flexio.registers();
+
+        let (tx_shifter, tx_timer) = (Self::tx_shifter(resources), resources.timer);
+        registers.shiftcfg[tx_shifter]
+            .write(SHIFTCFG::SSTART::Zero + SHIFTCFG::SSTOP::One + SHIFTCFG::INSRC::Pin);
+        registers.shiftctl[tx_shifter].write(
+            SHIFTCTL::SMOD::Transmit
+                + SHIFTCTL::PINSEL.val(self.tx_pin)
+                + SHIFTCTL::PINPOL::ActiveHigh
+                + SHIFTCTL::PINCFG::Output
+                + SHIFTCTL::TIMSEL.val(tx_timer as u32)
+                + SHIFTCTL::TIMPOL::PosEdge,
+        );
+        registers.timcmp[tx_timer].set(compare);
+        registers.timcfg[tx_timer].write(
+            TIMCFG::TSTART::SET
+                + TIMCFG::TSTOP::OnDisable
+                + TIMCFG::TIMENA::TriggerHigh
+                + TIMCFG::TIMDIS::OnCompare
+                + TIMCFG::TIMRST::Never
+                + TIMCFG::TIMDEC::FlexIoClock
+                + TIMCFG::TIMOUT::One,
+        );
+        // The timer runs while the transmit buffer holds data, that is while
+        // the shifter's status flag is low.
+        registers.timctl[tx_timer].write(
+            TIMCTL::TIMOD::DualBaud8
+                + TIMCTL::TRGSEL.val(4 * tx_shifter as u32 + 1)
+                + TIMCTL::TRGPOL::ActiveLow
+                + TIMCTL::TRGSRC::Internal
+                + TIMCTL::PINSEL.val(self.tx_pin)
+                + TIMCTL::PINPOL::ActiveHigh
+                + TIMCTL::PINCFG::Disabled,
+        );
+
+        let (rx_shifter, rx_timer) = (Self::rx_shifter(resources), resources.timer + 1);
+        registers.shiftcfg[rx_shifter]
+            .write(SHIFTCFG::SSTART::Zero + SHIFTCFG::SSTOP::One + SHIFTCFG::INSRC::Pin);
+        registers.shiftctl[rx_shifter].write(
+            SHIFTCTL::SMOD::Receive
+                + SHIFTCTL::PINSEL.val(self.rx_pin)
+                + SHIFTCTL::PINPOL::ActiveHigh
+                + SHIFTCTL::PINCFG::Disabled
+                + SHIFTCTL::TIMSEL.val(rx_timer as u32)
+                + SHIFTCTL::TIMPOL::NegEdge,
+        );
+        registers.timcmp[rx_timer].set(compare);
+        // The timer sees the pin inverted, so it starts on the falling edge
+        // of the start bit and resynchronises on every later falling edge.
+        registers.timcfg[rx_timer].write(
+            TIMCFG::TSTART::SET
+                + TIMCFG::TSTOP::OnDisable
+                + TIMCFG::TIMENA::PinRisingEdge
+                + TIMCFG::TIMDIS::OnCompare
+                + TIMCFG::TIMRST::PinRisingEdge
+                + TIMCFG::TIMDEC::FlexIoClock
+                + TIMCFG::TIMOUT::OneOnReset,
+        );
+        registers.timctl[rx_timer].write(
+            TIMCTL::TIMOD::DualBaud8
+                + TIMCTL::TRGPOL::ActiveHigh
+                + TIMCTL::TRGSRC::External
+                + TIMCTL::PINSEL.val(self.rx_pin)
+                + TIMCTL::PINPOL::ActiveLow
+                + TIMCTL::PINCFG::Disabled,
+        );
+        Ok(())
+    }
+}
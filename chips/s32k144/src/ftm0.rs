@@ -1,5 +1,5 @@
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
-use kernel::utilities::registers::{register_bitfields, ReadWrite};
+use kernel::utilities::registers::{register_bitfields, FieldValue, ReadWrite};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 
@@ -61,47 +61,55 @@ register_bitfields![u32,
             UpCounting = 0,
             UpDownCounting = 1
         ],
-        TOIE OFFSET(6) NUMBITS(1) [
+        RIE OFFSET(6) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
         ],
-        TOF OFFSET(7) NUMBITS(1) [
+        RF OFFSET(7) NUMBITS(1) [
+            NoReload = 0,
+            Reload = 1
+        ],
+        TOIE OFFSET(8) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+        TOF OFFSET(9) NUMBITS(1) [
             NoOverflow = 0,
             Overflow = 1
         ],
-        PWMEN0 OFFSET(8) NUMBITS(1) [
+        PWMEN0 OFFSET(16) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
         ],
-        PWMEN1 OFFSET(9) NUMBITS(1) [
+        PWMEN1 OFFSET(17) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
         ],
-        PWMEN2 OFFSET(10) NUMBITS(1) [
+        PWMEN2 OFFSET(18) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
         ],
-        PWMEN3 OFFSET(11) NUMBITS(1) [
+        PWMEN3 OFFSET(19) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
         ],
-        PWMEN4 OFFSET(12) NUMBITS(1) [
+        PWMEN4 OFFSET(20) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
         ],
-        PWMEN5 OFFSET(13) NUMBITS(1) [
+        PWMEN5 OFFSET(21) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
         ],
-        PWMEN6 OFFSET(14) NUMBITS(1) [
+        PWMEN6 OFFSET(22) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
         ],
-        PWMEN7 OFFSET(15) NUMBITS(1) [
+        PWMEN7 OFFSET(23) NUMBITS(1) [
             Disabled = 0,
             Enabled = 1
         ],
-        FLTPS OFFSET(16) NUMBITS(4) [
+        FLTPS OFFSET(24) NUMBITS(4) [
             DivideBy1 = 0,
             DivideBy2 = 1,
             DivideBy4 = 2,
@@ -604,6 +612,30 @@ register_bitfields![u32,
     ]
 ];
 
+/// Input clock of the FTM counter: the system clock, which runs from the
+/// 48 MHz FIRC after reset.
+const INPUT_CLOCK_HZ: usize = 48_000_000;
+
+/// Resolution of the duty cycle passed to `start`.
+const MAX_DUTY_CYCLE: usize = 100;
+
+/// Pins that carry an FTM0 channel on their ALT2 function, as
+/// (port, pin, channel).
+const CHANNEL_PINS: [(usize, usize, usize); 8] = [
+    (2, 0, 0),  // PTC0
+    (2, 1, 1),  // PTC1
+    (2, 2, 2),  // PTC2
+    (2, 3, 3),  // PTC3
+    (3, 15, 0), // PTD15
+    (3, 16, 1), // PTD16
+    (3, 0, 2),  // PTD0
+    (3, 1, 3),  // PTD1
+];
+
+/// Edge-aligned PWM on FTM0.
+///
+/// All channels share the counter, so starting a channel at a new frequency
+/// changes the period of the channels already running.
 pub struct Pwm {
     registers: StaticRef<FtmRegisters>,
 }
@@ -614,72 +646,123 @@ impl Pwm {
             registers: FTM0_BASE,
         }
     }
-    // ftm channel setting
+
+    fn channel(pin: Pinmux) -> Result<usize, ErrorCode> {
+        let (port, number) = pin.into();
+        CHANNEL_PINS
+            .iter()
+            .find(|&&(p, n, _)| p == port && n == number)
+            .map(|&(_, _, channel)| channel)
+            .ok_or(ErrorCode::INVAL)
+    }
+
+    /// The PWMENn bit of `channel`, which connects it to its pin.
+    fn pwmen(channel: usize, enabled: bool) -> FieldValue<u32, SC::Register> {
+        FieldValue::<u32, SC::Register>::new(1, 16 + channel, u32::from(enabled))
+    }
+
+    /// Make the output of `pin`'s channel low while active, for loads such
+    /// as LEDs that are on when driven low.
+    pub fn set_active_low(&self, pin: Pinmux, active_low: bool) -> Result<(), ErrorCode> {
+        let channel = Self::channel(pin)?;
+        let pol = self.registers.pol.get() & !(1 << channel);
+        self.registers
+            .pol
+            .set(pol | (u32::from(active_low) << channel));
+        Ok(())
+    }
+
     fn start_pwm(
         &self,
         pin: Pinmux,
         frequency_hz: usize,
         duty_cycle: usize,
     ) -> Result<(), ErrorCode> {
-        let (port, channel) = pin.into();
-
-        if channel >= 8 {
+        let channel = Self::channel(pin)?;
+        if frequency_hz == 0 || duty_cycle > MAX_DUTY_CYCLE {
             return Err(ErrorCode::INVAL);
         }
 
-        if frequency_hz == 0 {
-            return self.stop_pwm(pin);
+        // The smallest prescaler that fits the period in the 16-bit counter
+        // gives the finest duty cycle.
+        let prescaler = (0..8)
+            .find(|&ps| (INPUT_CLOCK_HZ >> ps) / frequency_hz <= 0x1_0000)
+            .ok_or(ErrorCode::INVAL)?;
+        let period = (INPUT_CLOCK_HZ >> prescaler) / frequency_hz;
+        if period == 0 {
+            return Err(ErrorCode::INVAL);
         }
 
-        let prescaler = 0;
-        let counter_top = (16000000 / frequency_hz) >> prescaler;
-        let dc_out = counter_top * duty_cycle / 100;
-
-        // 해당 핀을 PWM (FTM) 기능으로 설정
-        let port_reg = crate::pinmux::PORT_BASES[port];
-        port_reg.pcr[channel].modify(crate::pinmux::PCR::MUX::ALT2_FTM);
-
-        // FTM 설정
-        self.registers
-            .sc
-            .modify(SC::CLKS::SystemClock + SC::PS::DivideBy1);
-        self.registers.mod_.write(MOD::MOD.val(counter_top as u32));
-
-        // 해당 채널을 PWM 모드로 설정
-        self.registers.csc[channel].modify(CSC::MSB::Enabled + CSC::ELSB::High);
-        self.registers.cv[channel].write(CV::VAL.val(dc_out as u32));
-
-        // PWMLOAD를 사용하여 설정 적용
-        self.registers.pwmload.modify(PWMLOAD::LDOK::Enabled);
-
-        // 동기화 설정 적용
-        self.registers.sync.modify(SYNC::SWSYNC::SET);
+        // With the counter stopped, MOD and CnV take their new values
+        // immediately.
+        self.registers.sc.modify(SC::CLKS::NoClock);
+        self.registers.cntin.write(CNTIN::INIT.val(0));
+        self.registers.mod_.write(MOD::MOD.val(period as u32 - 1));
+        self.registers.csc[channel].write(CSC::MSB::Enabled + CSC::ELSB::High);
+        self.registers.cv[channel]
+            .write(CV::VAL.val((period * duty_cycle / MAX_DUTY_CYCLE) as u32));
+        self.registers.sc.modify(
+            SC::PS.val(prescaler as u32) + Self::pwmen(channel, true) + SC::CLKS::SystemClock,
+        );
 
+        let (port, number) = pin.into();
+        crate::pinmux::PORT_BASES[port].pcr[number].modify(crate::pinmux::PCR::MUX::ALT2_FTM);
         Ok(())
     }
 
+    /// Stop the channel and give the pin back to GPIO.
     fn stop_pwm(&self, pin: Pinmux) -> Result<(), ErrorCode> {
-        let (_, channel) = pin.into();
+        let channel = Self::channel(pin)?;
+        let (port, number) = pin.into();
+        crate::pinmux::PORT_BASES[port].pcr[number].modify(crate::pinmux::PCR::MUX::GPIO);
 
-        if channel >= 8 {
-            return Err(ErrorCode::INVAL);
+        self.registers.sc.modify(Self::pwmen(channel, false));
+        self.registers.csc[channel].set(0);
+        if self.registers.sc.get() & (0xFF << 16) == 0 {
+            self.registers.sc.modify(SC::CLKS::NoClock);
         }
-
-        self.registers.csc[channel].modify(CSC::MSB::Disabled);
         Ok(())
     }
 
-    pub fn handle_interrupt(&self) {
-        let status = self.registers.status.get();
+    /// Emit the FTM initialization trigger, which TRGMUX can route to
+    /// other peripherals, each time the counter reloads.
+    pub fn set_initialization_trigger(&self, enabled: bool) {
+        self.registers
+            .exttrig
+            .modify(EXTTRIG::INITTRIGEN.val(enabled.into()));
+    }
 
-        // 채널별 인터럽트 플래그 확인 및 처리
-        for channel in 0..8 {
-            if (status & (1 << channel)) != 0 {
-                // 해당 채널 인터럽트 발생 -> 처리 후 플래그 클리어
-                self.registers.status.modify(STATUS::CH0F::NoEvent);
+    /// Handle the interrupt shared by channels `2 * pair` and `2 * pair + 1`.
+    pub fn handle_channel_interrupt(&self, pair: usize) {
+        for channel in [2 * pair, 2 * pair + 1] {
+            // CHF is cleared by writing 0 after reading it set.
+            if self.registers.csc[channel].is_set(CSC::CHF) {
+                self.registers.csc[channel].modify(CSC::CHF::CLEAR);
             }
         }
     }
+
+    pub fn handle_fault_interrupt(&self) {
+        if self.registers.fms.is_set(FMS::FAULTF) {
+            self.registers.fms.modify(
+                FMS::FAULTF::CLEAR
+                    + FMS::FAULTF0::CLEAR
+                    + FMS::FAULTF1::CLEAR
+                    + FMS::FAULTF2::CLEAR
+                    + FMS::FAULTF3::CLEAR,
+            );
+        }
+    }
+
+    /// Handle the counter overflow and reload point interrupt.
+    pub fn handle_overflow_interrupt(&self) {
+        if self.registers.sc.is_set(SC::TOF) {
+            self.registers.sc.modify(SC::TOF::CLEAR);
+        }
+        if self.registers.sc.is_set(SC::RF) {
+            self.registers.sc.modify(SC::RF::CLEAR);
+        }
+    }
 }
 
 impl kernel::hil::pwm::Pwm for Pwm {
@@ -694,10 +777,11 @@ impl kernel::hil::pwm::Pwm for Pwm {
     }
 
     fn get_maximum_frequency_hz(&self) -> usize {
-        5333333
+        // Keep one count per step of the duty cycle.
+        INPUT_CLOCK_HZ / MAX_DUTY_CYCLE
     }
 
     fn get_maximum_duty_cycle(&self) -> usize {
-        100
+        MAX_DUTY_CYCLE
     }
 }
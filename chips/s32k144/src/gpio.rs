@@ -9,6 +9,8 @@ use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeabl
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite};
 use kernel::utilities::StaticRef;
 
+use crate::pinmux::{PCR, PORT_BASES};
+
 #[repr(C)]
 pub struct GpioRegisters {
     pub pdor: ReadWrite<u32, PDOR::Register>, // Port Data Output Register
@@ -31,7 +33,11 @@ register_bitfields![u32,
 ];
 
 /// S32K144 보드용 GPIO 포트 정의
-#[derive(Copy, Clone)]
+///
+/// Pins are numbered port by port, [`PINS_PER_PORT`] to a port, so a pin's
+/// value is its index in [`Port::pins`]. PTE17 is not bonded out on the
+/// S32K144.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Pin {
     PTA0,
     PTA1,
@@ -49,6 +55,8 @@ pub enum Pin {
     PTA13,
     PTA14,
     PTA15,
+    PTA16,
+    PTA17,
     PTB0,
     PTB1,
     PTB2,
@@ -57,6 +65,16 @@ pub enum Pin {
     PTB5,
     PTB6,
     PTB7,
+    PTB8,
+    PTB9,
+    PTB10,
+    PTB11,
+    PTB12,
+    PTB13,
+    PTB14,
+    PTB15,
+    PTB16,
+    PTB17,
     PTC0,
     PTC1,
     PTC2,
@@ -65,6 +83,16 @@ pub enum Pin {
     PTC5,
     PTC6,
     PTC7,
+    PTC8,
+    PTC9,
+    PTC10,
+    PTC11,
+    PTC12,
+    PTC13,
+    PTC14,
+    PTC15,
+    PTC16,
+    PTC17,
     PTD0,
     PTD1,
     PTD2,
@@ -73,6 +101,16 @@ pub enum Pin {
     PTD5,
     PTD6,
     PTD7,
+    PTD8,
+    PTD9,
+    PTD10,
+    PTD11,
+    PTD12,
+    PTD13,
+    PTD14,
+    PTD15,
+    PTD16,
+    PTD17,
     PTE0,
     PTE1,
     PTE2,
@@ -81,6 +119,35 @@ pub enum Pin {
     PTE5,
     PTE6,
     PTE7,
+    PTE8,
+    PTE9,
+    PTE10,
+    PTE11,
+    PTE12,
+    PTE13,
+    PTE14,
+    PTE15,
+    PTE16,
+    PTE17,
+}
+
+/// Number of pins of each port covered by [`Pin`].
+pub const PINS_PER_PORT: usize = 18;
+
+/// Number of pins in [`Pin`].
+pub const NUM_PINS: usize = 5 * PINS_PER_PORT;
+
+impl Pin {
+    /// PORT index (0 for PORTA) and pin number within that port.
+    pub const fn port_and_pin(self) -> (usize, usize) {
+        let index = self as usize;
+        (index / PINS_PER_PORT, index % PINS_PER_PORT)
+    }
+
+    fn pcr(self) -> &'static ReadWrite<u32, PCR::Register> {
+        let (port, pin) = self.port_and_pin();
+        &PORT_BASES[port].pcr[pin]
+    }
 }
 
 /// GPIO 베이스 주소 (레퍼런스 매뉴얼 기반)
@@ -111,44 +178,49 @@ impl<'a> GPIOPin<'a> {
         }
     }
 
+    /// Bit of this pin in the port's GPIO registers.
+    fn mask(&self) -> u32 {
+        1 << self.pin.port_and_pin().1
+    }
+
+    /// Mux the pin to GPIO and make it an output. The pin's PORT must be
+    /// clocked.
     pub fn enable_output(&self) {
+        self.pin.pcr().modify(PCR::MUX::GPIO);
         self.registers
             .pddr
-            .modify(PDDR::PDD.val(self.registers.pddr.get() | (1 << self.pin as u32)));
+            .set(self.registers.pddr.get() | self.mask());
     }
 
+    /// Mux the pin to GPIO and make it an input. The pin's PORT must be
+    /// clocked.
     pub fn enable_input(&self) {
+        self.pin.pcr().modify(PCR::MUX::GPIO);
         self.registers
             .pddr
-            .modify(PDDR::PDD.val(self.registers.pddr.get() & !(1 << self.pin as u32)));
+            .set(self.registers.pddr.get() & !self.mask());
     }
 
     pub fn set_high(&self) {
-        self.registers
-            .psor
-            .write(PSOR::PTSO.val(1 << self.pin as u32));
+        self.registers.psor.write(PSOR::PTSO.val(self.mask()));
     }
 
     pub fn set_low(&self) {
-        self.registers
-            .pcor
-            .write(PCOR::PTCO.val(1 << self.pin as u32));
+        self.registers.pcor.write(PCOR::PTCO.val(self.mask()));
     }
 
     pub fn toggle(&self) {
-        self.registers
-            .ptor
-            .write(PTOR::PTTO.val(1 << self.pin as u32));
+        self.registers.ptor.write(PTOR::PTTO.val(self.mask()));
     }
 
     pub fn read(&self) -> bool {
-        (self.registers.pdir.read(PDIR::PDI) & (1 << self.pin as u32)) != 0
+        (self.registers.pdir.read(PDIR::PDI) & self.mask()) != 0
     }
 }
 
 impl kernel::hil::gpio::Input for GPIOPin<'_> {
     fn read(&self) -> bool {
-        (self.registers.pdir.read(PDIR::PDI) & (1 << self.pin as u32)) != 0
+        (self.registers.pdir.read(PDIR::PDI) & self.mask()) != 0
     }
 }
 
@@ -162,9 +234,7 @@ impl<'a> kernel::hil::gpio::Output for GPIOPin<'a> {
     }
 
     fn toggle(&self) -> bool {
-        self.registers
-            .ptor
-            .write(PTOR::PTTO.val(1 << self.pin as u32));
+        self.registers.ptor.write(PTOR::PTTO.val(self.mask()));
         self.read() // return : current status
     }
 }
@@ -175,82 +245,82 @@ impl<'a> kernel::hil::gpio::Interrupt<'a> for GPIOPin<'a> {
     }
 
     fn enable_interrupts(&self, mode: InterruptEdge) {
-        // 실제 인터럽트 활성화를 위한 레지스터 설정
-        match mode {
-            InterruptEdge::RisingEdge => {
-                // Rising Edge 인터럽트 설정 (예시 코드)
-                self.registers
-                    .pddr
-                    .modify(PDDR::PDD.val(1 << self.pin as u32));
-            }
-            InterruptEdge::FallingEdge => {
-                // Falling Edge 인터럽트 설정 (예시 코드)
-                self.registers
-                    .pddr
-                    .modify(PDDR::PDD.val(0 << self.pin as u32));
-            }
-            InterruptEdge::EitherEdge => {
-                // Both Edge 인터럽트 설정 (예시 코드)
-                self.registers
-                    .pddr
-                    .modify(PDDR::PDD.val(1 << self.pin as u32 | 0 << self.pin as u32));
-            }
-        }
+        let irqc = match mode {
+            InterruptEdge::RisingEdge => PCR::IRQC::RisingEdge,
+            InterruptEdge::FallingEdge => PCR::IRQC::FallingEdge,
+            InterruptEdge::EitherEdge => PCR::IRQC::EitherEdge,
+        };
+        self.pin.pcr().modify(irqc + PCR::ISF::SET);
     }
 
     fn disable_interrupts(&self) {
-        // 인터럽트 비활성화: 특정 레지스터 값을 변경
-        self.registers.pddr.modify(PDDR::PDD.val(0));
+        self.pin.pcr().modify(PCR::IRQC::Disabled + PCR::ISF::SET);
     }
 
     fn is_pending(&self) -> bool {
-        (self.registers.pdir.read(PDIR::PDI) & (1 << self.pin as u32)) != 0
+        self.pin.pcr().is_set(PCR::ISF)
     }
 }
 
 impl<'a> kernel::hil::gpio::Configure for GPIOPin<'a> {
     fn configuration(&self) -> kernel::hil::gpio::Configuration {
-        if self.read() {
-            kernel::hil::gpio::Configuration::Input
+        let mux = self.pin.pcr().read(PCR::MUX);
+        if mux == PCR::MUX::Disabled.value {
+            kernel::hil::gpio::Configuration::LowPower
+        } else if mux != PCR::MUX::GPIO.value {
+            kernel::hil::gpio::Configuration::Function
+        } else if self.registers.pddr.get() & self.mask() != 0 {
+            // The input buffer stays on for outputs.
+            kernel::hil::gpio::Configuration::InputOutput
         } else {
-            kernel::hil::gpio::Configuration::Output
+            kernel::hil::gpio::Configuration::Input
         }
     }
 
-    fn make_input(&self) -> kernel::hil::gpio::Configuration {
-        self.enable_input();
-        kernel::hil::gpio::Configuration::Input
-    }
-
     fn make_output(&self) -> kernel::hil::gpio::Configuration {
         self.enable_output();
-        kernel::hil::gpio::Configuration::Output
+        self.configuration()
+    }
+
+    fn make_input(&self) -> kernel::hil::gpio::Configuration {
+        self.enable_input();
+        self.configuration()
     }
 
     fn disable_input(&self) -> kernel::hil::gpio::Configuration {
-        self.registers
-            .pddr
-            .modify(PDDR::PDD.val(self.registers.pddr.get() & !(1 << self.pin as u32)));
-        kernel::hil::gpio::Configuration::LowPower
+        // The input buffer of a GPIO pin is always on.
+        self.configuration()
     }
 
     fn disable_output(&self) -> kernel::hil::gpio::Configuration {
         self.registers
             .pddr
-            .modify(PDDR::PDD.val(self.registers.pddr.get() & !(1 << self.pin as u32)));
-        kernel::hil::gpio::Configuration::LowPower
+            .set(self.registers.pddr.get() & !self.mask());
+        self.configuration()
     }
 
     fn deactivate_to_low_power(&self) {
-        //TODOs
+        self.pin.pcr().modify(PCR::MUX::Disabled + PCR::PE::CLEAR);
     }
 
-    fn set_floating_state(&self, _mode: kernel::hil::gpio::FloatingState) {
-        // S32K144는 기본적으로 풀다운/풀업 설정이 별도 핀 컨트롤에 있음. 필요하면 구현
+    fn set_floating_state(&self, mode: kernel::hil::gpio::FloatingState) {
+        let pull = match mode {
+            kernel::hil::gpio::FloatingState::PullUp => PCR::PE::SET + PCR::PS::PullUp,
+            kernel::hil::gpio::FloatingState::PullDown => PCR::PE::SET + PCR::PS::PullDown,
+            kernel::hil::gpio::FloatingState::PullNone => PCR::PE::CLEAR + PCR::PS::PullDown,
+        };
+        self.pin.pcr().modify(pull);
     }
 
     fn floating_state(&self) -> kernel::hil::gpio::FloatingState {
-        kernel::hil::gpio::FloatingState::PullNone
+        let pcr = self.pin.pcr().extract();
+        if !pcr.is_set(PCR::PE) {
+            kernel::hil::gpio::FloatingState::PullNone
+        } else if pcr.is_set(PCR::PS) {
+            kernel::hil::gpio::FloatingState::PullUp
+        } else {
+            kernel::hil::gpio::FloatingState::PullDown
+        }
     }
 }
 
@@ -287,10 +357,23 @@ impl<'a, const N: usize> Port<'a, N> {
     pub fn new(pins: [GPIOPin<'a>; N]) -> Self {
         Port { pins }
     }
+
+    /// Handle the interrupt of PORT `port` (0 for PORTA): clear the pending
+    /// pin flags and notify the clients of those pins.
+    pub fn handle_interrupt(&self, port: usize) {
+        let pending = PORT_BASES[port].isfr.get();
+        PORT_BASES[port].isfr.set(pending);
+        for pin in self.pins.iter() {
+            let (pin_port, number) = pin.pin.port_and_pin();
+            if pin_port == port && pending & (1 << number) != 0 {
+                pin.client.map(|client| client.fired());
+            }
+        }
+    }
 }
 
 /// S32K144 GPIO 포트 초기화 함수
-pub fn s32k144_gpio_create<'a>() -> Port<'a, 48> {
+pub fn s32k144_gpio_create<'a>() -> Port<'a, NUM_PINS> {
     Port::new([
         // GPIOA
         GPIOPin::new(Pin::PTA0, GPIOA_BASE),
@@ -309,6 +392,8 @@ pub fn s32k144_gpio_create<'a>() -> Port<'a, 48> {
         GPIOPin::new(Pin::PTA13, GPIOA_BASE),
         GPIOPin::new(Pin::PTA14, GPIOA_BASE),
         GPIOPin::new(Pin::PTA15, GPIOA_BASE),
+        GPIOPin::new(Pin::PTA16, GPIOA_BASE),
+        GPIOPin::new(Pin::PTA17, GPIOA_BASE),
         // GPIOB
         GPIOPin::new(Pin::PTB0, GPIOB_BASE),
         GPIOPin::new(Pin::PTB1, GPIOB_BASE),
@@ -318,6 +403,16 @@ pub fn s32k144_gpio_create<'a>() -> Port<'a, 48> {
         GPIOPin::new(Pin::PTB5, GPIOB_BASE),
         GPIOPin::new(Pin::PTB6, GPIOB_BASE),
         GPIOPin::new(Pin::PTB7, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB8, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB9, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB10, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB11, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB12, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB13, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB14, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB15, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB16, GPIOB_BASE),
+        GPIOPin::new(Pin::PTB17, GPIOB_BASE),
         // GPIOC
         GPIOPin::new(Pin::PTC0, GPIOC_BASE),
         GPIOPin::new(Pin::PTC1, GPIOC_BASE),
@@ -327,6 +422,16 @@ pub fn s32k144_gpio_create<'a>() -> Port<'a, 48> {
         GPIOPin::new(Pin::PTC5, GPIOC_BASE),
         GPIOPin::new(Pin::PTC6, GPIOC_BASE),
         GPIOPin::new(Pin::PTC7, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC8, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC9, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC10, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC11, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC12, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC13, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC14, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC15, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC16, GPIOC_BASE),
+        GPIOPin::new(Pin::PTC17, GPIOC_BASE),
         // GPIOD
         GPIOPin::new(Pin::PTD0, GPIOD_BASE),
         GPIOPin::new(Pin::PTD1, GPIOD_BASE),
@@ -336,6 +441,16 @@ pub fn s32k144_gpio_create<'a>() -> Port<'a, 48> {
         GPIOPin::new(Pin::PTD5, GPIOD_BASE),
         GPIOPin::new(Pin::PTD6, GPIOD_BASE),
         GPIOPin::new(Pin::PTD7, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD8, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD9, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD10, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD11, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD12, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD13, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD14, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD15, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD16, GPIOD_BASE),
+        GPIOPin::new(Pin::PTD17, GPIOD_BASE),
         // GPIOE
         GPIOPin::new(Pin::PTE0, GPIOE_BASE),
         GPIOPin::new(Pin::PTE1, GPIOE_BASE),
@@ -345,5 +460,15 @@ pub fn s32k144_gpio_create<'a>() -> Port<'a, 48> {
         GPIOPin::new(Pin::PTE5, GPIOE_BASE),
         GPIOPin::new(Pin::PTE6, GPIOE_BASE),
         GPIOPin::new(Pin::PTE7, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE8, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE9, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE10, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE11, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE12, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE13, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE14, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE15, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE16, GPIOE_BASE),
+        GPIOPin::new(Pin::PTE17, GPIOE_BASE),
     ])
 }
@@ -3,6 +3,7 @@
 // Copyright Tock Contributors 2024.
 
 use crate::chip::S32K144DefaultPeripherals;
+use kernel::platform::chip::InterruptService;
 
 /// This struct initializes all peripheral drivers for the S32K144.
 /// If a board wishes to use only a subset of these peripherals, this
@@ -29,21 +30,8 @@ impl<'a> S32K144InterruptService<'a> {
     }
 }
 
-impl kernel::platform::chip::InterruptService for S32K144InterruptService<'_> {
+impl InterruptService for S32K144InterruptService<'_> {
     unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
-        match interrupt {
-            crate::peripheral_interrupts::POWER_CLOCK => {
-                self.peripherals.pwr_clk.handle_interrupt()
-            }
-            crate::peripheral_interrupts::RTC => self.peripherals.rtc.handle_interrupt(),
-            crate::peripheral_interrupts::TIMER0 => self.peripherals.timer0.handle_interrupt(),
-            crate::peripheral_interrupts::TIMER1 => self.peripherals.timer1.handle_interrupt(),
-            crate::peripheral_interrupts::TIMER2 => self.peripherals.timer2.handle_interrupt(),
-            crate::peripheral_interrupts::LPUART0 => self.peripherals.lpuart0.handle_interrupt(),
-            crate::peripheral_interrupts::FTM0 => self.peripherals.pwm0.handle_interrupt(),
-            crate::peripheral_interrupts::FTFC => self.peripherals.nvmc.handle_interrupt(),
-            _ => return false,
-        }
-        true
+        self.peripherals.service_interrupt(interrupt)
     }
 }
@@ -6,20 +6,79 @@
 #![crate_name = "s32k144"]
 #![crate_type = "rlib"]
 
-//pub mod can0;
+use cortexm4f::{initialize_ram_jump_to_main, unhandled_interrupt, CortexM4F, CortexMVariant};
+
+pub mod adc;
+pub mod can0;
 pub mod chip;
 pub mod clock;
+pub mod cmp;
+pub mod crc;
+pub mod csec;
+pub mod eee;
+pub mod eim;
+pub mod erm;
+pub mod flash_config;
+pub mod flexio;
+pub mod flexio_i2c;
+pub mod flexio_spi;
+pub mod flexio_uart;
 pub mod ftm0;
 pub mod interrupt_service;
+pub mod lin;
+pub mod lmem;
 pub mod lpit0;
 pub mod lptmr0;
 pub mod lpuart0;
 pub mod nvmc;
+pub mod pdb;
 pub mod peripheral_interrupts;
 pub mod pinmux;
 //pub mod portc;
 //pub mod portd;
 pub mod power;
+pub mod rcm;
 pub mod rtc;
+pub mod sim;
+pub mod trgmux;
 //pub mod wdog;
 pub mod gpio;
+
+extern "C" {
+    // _estack is not really a function, but it makes the types work
+    // You should never actually invoke it!!
+    fn _estack();
+}
+
+#[cfg_attr(
+    all(target_arch = "arm", target_os = "none"),
+    link_section = ".vectors"
+)]
+// used Ensures that the symbol is kept until the final binary
+#[cfg_attr(all(target_arch = "arm", target_os = "none"), used)]
+pub static BASE_VECTORS: [unsafe extern "C" fn(); 16] = [
+    _estack,
+    initialize_ram_jump_to_main,
+    unhandled_interrupt,           // NMI
+    CortexM4F::HARD_FAULT_HANDLER, // Hard Fault
+    unhandled_interrupt,           // MemManage
+    unhandled_interrupt,           // BusFault
+    unhandled_interrupt,           // UsageFault
+    unhandled_interrupt,
+    unhandled_interrupt,
+    unhandled_interrupt,
+    unhandled_interrupt,
+    CortexM4F::SVC_HANDLER, // SVC
+    unhandled_interrupt,    // DebugMon
+    unhandled_interrupt,
+    unhandled_interrupt,        // PendSV
+    CortexM4F::SYSTICK_HANDLER, // SysTick
+];
+
+// Every peripheral vector, reserved ones included, goes to the generic ISR,
+// which defers the interrupt to `S32K144::service_pending_interrupts`.
+#[cfg_attr(all(target_arch = "arm", target_os = "none"), link_section = ".irqs")]
+// used Ensures that the symbol is kept until the final binary
+#[cfg_attr(all(target_arch = "arm", target_os = "none"), used)]
+pub static IRQS: [unsafe extern "C" fn(); peripheral_interrupts::NUM_IRQS] =
+    [CortexM4F::GENERIC_ISR; peripheral_interrupts::NUM_IRQS];
@@ -0,0 +1,343 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! LIN controller on an LPUART, for the S32K144.
+//!
+//! The LPUART detects breaks of eleven or more bit times in hardware and
+//! sends 13-bit breaks as a special character. Everything else is done one
+//! byte at a time from the receive interrupt: the transceiver echoes every
+//! byte sent back to the receiver, so each byte is written once the echo of
+//! the previous one has been read and compared, which both paces the
+//! transmission and detects bit errors.
+//!
+//! The EVB's TJA1027 LIN transceiver is on LPUART2, PTD6 (RX) and PTD7
+//! (TX). LPUART1 and LPUART2 run from FIRCDIV2 at 48 MHz.
+
+use core::cell::Cell;
+use kernel::hil::lin::{self, ChecksumModel, Error};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::FieldValue;
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::clock::{Clock, Peripheral};
+use crate::lpuart0::{LpuartRegisters, BAUD, CTRL, DATA, GLOBAL, STAT};
+
+/// Frequency of the LPUART functional clock.
+const CLOCK_HZ: u32 = 48_000_000;
+
+/// Receiver oversampling ratio.
+const OVERSAMPLING: u32 = 16;
+
+/// The baud rate divider for `baud_rate`, rounded to nearest, if the LPUART
+/// can produce it.
+fn divider(baud_rate: u32) -> Option<u32> {
+    let bit_clock = OVERSAMPLING.checked_mul(baud_rate)?;
+    let divider = (CLOCK_HZ + bit_clock / 2).checked_div(bit_clock)?;
+    (1..=0x1FFF).contains(&divider).then_some(divider)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Disabled,
+    /// Waiting for a break.
+    Idle,
+    /// A break was seen, waiting for the sync byte.
+    Sync,
+    /// Waiting for the protected identifier.
+    Identifier,
+    /// A header was received and the client decides about the response.
+    Header,
+    Sending,
+    Receiving,
+}
+
+pub struct Lin<'a> {
+    registers: StaticRef<LpuartRegisters>,
+    clock: Clock,
+    peripheral: Peripheral,
+    client: OptionalCell<&'a dyn lin::Client>,
+    baud_rate: Cell<u32>,
+    state: Cell<State>,
+    /// Protected identifier of the header being sent by this node.
+    sending_header: OptionalCell<u8>,
+    /// Protected identifier of the last header.
+    pid: Cell<u8>,
+    buffer: TakeCell<'static, [u8]>,
+    len: Cell<usize>,
+    /// Bytes of the response sent or received, including the checksum.
+    position: Cell<usize>,
+    model: Cell<ChecksumModel>,
+    checksum: Cell<u8>,
+}
+
+impl Lin<'_> {
+    pub const fn new(registers: StaticRef<LpuartRegisters>, peripheral: Peripheral) -> Self {
+        Self {
+            registers,
+            clock: Clock::new(),
+            peripheral,
+            client: OptionalCell::empty(),
+            baud_rate: Cell::new(19200),
+            state: Cell::new(State::Disabled),
+            sending_header: OptionalCell::empty(),
+            pid: Cell::new(0),
+            buffer: TakeCell::empty(),
+            len: Cell::new(0),
+            position: Cell::new(0),
+            model: Cell::new(ChecksumModel::Enhanced),
+            checksum: Cell::new(0),
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        let stat = self.registers.stat.extract();
+        if stat.is_set(STAT::LBKDIF) {
+            self.clear_flags(STAT::LBKDIF::SET);
+            self.break_received();
+        }
+        if stat.is_set(STAT::RDRF) {
+            let framing_error = stat.is_set(STAT::FE);
+            if framing_error || stat.is_set(STAT::OR) {
+                self.clear_flags(STAT::FE::SET + STAT::OR::SET);
+            }
+            let byte = self.registers.data.get() as u8;
+            self.byte_received(byte, framing_error);
+        }
+    }
+
+    /// Clear the given write-1-to-clear flags, keeping the LIN settings.
+    fn clear_flags(&self, flags: FieldValue<u32, STAT::Register>) {
+        self.registers
+            .stat
+            .write(STAT::LBKDE::Enabled + STAT::BRK13::LongBreak + flags);
+    }
+
+    fn send(&self, byte: u8) {
+        self.registers.data.set(u32::from(byte));
+    }
+
+    fn break_received(&self) {
+        if matches!(self.state.get(), State::Sending | State::Receiving) {
+            // The master has moved on to the next frame.
+            self.finish_response(Err(Error::Timeout));
+        }
+        self.state.set(State::Sync);
+        if self.sending_header.is_some() {
+            self.send(lin::SYNC);
+        }
+    }
+
+    fn byte_received(&self, byte: u8, framing_error: bool) {
+        match self.state.get() {
+            State::Sync => {
+                if framing_error || byte != lin::SYNC {
+                    return self.header_error();
+                }
+                self.state.set(State::Identifier);
+                self.sending_header.map(|pid| self.send(pid));
+            }
+            State::Identifier => {
+                let id = match lin::id_from_protected(byte) {
+                    Some(id) if !framing_error => id,
+                    _ => return self.header_error(),
+                };
+                self.sending_header.clear();
+                self.pid.set(byte);
+                self.state.set(State::Header);
+                self.client.map(|client| client.header_received(id));
+            }
+            State::Sending => {
+                let position = self.position.get();
+                let expected = self.response_byte(position);
+                if framing_error {
+                    return self.finish_response(Err(Error::Framing));
+                }
+                if byte != expected {
+                    return self.finish_response(Err(Error::Bit));
+                }
+                self.position.set(position + 1);
+                if position == self.len.get() {
+                    self.finish_response(Ok(()));
+                } else {
+                    self.send(self.response_byte(position + 1));
+                }
+            }
+            State::Receiving => {
+                if framing_error {
+                    return self.finish_response(Err(Error::Framing));
+                }
+                let position = self.position.get();
+                let len = self.len.get();
+                if position < len {
+                    self.buffer.map(|buffer| buffer[position] = byte);
+                    self.position.set(position + 1);
+                } else {
+                    let checksum = self.buffer.map_or(0, |buffer| {
+                        lin::checksum(self.model.get(), self.pid.get(), &buffer[..len])
+                    });
+                    self.finish_response(if byte == checksum {
+                        Ok(())
+                    } else {
+                        Err(Error::Checksum)
+                    });
+                }
+            }
+            // Responses of frames this node does not take part in.
+            State::Disabled | State::Idle | State::Header => {}
+        }
+    }
+
+    /// Byte `position` of the response being sent, the checksum coming
+    /// after the data.
+    fn response_byte(&self, position: usize) -> u8 {
+        if position < self.len.get() {
+            self.buffer.map_or(0, |buffer| buffer[position])
+        } else {
+            self.checksum.get()
+        }
+    }
+
+    fn header_error(&self) {
+        self.state.set(State::Idle);
+        self.sending_header.clear();
+        self.client.map(|client| client.bus_error(Error::Header));
+    }
+
+    fn finish_response(&self, result: Result<(), Error>) {
+        let sending = self.state.get() == State::Sending;
+        self.state.set(State::Idle);
+        self.buffer.take().map(|buffer| {
+            self.client.map(move |client| {
+                if sending {
+                    client.response_sent(buffer, result);
+                } else {
+                    client.response_received(buffer, self.len.get(), result);
+                }
+            })
+        });
+    }
+
+    /// Check that a response of `len` bytes can start now.
+    fn response_allowed(&self, buffer: &[u8], len: usize) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Disabled => Err(ErrorCode::OFF),
+            State::Header if len == 0 || len > lin::MAX_DATA_LEN || len > buffer.len() => {
+                Err(ErrorCode::SIZE)
+            }
+            State::Header => Ok(()),
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+}
+
+impl<'a> lin::Lin<'a> for Lin<'a> {
+    fn set_client(&self, client: &'a dyn lin::Client) {
+        self.client.set(client);
+    }
+
+    fn set_baud_rate(&self, baud_rate: u32) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Disabled {
+            return Err(ErrorCode::BUSY);
+        }
+        divider(baud_rate).ok_or(ErrorCode::INVAL)?;
+        self.baud_rate.set(baud_rate);
+        Ok(())
+    }
+
+    fn enable(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Disabled {
+            return Err(ErrorCode::ALREADY);
+        }
+        let divider = divider(self.baud_rate.get()).ok_or(ErrorCode::INVAL)?;
+        self.clock.enable_peripheral_clock(self.peripheral);
+        self.registers.global.write(GLOBAL::RST::SET);
+        self.registers.global.set(0);
+        self.registers.baud.write(
+            BAUD::OSR.val(OVERSAMPLING - 1) + BAUD::SBR.val(divider) + BAUD::LBKDIE::Enabled,
+        );
+        self.clear_flags(STAT::LBKDIF::SET);
+        self.registers
+            .ctrl
+            .write(CTRL::RE::SET + CTRL::TE::SET + CTRL::RIE::SET);
+        self.state.set(State::Idle);
+        Ok(())
+    }
+
+    fn disable(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Disabled => return Err(ErrorCode::ALREADY),
+            State::Sending | State::Receiving => return Err(ErrorCode::BUSY),
+            _ => {}
+        }
+        self.registers.ctrl.set(0);
+        self.clock.disable_peripheral_clock(self.peripheral);
+        self.sending_header.clear();
+        self.state.set(State::Disabled);
+        Ok(())
+    }
+
+    fn send_header(&self, id: u8) -> Result<(), ErrorCode> {
+        if id > lin::MAX_ID {
+            return Err(ErrorCode::INVAL);
+        }
+        match self.state.get() {
+            State::Disabled => return Err(ErrorCode::OFF),
+            State::Idle | State::Header => {}
+            _ => return Err(ErrorCode::BUSY),
+        }
+        self.sending_header.set(lin::protected_id(id));
+        self.state.set(State::Idle);
+        // An all-zero special character is a break.
+        self.registers.data.write(DATA::FRETSC::SET);
+        Ok(())
+    }
+
+    fn send_response(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+        model: ChecksumModel,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(error) = self.response_allowed(buffer, len) {
+            return Err((error, buffer));
+        }
+        self.checksum
+            .set(lin::checksum(model, self.pid.get(), &buffer[..len]));
+        self.len.set(len);
+        self.position.set(0);
+        self.state.set(State::Sending);
+        self.send(buffer[0]);
+        self.buffer.replace(buffer);
+        Ok(())
+    }
+
+    fn receive_response(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+        model: ChecksumModel,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(error) = self.response_allowed(buffer, len) {
+            return Err((error, buffer));
+        }
+        self.model.set(model);
+        self.len.set(len);
+        self.position.set(0);
+        self.state.set(State::Receiving);
+        self.buffer.replace(buffer);
+        Ok(())
+    }
+
+    fn abort_response(&self) -> Option<&'static mut [u8]> {
+        match self.state.get() {
+            State::Sending | State::Receiving => {
+                self.state.set(State::Idle);
+                self.buffer.take()
+            }
+            _ => None,
+        }
+    }
+}
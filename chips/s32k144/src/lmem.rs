@@ -0,0 +1,127 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Local Memory Controller (LMEM) code cache, and flash prefetch, for the
+//! S32K144.
+//!
+//! The 4 KiB processor code cache sits on the code bus in front of the
+//! P-Flash and FlexNVM. The flash needs wait states at core clocks above
+//! its own, so without the cache execution stalls on nearly every fetch.
+//! The cache is off after reset.
+//!
+//! The cache does not see the FTFC reprogramming the flash, so the FTFC
+//! driver invalidates it after every command.
+//!
+//! The flash controller also prefetches (speculates) the next flash line,
+//! separately for instruction and data reads, configured through the MSCM.
+//!
+//! The S32K144 LMEM has no hit or miss counters.
+
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite};
+use kernel::utilities::StaticRef;
+
+register_structs! {
+    LmemRegisters {
+        /// Cache control register
+        (0x000 => pcccr: ReadWrite<u32, PCCCR::Register>),
+        /// Cache line control register
+        (0x004 => pcclcr: ReadWrite<u32>),
+        /// Cache search address register
+        (0x008 => pccsar: ReadWrite<u32>),
+        /// Cache read/write value register
+        (0x00C => pcccvr: ReadWrite<u32>),
+        (0x010 => _reserved0),
+        /// Cache regions mode register
+        (0x020 => pccrmr: ReadWrite<u32>),
+        (0x024 => @END),
+    }
+}
+
+register_bitfields![u32,
+    PCCCR [
+        /// Cache enable
+        ENCACHE OFFSET(0) NUMBITS(1) [],
+        /// Invalidate way 0
+        INVW0 OFFSET(24) NUMBITS(1) [],
+        /// Push way 0
+        PUSHW0 OFFSET(25) NUMBITS(1) [],
+        /// Invalidate way 1
+        INVW1 OFFSET(26) NUMBITS(1) [],
+        /// Push way 1
+        PUSHW1 OFFSET(27) NUMBITS(1) [],
+        /// Initiate cache command, cleared by hardware when done
+        GO OFFSET(31) NUMBITS(1) []
+    ]
+];
+
+register_structs! {
+    OcmdrRegisters {
+        /// On-Chip Memory Descriptor Register for the P-Flash
+        (0x0 => ocmdr0: ReadWrite<u32, OCMDR::Register>),
+        (0x4 => @END),
+    }
+}
+
+register_bitfields![u32,
+    OCMDR [
+        /// Disable flash data speculation
+        DATA_SPECULATION_DISABLE OFFSET(4) NUMBITS(1) [],
+        /// Disable flash instruction speculation
+        INSTRUCTION_SPECULATION_DISABLE OFFSET(5) NUMBITS(1) []
+    ]
+];
+
+const LMEM_BASE: StaticRef<LmemRegisters> =
+    unsafe { StaticRef::new(0xE008_2000 as *const LmemRegisters) };
+
+const MSCM_OCMDR_BASE: StaticRef<OcmdrRegisters> =
+    unsafe { StaticRef::new(0x4000_1400 as *const OcmdrRegisters) };
+
+pub struct Lmem {
+    registers: StaticRef<LmemRegisters>,
+    ocmdr: StaticRef<OcmdrRegisters>,
+}
+
+impl Lmem {
+    pub const fn new() -> Self {
+        Self {
+            registers: LMEM_BASE,
+            ocmdr: MSCM_OCMDR_BASE,
+        }
+    }
+
+    /// Invalidate and then enable the code cache.
+    pub fn enable(&self) {
+        self.invalidate();
+        self.registers.pcccr.modify(PCCCR::ENCACHE::SET);
+    }
+
+    pub fn disable(&self) {
+        self.registers.pcccr.modify(PCCCR::ENCACHE::CLEAR);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.registers.pcccr.is_set(PCCCR::ENCACHE)
+    }
+
+    /// Drop every line of the cache, so the next fetches read the flash.
+    /// The cache is write-through, so nothing is lost.
+    pub fn invalidate(&self) {
+        let enable = self.registers.pcccr.read(PCCCR::ENCACHE);
+        self.registers.pcccr.write(
+            PCCCR::ENCACHE.val(enable) + PCCCR::INVW0::SET + PCCCR::INVW1::SET + PCCCR::GO::SET,
+        );
+        while self.registers.pcccr.is_set(PCCCR::GO) {}
+    }
+
+    /// Set whether the flash prefetches the line after an instruction or a
+    /// data read. Both are enabled after reset.
+    pub fn set_flash_prefetch(&self, instructions: bool, data: bool) {
+        self.ocmdr.ocmdr0.modify(
+            OCMDR::INSTRUCTION_SPECULATION_DISABLE.val(u32::from(!instructions))
+                + OCMDR::DATA_SPECULATION_DISABLE.val(u32::from(!data)),
+        );
+    }
+}
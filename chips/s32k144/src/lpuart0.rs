@@ -10,17 +10,21 @@ use kernel::ErrorCode;
 
 pub const LPUART0_BASE: StaticRef<LpuartRegisters> =
     unsafe { StaticRef::new(0x4006A000 as *const LpuartRegisters) };
+pub const LPUART1_BASE: StaticRef<LpuartRegisters> =
+    unsafe { StaticRef::new(0x4006B000 as *const LpuartRegisters) };
+pub const LPUART2_BASE: StaticRef<LpuartRegisters> =
+    unsafe { StaticRef::new(0x4006C000 as *const LpuartRegisters) };
 
 #[repr(C)]
 pub struct LpuartRegisters {
     verid: ReadOnly<u32, VERID::Register>,
     param: ReadOnly<u32, PARAM::Register>,
-    global: ReadWrite<u32, GLOBAL::Register>,
+    pub(crate) global: ReadWrite<u32, GLOBAL::Register>,
     pincfg: ReadWrite<u32, PINCFG::Register>,
-    baud: ReadWrite<u32, BAUD::Register>,
-    stat: ReadWrite<u32, STAT::Register>,
-    ctrl: ReadWrite<u32, CTRL::Register>,
-    data: ReadWrite<u32, DATA::Register>,
+    pub(crate) baud: ReadWrite<u32, BAUD::Register>,
+    pub(crate) stat: ReadWrite<u32, STAT::Register>,
+    pub(crate) ctrl: ReadWrite<u32, CTRL::Register>,
+    pub(crate) data: ReadWrite<u32, DATA::Register>,
     match_: ReadWrite<u32, MATCH::Register>,
     modir: ReadWrite<u32, MODIR::Register>,
     fifo: ReadWrite<u32, FIFO::Register>,
@@ -40,13 +44,13 @@ register_bitfields![u32,
         TXFIFO OFFSET(0) NUMBITS(8) [],
         RXFIFO OFFSET(8) NUMBITS(8) []
     ],
-    GLOBAL [
+    pub(crate) GLOBAL [
         RST OFFSET(1) NUMBITS(1) []
     ],
     PINCFG [
         TRGSEL OFFSET(0) NUMBITS(2) []
     ],
-    BAUD [
+    pub(crate) BAUD [
         SBR OFFSET(0) NUMBITS(13) [],
         SBNS OFFSET(13) NUMBITS(1) [
             OneStopBit = 0,
@@ -95,7 +99,7 @@ register_bitfields![u32,
             MatchMode = 1
         ]
     ],
-    STAT [
+    pub(crate) STAT [
         MA2F OFFSET(14) NUMBITS(1) [
             NotEqual = 0,
             Equal = 1
@@ -169,7 +173,7 @@ register_bitfields![u32,
             BreakDetected = 1
         ]
     ],
-    CTRL [
+    pub(crate) CTRL [
         PT OFFSET(0) NUMBITS(1) [
             Even = 0,
             Odd = 1
@@ -283,7 +287,7 @@ register_bitfields![u32,
         R9T8 OFFSET(30) NUMBITS(1) [],
         R8T9 OFFSET(31) NUMBITS(1) []
     ],
-    DATA [
+    pub(crate) DATA [
         R0T0 OFFSET(0) NUMBITS(1) [],
         R1T1 OFFSET(1) NUMBITS(1) [],
         R2T2 OFFSET(2) NUMBITS(1) [],
@@ -493,6 +497,14 @@ impl<'a> Lpuart0<'a> {
             .modify(CTRL::TIE::CLEAR + CTRL::TCIE::CLEAR);
     }
 
+    /// Send `byte` by polling, bypassing any transmission in progress. This
+    /// is for output that cannot wait for interrupts, such as a last flush
+    /// of the debug buffer.
+    pub fn send_byte(&self, byte: u8) {
+        while !self.registers.stat.is_set(STAT::TDRE) {}
+        self.registers.data.set(u32::from(byte));
+    }
+
     pub fn handle_interrupt(&self) {
         if self.registers.stat.is_set(STAT::TDRE) {
             self.tx_buffer.take().map(|buf| {
@@ -514,21 +526,22 @@ impl<'a> Lpuart0<'a> {
             });
         }
 
-        if self.registers.stat.is_set(STAT::TDRE) {
+        // An overrun stops the receiver until the flag is cleared.
+        if self.registers.stat.is_set(STAT::OR) {
+            self.registers.stat.write(STAT::OR::SET);
+        }
+
+        if self.registers.stat.is_set(STAT::RDRF) {
             self.rx_buffer.take().map(|buf| {
                 let pos = self.rx_position.get();
-                if pos < self.rx_len.get() {
-                    self.registers.data.set(buf[pos] as u32);
-                    self.rx_position.set(pos + 1);
+                buf[pos] = self.registers.data.get() as u8;
+                self.rx_position.set(pos + 1);
+                if pos + 1 < self.rx_len.get() {
                     self.rx_buffer.replace(buf);
                 } else {
                     self.disable_rx_interrupts();
-                    self.rx_client.map(|client| {
-                        self.rx_buffer.replace(buf);
-
-                        self.rx_buffer.take().map(|buf| {
-                            client.received_buffer(buf, self.rx_len.get(), Ok(()), Error::None);
-                        });
+                    self.rx_client.map(move |client| {
+                        client.received_buffer(buf, self.rx_len.get(), Ok(()), Error::None);
                     });
                 }
             });
@@ -584,7 +597,7 @@ impl<'a> Receive<'a> for Lpuart0<'a> {
         buffer: &'static mut [u8],
         len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
-        if len == 0 {
+        if len == 0 || len > buffer.len() {
             return Err((ErrorCode::SIZE, buffer));
         }
         if self.rx_buffer.is_some() {
@@ -1,3 +1,37 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Flash Memory Module (FTFC) driver for the S32K144.
+//!
+//! The FTFC is not written through the memory bus. Every program or erase
+//! operation is a command loaded into the FCCOB registers and launched by
+//! clearing `FSTAT[CCIF]`. Completion raises the command-complete interrupt
+//! (when `FCNFG[CCIE]` is set), and the command result is reported in
+//! `FSTAT[ACCERR, FPVIOL, MGSTAT0]`.
+//!
+//! The `hil::flash` page of this driver is one P-Flash sector (4 KiB), the
+//! smallest erasable unit. Writing a page erases the sector, checks it reads
+//! back as all 1s, programs it one 8-byte phrase at a time and finally runs
+//! a Program Check over every longword. Each step is one FTFC command, and
+//! the driver advances to the next step from the command-complete interrupt.
+//!
+//! The P-Flash cannot be read while a P-Flash command is in progress, so the
+//! code which launches a command and waits for it lives in RAM (`.ramfunc`)
+//! and runs with interrupts disabled. Commands targeting the FlexNVM block do
+//! not stall the core and are launched directly.
+//!
+//! The LMEM code cache is invalidated after every command, since it would
+//! otherwise keep returning the old flash contents.
+//!
+//! FSTAT errors are reported as:
+//!
+//! | FSTAT flag | `ErrorCode` | `hil::flash::Error`          |
+//! |------------|-------------|------------------------------|
+//! | ACCERR     | `INVAL`     | `FlashError`                 |
+//! | FPVIOL     | `RESERVE`   | `FlashMemoryProtectionError` |
+//! | MGSTAT0    | `FAIL`      | `FlashError`                 |
+
 use core::cell::Cell;
 use core::ops::{Index, IndexMut};
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
@@ -5,7 +39,7 @@ use kernel::hil::flash;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
-use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite};
+use kernel::utilities::registers::{register_bitfields, LocalRegisterCopy, ReadOnly, ReadWrite};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 
@@ -18,18 +52,11 @@ pub struct FtfcRegisters {
     pub fcnfg: ReadWrite<u8, FCNFG::Register>,
     pub fsec: ReadOnly<u8, FSEC::Register>,
     pub fopt: ReadOnly<u8, FOPT::Register>,
-    pub fccob3: ReadWrite<u8, FCCOB3::Register>,
-    pub fccob2: ReadWrite<u8, FCCOB2::Register>,
-    pub fccob1: ReadWrite<u8, FCCOB1::Register>,
-    pub fccob0: ReadWrite<u8, FCCOB0::Register>,
-    pub fccob7: ReadWrite<u8, FCCOB7::Register>,
-    pub fccob6: ReadWrite<u8, FCCOB6::Register>,
-    pub fccob5: ReadWrite<u8, FCCOB5::Register>,
-    pub fccob4: ReadWrite<u8, FCCOB4::Register>,
-    pub fccobb: ReadWrite<u8, FCCOBB::Register>,
-    pub fccoba: ReadWrite<u8, FCCOBA::Register>,
-    pub fccob9: ReadWrite<u8, FCCOB9::Register>,
-    pub fccob8: ReadWrite<u8, FCCOB8::Register>,
+    /// Flash Common Command Object registers. The registers are laid out
+    /// big-endian within each word (FCCOB3, FCCOB2, FCCOB1, FCCOB0, FCCOB7,
+    /// ...), so FCCOBn lives at index `n ^ 3`. Use `Ftfc::set_fccob` rather
+    /// than indexing this array directly.
+    pub fccob: [ReadWrite<u8>; 12],
     pub fprot3: ReadWrite<u8, FPROT3::Register>,
     pub fprot2: ReadWrite<u8, FPROT2::Register>,
     pub fprot1: ReadWrite<u8, FPROT1::Register>,
@@ -37,6 +64,11 @@ pub struct FtfcRegisters {
     _reserved0: [u8; 2],
     pub feprot: ReadWrite<u8, FEPROT::Register>,
     pub fdprot: ReadWrite<u8, FDPROT::Register>,
+    _reserved1: [u8; 20],
+    pub fcsestat: ReadOnly<u8, FCSESTAT::Register>,
+    _reserved2: u8,
+    pub ferstat: ReadWrite<u8, FERSTAT::Register>,
+    pub fercnfg: ReadWrite<u8, FERCNFG::Register>,
 }
 
 register_bitfields![u8,
@@ -79,25 +111,13 @@ register_bitfields![u8,
     FOPT[
         OPT OFFSET(0) NUMBITS(8) []
     ],
-    FCCOB3[CCOB3 OFFSET(0) NUMBITS(8) []],
-    FCCOB2[CCOB2 OFFSET(0) NUMBITS(8) []],
-    FCCOB1[CCOB1 OFFSET(0) NUMBITS(8) []],
-    FCCOB0[CCOB0 OFFSET(0) NUMBITS(8) []],
-    FCCOB7[CCOB7 OFFSET(0) NUMBITS(8) []],
-    FCCOB6[CCOB6 OFFSET(0) NUMBITS(8) []],
-    FCCOB5[CCOB5 OFFSET(0) NUMBITS(8) []],
-    FCCOB4[CCOB4 OFFSET(0) NUMBITS(8) []],
-    FCCOBB[CCOBB OFFSET(0) NUMBITS(8) []],
-    FCCOBA[CCOBA OFFSET(0) NUMBITS(8) []],
-    FCCOB9[CCOB9 OFFSET(0) NUMBITS(8) []],
-    FCCOB8[CCOB8 OFFSET(0) NUMBITS(8) []],
     FPROT3[PROT OFFSET(0) NUMBITS(8) []],
     FPROT2[PROT OFFSET(0) NUMBITS(8) []],
     FPROT1[PROT OFFSET(0) NUMBITS(8) []],
     FPROT0[PROT OFFSET(0) NUMBITS(8) []],
     FEPROT[EPROT OFFSET(0) NUMBITS(8)],
     FDPROT[DPROT OFFSET(0) NUMBITS(8)],
-    FCSESTAT[
+    pub FCSESTAT[
         BSY OFFSET(0) NUMBITS(1) [],
         SB OFFSET(1) NUMBITS(1) [],
         BIN OFFSET(2) NUMBITS(1) [],
@@ -105,7 +125,7 @@ register_bitfields![u8,
         BOK OFFSET(4) NUMBITS(1) [],
         RIN OFFSET(5) NUMBITS(1) [],
         EDB OFFSET(6) NUMBITS(1) [],
-        IDB OFFSET(7) NUMBITS(1) [],
+        IDB OFFSET(7) NUMBITS(1) []
     ],
     FERSTAT[
         DFDIF OFFSET(1) NUMBITS(1)
@@ -116,7 +136,145 @@ register_bitfields![u8,
     ]
 ];
 
-const PAGE_SIZE: usize = 4096;
+/// Size of a P-Flash sector, the smallest erasable unit of P-Flash.
+pub const PAGE_SIZE: usize = 4096;
+/// Size of a phrase, the unit of the Program Phrase command.
+pub const PHRASE_SIZE: usize = 8;
+/// Alignment unit of the Read 1s Section command.
+const SECTION_UNIT: usize = 16;
+/// Size of a longword, the unit of the Program Check command.
+const LONGWORD_SIZE: usize = 4;
+
+/// Start and size of the P-Flash block in the memory map.
+pub const PFLASH_BASE: usize = 0x0000_0000;
+pub const PFLASH_SIZE: usize = 512 * 1024;
+
+/// Margin level used for the Read 1s Section and Program Check commands.
+/// The user margin is what NXP recommends for verifying after an operation.
+const MARGIN_USER: u8 = 0x01;
+
+/// FTFC commands, written to FCCOB0.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum FlashCommand {
+    Read1sBlock = 0x00,
+    Read1sSection = 0x01,
+    ProgramCheck = 0x02,
+    ReadResource = 0x03,
+    ProgramPhrase = 0x07,
+    EraseFlashBlock = 0x08,
+    EraseFlashSector = 0x09,
+    ProgramPartition = 0x80,
+    SetFlexRamFunction = 0x81,
+}
+
+/// Size of the emulated EEPROM carved out of FlexRAM, as encoded in the
+/// Program Partition EEPROM data size code.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum EeeSize {
+    Size4K = 0x2,
+    Size2K = 0x3,
+    Size1K = 0x4,
+    Size512 = 0x5,
+    Size256 = 0x6,
+    Size128 = 0x7,
+    Size64 = 0x8,
+    Size32 = 0x9,
+    None = 0xF,
+}
+
+impl EeeSize {
+    pub fn bytes(&self) -> usize {
+        match self {
+            EeeSize::None => 0,
+            size => 0x4000 >> (*size as u8),
+        }
+    }
+
+    fn from_code(code: u32) -> Self {
+        match code {
+            0x2 => EeeSize::Size4K,
+            0x3 => EeeSize::Size2K,
+            0x4 => EeeSize::Size1K,
+            0x5 => EeeSize::Size512,
+            0x6 => EeeSize::Size256,
+            0x7 => EeeSize::Size128,
+            0x8 => EeeSize::Size64,
+            0x9 => EeeSize::Size32,
+            _ => EeeSize::None,
+        }
+    }
+}
+
+/// Split of the 64 KiB FlexNVM block between D-Flash and the EEPROM backup
+/// used by the emulated EEPROM, as encoded in the FlexNVM partition code.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum FlexNvmPartition {
+    /// 64 KiB D-Flash, no EEPROM backup.
+    DFlash64K = 0x0,
+    /// 32 KiB D-Flash, 32 KiB EEPROM backup.
+    DFlash32K = 0x3,
+    /// No D-Flash, 64 KiB EEPROM backup.
+    DFlash0K = 0x4,
+    /// 16 KiB D-Flash, 48 KiB EEPROM backup.
+    DFlash16K = 0xA,
+}
+
+/// Number of CSEc key slots reserved out of FlexRAM by Program Partition.
+/// The partition can only be programmed once, so keys must be reserved at
+/// the same time as the emulated EEPROM.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum CsecKeySize {
+    None = 0x0,
+    Keys5 = 0x1,
+    Keys10 = 0x2,
+    Keys20 = 0x3,
+}
+
+impl CsecKeySize {
+    pub fn bytes(&self) -> usize {
+        match self {
+            CsecKeySize::None => 0,
+            size => 64 << (*size as u8),
+        }
+    }
+}
+
+/// Parameters of the Program Partition command.
+#[derive(Clone, Copy)]
+pub struct PartitionConfig {
+    pub eee_size: EeeSize,
+    pub flexnvm: FlexNvmPartition,
+    pub csec_keys: CsecKeySize,
+    /// Whether FlexRAM is loaded with the EEPROM contents during reset. If
+    /// not, `set_flexram_function` must be called before using the EEE.
+    pub load_flexram_at_reset: bool,
+}
+
+/// Function of FlexRAM, selected with the Set FlexRAM Function command.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum FlexRamFunction {
+    EmulatedEeprom = 0x00,
+    Ram = 0xFF,
+}
+
+/// Drivers that share the FTFC command interface and start their own
+/// operations with `Ftfc::delegate`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CommandOwner {
+    Eeprom = 0,
+    Csec = 1,
+}
+
+/// Receives the command-complete interrupt while another driver sharing the
+/// FTFC, such as the emulated EEPROM, has an operation in flight.
+pub trait CommandClient {
+    fn command_complete(&self, result: Result<(), ErrorCode>);
+}
 
 pub struct FtfcPage(pub [u8; PAGE_SIZE]);
 
@@ -127,9 +285,9 @@ impl Default for FtfcPage {
 }
 
 impl FtfcPage {
-    //fn len(&self) -> usize {
-    //    self.0.len()
-    //}
+    fn len(&self) -> usize {
+        self.0.len()
+    }
 }
 
 impl Index<usize> for FtfcPage {
@@ -152,12 +310,27 @@ impl AsMut<[u8]> for FtfcPage {
     }
 }
 
+/// Step of the current flash operation. Every state other than `Ready` and
+/// `Read` has an FTFC command in flight.
 #[derive(Clone, Copy, PartialEq)]
 pub enum FlashState {
     Ready,
     Read,
-    Write,
-    Erase,
+    /// Erasing the sector. `write` is set when the erase is the first step
+    /// of a page write.
+    Erase {
+        write: bool,
+    },
+    /// Checking the erased sector reads back as all 1s.
+    EraseVerify {
+        write: bool,
+    },
+    /// Programming the phrase at this offset into the page.
+    Program(usize),
+    /// Checking the longword at this offset into the page.
+    ProgramCheck(usize),
+    /// An operation started by this `CommandClient` is in flight.
+    Delegated(CommandOwner),
 }
 
 pub struct Ftfc {
@@ -165,7 +338,28 @@ pub struct Ftfc {
     client: OptionalCell<&'static dyn flash::Client<Ftfc>>,
     buffer: TakeCell<'static, FtfcPage>,
     state: Cell<FlashState>,
+    page: Cell<usize>,
+    command_clients: [OptionalCell<&'static dyn CommandClient>; 2],
     deferred_call: DeferredCall,
+    /// The code cache, which does not see the flash change under it.
+    cache: crate::lmem::Lmem,
+    /// Set by `abort` to end the current page operation after its command.
+    aborting: Cell<bool>,
+}
+
+/// Launch the command loaded into the FCCOB registers and spin until it
+/// completes.
+///
+/// This is placed in RAM because the core cannot fetch instructions from the
+/// P-Flash block while it is being programmed or erased. It must be called
+/// with interrupts disabled, and must not call any function that may live in
+/// flash.
+#[link_section = ".ramfunc"]
+#[inline(never)]
+unsafe fn ram_launch_command(fstat: *mut u8) {
+    let ccif = 1 << 7;
+    core::ptr::write_volatile(fstat, ccif);
+    while core::ptr::read_volatile(fstat) & ccif == 0 {}
 }
 
 impl Ftfc {
@@ -175,40 +369,277 @@ impl Ftfc {
             client: OptionalCell::empty(),
             buffer: TakeCell::empty(),
             state: Cell::new(FlashState::Ready),
+            page: Cell::new(0),
+            command_clients: [OptionalCell::empty(), OptionalCell::empty()],
             deferred_call: DeferredCall::new(),
+            cache: crate::lmem::Lmem::new(),
+            aborting: Cell::new(false),
         }
     }
 
+    /// Clear the error flags left over from the previous command. These
+    /// flags are write-1-to-clear, and CCIF must be written as 0 here so no
+    /// command is launched.
     pub fn clear_fstat_errors(&self) {
         self.registers
             .fstat
-            .modify(FSTAT::FPVIOL::SET + FSTAT::ACCERR::SET + FSTAT::RDCOLERR::SET);
+            .write(FSTAT::FPVIOL::SET + FSTAT::ACCERR::SET + FSTAT::RDCOLERR::SET);
     }
 
+    /// Whether the FTFC is idle and can accept a new command.
     pub fn is_ready(&self) -> bool {
         self.registers.fstat.is_set(FSTAT::CCIF)
     }
 
-    pub fn configure_writeable(&self) {
-        self.registers.fcnfg.modify(FCNFG::CCIE::SET);
+    /// Result of the last completed command, from the FSTAT error flags.
+    pub fn command_result(&self) -> Result<(), ErrorCode> {
+        let fstat = self.registers.fstat.extract();
+        if fstat.is_set(FSTAT::ACCERR) {
+            Err(ErrorCode::INVAL)
+        } else if fstat.is_set(FSTAT::FPVIOL) {
+            Err(ErrorCode::RESERVE)
+        } else if fstat.is_set(FSTAT::MGSTAT0) {
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
     }
 
-    pub fn configure_eraseable(&self) {
-        self.registers.fcnfg.modify(FCNFG::ERSAREQ::SET);
+    fn set_fccob(&self, index: usize, value: u8) {
+        self.registers.fccob[index ^ 3].set(value);
     }
 
-    pub fn erase_uicr(&self) {
+    /// Load and launch an FTFC command.
+    ///
+    /// `address` is written to FCCOB1-3 and `args` to FCCOB4 onwards.
+    /// Commands which do not take an address use FCCOB1-3 for their own
+    /// parameters, packed into `address` most significant byte first. When
+    /// `interrupt` is set the command-complete interrupt is enabled, and the
+    /// caller is expected to handle the result from `handle_interrupt`.
+    /// Otherwise the command has completed and its result is returned.
+    pub(crate) fn launch_command(
+        &self,
+        command: FlashCommand,
+        address: usize,
+        args: &[u8],
+        interrupt: bool,
+    ) -> Result<(), ErrorCode> {
+        if !self.is_ready() {
+            return Err(ErrorCode::BUSY);
+        }
+        if args.len() > 8 {
+            return Err(ErrorCode::SIZE);
+        }
+
         self.clear_fstat_errors();
-        self.write_command(0x49, 0x10001000);
-        self.registers.fstat.modify(FSTAT::CCIF::SET);
-        self.wait_for_command_complete();
+        self.set_fccob(0, command as u8);
+        self.set_fccob(1, (address >> 16) as u8);
+        self.set_fccob(2, (address >> 8) as u8);
+        self.set_fccob(3, address as u8);
+        for (i, arg) in args.iter().enumerate() {
+            self.set_fccob(4 + i, *arg);
+        }
+
+        if interrupt {
+            self.registers.fcnfg.modify(FCNFG::CCIE::SET);
+        }
+
+        if (PFLASH_BASE..PFLASH_BASE + PFLASH_SIZE).contains(&address) {
+            let fstat = core::ptr::from_ref(&self.registers.fstat)
+                .cast::<u8>()
+                .cast_mut();
+            unsafe { cortexm4f::support::atomic(|| ram_launch_command(fstat)) };
+        } else {
+            self.registers.fstat.write(FSTAT::CCIF::SET);
+        }
+
+        if interrupt {
+            Ok(())
+        } else {
+            while !self.is_ready() {}
+            self.cache.invalidate();
+            self.command_result()
+        }
     }
 
-    fn erase_page_helper(&self, page_number: usize) {
+    fn page_address(&self, page_number: usize) -> Result<usize, ErrorCode> {
+        if page_number >= PFLASH_SIZE / PAGE_SIZE {
+            return Err(ErrorCode::INVAL);
+        }
+        Ok(PFLASH_BASE + page_number * PAGE_SIZE)
+    }
+
+    fn erase_sector(&self) -> Result<(), ErrorCode> {
+        let address = PFLASH_BASE + self.page.get() * PAGE_SIZE;
+        self.launch_command(FlashCommand::EraseFlashSector, address, &[], true)
+    }
+
+    fn verify_erased(&self) -> Result<(), ErrorCode> {
+        let address = PFLASH_BASE + self.page.get() * PAGE_SIZE;
+        let units = (PAGE_SIZE / SECTION_UNIT) as u16;
+        let [hi, lo] = units.to_be_bytes();
+        self.launch_command(
+            FlashCommand::Read1sSection,
+            address,
+            &[hi, lo, MARGIN_USER],
+            true,
+        )
+    }
+
+    /// Start programming at the first phrase at or after `offset` which is
+    /// not all 1s. Erased flash already reads as 1s, so those phrases are
+    /// skipped. Returns `Ok(false)` if there is nothing left to program.
+    fn program_from(&self, offset: usize) -> Result<bool, ErrorCode> {
+        let address = PFLASH_BASE + self.page.get() * PAGE_SIZE;
+        self.buffer.map_or(Err(ErrorCode::NOMEM), |buffer| {
+            let next = (offset..buffer.len())
+                .step_by(PHRASE_SIZE)
+                .find(|&o| buffer.0[o..o + PHRASE_SIZE].iter().any(|b| *b != 0xFF));
+            match next {
+                Some(o) => {
+                    self.state.set(FlashState::Program(o));
+                    self.launch_command(
+                        FlashCommand::ProgramPhrase,
+                        address + o,
+                        &buffer.0[o..o + PHRASE_SIZE],
+                        true,
+                    )
+                    .map(|()| true)
+                }
+                None => Ok(false),
+            }
+        })
+    }
+
+    fn program_check(&self, offset: usize) -> Result<(), ErrorCode> {
+        let address = PFLASH_BASE + self.page.get() * PAGE_SIZE;
+        self.buffer.map_or(Err(ErrorCode::NOMEM), |buffer| {
+            let expected = &buffer.0[offset..offset + LONGWORD_SIZE];
+            self.state.set(FlashState::ProgramCheck(offset));
+            self.launch_command(
+                FlashCommand::ProgramCheck,
+                address + offset,
+                &[
+                    MARGIN_USER,
+                    0,
+                    0,
+                    0,
+                    expected[0],
+                    expected[1],
+                    expected[2],
+                    expected[3],
+                ],
+                true,
+            )
+        })
+    }
+
+    /// Set the client notified when an operation that `owner` started with
+    /// `delegate` completes.
+    pub fn set_command_client(&self, owner: CommandOwner, client: &'static dyn CommandClient) {
+        self.command_clients[owner as usize].set(client);
+    }
+
+    /// Run an operation on behalf of the `CommandClient` of `owner`.
+    ///
+    /// `start` is called once the FTFC is idle and must start an operation
+    /// that clears CCIF, for example a write to the emulated EEPROM or to the
+    /// CSEc command header. The client is notified from the command-complete
+    /// interrupt.
+    pub fn delegate<F: FnOnce()>(&self, owner: CommandOwner, start: F) -> Result<(), ErrorCode> {
+        if self.state.get() != FlashState::Ready || !self.is_ready() {
+            return Err(ErrorCode::BUSY);
+        }
         self.clear_fstat_errors();
-        self.write_command(0x09, (page_number * PAGE_SIZE) as u32);
-        self.registers.fstat.modify(FSTAT::CCIF::SET);
-        self.wait_for_command_complete();
+        self.state.set(FlashState::Delegated(owner));
+        start();
+        self.registers.fcnfg.modify(FCNFG::CCIE::SET);
+        Ok(())
+    }
+
+    /// Run an operation on behalf of another driver sharing the FTFC and wait
+    /// for it to complete, like `delegate` but without the interrupt. This is
+    /// meant for one-off configuration during boot.
+    pub fn delegate_blocking<F: FnOnce()>(&self, start: F) -> Result<(), ErrorCode> {
+        if self.state.get() != FlashState::Ready || !self.is_ready() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.clear_fstat_errors();
+        start();
+        while !self.is_ready() {}
+        self.cache.invalidate();
+        self.command_result()
+    }
+
+    /// Status of the CSEc security engine.
+    pub fn csec_status(&self) -> LocalRegisterCopy<u8, FCSESTAT::Register> {
+        self.registers.fcsestat.extract()
+    }
+
+    /// FlexNVM partition programmed into this part, if any. Returns the size
+    /// of the emulated EEPROM, which is `EeeSize::None` when the FlexNVM is
+    /// used only as D-Flash.
+    pub fn partition(&self) -> Option<EeeSize> {
+        let fcfg1 = crate::sim::SIM_BASE.fcfg1.extract();
+        if fcfg1.read(crate::sim::FCFG1::DEPART) == 0xF {
+            None
+        } else {
+            Some(EeeSize::from_code(
+                fcfg1.read(crate::sim::FCFG1::EEERAMSIZE),
+            ))
+        }
+    }
+
+    /// Partition the FlexNVM block between D-Flash and EEPROM backup, and
+    /// reserve the emulated EEPROM and CSEc key storage in FlexRAM.
+    ///
+    /// This can be done only once; the partition is kept until the part is
+    /// mass erased. The command blocks until it has completed.
+    pub fn program_partition(&self, config: &PartitionConfig) -> Result<(), ErrorCode> {
+        if self.state.get() != FlashState::Ready {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.partition().is_some() {
+            return Err(ErrorCode::ALREADY);
+        }
+        let parameters = u32::from_be_bytes([
+            0,
+            config.csec_keys as u8,
+            0, // No security flag extension.
+            u8::from(!config.load_flexram_at_reset),
+        ]);
+        self.launch_command(
+            FlashCommand::ProgramPartition,
+            parameters as usize,
+            &[config.eee_size as u8, config.flexnvm as u8],
+            false,
+        )
+    }
+
+    /// Select whether FlexRAM is used as emulated EEPROM or as plain RAM.
+    /// When switching to EEPROM, this waits until the EEPROM contents have
+    /// been loaded into FlexRAM.
+    pub fn set_flexram_function(&self, function: FlexRamFunction) -> Result<(), ErrorCode> {
+        if self.state.get() != FlashState::Ready {
+            return Err(ErrorCode::BUSY);
+        }
+        let parameters = u32::from_be_bytes([0, function as u8, 0, 0]);
+        self.launch_command(
+            FlashCommand::SetFlexRamFunction,
+            parameters as usize,
+            &[],
+            false,
+        )?;
+        match function {
+            FlexRamFunction::EmulatedEeprom => while !self.eee_ready() {},
+            FlexRamFunction::Ram => while !self.registers.fcnfg.is_set(FCNFG::RAMRDY) {},
+        }
+        Ok(())
+    }
+
+    /// Whether FlexRAM is in emulated EEPROM mode and ready to be written.
+    pub fn eee_ready(&self) -> bool {
+        self.registers.fcnfg.is_set(FCNFG::EEERDY)
     }
 
     pub fn read_range(
@@ -216,13 +647,23 @@ impl Ftfc {
         page_number: usize,
         buffer: &'static mut FtfcPage,
     ) -> Result<(), (ErrorCode, &'static mut FtfcPage)> {
-        let mut addr = (page_number * PAGE_SIZE) as *const u8;
+        if self.state.get() != FlashState::Ready {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        let address = match self.page_address(page_number) {
+            Ok(address) => address,
+            Err(e) => return Err((e, buffer)),
+        };
+
+        // P-Flash is memory mapped, so reads are a plain copy.
+        let mut byte = address as *const u8;
         unsafe {
-            for i in 0..PAGE_SIZE {
-                buffer.0[i] = *addr;
-                addr = addr.offset(1);
+            for i in 0..buffer.len() {
+                buffer[i] = core::ptr::read_volatile(byte);
+                byte = byte.offset(1);
             }
         }
+
         self.buffer.replace(buffer);
         self.state.set(FlashState::Read);
         self.deferred_call.set();
@@ -234,74 +675,236 @@ impl Ftfc {
         page_number: usize,
         buffer: &'static mut FtfcPage,
     ) -> Result<(), (ErrorCode, &'static mut FtfcPage)> {
-        // 복사본을 만들어서 map_err에서 사용
-        let buffer_ref = self
-            .buffer
-            .replace(buffer)
-            .unwrap_or_else(|| panic!("Buffer should be available"));
-
-        self.erase_page(page_number).map_err(|e| {
-            let buf = self.buffer.take().unwrap(); // 에러 발생 시 원래 버퍼 반환
-            (e, buf)
-        })?;
-
-        let mut addr = (page_number * PAGE_SIZE) as *mut u8;
-        unsafe {
-            for i in 0..PAGE_SIZE {
-                addr.write_volatile(buffer_ref.0[i]);
-                addr = addr.offset(1);
-            }
+        if self.state.get() != FlashState::Ready {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if let Err(e) = self.page_address(page_number) {
+            return Err((e, buffer));
         }
 
-        self.buffer.replace(buffer_ref); // 다시 저장
-        self.state.set(FlashState::Write);
-        self.deferred_call.set();
-
-        Ok(())
+        self.page.set(page_number);
+        self.buffer.replace(buffer);
+        self.state.set(FlashState::Erase { write: true });
+        self.erase_sector().map_err(|e| {
+            self.state.set(FlashState::Ready);
+            (e, self.buffer.take().unwrap())
+        })
     }
 
     pub fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
-        self.erase_page_helper(page_number);
-        self.state.set(FlashState::Erase);
-        self.deferred_call.set();
-        Ok(())
+        if self.state.get() != FlashState::Ready {
+            return Err(ErrorCode::BUSY);
+        }
+        self.page_address(page_number)?;
+
+        self.page.set(page_number);
+        self.state.set(FlashState::Erase { write: false });
+        self.erase_sector().inspect_err(|_| {
+            self.state.set(FlashState::Ready);
+        })
     }
 
-    fn write_command(&self, command: u8, address: u32) {
-        self.registers.fccob0.set(command);
-        self.registers.fccob1.set(((address >> 16) & 0xFF) as u8);
-        self.registers.fccob2.set(((address >> 8) & 0xFF) as u8);
-        self.registers.fccob3.set((address & 0xFF) as u8);
+    /// Erase sector `page_number` and wait for the erase to complete, like
+    /// `delegate_blocking`. This is meant for code that runs without the
+    /// kernel's main loop, such as a bootloader.
+    pub fn erase_page_blocking(&self, page_number: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != FlashState::Ready {
+            return Err(ErrorCode::BUSY);
+        }
+        let address = self.page_address(page_number)?;
+        self.launch_command(FlashCommand::EraseFlashSector, address, &[], false)?;
+        let [hi, lo] = ((PAGE_SIZE / SECTION_UNIT) as u16).to_be_bytes();
+        self.launch_command(
+            FlashCommand::Read1sSection,
+            address,
+            &[hi, lo, MARGIN_USER],
+            false,
+        )
     }
 
-    fn wait_for_command_complete(&self) {
-        while !self.is_ready() {}
+    /// Program `data` into erased P-Flash at `address` and wait for it to
+    /// complete, like `erase_page_blocking`. `address` must be phrase aligned
+    /// and `data` a whole number of phrases. Phrases of all 1s are skipped,
+    /// so they can still be programmed by a later call.
+    pub fn program_blocking(&self, address: usize, data: &[u8]) -> Result<(), ErrorCode> {
+        if self.state.get() != FlashState::Ready {
+            return Err(ErrorCode::BUSY);
+        }
+        let in_pflash = address
+            .checked_sub(PFLASH_BASE)
+            .is_some_and(|offset| offset + data.len() <= PFLASH_SIZE);
+        if !in_pflash || address % PHRASE_SIZE != 0 || data.len() % PHRASE_SIZE != 0 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        for (i, phrase) in data.chunks_exact(PHRASE_SIZE).enumerate() {
+            if phrase.iter().all(|b| *b == 0xFF) {
+                continue;
+            }
+            let phrase_address = address + i * PHRASE_SIZE;
+            self.launch_command(FlashCommand::ProgramPhrase, phrase_address, phrase, false)?;
+            for (j, expected) in phrase.chunks_exact(LONGWORD_SIZE).enumerate() {
+                self.launch_command(
+                    FlashCommand::ProgramCheck,
+                    phrase_address + j * LONGWORD_SIZE,
+                    &[
+                        MARGIN_USER,
+                        0,
+                        0,
+                        0,
+                        expected[0],
+                        expected[1],
+                        expected[2],
+                        expected[3],
+                    ],
+                    false,
+                )?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn handle_interrupt(&self) {
-        let state = self.state.get();
+    /// Finish the current operation and report `result` to the client.
+    fn complete(&self, state: FlashState, result: Result<(), ErrorCode>) {
+        self.registers.fcnfg.modify(FCNFG::CCIE::CLEAR);
         self.state.set(FlashState::Ready);
+        self.cache.invalidate();
+
+        let result = result.map_err(|e| match e {
+            ErrorCode::RESERVE => flash::Error::FlashMemoryProtectionError,
+            _ => flash::Error::FlashError,
+        });
         match state {
-            FlashState::Read => {
-                self.client.map(|client| {
-                    self.buffer.take().map(|buffer| {
-                        client.read_complete(buffer, Ok(()));
-                    });
-                });
+            FlashState::Erase { write: false } | FlashState::EraseVerify { write: false } => {
+                self.client.map(|client| client.erase_complete(result));
             }
-            FlashState::Write => {
-                self.client.map(|client| {
-                    self.buffer.take().map(|buffer| {
-                        client.write_complete(buffer, Ok(()));
-                    });
+            _ => {
+                self.buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.write_complete(buffer, result));
                 });
             }
-            FlashState::Erase => {
-                self.client.map(|client| {
-                    client.erase_complete(Ok(()));
-                });
+        }
+    }
+
+    /// Stop the page write or erase in progress once its current FTFC
+    /// command completes, reporting it as failed. A command cannot be
+    /// stopped, but no further one is launched, which is what matters when
+    /// the supply is failing. Returns `ALREADY` if no page operation is in
+    /// progress.
+    pub fn abort(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            FlashState::Ready | FlashState::Read | FlashState::Delegated(_) => {
+                Err(ErrorCode::ALREADY)
+            }
+            _ => {
+                self.aborting.set(true);
+                Ok(())
+            }
+        }
+    }
+
+    /// Advance the current operation after an FTFC command completed.
+    fn command_complete(&self, state: FlashState) {
+        if self.aborting.take() {
+            self.complete(state, Err(ErrorCode::CANCEL));
+            return;
+        }
+        if let Err(e) = self.command_result() {
+            self.complete(state, Err(e));
+            return;
+        }
+
+        let next = match state {
+            FlashState::Erase { write } => {
+                self.state.set(FlashState::EraseVerify { write });
+                self.verify_erased()
+            }
+            FlashState::EraseVerify { write: false } => {
+                self.complete(state, Ok(()));
+                return;
+            }
+            FlashState::EraseVerify { write: true } => self.program_from(0).and_then(|started| {
+                if started {
+                    Ok(())
+                } else {
+                    self.program_check(0)
+                }
+            }),
+            FlashState::Program(offset) => {
+                self.program_from(offset + PHRASE_SIZE).and_then(|started| {
+                    if started {
+                        Ok(())
+                    } else {
+                        self.program_check(0)
+                    }
+                })
             }
-            _ => {}
+            FlashState::ProgramCheck(offset) => {
+                if offset + LONGWORD_SIZE < PAGE_SIZE {
+                    self.program_check(offset + LONGWORD_SIZE)
+                } else {
+                    self.complete(state, Ok(()));
+                    return;
+                }
+            }
+            FlashState::Ready | FlashState::Read | FlashState::Delegated(_) => Ok(()),
+        };
+
+        if let Err(e) = next {
+            self.complete(state, Err(e));
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        match self.state.get() {
+            FlashState::Delegated(owner) => {
+                if self.is_ready() {
+                    self.registers.fcnfg.modify(FCNFG::CCIE::CLEAR);
+                    self.state.set(FlashState::Ready);
+                    self.cache.invalidate();
+                    let result = self.command_result();
+                    self.command_clients[owner as usize]
+                        .map(|client| client.command_complete(result));
+                }
+            }
+            FlashState::Ready | FlashState::Read => {
+                // Nothing in flight. Mask the interrupt, since CCIF stays set
+                // while the FTFC is idle.
+                self.registers.fcnfg.modify(FCNFG::CCIE::CLEAR);
+            }
+            state => {
+                if self.is_ready() {
+                    self.command_complete(state);
+                }
+            }
+        }
+    }
+
+    /// Raise the FTFC fault interrupt when a flash read hits an
+    /// uncorrectable (double-bit) ECC error.
+    pub fn enable_double_bit_fault_interrupt(&self) {
+        self.registers.fercnfg.modify(FERCNFG::DFDIE::SET);
+    }
+
+    /// Whether a flash read hit an uncorrectable ECC error since the last
+    /// call. The flash does not record the address of the read.
+    pub fn take_double_bit_fault(&self) -> bool {
+        let fault = self.registers.ferstat.is_set(FERSTAT::DFDIF);
+        if fault {
+            self.registers.ferstat.write(FERSTAT::DFDIF::SET);
+        }
+        fault
+    }
+
+    fn handle_read_complete(&self) {
+        if self.state.get() == FlashState::Read {
+            self.state.set(FlashState::Ready);
+            self.client.map(|client| {
+                self.buffer.take().map(|buffer| {
+                    client.read_complete(buffer, Ok(()));
+                });
+            });
         }
     }
 }
@@ -338,7 +941,7 @@ impl flash::Flash for Ftfc {
 
 impl DeferredCallClient for Ftfc {
     fn handle_deferred_call(&self) {
-        self.handle_interrupt();
+        self.handle_read_complete();
     }
 
     fn register(&'static self) {
@@ -0,0 +1,313 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Programmable Delay Block (PDB) for the S32K144.
+//!
+//! A PDB counts up from its trigger and fires the hardware triggers of an
+//! ADC at programmed counts. PDB0 drives ADC0 and PDB1 drives ADC1. Each
+//! PDB channel has eight pre-triggers, and pre-trigger `n` starts the
+//! conversion set up in the ADC's `SC1[n]`. A pre-trigger fires either
+//! after its own delay or back-to-back, when the conversion of the previous
+//! pre-trigger completes.
+//!
+//! The counter is started by software or by trigger input 0, which TRGMUX
+//! connects to a source such as an FTM initialization trigger. In
+//! continuous mode the counter restarts at the end of every period.
+//!
+//! A sketch of sampling two channels half a period after each FTM0 reload:
+//!
+//! ```rust,ignore
+//! trgmux.route(trgmux::Target::Pdb0, 0, trgmux::Source::Ftm0InitTrigger)?;
+//! pdb0.configure(Trigger::Trgmux, Prescaler::Div1, Multiplier::X1, period, false);
+//! pdb0.set_pre_trigger(0, 0, PreTrigger::Delayed(period / 2))?;
+//! pdb0.set_pre_trigger(0, 1, PreTrigger::BackToBack)?;
+//! adc0.start_triggered(&[adc::Channel::AD0, adc::Channel::AD1])?;
+//! ```
+
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::clock::{Clock, Peripheral};
+
+register_structs! {
+    PdbChannelRegisters {
+        /// Channel n Control register 1
+        (0x00 => c1: ReadWrite<u32, C1::Register>),
+        /// Channel n Status register
+        (0x04 => s: ReadWrite<u32, S::Register>),
+        /// Channel n Delay m register
+        (0x08 => dly: [ReadWrite<u32>; 8]),
+        (0x28 => @END),
+    },
+
+    pub PdbRegisters {
+        /// Status and Control register
+        (0x000 => sc: ReadWrite<u32, SC::Register>),
+        /// Modulus register
+        (0x004 => mod_: ReadWrite<u32>),
+        /// Counter register
+        (0x008 => cnt: ReadWrite<u32>),
+        /// Interrupt Delay register
+        (0x00C => idly: ReadWrite<u32>),
+        (0x010 => channels: [PdbChannelRegisters; 2]),
+        (0x060 => _reserved0),
+        /// Pulse-Out n Enable register
+        (0x190 => poen: ReadWrite<u32>),
+        /// Pulse-Out n Delay register
+        (0x194 => podly: ReadWrite<u32>),
+        (0x198 => @END),
+    }
+}
+
+register_bitfields![u32,
+    SC [
+        /// Load OK
+        LDOK OFFSET(0) NUMBITS(1) [],
+        /// Continuous mode enable
+        CONT OFFSET(1) NUMBITS(1) [],
+        /// Multiplication factor select for prescaler
+        MULT OFFSET(2) NUMBITS(2) [],
+        /// PDB interrupt enable
+        PDBIE OFFSET(5) NUMBITS(1) [],
+        /// PDB interrupt flag
+        PDBIF OFFSET(6) NUMBITS(1) [],
+        /// PDB enable
+        PDBEN OFFSET(7) NUMBITS(1) [],
+        /// Trigger input source select
+        TRGSEL OFFSET(8) NUMBITS(4) [],
+        /// Prescaler divider select
+        PRESCALER OFFSET(12) NUMBITS(3) [],
+        /// DMA enable
+        DMAEN OFFSET(15) NUMBITS(1) [],
+        /// Software trigger
+        SWTRIG OFFSET(16) NUMBITS(1) [],
+        /// PDB sequence error interrupt enable
+        PDBEIE OFFSET(17) NUMBITS(1) [],
+        /// Load mode select
+        LDMOD OFFSET(18) NUMBITS(2) [
+            Immediate = 0,
+            AtModulus = 1,
+            AtTrigger = 2,
+            AtModulusOrTrigger = 3
+        ]
+    ],
+    C1 [
+        /// Pre-trigger enable
+        EN OFFSET(0) NUMBITS(8) [],
+        /// Pre-trigger output select, set for delayed pre-triggers
+        TOS OFFSET(8) NUMBITS(8) [],
+        /// Pre-trigger back-to-back operation enable
+        BB OFFSET(16) NUMBITS(8) []
+    ],
+    S [
+        /// Sequence error flags (write 0 to clear)
+        ERR OFFSET(0) NUMBITS(8) [],
+        /// Pre-trigger delay reached flags
+        CF OFFSET(16) NUMBITS(8) []
+    ]
+];
+
+pub const PDB0_BASE: StaticRef<PdbRegisters> =
+    unsafe { StaticRef::new(0x4003_6000 as *const PdbRegisters) };
+pub const PDB1_BASE: StaticRef<PdbRegisters> =
+    unsafe { StaticRef::new(0x4003_1000 as *const PdbRegisters) };
+
+/// Number of channels of each PDB.
+pub const NUM_CHANNELS: usize = 2;
+
+/// Number of pre-triggers of each PDB channel.
+pub const NUM_PRE_TRIGGERS: usize = 8;
+
+/// What starts the PDB counter.
+#[derive(Clone, Copy)]
+pub enum Trigger {
+    /// Trigger input 0, routed by TRGMUX.
+    Trgmux = 0,
+    /// `Pdb::software_trigger`.
+    Software = 15,
+}
+
+/// Division of the bus clock feeding the counter, applied together with
+/// the `Multiplier`.
+#[derive(Clone, Copy)]
+pub enum Prescaler {
+    Div1 = 0,
+    Div2 = 1,
+    Div4 = 2,
+    Div8 = 3,
+    Div16 = 4,
+    Div32 = 5,
+    Div64 = 6,
+    Div128 = 7,
+}
+
+/// Further division of the counter clock.
+#[derive(Clone, Copy)]
+pub enum Multiplier {
+    X1 = 0,
+    X10 = 1,
+    X20 = 2,
+    X40 = 3,
+}
+
+/// How a pre-trigger fires.
+#[derive(Clone, Copy)]
+pub enum PreTrigger {
+    Disabled,
+    /// When the counter reaches the given count.
+    Delayed(u16),
+    /// When the conversion started by the previous pre-trigger completes.
+    BackToBack,
+}
+
+pub trait PdbClient {
+    /// The counter reached the delay set with `set_interrupt_delay`.
+    fn delay_reached(&self);
+
+    /// Pre-triggers in the `pre_triggers` mask of `channel` fired before
+    /// the ADC had finished the conversion they were to start.
+    fn sequence_error(&self, channel: usize, pre_triggers: u8);
+}
+
+pub struct Pdb<'a> {
+    registers: StaticRef<PdbRegisters>,
+    clock: Clock,
+    peripheral: Peripheral,
+    client: OptionalCell<&'a dyn PdbClient>,
+}
+
+impl<'a> Pdb<'a> {
+    pub const fn new(registers: StaticRef<PdbRegisters>, peripheral: Peripheral) -> Self {
+        Self {
+            registers,
+            clock: Clock::new(),
+            peripheral,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn PdbClient) {
+        self.client.set(client);
+    }
+
+    /// Enable the PDB with a counter of `period` ticks of the bus clock
+    /// divided by `prescaler` and `multiplier`. All pre-triggers start
+    /// disabled.
+    pub fn configure(
+        &self,
+        trigger: Trigger,
+        prescaler: Prescaler,
+        multiplier: Multiplier,
+        period: u16,
+        continuous: bool,
+    ) {
+        self.clock.enable_peripheral_clock(self.peripheral);
+        self.registers.sc.write(
+            SC::TRGSEL.val(trigger as u32)
+                + SC::PRESCALER.val(prescaler as u32)
+                + SC::MULT.val(multiplier as u32)
+                + SC::CONT.val(continuous.into())
+                + SC::PDBEIE::SET
+                + SC::LDMOD::Immediate
+                + SC::PDBEN::SET,
+        );
+        for channel in self.registers.channels.iter() {
+            channel.c1.set(0);
+            channel.s.set(0);
+        }
+        self.registers.mod_.set(period.into());
+        self.load();
+    }
+
+    /// Set how pre-trigger `pre_trigger` of `channel` fires.
+    pub fn set_pre_trigger(
+        &self,
+        channel: usize,
+        pre_trigger: usize,
+        mode: PreTrigger,
+    ) -> Result<(), ErrorCode> {
+        if channel >= NUM_CHANNELS || pre_trigger >= NUM_PRE_TRIGGERS {
+            return Err(ErrorCode::INVAL);
+        }
+        if !self.registers.sc.is_set(SC::PDBEN) {
+            return Err(ErrorCode::OFF);
+        }
+        let registers = &self.registers.channels[channel];
+        let mask = 1 << pre_trigger;
+        let c1 = registers.c1.extract();
+        let (mut enabled, mut delayed, mut back_to_back) =
+            (c1.read(C1::EN), c1.read(C1::TOS), c1.read(C1::BB));
+        enabled &= !mask;
+        delayed &= !mask;
+        back_to_back &= !mask;
+        match mode {
+            PreTrigger::Disabled => {}
+            PreTrigger::Delayed(delay) => {
+                registers.dly[pre_trigger].set(delay.into());
+                enabled |= mask;
+                delayed |= mask;
+            }
+            PreTrigger::BackToBack => {
+                enabled |= mask;
+                back_to_back |= mask;
+            }
+        }
+        registers
+            .c1
+            .write(C1::EN.val(enabled) + C1::TOS.val(delayed) + C1::BB.val(back_to_back));
+        self.load();
+        Ok(())
+    }
+
+    /// Call `delay_reached` whenever the counter reaches `delay`, or stop
+    /// doing so with `None`.
+    pub fn set_interrupt_delay(&self, delay: Option<u16>) -> Result<(), ErrorCode> {
+        if !self.registers.sc.is_set(SC::PDBEN) {
+            return Err(ErrorCode::OFF);
+        }
+        match delay {
+            Some(delay) => {
+                self.registers.idly.set(delay.into());
+                self.load();
+                self.registers.sc.modify(SC::PDBIE::SET);
+            }
+            None => self.registers.sc.modify(SC::PDBIE::CLEAR),
+        }
+        Ok(())
+    }
+
+    /// Start the counter, for PDBs configured with `Trigger::Software`.
+    pub fn software_trigger(&self) {
+        self.registers.sc.modify(SC::SWTRIG::SET);
+    }
+
+    pub fn disable(&self) {
+        self.registers.sc.set(0);
+        self.clock.disable_peripheral_clock(self.peripheral);
+    }
+
+    pub fn handle_interrupt(&self) {
+        if self.registers.sc.is_set(SC::PDBIF) {
+            self.registers.sc.modify(SC::PDBIF::CLEAR);
+            self.client.map(|client| client.delay_reached());
+        }
+        for (index, channel) in self.registers.channels.iter().enumerate() {
+            let errors = channel.s.read(S::ERR);
+            if errors != 0 {
+                // Error flags clear when 0 is written to them.
+                channel.s.write(S::ERR.val(!errors & 0xFF));
+                self.client
+                    .map(|client| client.sequence_error(index, errors as u8));
+            }
+        }
+    }
+
+    /// Latch the buffered modulus and delay registers.
+    fn load(&self) {
+        self.registers.sc.modify(SC::LDOK::SET);
+    }
+}
@@ -3,30 +3,98 @@
 // Copyright Tock Contributors 2024.
 
 // S32K144 Interrupt Vector Mapping
+//
+// NVIC interrupt numbers, from the interrupt vector assignments in the
+// reference manual. Numbers missing from the list are reserved.
 
+/// Number of NVIC interrupts on the S32K144, including reserved ones.
+pub const NUM_IRQS: usize = 123;
+
+pub const DMA0: u32 = 0;
+pub const DMA1: u32 = 1;
+pub const DMA2: u32 = 2;
+pub const DMA3: u32 = 3;
+pub const DMA4: u32 = 4;
+pub const DMA5: u32 = 5;
+pub const DMA6: u32 = 6;
+pub const DMA7: u32 = 7;
+pub const DMA8: u32 = 8;
+pub const DMA9: u32 = 9;
+pub const DMA10: u32 = 10;
+pub const DMA11: u32 = 11;
+pub const DMA12: u32 = 12;
+pub const DMA13: u32 = 13;
+pub const DMA14: u32 = 14;
+pub const DMA15: u32 = 15;
+pub const DMA_ERROR: u32 = 16;
+pub const MCM: u32 = 17; // FPU exceptions
+pub const FTFC: u32 = 18; // Flash command complete
+pub const FTFC_READ_COLLISION: u32 = 19;
+pub const POWER_CLOCK: u32 = 20; // PMC low-voltage detect and warning
+pub const FTFC_FAULT: u32 = 21; // Double-bit ECC fault on a flash read
+pub const WDOG_EWM: u32 = 22;
+pub const RCM: u32 = 23;
+pub const LPI2C0_MASTER: u32 = 24;
+pub const LPI2C0_SLAVE: u32 = 25;
+pub const LPSPI0: u32 = 26;
+pub const LPSPI1: u32 = 27;
+pub const LPSPI2: u32 = 28;
+pub const LPUART0: u32 = 31;
+pub const LPUART1: u32 = 33;
+pub const LPUART2: u32 = 35;
+pub const ADC0: u32 = 39;
+pub const ADC1: u32 = 40;
+pub const CMP0: u32 = 41;
+pub const ERM_SINGLE_FAULT: u32 = 44;
+pub const ERM_DOUBLE_FAULT: u32 = 45;
+pub const RTC: u32 = 46; // Alarm, overflow and invalid time
+pub const RTC_SECONDS: u32 = 47;
+pub const LPIT0_CH0: u32 = 48;
+pub const LPIT0_CH1: u32 = 49;
+pub const LPIT0_CH2: u32 = 50;
+pub const LPIT0_CH3: u32 = 51;
+pub const PDB0: u32 = 52;
+pub const SCG: u32 = 57;
+pub const LPTMR0: u32 = 58;
 pub const PORTA: u32 = 59;
 pub const PORTB: u32 = 60;
 pub const PORTC: u32 = 61;
 pub const PORTD: u32 = 62;
 pub const PORTE: u32 = 63;
-pub const LPUART0: u32 = 31;
-pub const RTC: u32 = 46;
-//pub const RTC_ALARM: u32 = 46;
-//pub const RTC_SEC: u32 = 47;
-pub const FTM0: u32 = 99;
-//pub const FTM0_CH0: u32 = 99;
-//pub const FTM0_CH1: u32 = 99;
-//pub const FTM0_CH2: u32 = 100;
-//pub const FTM0_CH3: u32 = 100;
-//pub const FTM0_CH4: u32 = 101;
-//pub const FTM0_CH5: u32 = 101;
-//pub const FTM0_CH6: u32 = 102;
-//pub const FTM0_CH7: u32 = 102;
-//pub const FTM0_FAULT: u32 = 103;
-//pub const FTM0_OF: u32 = 104;
-//pub const FTM0_RELOAD: u32 = 104;
-pub const TIMER0: u32 = 48; // Low Power Periodic Timer
-pub const TIMER1: u32 = 49; // Low Power Periodic Timer
-pub const TIMER2: u32 = 50; // Low Power Periodic Timer
-pub const POWER_CLOCK: u32 = 20; //pmc:20
-pub const FTFC: u32 = 18; //19,21
+pub const PDB1: u32 = 68;
+pub const FLEXIO: u32 = 69;
+pub const CAN0_ORED: u32 = 78; // Bus off, transmit and receive warnings
+pub const CAN0_ERROR: u32 = 79;
+pub const CAN0_WAKE_UP: u32 = 80;
+pub const CAN0_ORED_0_15_MB: u32 = 81;
+pub const CAN0_ORED_16_31_MB: u32 = 82;
+pub const CAN1_ORED: u32 = 85;
+pub const CAN1_ERROR: u32 = 86;
+pub const CAN1_ORED_0_15_MB: u32 = 88;
+pub const CAN2_ORED: u32 = 92;
+pub const CAN2_ERROR: u32 = 93;
+pub const CAN2_ORED_0_15_MB: u32 = 95;
+pub const FTM0_CH0_CH1: u32 = 99;
+pub const FTM0_CH2_CH3: u32 = 100;
+pub const FTM0_CH4_CH5: u32 = 101;
+pub const FTM0_CH6_CH7: u32 = 102;
+pub const FTM0_FAULT: u32 = 103;
+pub const FTM0_OVF_RELOAD: u32 = 104;
+pub const FTM1_CH0_CH1: u32 = 105;
+pub const FTM1_CH2_CH3: u32 = 106;
+pub const FTM1_CH4_CH5: u32 = 107;
+pub const FTM1_CH6_CH7: u32 = 108;
+pub const FTM1_FAULT: u32 = 109;
+pub const FTM1_OVF_RELOAD: u32 = 110;
+pub const FTM2_CH0_CH1: u32 = 111;
+pub const FTM2_CH2_CH3: u32 = 112;
+pub const FTM2_CH4_CH5: u32 = 113;
+pub const FTM2_CH6_CH7: u32 = 114;
+pub const FTM2_FAULT: u32 = 115;
+pub const FTM2_OVF_RELOAD: u32 = 116;
+pub const FTM3_CH0_CH1: u32 = 117;
+pub const FTM3_CH2_CH3: u32 = 118;
+pub const FTM3_CH4_CH5: u32 = 119;
+pub const FTM3_CH6_CH7: u32 = 120;
+pub const FTM3_FAULT: u32 = 121;
+pub const FTM3_OVF_RELOAD: u32 = 122;
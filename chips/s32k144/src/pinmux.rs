@@ -26,17 +26,36 @@ pub const PORT_BASES: [StaticRef<PortRegisters>; NUM_PORTS] = [
 #[repr(C)]
 pub struct PortRegisters {
     pub pcr: [ReadWrite<u32, PCR::Register>; 32], // 각 핀의 설정 레지스터 (PCR)
+    pub gpclr: ReadWrite<u32>,                    // Global Pin Control Low Register
+    pub gpchr: ReadWrite<u32>,                    // Global Pin Control High Register
+    pub giclr: ReadWrite<u32>,                    // Global Interrupt Control Low Register
+    pub gichr: ReadWrite<u32>,                    // Global Interrupt Control High Register
+    _reserved0: [u32; 4],
+    pub isfr: ReadWrite<u32>, // Interrupt Status Flag Register, write 1 to clear
 }
 
 register_bitfields![u32,
     pub PCR [
+        PS OFFSET(0) NUMBITS(1) [
+            PullDown = 0,
+            PullUp = 1
+        ],
+        PE OFFSET(1) NUMBITS(1) [],
         MUX OFFSET(8) NUMBITS(3) [
+            Disabled = 0,  // 아날로그 입력 또는 비활성
             GPIO = 1,      // 일반 GPIO 모드
             ALT2_FTM = 2,  // FTM (PWM) 기능
             ALT3_UART = 3, // UART 기능
             ALT4_CAN = 4,  // CAN 기능
             ALT5_SPI = 5   // SPI 기능
-        ]
+        ],
+        IRQC OFFSET(16) NUMBITS(4) [
+            Disabled = 0,
+            RisingEdge = 9,
+            FallingEdge = 10,
+            EitherEdge = 11
+        ],
+        ISF OFFSET(24) NUMBITS(1) []
     ]
 ];
 
@@ -1,5 +1,14 @@
+//! Power Management Controller (PMC) and System Mode Controller (SMC) for
+//! the S32K144.
+//!
+//! The PMC watches the supply with two fixed thresholds: the low-voltage
+//! warning (LVW) trips first, giving software time to save state, and the
+//! low-voltage detect (LVD) closer to the point where operation is no longer
+//! guaranteed. LVD can reset the chip or interrupt, LVW can only interrupt.
+//! The thresholds are given in the datasheet and cannot be changed.
+
 use kernel::utilities::cells::OptionalCell;
-use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite};
 use kernel::utilities::StaticRef;
 
@@ -164,12 +173,24 @@ register_bitfields![u32,
     ]
 ];
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PowerEvent {
     LowVoltageDetected,
     LowVoltageWarning,
     EnteredLowPowerMode,
     ExitedLowPowerMode,
 }
+
+/// What the PMC does when the supply falls below the LVD threshold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LowVoltageAction {
+    /// Hold the chip in reset until the supply recovers.
+    Reset,
+    /// Report [`PowerEvent::LowVoltageDetected`].
+    Interrupt,
+    Ignore,
+}
+
 /// 전력 관리 이벤트를 수신할 클라이언트 트레이트
 pub trait PowerClient {
     fn handle_power_event(&self, event: PowerEvent);
@@ -196,18 +217,34 @@ impl<'a> Power<'a> {
         self.client.set(client);
     }
 
+    /// Set the response to a low-voltage detect, and whether a low-voltage
+    /// warning interrupts. Flags raised before this call are dropped.
+    ///
+    /// The LVD reset enable may only be writable once after reset.
+    pub fn configure_low_voltage(&self, detect: LowVoltageAction, warning_interrupt: bool) {
+        self.pmc.lvdsc1.write(
+            LVDSC1::LVDACK::SET
+                + LVDSC1::LVDRE.val(u8::from(detect == LowVoltageAction::Reset))
+                + LVDSC1::LVDIE.val(u8::from(detect == LowVoltageAction::Interrupt)),
+        );
+        self.pmc
+            .lvdsc2
+            .write(LVDSC2::LVWACK::Acknowledge + LVDSC2::LVWIE.val(u8::from(warning_interrupt)));
+    }
+
     /// 전력 관리 인터럽트 핸들러
     pub fn handle_interrupt(&self) {
-        // Low Voltage Detect (LVDF) 발생 여부 확인
+        // Low Voltage Detect (LVDF) 발생 여부 확인. The flags are read-only
+        // and cleared by writing the acknowledge bits.
         if self.pmc.lvdsc1.is_set(LVDSC1::LVDF) {
-            self.pmc.lvdsc1.modify(LVDSC1::LVDF::CLEAR);
+            self.pmc.lvdsc1.modify(LVDSC1::LVDACK::SET);
             self.client
                 .map(|client| client.handle_power_event(PowerEvent::LowVoltageDetected));
         }
 
         // Low Voltage Warning (LVWF) 발생 여부 확인
         if self.pmc.lvdsc2.is_set(LVDSC2::LVWF) {
-            self.pmc.lvdsc2.modify(LVDSC2::LVWF::CLEAR);
+            self.pmc.lvdsc2.modify(LVDSC2::LVWACK::Acknowledge);
             self.client
                 .map(|client| client.handle_power_event(PowerEvent::LowVoltageWarning));
         }
@@ -0,0 +1,118 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Reset Control Module (RCM) for the S32K144.
+//!
+//! The RCM latches what caused the last reset. SRAM keeps its contents
+//! through every reset but a power-on or low-voltage one, after which it
+//! holds random data with invalid ECC: reading it before writing it is an
+//! uncorrectable ECC error.
+
+use kernel::utilities::registers::interfaces::Readable;
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadOnly, ReadWrite};
+use kernel::utilities::StaticRef;
+
+register_structs! {
+    RcmRegisters {
+        /// Version ID Register
+        (0x000 => verid: ReadOnly<u32>),
+        /// Parameter Register
+        (0x004 => param: ReadOnly<u32>),
+        /// System Reset Status Register
+        (0x008 => srs: ReadOnly<u32, SRS::Register>),
+        /// Reset Pin Control register
+        (0x00C => rpc: ReadWrite<u32>),
+        (0x010 => _reserved0),
+        /// Sticky System Reset Status Register
+        (0x018 => ssrs: ReadWrite<u32, SRS::Register>),
+        /// System Reset Interrupt Enable Register
+        (0x01C => srie: ReadWrite<u32>),
+        (0x020 => @END),
+    }
+}
+
+register_bitfields![u32,
+    SRS [
+        /// Low-Voltage Detect Reset or High-Voltage Detect Reset
+        LVD OFFSET(1) NUMBITS(1) [],
+        /// Loss-of-Clock Reset
+        LOC OFFSET(2) NUMBITS(1) [],
+        /// Loss-of-Lock Reset
+        LOL OFFSET(3) NUMBITS(1) [],
+        /// CMU Loss-of-Clock Reset
+        CMU_LOC OFFSET(4) NUMBITS(1) [],
+        /// Watchdog
+        WDOG OFFSET(5) NUMBITS(1) [],
+        /// External Reset Pin
+        PIN OFFSET(6) NUMBITS(1) [],
+        /// Power-On Reset
+        POR OFFSET(7) NUMBITS(1) [],
+        /// JTAG generated reset
+        JTAG OFFSET(8) NUMBITS(1) [],
+        /// Core Lockup
+        LOCKUP OFFSET(9) NUMBITS(1) [],
+        /// Software
+        SW OFFSET(10) NUMBITS(1) [],
+        /// MDM-AP System Reset Request
+        MDM_AP OFFSET(11) NUMBITS(1) [],
+        /// Stop Acknowledge Error
+        SACKERR OFFSET(13) NUMBITS(1) []
+    ]
+];
+
+const RCM_BASE: StaticRef<RcmRegisters> =
+    unsafe { StaticRef::new(0x4007_F000 as *const RcmRegisters) };
+
+/// What caused the last reset. More than one cause can be set.
+#[derive(Clone, Copy, Debug)]
+pub struct ResetCauses {
+    pub power_on: bool,
+    pub low_voltage: bool,
+    pub loss_of_clock: bool,
+    pub loss_of_lock: bool,
+    pub watchdog: bool,
+    pub pin: bool,
+    pub jtag: bool,
+    pub lockup: bool,
+    pub software: bool,
+    pub debugger: bool,
+    pub stop_ack_error: bool,
+}
+
+impl ResetCauses {
+    /// Whether SRAM kept its contents through the reset.
+    pub fn sram_retained(&self) -> bool {
+        !self.power_on && !self.low_voltage
+    }
+}
+
+pub struct Rcm {
+    registers: StaticRef<RcmRegisters>,
+}
+
+impl Rcm {
+    pub const fn new() -> Self {
+        Self {
+            registers: RCM_BASE,
+        }
+    }
+
+    /// The causes of the last reset.
+    pub fn reset_causes(&self) -> ResetCauses {
+        let srs = self.registers.srs.extract();
+        ResetCauses {
+            power_on: srs.is_set(SRS::POR),
+            low_voltage: srs.is_set(SRS::LVD),
+            loss_of_clock: srs.is_set(SRS::LOC) || srs.is_set(SRS::CMU_LOC),
+            loss_of_lock: srs.is_set(SRS::LOL),
+            watchdog: srs.is_set(SRS::WDOG),
+            pin: srs.is_set(SRS::PIN),
+            jtag: srs.is_set(SRS::JTAG),
+            lockup: srs.is_set(SRS::LOCKUP),
+            software: srs.is_set(SRS::SW),
+            debugger: srs.is_set(SRS::MDM_AP),
+            stop_ack_error: srs.is_set(SRS::SACKERR),
+        }
+    }
+}
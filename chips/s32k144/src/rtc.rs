@@ -102,19 +102,27 @@ register_bitfields![u32,
     ]
 ];
 
+/// Receives the once-a-second interrupt raised when the seconds counter
+/// increments.
+pub trait SecondsClient {
+    fn second(&self);
+}
+
 pub struct Rtc<'a> {
     registers: StaticRef<RtcRegisters>,
     overflow_client: OptionalCell<&'a dyn time::OverflowClient>,
     alarm_client: OptionalCell<&'a dyn time::AlarmClient>,
+    seconds_client: OptionalCell<&'a dyn SecondsClient>,
     enabled: Cell<bool>,
 }
 
-impl Rtc<'_> {
+impl<'a> Rtc<'a> {
     pub const fn new() -> Self {
         Self {
             registers: RTC_BASE,
             overflow_client: OptionalCell::empty(),
             alarm_client: OptionalCell::empty(),
+            seconds_client: OptionalCell::empty(),
             enabled: Cell::new(false),
         }
     }
@@ -131,6 +139,17 @@ impl Rtc<'_> {
             self.alarm_client.map(|client| client.alarm());
         }
     }
+
+    /// Set the client of the seconds interrupt, and enable it.
+    pub fn set_seconds_client(&self, client: &'a dyn SecondsClient) {
+        self.seconds_client.set(client);
+        self.registers.ier.modify(IER::TSIE::SET);
+    }
+
+    /// The seconds interrupt has no status flag to clear.
+    pub fn handle_seconds_interrupt(&self) {
+        self.seconds_client.map(|client| client.second());
+    }
 }
 
 impl Time for Rtc<'_> {
@@ -0,0 +1,70 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! System Integration Module (SIM) for the S32K144.
+//!
+//! Besides chip configuration, the SIM holds the 128-bit unique ID programmed
+//! into every part at the factory, from which [`unique_id`] and [`eui64`]
+//! identify a board without any provisioning.
+
+use kernel::utilities::registers::interfaces::Readable;
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadOnly};
+use kernel::utilities::StaticRef;
+
+register_structs! {
+    pub SimRegisters {
+        (0x00 => _reserved0),
+        /// Flash Configuration Register 1
+        (0x4C => pub fcfg1: ReadOnly<u32, FCFG1::Register>),
+        (0x50 => _reserved1),
+        /// Unique Identification Register High
+        (0x54 => pub uidh: ReadOnly<u32>),
+        /// Unique Identification Register Mid-High
+        (0x58 => pub uidmh: ReadOnly<u32>),
+        /// Unique Identification Register Mid Low
+        (0x5C => pub uidml: ReadOnly<u32>),
+        /// Unique Identification Register Low
+        (0x60 => pub uidl: ReadOnly<u32>),
+        (0x64 => @END),
+    }
+}
+
+register_bitfields![u32,
+    pub FCFG1 [
+        /// FlexNVM partition, as programmed by Program Partition. Reads 0xF
+        /// until the FlexNVM has been partitioned.
+        DEPART OFFSET(12) NUMBITS(4) [],
+        /// Size of the emulated EEPROM in FlexRAM.
+        EEERAMSIZE OFFSET(16) NUMBITS(4) []
+    ]
+];
+
+pub const SIM_BASE: StaticRef<SimRegisters> =
+    unsafe { StaticRef::new(0x40048000 as *const SimRegisters) };
+
+/// The 128-bit unique ID of this part, UIDH in the most significant word.
+pub fn unique_id() -> u128 {
+    let registers = SIM_BASE;
+    [
+        registers.uidh.get(),
+        registers.uidmh.get(),
+        registers.uidml.get(),
+        registers.uidl.get(),
+    ]
+    .iter()
+    .fold(0, |id, &word| (id << 32) | u128::from(word))
+}
+
+/// An EUI-64 derived from [`unique_id`], first octet in the most
+/// significant byte.
+///
+/// The two halves of the unique ID are XORed together, which keeps parts
+/// that differ in only one half distinct, and the first octet is marked as a
+/// locally administered unicast address since the ID is not an assigned
+/// OUI.
+pub fn eui64() -> u64 {
+    let id = unique_id();
+    let folded = (id >> 64) as u64 ^ id as u64;
+    (folded & !(0x01 << 56)) | (0x02 << 56)
+}
@@ -0,0 +1,153 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Trigger multiplexer (TRGMUX) for the S32K144.
+//!
+//! TRGMUX connects trigger outputs, such as an FTM initialization trigger or
+//! an LPIT channel, to the trigger inputs of other peripherals without the
+//! CPU. Each target peripheral has one register with up to four inputs,
+//! selected by index.
+//!
+//! For example, to start PDB0 on every FTM0 counter reload:
+//!
+//! ```rust,ignore
+//! trgmux.route(Target::Pdb0, 0, Source::Ftm0InitTrigger)?;
+//! ```
+
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+register_structs! {
+    TrgmuxRegisters {
+        /// One register per target peripheral, indexed by `Target`.
+        (0x00 => targets: [ReadWrite<u32, TRGMUX::Register>; 26]),
+        (0x68 => @END),
+    }
+}
+
+register_bitfields![u32,
+    TRGMUX [
+        /// Trigger source of target input 0
+        SEL0 OFFSET(0) NUMBITS(7) [],
+        /// Trigger source of target input 1
+        SEL1 OFFSET(8) NUMBITS(7) [],
+        /// Trigger source of target input 2
+        SEL2 OFFSET(16) NUMBITS(7) [],
+        /// Trigger source of target input 3
+        SEL3 OFFSET(24) NUMBITS(7) [],
+        /// Register lock, only cleared by reset
+        LK OFFSET(31) NUMBITS(1) []
+    ]
+];
+
+const TRGMUX_BASE: StaticRef<TrgmuxRegisters> =
+    unsafe { StaticRef::new(0x4006_3000 as *const TrgmuxRegisters) };
+
+/// Peripherals that take their triggers through TRGMUX.
+#[derive(Clone, Copy)]
+pub enum Target {
+    Dmamux0 = 0,
+    ExtOut0 = 1,
+    ExtOut1 = 2,
+    Adc0 = 3,
+    Adc1 = 4,
+    Cmp0 = 7,
+    Ftm0 = 10,
+    Ftm1 = 11,
+    Ftm2 = 12,
+    Ftm3 = 13,
+    Pdb0 = 14,
+    Pdb1 = 15,
+    FlexIo = 17,
+    Lpit0 = 18,
+    Lpuart0 = 19,
+    Lpuart1 = 20,
+    Lpi2c0 = 21,
+    Lpspi0 = 23,
+    Lpspi1 = 24,
+    Lptmr0 = 25,
+}
+
+/// Trigger outputs that TRGMUX can route.
+#[derive(Clone, Copy)]
+pub enum Source {
+    Disabled = 0,
+    Vdd = 1,
+    TrgmuxIn0 = 2,
+    TrgmuxIn1 = 3,
+    TrgmuxIn2 = 4,
+    TrgmuxIn3 = 5,
+    TrgmuxIn4 = 6,
+    TrgmuxIn5 = 7,
+    TrgmuxIn6 = 8,
+    TrgmuxIn7 = 9,
+    TrgmuxIn8 = 10,
+    TrgmuxIn9 = 11,
+    TrgmuxIn10 = 12,
+    TrgmuxIn11 = 13,
+    Cmp0Output = 14,
+    Lpit0Channel0 = 17,
+    Lpit0Channel1 = 18,
+    Lpit0Channel2 = 19,
+    Lpit0Channel3 = 20,
+    Lptmr0 = 21,
+    Ftm0InitTrigger = 22,
+    Ftm0ExtTrigger = 23,
+    Ftm1InitTrigger = 24,
+    Ftm1ExtTrigger = 25,
+    Ftm2InitTrigger = 26,
+    Ftm2ExtTrigger = 27,
+    Ftm3InitTrigger = 28,
+    Ftm3ExtTrigger = 29,
+    Adc0Sc1aComplete = 30,
+    Adc0Sc1bComplete = 31,
+    Adc1Sc1aComplete = 32,
+    Adc1Sc1bComplete = 33,
+    Pdb0Channel0Trigger = 34,
+    Pdb0PulseOut = 36,
+    Pdb1Channel0Trigger = 37,
+    Pdb1PulseOut = 39,
+    RtcAlarm = 44,
+    RtcSeconds = 45,
+    SimSoftwareTrigger = 64,
+}
+
+pub struct Trgmux {
+    registers: StaticRef<TrgmuxRegisters>,
+}
+
+impl Trgmux {
+    pub const fn new() -> Self {
+        Self {
+            registers: TRGMUX_BASE,
+        }
+    }
+
+    /// Connect `source` to input `input` (0 to 3) of `target`.
+    ///
+    /// Returns `INVAL` for an input that does not exist and `RESERVE` if the
+    /// target has been locked.
+    pub fn route(&self, target: Target, input: usize, source: Source) -> Result<(), ErrorCode> {
+        let register = &self.registers.targets[target as usize];
+        if register.is_set(TRGMUX::LK) {
+            return Err(ErrorCode::RESERVE);
+        }
+        let field = match input {
+            0 => TRGMUX::SEL0,
+            1 => TRGMUX::SEL1,
+            2 => TRGMUX::SEL2,
+            3 => TRGMUX::SEL3,
+            _ => return Err(ErrorCode::INVAL),
+        };
+        register.modify(field.val(source as u32));
+        Ok(())
+    }
+
+    /// Lock the routing of `target` until the next reset.
+    pub fn lock(&self, target: Target) {
+        self.registers.targets[target as usize].modify(TRGMUX::LK::SET);
+    }
+}
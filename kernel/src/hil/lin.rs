@@ -0,0 +1,167 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Interface for LIN (Local Interconnect Network) controllers.
+//!
+//! A LIN frame is a header sent by the master node, made of a break, the
+//! sync byte `0x55` and a protected identifier, followed by a response of
+//! one to eight data bytes and a checksum sent by whichever node publishes
+//! that frame. The master node also runs a slave task, so every node,
+//! including the master, sees each header through
+//! [`Client::header_received`] and then sends or receives the response, or
+//! ignores the frame.
+//!
+//! Timing of the schedule and response timeouts is left to the user of this
+//! interface, which can stop a response that takes too long with
+//! [`Lin::abort_response`].
+
+use crate::ErrorCode;
+
+/// Most data bytes in a response.
+pub const MAX_DATA_LEN: usize = 8;
+
+/// Highest frame identifier.
+pub const MAX_ID: u8 = 0x3F;
+
+/// The sync byte that follows the break.
+pub const SYNC: u8 = 0x55;
+
+/// How the checksum of a response is computed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumModel {
+    /// Over the data bytes only (LIN 1.x, and diagnostic frames).
+    Classic,
+    /// Over the protected identifier and the data bytes (LIN 2.x).
+    Enhanced,
+}
+
+/// Errors seen on the bus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// A break was not followed by a valid sync byte and protected
+    /// identifier.
+    Header,
+    /// A byte read back while transmitting differed from the byte sent.
+    Bit,
+    /// The checksum of a received response did not match.
+    Checksum,
+    /// A byte had no valid stop bit.
+    Framing,
+    /// The response was not complete in time.
+    Timeout,
+}
+
+/// Add the two parity bits to a frame identifier.
+pub fn protected_id(id: u8) -> u8 {
+    let id = id & MAX_ID;
+    let bit = |n: u8| (id >> n) & 1;
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 1;
+    id | (p0 << 6) | (p1 << 7)
+}
+
+/// The frame identifier of a protected identifier, or `None` if its parity
+/// bits are wrong.
+pub fn id_from_protected(pid: u8) -> Option<u8> {
+    let id = pid & MAX_ID;
+    (protected_id(id) == pid).then_some(id)
+}
+
+/// The checksum of a response with protected identifier `pid`: the inverted
+/// eight-bit sum with carry of its bytes.
+pub fn checksum(model: ChecksumModel, pid: u8, data: &[u8]) -> u8 {
+    let initial = match model {
+        ChecksumModel::Classic => 0,
+        ChecksumModel::Enhanced => u16::from(pid),
+    };
+    let sum = data.iter().fold(initial, |sum, &byte| {
+        let sum = sum + u16::from(byte);
+        if sum > 0xFF {
+            sum - 0xFF
+        } else {
+            sum
+        }
+    });
+    !(sum as u8)
+}
+
+/// A LIN controller.
+pub trait Lin<'a> {
+    fn set_client(&self, client: &'a dyn Client);
+
+    /// Set the bit rate, typically 19200 or 9600 bit/s.
+    fn set_baud_rate(&self, baud_rate: u32) -> Result<(), ErrorCode>;
+
+    /// Start listening for headers.
+    fn enable(&self) -> Result<(), ErrorCode>;
+
+    /// Stop all bus activity. Returns `BUSY` while a response is in
+    /// progress, which must be aborted first.
+    fn disable(&self) -> Result<(), ErrorCode>;
+
+    /// Send the header of frame `id`. Only the master node does this. The
+    /// header is reported back through `header_received` like any other.
+    ///
+    /// Returns `BUSY` while a frame is on the bus.
+    fn send_header(&self, id: u8) -> Result<(), ErrorCode>;
+
+    /// Publish `buffer[..len]` and its checksum as the response to the last
+    /// header. Completes with `response_sent`.
+    fn send_response(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+        model: ChecksumModel,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Receive a response of `len` data bytes to the last header into
+    /// `buffer`. Completes with `response_received`.
+    fn receive_response(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+        model: ChecksumModel,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Stop the response being sent or received, without a callback, and
+    /// return its buffer. Returns `None` if there is none.
+    fn abort_response(&self) -> Option<&'static mut [u8]>;
+}
+
+pub trait Client {
+    /// A valid header for frame `id` was seen on the bus.
+    fn header_received(&self, id: u8);
+
+    /// An error occurred outside of a response, such as a broken header.
+    fn bus_error(&self, error: Error);
+
+    /// The response passed to `send_response` has been sent.
+    fn response_sent(&self, buffer: &'static mut [u8], result: Result<(), Error>);
+
+    /// The response asked for with `receive_response` has arrived in
+    /// `buffer[..len]`.
+    fn response_received(&self, buffer: &'static mut [u8], len: usize, result: Result<(), Error>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protected_identifiers() {
+        assert_eq!(protected_id(0x00), 0x80);
+        assert_eq!(protected_id(0x3C), 0x3C);
+        assert_eq!(protected_id(0x3D), 0x7D);
+        assert_eq!(id_from_protected(0x7D), Some(0x3D));
+        assert_eq!(id_from_protected(0x3D), None);
+    }
+
+    #[test]
+    fn checksums() {
+        // The example frame of the LIN 2.1 specification.
+        let data = [0x55, 0x93, 0xE5];
+        assert_eq!(checksum(ChecksumModel::Enhanced, 0x4A, &data), 0xE6);
+        assert_eq!(checksum(ChecksumModel::Classic, 0x4A, &data), 0x31);
+    }
+}
@@ -24,6 +24,7 @@ pub mod hw_debug;
 pub mod i2c;
 pub mod kv;
 pub mod led;
+pub mod lin;
 pub mod log;
 pub mod nonvolatile_storage;
 pub mod public_key_crypto;
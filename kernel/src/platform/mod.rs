@@ -19,3 +19,4 @@ pub use self::platform::ProcessFault;
 pub use self::platform::SyscallDriverLookup;
 pub use self::platform::SyscallFilter;
 pub use self::platform::TbfHeaderFilterDefaultAllow;
+pub use self::platform::TbfHeaderFilterDefaultDeny;
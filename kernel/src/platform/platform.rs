@@ -155,77 +155,105 @@ impl SyscallFilter for TbfHeaderFilterDefaultAllow {
         process: &dyn process::Process,
         syscall: &syscall::Syscall,
     ) -> Result<(), errorcode::ErrorCode> {
-        match syscall {
-            // Subscribe is allowed if any commands are
-            syscall::Syscall::Subscribe {
-                driver_number,
-                subdriver_number: _,
-                upcall_ptr: _,
-                appdata: _,
-            } => match process.get_command_permissions(*driver_number, 0) {
-                CommandPermissions::NoPermsAtAll => Ok(()),
-                CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
-                CommandPermissions::Mask(_allowed) => Ok(()),
-            },
-
-            syscall::Syscall::Command {
-                driver_number,
-                subdriver_number,
-                arg0: _,
-                arg1: _,
-            } => match process.get_command_permissions(*driver_number, subdriver_number / 64) {
-                CommandPermissions::NoPermsAtAll => Ok(()),
-                CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
-                CommandPermissions::Mask(allowed) => {
-                    if (1 << (subdriver_number % 64)) & allowed > 0 {
-                        Ok(())
-                    } else {
-                        Err(errorcode::ErrorCode::NODEVICE)
-                    }
+        filter_by_tbf_permissions(process, syscall, Ok(()))
+    }
+}
+
+/// An allow list system call filter based on the TBF header, with a default
+/// deny all fallback.
+///
+/// This behaves like [`TbfHeaderFilterDefaultAllow`] for processes that have
+/// TbfHeaderPermissions. Processes without them may not use any driver; they
+/// can still yield, exit and use memop.
+pub struct TbfHeaderFilterDefaultDeny {}
+
+impl SyscallFilter for TbfHeaderFilterDefaultDeny {
+    fn filter_syscall(
+        &self,
+        process: &dyn process::Process,
+        syscall: &syscall::Syscall,
+    ) -> Result<(), errorcode::ErrorCode> {
+        filter_by_tbf_permissions(process, syscall, Err(errorcode::ErrorCode::NODEVICE))
+    }
+}
+
+/// Check `syscall` against the TbfHeaderPermissions of `process`, returning
+/// `no_permissions` if the process has none.
+fn filter_by_tbf_permissions(
+    process: &dyn process::Process,
+    syscall: &syscall::Syscall,
+    no_permissions: Result<(), errorcode::ErrorCode>,
+) -> Result<(), errorcode::ErrorCode> {
+    match syscall {
+        // Subscribe is allowed if any commands are
+        syscall::Syscall::Subscribe {
+            driver_number,
+            subdriver_number: _,
+            upcall_ptr: _,
+            appdata: _,
+        } => match process.get_command_permissions(*driver_number, 0) {
+            CommandPermissions::NoPermsAtAll => no_permissions,
+            CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
+            CommandPermissions::Mask(_allowed) => Ok(()),
+        },
+
+        syscall::Syscall::Command {
+            driver_number,
+            subdriver_number,
+            arg0: _,
+            arg1: _,
+        } => match process.get_command_permissions(*driver_number, subdriver_number / 64) {
+            CommandPermissions::NoPermsAtAll => no_permissions,
+            CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
+            CommandPermissions::Mask(allowed) => {
+                if (1 << (subdriver_number % 64)) & allowed > 0 {
+                    Ok(())
+                } else {
+                    Err(errorcode::ErrorCode::NODEVICE)
                 }
-            },
-
-            // Allow is allowed if any commands are
-            syscall::Syscall::ReadWriteAllow {
-                driver_number,
-                subdriver_number: _,
-                allow_address: _,
-                allow_size: _,
-            } => match process.get_command_permissions(*driver_number, 0) {
-                CommandPermissions::NoPermsAtAll => Ok(()),
-                CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
-                CommandPermissions::Mask(_allowed) => Ok(()),
-            },
-
-            // Allow is allowed if any commands are
-            syscall::Syscall::UserspaceReadableAllow {
-                driver_number,
-                subdriver_number: _,
-                allow_address: _,
-                allow_size: _,
-            } => match process.get_command_permissions(*driver_number, 0) {
-                CommandPermissions::NoPermsAtAll => Ok(()),
-                CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
-                CommandPermissions::Mask(_allowed) => Ok(()),
-            },
-
-            // Allow is allowed if any commands are
-            syscall::Syscall::ReadOnlyAllow {
-                driver_number,
-                subdriver_number: _,
-                allow_address: _,
-                allow_size: _,
-            } => match process.get_command_permissions(*driver_number, 0) {
-                CommandPermissions::NoPermsAtAll => Ok(()),
-                CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
-                CommandPermissions::Mask(_allowed) => Ok(()),
-            },
-
-            // Non-filterable system calls
-            syscall::Syscall::Yield { .. }
-            | syscall::Syscall::Memop { .. }
-            | syscall::Syscall::Exit { .. } => Ok(()),
-        }
+            }
+        },
+
+        // Allow is allowed if any commands are
+        syscall::Syscall::ReadWriteAllow {
+            driver_number,
+            subdriver_number: _,
+            allow_address: _,
+            allow_size: _,
+        } => match process.get_command_permissions(*driver_number, 0) {
+            CommandPermissions::NoPermsAtAll => no_permissions,
+            CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
+            CommandPermissions::Mask(_allowed) => Ok(()),
+        },
+
+        // Allow is allowed if any commands are
+        syscall::Syscall::UserspaceReadableAllow {
+            driver_number,
+            subdriver_number: _,
+            allow_address: _,
+            allow_size: _,
+        } => match process.get_command_permissions(*driver_number, 0) {
+            CommandPermissions::NoPermsAtAll => no_permissions,
+            CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
+            CommandPermissions::Mask(_allowed) => Ok(()),
+        },
+
+        // Allow is allowed if any commands are
+        syscall::Syscall::ReadOnlyAllow {
+            driver_number,
+            subdriver_number: _,
+            allow_address: _,
+            allow_size: _,
+        } => match process.get_command_permissions(*driver_number, 0) {
+            CommandPermissions::NoPermsAtAll => no_permissions,
+            CommandPermissions::NoPermsThisDriver => Err(errorcode::ErrorCode::NODEVICE),
+            CommandPermissions::Mask(_allowed) => Ok(()),
+        },
+
+        // Non-filterable system calls
+        syscall::Syscall::Yield { .. }
+        | syscall::Syscall::Memop { .. }
+        | syscall::Syscall::Exit { .. } => Ok(()),
     }
 }
 
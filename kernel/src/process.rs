@@ -23,7 +23,7 @@ use crate::utilities::capability_ptr::CapabilityPtr;
 use tock_tbf::types::CommandPermissions;
 
 // Export all process related types via `kernel::process::`.
-pub use crate::process_binary::ProcessBinary;
+pub use crate::process_binary::{ProcessBinary, ProcessBinaryError};
 pub use crate::process_checker::AcceptedCredential;
 pub use crate::process_checker::{ProcessCheckerMachine, ProcessCheckerMachineClient};
 pub use crate::process_loading::load_processes;
@@ -0,0 +1,212 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! RAM that keeps its contents across warm resets.
+//!
+//! A board reserves a RAM region that startup code neither zeroes nor
+//! initializes, and hands it to [`RetainedRam`], which divides it into typed
+//! slots. Each slot stores its value with the value's size and a CRC32, so
+//! after a reset a slot only returns a value that was written to it, and not
+//! whatever the RAM happened to hold.
+//!
+//! Slots are allocated in order from the start of the region, so a slot is
+//! at the same place after a reset as long as the slots before it are
+//! allocated in the same order with the same types.
+//!
+//! ```ignore
+//! let retained = RetainedRam::new(region, !power_on_reset);
+//! let boots: RetainedSlot<u32> = retained.slot().unwrap();
+//! boots.set(boots.get().unwrap_or(0) + 1);
+//! ```
+
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+
+use crate::utilities::helpers::crc32_posix;
+
+/// Types that can be kept in retained RAM.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid value of the type, and the type must
+/// have no padding bytes.
+pub unsafe trait Retained: Copy {}
+
+unsafe impl Retained for u8 {}
+unsafe impl Retained for u16 {}
+unsafe impl Retained for u32 {}
+unsafe impl Retained for u64 {}
+unsafe impl Retained for usize {}
+unsafe impl Retained for i8 {}
+unsafe impl Retained for i16 {}
+unsafe impl Retained for i32 {}
+unsafe impl Retained for i64 {}
+unsafe impl Retained for isize {}
+unsafe impl<T: Retained, const N: usize> Retained for [T; N] {}
+
+/// Words before the value in a slot: its size, then its CRC.
+const HEADER_WORDS: usize = 2;
+
+/// A region of retained RAM, divided into slots.
+pub struct RetainedRam<'a> {
+    free: Cell<&'a [Cell<u32>]>,
+}
+
+impl<'a> RetainedRam<'a> {
+    /// Manage `region`.
+    ///
+    /// `contents_valid` is false if the region may hold garbage, e.g. after
+    /// a power-on reset. The region is then zeroed first, which on parts with
+    /// ECC-protected RAM also makes it readable.
+    pub fn new(region: &'a mut [u32], contents_valid: bool) -> Self {
+        if !contents_valid {
+            for word in region.iter_mut() {
+                // Safety: `word` is a valid, aligned reference.
+                unsafe { core::ptr::write_volatile(word, 0) };
+            }
+        }
+        Self {
+            free: Cell::new(Cell::from_mut(region).as_slice_of_cells()),
+        }
+    }
+
+    /// Allocate the next slot, for a value of type `T`. Returns `None` if
+    /// the region has no room left for it.
+    pub fn slot<T: Retained>(&self) -> Option<RetainedSlot<'a, T>> {
+        let free = self.free.get();
+        // Align the value, which follows the header, for `T`.
+        let align = align_of::<T>().max(size_of::<u32>());
+        let value_address = free.as_ptr() as usize + HEADER_WORDS * size_of::<u32>();
+        let skip = value_address.next_multiple_of(align) - value_address;
+        let words = HEADER_WORDS + size_of::<T>().div_ceil(size_of::<u32>());
+        let start = skip / size_of::<u32>();
+        if start + words > free.len() {
+            return None;
+        }
+        let (slot, rest) = free[start..].split_at(words);
+        self.free.set(rest);
+        Some(RetainedSlot {
+            words: slot,
+            _value: PhantomData,
+        })
+    }
+}
+
+/// A value of type `T` in retained RAM.
+pub struct RetainedSlot<'a, T: Retained> {
+    words: &'a [Cell<u32>],
+    _value: PhantomData<T>,
+}
+
+impl<T: Retained> RetainedSlot<'_, T> {
+    fn value(&self) -> *mut T {
+        self.words[HEADER_WORDS..].as_ptr().cast::<T>().cast_mut()
+    }
+
+    fn crc(value: &T) -> u32 {
+        // Safety: `T` has no padding, so all of its bytes are initialized.
+        let bytes = unsafe {
+            core::slice::from_raw_parts(core::ptr::from_ref(value).cast::<u8>(), size_of::<T>())
+        };
+        crc32_posix(bytes)
+    }
+
+    /// The value last stored, if the slot holds one.
+    pub fn get(&self) -> Option<T> {
+        // Safety: the value is aligned and within the slot, the slot is only
+        // accessed through `Cell`s, and every bit pattern is a valid `T`.
+        let value = unsafe { core::ptr::read_volatile(self.value()) };
+        let size = self.words[0].get();
+        let crc = self.words[1].get();
+        (size as usize == size_of::<T>() && crc == Self::crc(&value)).then_some(value)
+    }
+
+    /// Store `value`.
+    pub fn set(&self, value: T) {
+        // Safety: as for `get`.
+        unsafe { core::ptr::write_volatile(self.value(), value) };
+        self.words[0].set(size_of::<T>() as u32);
+        self.words[1].set(Self::crc(&value));
+    }
+
+    /// Empty the slot.
+    pub fn clear(&self) {
+        self.words[0].set(0);
+    }
+
+    /// The value last stored, if any, leaving the slot empty.
+    pub fn take(&self) -> Option<T> {
+        let value = self.get();
+        self.clear();
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RetainedRam, RetainedSlot};
+
+    #[test]
+    fn test_invalid_contents_are_cleared() {
+        let mut region = [0xA5A5_A5A5; 8];
+        let retained = RetainedRam::new(&mut region, false);
+        let slot: RetainedSlot<u32> = retained.slot().unwrap();
+        assert_eq!(slot.get(), None);
+        slot.set(7);
+        assert_eq!(slot.get(), Some(7));
+    }
+
+    #[test]
+    fn test_value_survives_reset() {
+        let mut region = [0; 8];
+        {
+            let retained = RetainedRam::new(&mut region, false);
+            let first: RetainedSlot<u32> = retained.slot().unwrap();
+            let second: RetainedSlot<[u8; 6]> = retained.slot().unwrap();
+            first.set(1);
+            second.set(*b"tock!!");
+        }
+        let retained = RetainedRam::new(&mut region, true);
+        let first: RetainedSlot<u32> = retained.slot().unwrap();
+        let second: RetainedSlot<[u8; 6]> = retained.slot().unwrap();
+        assert_eq!(first.take(), Some(1));
+        assert_eq!(first.get(), None);
+        assert_eq!(second.get(), Some(*b"tock!!"));
+    }
+
+    #[test]
+    fn test_corruption_and_type_change_are_detected() {
+        let mut region = [0; 8];
+        {
+            let retained = RetainedRam::new(&mut region, false);
+            let slot: RetainedSlot<u32> = retained.slot().unwrap();
+            slot.set(0x1234_5678);
+        }
+        region[2] ^= 1;
+        {
+            let retained = RetainedRam::new(&mut region, true);
+            let slot: RetainedSlot<u32> = retained.slot().unwrap();
+            assert_eq!(slot.get(), None);
+            slot.set(0x1234_5678);
+        }
+        let retained = RetainedRam::new(&mut region, true);
+        let slot: RetainedSlot<u16> = retained.slot().unwrap();
+        assert_eq!(slot.get(), None);
+    }
+
+    #[test]
+    fn test_slots_are_aligned_and_bounded() {
+        let mut region = [0; 8];
+        let retained = RetainedRam::new(&mut region, false);
+        let small: RetainedSlot<u8> = retained.slot().unwrap();
+        let wide: RetainedSlot<u64> = retained.slot().unwrap();
+        assert_eq!(wide.value() as usize % core::mem::align_of::<u64>(), 0);
+        small.set(3);
+        wide.set(u64::MAX);
+        assert_eq!(small.get(), Some(3));
+        assert_eq!(wide.get(), Some(u64::MAX));
+        assert!(retained.slot::<u64>().is_none());
+    }
+}